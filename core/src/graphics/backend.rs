@@ -1,4 +1,4 @@
-use crate::graphics::{Instance, TextureShaderResourceView, Fence};
+use crate::graphics::{Instance, TextureShaderResourceView, Fence, QueryPool};
 use crate::graphics::Adapter;
 use crate::graphics::Device;
 use crate::graphics::Surface;
@@ -36,4 +36,5 @@ pub trait Backend: 'static + Sized {
   type Fence : Fence + Send + Sync;
   type Semaphore : Send + Sync;
   type Queue : Queue<Self> + Send + Sync;
+  type QueryPool : QueryPool + Send + Sync;
 }
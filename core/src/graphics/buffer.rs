@@ -19,6 +19,8 @@ bitflags! {
     const VERTEX_SHADER_CONSTANT             = 0b100000000000;
     const COMPUTE_SHADER_CONSTANT            = 0b1000000000000;
     const INDIRECT                           = 0b10000000000000;
+    /// Read by the CPU after the GPU is done with it
+    const HOST_READ                          = 0b100000000000000;
 
     const STORAGE = Self::VERTEX_SHADER_STORAGE_READ.bits() | Self::VERTEX_SHADER_STORAGE_WRITE.bits()
      | Self::FRAGMENT_SHADER_STORAGE_READ.bits() | Self::FRAGMENT_SHADER_STORAGE_WRITE.bits()
@@ -11,6 +11,7 @@ use super::RenderpassRecordingMode;
 use super::ShaderType;
 use super::StoreOp;
 use super::SubpassInfo;
+use super::TimestampStage;
 
 pub struct Viewport {
   pub position: Vec2,
@@ -24,6 +25,22 @@ pub struct Scissor {
   pub extent: Vec2UI
 }
 
+#[repr(C)]
+#[derive(Clone, Debug, Copy, Default, PartialEq)]
+pub struct DrawIndexedIndirectCommand {
+  pub index_count: u32,
+  pub instance_count: u32,
+  pub first_index: u32,
+  pub vertex_offset: i32,
+  pub first_instance: u32
+}
+
+#[derive(Clone, Debug, Copy, PartialEq, Eq, Hash)]
+pub enum IndexFormat {
+  U16,
+  U32
+}
+
 #[derive(Clone, Debug, Copy, PartialEq, Hash)]
 pub enum CommandBufferType {
   PRIMARY,
@@ -39,25 +56,35 @@ pub enum PipelineBinding<'a, B: Backend> {
 pub trait CommandBuffer<B: Backend> {
   fn set_pipeline(&mut self, pipeline: PipelineBinding<B>);
   fn set_vertex_buffer(&mut self, vertex_buffer: &Arc<B::Buffer>);
-  fn set_index_buffer(&mut self, index_buffer: &Arc<B::Buffer>);
+  /// Binds the buffers to consecutive binding slots, the offsets are relative to the start of each buffer.
+  fn set_vertex_buffers(&mut self, first_binding: u32, vertex_buffers: &[&Arc<B::Buffer>], offsets: &[usize]);
+  fn set_index_buffer(&mut self, index_buffer: &Arc<B::Buffer>, format: IndexFormat);
   fn set_viewports(&mut self, viewports: &[ Viewport ]);
   fn set_scissors(&mut self, scissors: &[ Scissor ]);
   fn init_texture_mip_level(&mut self, src_buffer: &Arc<B::Buffer>, texture: &Arc<B::Texture>, mip_level: u32, array_layer: u32);
   fn upload_dynamic_data<T>(&mut self, data: &[T], usage: BufferUsage) -> Arc<B::Buffer>
   where T: 'static + Send + Sync + Sized + Clone;
+  /// Passes the data as push constants, it must fit into the push constants declared by the shader.
+  /// Those can't exceed 128 bytes because that's all the hardware is guaranteed to support.
   fn upload_dynamic_data_inline<T>(&mut self, data: &[T], visible_for_shader_stage: ShaderType)
     where T: 'static + Send + Sync + Sized + Clone;
   fn draw(&mut self, vertices: u32, offset: u32);
   fn draw_indexed(&mut self, instances: u32, first_instance: u32, indices: u32, first_index: u32, vertex_offset: i32);
+  fn draw_indexed_indirect(&mut self, draw_buffer: &Arc<B::Buffer>, draw_buffer_offset: u32, draw_count: u32, stride: u32);
   fn bind_texture_view(&mut self, frequency: BindingFrequency, binding: u32, texture: &Arc<B::TextureShaderResourceView>, sampler: &Arc<B::Sampler>);
   fn bind_uniform_buffer(&mut self, frequency: BindingFrequency, binding: u32, buffer: &Arc<B::Buffer>);
   fn bind_storage_buffer(&mut self, frequency: BindingFrequency, binding: u32, buffer: &Arc<B::Buffer>);
   fn bind_storage_texture(&mut self, frequency: BindingFrequency, binding: u32, texture: &Arc<B::TextureUnorderedAccessView>);
   fn finish_binding(&mut self);
-  fn begin_label(&mut self, label: &str);
+  fn begin_label(&mut self, label: &str, color: [f32; 4]);
   fn end_label(&mut self);
   fn dispatch(&mut self, group_count_x: u32, group_count_y: u32, group_count_z: u32);
+  fn dispatch_indirect(&mut self, buffer: &Arc<B::Buffer>, offset: u32);
+  fn reset_query_pool(&mut self, query_pool: &Arc<B::QueryPool>, first_query: u32, query_count: u32);
+  fn write_timestamp(&mut self, query_pool: &Arc<B::QueryPool>, query_index: u32, stage: TimestampStage);
   fn blit(&mut self, src_texture: &Arc<B::Texture>, src_array_layer: u32, src_mip_level: u32, dst_texture: &Arc<B::Texture>, dst_array_layer: u32, dst_mip_level: u32);
+  fn resolve(&mut self, src_texture: &Arc<B::Texture>, dst_texture: &Arc<B::Texture>);
+  fn copy_texture_to_buffer(&mut self, src_texture: &Arc<B::Texture>, src_array_layer: u32, src_mip_level: u32, dst_buffer: &Arc<B::Buffer>);
   fn finish(self) -> B::CommandBufferSubmission;
 
   fn begin_render_pass_1(&mut self, renderpass_info: &RenderPassBeginInfo<B>, recording_mode: RenderpassRecordingMode);
@@ -74,7 +101,8 @@ pub trait CommandBuffer<B: Backend> {
 pub trait Queue<B: Backend> {
   fn create_command_buffer(&self) -> B::CommandBuffer;
   fn create_inner_command_buffer(&self, inheritance: &<B::CommandBuffer as CommandBuffer<B>>::CommandBufferInheritance) -> B::CommandBuffer;
-  fn submit(&self, submission: B::CommandBufferSubmission, fence: Option<&Arc<B::Fence>>, wait_semaphores: &[&Arc<B::Semaphore>], signal_semaphores: &[&Arc<B::Semaphore>]);
+  /// Each wait semaphore comes with the first texture usage that depends on it, earlier work can start right away.
+  fn submit(&self, submission: B::CommandBufferSubmission, fence: Option<&Arc<B::Fence>>, wait_semaphores: &[(&Arc<B::Semaphore>, TextureUsage)], signal_semaphores: &[&Arc<B::Semaphore>]);
   fn present(&self, swapchain: &Arc<B::Swapchain>, wait_semaphores: &[&Arc<B::Semaphore>]);
 }
 
@@ -2,7 +2,7 @@ use std::sync::Arc;
 
 use crate::graphics::{TextureInfo, TextureShaderResourceViewInfo, BufferUsage, GraphicsPipelineInfo, ShaderType, Backend};
 
-use super::{RenderPassInfo, TextureRenderTargetViewInfo, buffer::BufferInfo, texture::{SamplerInfo, TextureDepthStencilViewInfo, TextureUnorderedAccessViewInfo}};
+use super::{RenderPassInfo, SampleCount, TextureRenderTargetViewInfo, buffer::BufferInfo, texture::{SamplerInfo, TextureDepthStencilViewInfo, TextureUnorderedAccessViewInfo}};
 
 #[derive(Clone, Debug, Copy, PartialEq, Eq, Hash)]
 pub enum AdapterType {
@@ -40,11 +40,18 @@ pub trait Device<B: Backend> {
   fn create_graphics_pipeline(&self, info: &GraphicsPipelineInfo<B>, renderpass_info: &RenderPassInfo, subpass: u32) -> Arc<B::GraphicsPipeline>;
   fn wait_for_idle(&self);
   fn init_texture(&self, texture: &Arc<B::Texture>, buffer: &Arc<B::Buffer>, mip_level: u32, array_layer: u32);
+  fn init_texture_mips(&self, texture: &Arc<B::Texture>, buffers: &[Arc<B::Buffer>], array_layer: u32);
   fn init_texture_async(&self, texture: &Arc<B::Texture>, buffer: &Arc<B::Buffer>, mip_level: u32, array_layer: u32) -> Option<Arc<B::Fence>>;
   fn init_buffer(&self, src_buffer: &Arc<B::Buffer>, dst_buffer: &Arc<B::Buffer>);
   fn flush_transfers(&self);
   fn free_completed_transfers(&self);
   fn create_fence(&self) -> Arc<B::Fence>;
+  fn wait_for_fences(&self, fences: &[&Arc<B::Fence>], wait_all: bool, timeout_ns: u64) -> bool;
   fn create_semaphore(&self) -> Arc<B::Semaphore>;
+  fn create_timestamp_query_pool(&self, query_count: u32) -> Arc<B::QueryPool>;
+  /// Writes the timestamps in nanoseconds, returns false if the results aren't available yet.
+  fn get_query_results(&self, query_pool: &Arc<B::QueryPool>, first_query: u32, results: &mut [u64]) -> bool;
   fn graphics_queue(&self) -> &Arc<B::Queue>;
+  /// The highest sample count that color and depth render targets both support.
+  fn max_sample_count(&self) -> SampleCount;
 }
@@ -5,17 +5,29 @@ pub enum Format {
   R32,
   R16,
   RGBA8,
+  SRGBA8,
   BGR8UNorm,
   BGRA8UNorm,
+  BGRA8Srgb,
   DXT1,
+  DXT1Srgb,
   DXT1Alpha,
+  DXT1AlphaSrgb,
   DXT3,
+  DXT3Srgb,
   DXT5,
+  DXT5Srgb,
+  BC4,
+  BC5,
+  BC7,
+  BC7Srgb,
   R16Float,
   R32Float,
   RG32Float,
   RGB32Float,
   RGBA32Float,
+  RGBA16Float,
+  RGB10A2,
 
   D16,
   D16S8,
@@ -5,6 +5,7 @@ pub use self::instance::Instance;
 pub use self::surface::Surface;
 pub use self::surface::Swapchain;
 pub use self::surface::SwapchainError;
+pub use self::surface::PresentMode;
 pub use self::command::CommandBuffer;
 pub use self::command::CommandBufferType;
 pub use self::command::InnerCommandBufferProvider;
@@ -20,10 +21,13 @@ pub use self::format::Format;
 pub use self::pipeline::*;
 pub use self::texture::Texture;
 pub use self::texture::TextureInfo;
+pub use self::texture::TextureDimension;
 pub use self::texture::TextureUsage;
 pub use self::renderpass::*;
 pub use self::command::Viewport;
 pub use self::command::Scissor;
+pub use self::command::DrawIndexedIndirectCommand;
+pub use self::command::IndexFormat;
 pub use self::command::Barrier;
 pub use self::backend::Backend;
 pub use self::command::BindingFrequency;
@@ -37,6 +41,7 @@ pub use self::texture::{
   TextureDepthStencilView, TextureDepthStencilViewInfo 
 };
 pub use self::sync::Fence;
+pub use self::query::{QueryPool, TimestampStage};
 
 mod device;
 mod instance;
@@ -50,6 +55,7 @@ mod renderpass;
 mod backend;
 mod sync;
 mod resource;
+mod query;
 
 // TODO: find a better place for this
 pub trait Resettable {
@@ -86,6 +86,17 @@ pub enum SampleCount {
   Samples8
 }
 
+impl SampleCount {
+  pub fn count(&self) -> u32 {
+    match self {
+      SampleCount::Samples1 => 1,
+      SampleCount::Samples2 => 2,
+      SampleCount::Samples4 => 4,
+      SampleCount::Samples8 => 8
+    }
+  }
+}
+
 #[derive(Hash, Eq, PartialEq, Clone)]
 pub struct RasterizerInfo {
   pub fill_mode: FillMode,
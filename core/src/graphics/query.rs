@@ -0,0 +1,9 @@
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TimestampStage {
+  Top,
+  Bottom
+}
+
+pub trait QueryPool {
+  fn query_count(&self) -> u32;
+}
@@ -6,10 +6,19 @@ pub trait Surface {
 
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PresentMode {
+  Fifo,
+  FifoRelaxed,
+  Mailbox,
+  Immediate
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum SwapchainError {
   ZeroExtents,
   SurfaceLost,
+  OutOfDate,
   Other
 }
 
@@ -19,7 +28,7 @@ pub trait Swapchain<B: Backend> : Sized {
   fn sample_count(&self) -> SampleCount;
   fn format(&self) -> Format;
   fn surface(&self) -> &Arc<B::Surface>;
-  fn prepare_back_buffer(&self, semaphore: &Arc<B::Semaphore>) -> Option<Arc<B::TextureRenderTargetView>>;
+  fn prepare_back_buffer(&self, semaphore: &Arc<B::Semaphore>) -> Result<Arc<B::TextureRenderTargetView>, SwapchainError>;
   fn width(&self) -> u32;
   fn height(&self) -> u32;
 }
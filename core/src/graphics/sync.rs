@@ -1,4 +1,7 @@
 pub trait Fence {
   fn is_signaled(&self) -> bool;
   fn await_signal(&self);
+  fn wait(&self, timeout_ns: u64) -> bool;
+  /// Only valid if the fence is signalled or wasn't submitted.
+  fn reset(&self);
 }
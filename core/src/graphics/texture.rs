@@ -28,8 +28,16 @@ bitflags! {
   }
 }
 
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum TextureDimension {
+  Dim1D,
+  Dim2D,
+  Dim3D
+}
+
 #[derive(Clone, Eq, PartialEq, Hash)]
 pub struct TextureInfo {
+  pub dimension: TextureDimension,
   pub format: Format,
   pub width: u32,
   pub height: u32,
@@ -37,7 +45,9 @@ pub struct TextureInfo {
   pub mip_levels: u32,
   pub array_length: u32,
   pub samples: SampleCount,
-  pub usage: TextureUsage
+  pub usage: TextureUsage,
+  /// Every 6 array layers form a cube, in the +X, -X, +Y, -Y, +Z, -Z order
+  pub is_cubemap: bool
 }
 
 pub trait Texture {
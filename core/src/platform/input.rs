@@ -21,7 +21,11 @@ pub struct InputState {
   mouse_pos: Vec2I,
   mouse_buttons: u32,
   fingers_down: u32,
-  finger_pos: [Vec2; 6]
+  finger_pos: [Vec2; 6],
+  left_stick: Vec2,
+  right_stick: Vec2,
+  left_trigger: f32,
+  right_trigger: f32
 }
 
 impl InputState {
@@ -56,6 +60,18 @@ impl InputState {
   pub fn set_finger_position(&mut self, finger_index: u32, position: Vec2) {
     self.finger_pos[finger_index as usize] = position;
   }
+  pub fn set_left_stick(&mut self, position: Vec2) {
+    self.left_stick = position;
+  }
+  pub fn set_right_stick(&mut self, position: Vec2) {
+    self.right_stick = position;
+  }
+  pub fn set_left_trigger(&mut self, value: f32) {
+    self.left_trigger = value;
+  }
+  pub fn set_right_trigger(&mut self, value: f32) {
+    self.right_trigger = value;
+  }
 
   pub fn is_key_down(&self, key: Key) -> bool {
     self.keyboard_keys.bit_test(key as usize)
@@ -72,6 +88,20 @@ impl InputState {
   pub fn finger_position(&self, finger_index: u32) -> Vec2 {
     self.finger_pos[finger_index as usize]
   }
+  /// Both axes of the sticks are in the range -1..1, +Y points down.
+  pub fn left_stick(&self) -> Vec2 {
+    self.left_stick
+  }
+  pub fn right_stick(&self) -> Vec2 {
+    self.right_stick
+  }
+  /// The triggers are in the range 0..1.
+  pub fn left_trigger(&self) -> f32 {
+    self.left_trigger
+  }
+  pub fn right_trigger(&self) -> f32 {
+    self.right_trigger
+  }
 }
 
 #[derive(Default)]
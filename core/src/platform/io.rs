@@ -1,5 +1,6 @@
 use std::io::{Read, Seek, Result as IOResult};
 use std::path::Path;
+use std::time::SystemTime;
 
 pub trait IO {
   type File: Read + Seek + Send;
@@ -7,4 +8,7 @@ pub trait IO {
   fn asset_exists<P: AsRef<Path>>(path: P) -> bool;
   fn open_external_asset<P: AsRef<Path>>(path: P) -> IOResult<Self::File>;
   fn external_asset_exists<P: AsRef<Path>>(path: P) -> bool;
+  fn external_asset_modified_time<P: AsRef<Path>>(_path: P) -> Option<SystemTime> {
+    None
+  }
 }
@@ -0,0 +1,26 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform sampler2D inputTexture;
+layout(set = 0, binding = 1, rgba16f) uniform writeonly image2D outputTexture;
+layout(set = 0, binding = 2) uniform Params {
+  vec2 direction;
+};
+
+const float weights[5] = float[](0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+
+void main() {
+  ivec2 texSize = textureSize(inputTexture, 0);
+  ivec2 storageTexCoord = ivec2(int(gl_GlobalInvocationID.x), int(gl_GlobalInvocationID.y));
+  if (storageTexCoord.x >= texSize.x || storageTexCoord.y >= texSize.y) {
+    return;
+  }
+
+  vec2 texCoord = (vec2(storageTexCoord) + 0.5) / vec2(texSize);
+  vec2 texelStep = direction / vec2(texSize);
+  vec3 sum = texture(inputTexture, texCoord).rgb * weights[0];
+  for (int i = 1; i < 5; i++) {
+    sum += texture(inputTexture, texCoord + texelStep * float(i)).rgb * weights[i];
+    sum += texture(inputTexture, texCoord - texelStep * float(i)).rgb * weights[i];
+  }
+  imageStore(outputTexture, storageTexCoord, vec4(sum, 1.0));
+}
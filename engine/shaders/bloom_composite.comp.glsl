@@ -0,0 +1,24 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform sampler2D frame;
+layout(set = 0, binding = 1) uniform sampler2D bloom;
+layout(set = 0, binding = 2, rgba16f) uniform writeonly image2D outputTexture;
+layout(set = 0, binding = 3) uniform Params {
+  float intensity;
+  uint mipCount;
+};
+
+void main() {
+  ivec2 textureSize = textureSize(frame, 0);
+  vec2 texCoord = vec2((float(gl_GlobalInvocationID.x) + 0.5) / float(textureSize.x), (float(gl_GlobalInvocationID.y) + 0.5) / float(textureSize.y));
+  ivec2 storageTexCoord = ivec2(int(gl_GlobalInvocationID.x), int(gl_GlobalInvocationID.y));
+
+  vec3 bloomColor = vec3(0.0);
+  for (uint i = 0; i < mipCount; i++) {
+    bloomColor += textureLod(bloom, texCoord, float(i)).rgb;
+  }
+  bloomColor /= float(max(mipCount, 1));
+
+  vec3 color = texture(frame, texCoord).rgb;
+  imageStore(outputTexture, storageTexCoord, vec4(color + bloomColor * intensity, 1.0));
+}
@@ -0,0 +1,22 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform sampler2D inputTexture;
+layout(set = 0, binding = 1, rgba16f) uniform writeonly image2D outputTexture;
+layout(set = 0, binding = 2) uniform Params {
+  float threshold;
+};
+
+void main() {
+  ivec2 outputSize = imageSize(outputTexture);
+  ivec2 storageTexCoord = ivec2(int(gl_GlobalInvocationID.x), int(gl_GlobalInvocationID.y));
+  if (storageTexCoord.x >= outputSize.x || storageTexCoord.y >= outputSize.y) {
+    return;
+  }
+
+  // The output is half the size of the input so the bilinear sample averages a 2x2 block.
+  vec2 texCoord = (vec2(storageTexCoord) + 0.5) / vec2(outputSize);
+  vec3 color = texture(inputTexture, texCoord).rgb;
+  float brightness = max(color.r, max(color.g, color.b));
+  float contribution = max(brightness - threshold, 0.0) / max(brightness, 0.0001);
+  imageStore(outputTexture, storageTexCoord, vec4(color * contribution, 1.0));
+}
@@ -12,8 +12,15 @@ layout(set = 0, binding = 1, std140) buffer Camera {
   mat4 invProj;
   mat4 view;
   mat4 proj;
+  vec2 jitter;
+  vec2 oldJitter;
 } camera;
 
+layout(set = 0, binding = 2, std140) uniform Jitter {
+  vec2 jitter;
+  vec2 oldJitter;
+} jitterParams;
+
 void main() {
   mat4 proj = cameras.proj[cameras.proj_index];
   mat4 view = cameras.view[cameras.view_index];
@@ -21,4 +28,6 @@ void main() {
   camera.proj = proj;
   camera.viewProj = proj * view;
   camera.invProj = inverse(proj);
+  camera.jitter = jitterParams.jitter;
+  camera.oldJitter = jitterParams.oldJitter;
 }
@@ -0,0 +1,157 @@
+#version 450
+
+// Luma based edge detection and blending following FXAA 3.11 (quality preset)
+
+layout(set = 0, binding = 0) uniform sampler2D frame;
+layout(set = 0, binding = 1, rgba16f) uniform writeonly image2D outputTexture;
+
+const float EDGE_THRESHOLD_MIN = 0.0312;
+const float EDGE_THRESHOLD_MAX = 0.125;
+const float SUBPIXEL_QUALITY = 0.75;
+const int ITERATIONS = 12;
+const float QUALITY[ITERATIONS] = float[](1.0, 1.0, 1.0, 1.0, 1.0, 1.5, 2.0, 2.0, 2.0, 2.0, 4.0, 8.0);
+
+// The input is HDR, compress it first so the edge thresholds keep working on bright pixels.
+float luma(vec3 color) {
+  color = color / (color + vec3(1.0));
+  return sqrt(dot(color, vec3(0.299, 0.587, 0.114)));
+}
+
+float lumaAt(vec2 uv) {
+  return luma(textureLod(frame, uv, 0.0).rgb);
+}
+
+void main() {
+  ivec2 texSize = textureSize(frame, 0);
+  ivec2 storageTexCoord = ivec2(int(gl_GlobalInvocationID.x), int(gl_GlobalInvocationID.y));
+  if (storageTexCoord.x >= texSize.x || storageTexCoord.y >= texSize.y) {
+    return;
+  }
+  vec2 inverseSize = vec2(1.0 / float(texSize.x), 1.0 / float(texSize.y));
+  vec2 texCoord = (vec2(storageTexCoord) + 0.5) * inverseSize;
+
+  vec3 colorCenter = textureLod(frame, texCoord, 0.0).rgb;
+  float lumaCenter = luma(colorCenter);
+  float lumaDown = luma(textureLodOffset(frame, texCoord, 0.0, ivec2(0, -1)).rgb);
+  float lumaUp = luma(textureLodOffset(frame, texCoord, 0.0, ivec2(0, 1)).rgb);
+  float lumaLeft = luma(textureLodOffset(frame, texCoord, 0.0, ivec2(-1, 0)).rgb);
+  float lumaRight = luma(textureLodOffset(frame, texCoord, 0.0, ivec2(1, 0)).rgb);
+
+  float lumaMin = min(lumaCenter, min(min(lumaDown, lumaUp), min(lumaLeft, lumaRight)));
+  float lumaMax = max(lumaCenter, max(max(lumaDown, lumaUp), max(lumaLeft, lumaRight)));
+  float lumaRange = lumaMax - lumaMin;
+
+  // Skip pixels that aren't on an edge or are too dark to matter
+  if (lumaRange < max(EDGE_THRESHOLD_MIN, lumaMax * EDGE_THRESHOLD_MAX)) {
+    imageStore(outputTexture, storageTexCoord, vec4(colorCenter, 1.0));
+    return;
+  }
+
+  float lumaDownLeft = luma(textureLodOffset(frame, texCoord, 0.0, ivec2(-1, -1)).rgb);
+  float lumaUpRight = luma(textureLodOffset(frame, texCoord, 0.0, ivec2(1, 1)).rgb);
+  float lumaUpLeft = luma(textureLodOffset(frame, texCoord, 0.0, ivec2(-1, 1)).rgb);
+  float lumaDownRight = luma(textureLodOffset(frame, texCoord, 0.0, ivec2(1, -1)).rgb);
+
+  float lumaDownUp = lumaDown + lumaUp;
+  float lumaLeftRight = lumaLeft + lumaRight;
+  float lumaLeftCorners = lumaDownLeft + lumaUpLeft;
+  float lumaDownCorners = lumaDownLeft + lumaDownRight;
+  float lumaRightCorners = lumaDownRight + lumaUpRight;
+  float lumaUpCorners = lumaUpRight + lumaUpLeft;
+
+  float edgeHorizontal = abs(-2.0 * lumaLeft + lumaLeftCorners) + abs(-2.0 * lumaCenter + lumaDownUp) * 2.0 + abs(-2.0 * lumaRight + lumaRightCorners);
+  float edgeVertical = abs(-2.0 * lumaUp + lumaUpCorners) + abs(-2.0 * lumaCenter + lumaLeftRight) * 2.0 + abs(-2.0 * lumaDown + lumaDownCorners);
+  bool isHorizontal = edgeHorizontal >= edgeVertical;
+
+  // Pick the side of the edge with the steeper gradient
+  float luma1 = isHorizontal ? lumaDown : lumaLeft;
+  float luma2 = isHorizontal ? lumaUp : lumaRight;
+  float gradient1 = luma1 - lumaCenter;
+  float gradient2 = luma2 - lumaCenter;
+  bool is1Steepest = abs(gradient1) >= abs(gradient2);
+  float gradientScaled = 0.25 * max(abs(gradient1), abs(gradient2));
+
+  float stepLength = isHorizontal ? inverseSize.y : inverseSize.x;
+  float lumaLocalAverage;
+  if (is1Steepest) {
+    stepLength = -stepLength;
+    lumaLocalAverage = 0.5 * (luma1 + lumaCenter);
+  } else {
+    lumaLocalAverage = 0.5 * (luma2 + lumaCenter);
+  }
+
+  vec2 currentUv = texCoord;
+  if (isHorizontal) {
+    currentUv.y += stepLength * 0.5;
+  } else {
+    currentUv.x += stepLength * 0.5;
+  }
+
+  // Walk along the edge in both directions until its end is found
+  vec2 offset = isHorizontal ? vec2(inverseSize.x, 0.0) : vec2(0.0, inverseSize.y);
+  vec2 uv1 = currentUv - offset;
+  vec2 uv2 = currentUv + offset;
+  float lumaEnd1 = lumaAt(uv1) - lumaLocalAverage;
+  float lumaEnd2 = lumaAt(uv2) - lumaLocalAverage;
+  bool reached1 = abs(lumaEnd1) >= gradientScaled;
+  bool reached2 = abs(lumaEnd2) >= gradientScaled;
+  bool reachedBoth = reached1 && reached2;
+  if (!reached1) {
+    uv1 -= offset;
+  }
+  if (!reached2) {
+    uv2 += offset;
+  }
+
+  if (!reachedBoth) {
+    for (int i = 2; i < ITERATIONS; i++) {
+      if (!reached1) {
+        lumaEnd1 = lumaAt(uv1) - lumaLocalAverage;
+      }
+      if (!reached2) {
+        lumaEnd2 = lumaAt(uv2) - lumaLocalAverage;
+      }
+      reached1 = abs(lumaEnd1) >= gradientScaled;
+      reached2 = abs(lumaEnd2) >= gradientScaled;
+      reachedBoth = reached1 && reached2;
+      if (!reached1) {
+        uv1 -= offset * QUALITY[i];
+      }
+      if (!reached2) {
+        uv2 += offset * QUALITY[i];
+      }
+      if (reachedBoth) {
+        break;
+      }
+    }
+  }
+
+  float distance1 = isHorizontal ? (texCoord.x - uv1.x) : (texCoord.y - uv1.y);
+  float distance2 = isHorizontal ? (uv2.x - texCoord.x) : (uv2.y - texCoord.y);
+  bool isDirection1 = distance1 < distance2;
+  float distanceFinal = min(distance1, distance2);
+  float edgeThickness = distance1 + distance2;
+  float pixelOffset = -distanceFinal / edgeThickness + 0.5;
+
+  // Only blend if the luma variation at the closest end matches the side we're on
+  bool isLumaCenterSmaller = lumaCenter < lumaLocalAverage;
+  bool correctVariation = ((isDirection1 ? lumaEnd1 : lumaEnd2) < 0.0) != isLumaCenterSmaller;
+  float finalOffset = correctVariation ? pixelOffset : 0.0;
+
+  // Subpixel anti-aliasing for single pixel features
+  float lumaAverage = (1.0 / 12.0) * (2.0 * (lumaDownUp + lumaLeftRight) + lumaLeftCorners + lumaRightCorners);
+  float subPixelOffset1 = clamp(abs(lumaAverage - lumaCenter) / lumaRange, 0.0, 1.0);
+  float subPixelOffset2 = (-2.0 * subPixelOffset1 + 3.0) * subPixelOffset1 * subPixelOffset1;
+  float subPixelOffsetFinal = subPixelOffset2 * subPixelOffset2 * SUBPIXEL_QUALITY;
+  finalOffset = max(finalOffset, subPixelOffsetFinal);
+
+  vec2 finalUv = texCoord;
+  if (isHorizontal) {
+    finalUv.y += finalOffset * stepLength;
+  } else {
+    finalUv.x += finalOffset * stepLength;
+  }
+
+  vec3 finalColor = textureLod(frame, finalUv, 0.0).rgb;
+  imageStore(outputTexture, storageTexCoord, vec4(finalColor, 1.0));
+}
@@ -0,0 +1,31 @@
+#version 450
+
+#define HI_Z_WIDTH 128
+#define HI_Z_HEIGHT 64
+
+layout(local_size_x = 1, local_size_y = 1, local_size_z = 1) in;
+
+layout(set = 0, binding = 0) uniform sampler2D depth;
+layout(std430, set = 0, binding = 1) writeonly buffer HiZ {
+  float hiZ[];
+};
+
+void main() {
+  uvec2 texel = gl_GlobalInvocationID.xy;
+  if (texel.x >= HI_Z_WIDTH || texel.y >= HI_Z_HEIGHT) {
+    return;
+  }
+
+  ivec2 depthSize = textureSize(depth, 0);
+  ivec2 start = ivec2(texel * uvec2(depthSize) / uvec2(HI_Z_WIDTH, HI_Z_HEIGHT));
+  ivec2 end = ivec2((texel + 1) * uvec2(depthSize) / uvec2(HI_Z_WIDTH, HI_Z_HEIGHT));
+  end = clamp(end, start + 1, depthSize);
+
+  float maxDepth = 0.0;
+  for (int y = start.y; y < end.y; y++) {
+    for (int x = start.x; x < end.x; x++) {
+      maxDepth = max(maxDepth, texelFetch(depth, ivec2(x, y), 0).r);
+    }
+  }
+  hiZ[texel.y * HI_Z_WIDTH + texel.x] = maxDepth;
+}
@@ -22,6 +22,7 @@ layout(std430, set = 0, binding = 1, std430) readonly buffer clusterAABB {
 layout(std430, set = 0, binding = 2, std430) readonly buffer setupBuffer {
   uint clusterCount;
   uint pointLightCount;
+  uint spotLightCount;
 };
 
 struct PointLight {
@@ -36,7 +37,23 @@ layout (std430, set = 0, binding = 4) buffer lightBitmasksBuffer {
   uint lightBitmasks[];
 };
 
+struct SpotLight {
+  vec3 position;
+  float range;
+  vec3 direction;
+  float angle;
+};
+layout(std430, set = 0, binding = 5, std430) readonly buffer spotLightsBuffer {
+  SpotLight spotLights[];
+};
+
+layout (std430, set = 0, binding = 6) buffer spotLightBitmasksBuffer {
+  uint spotLightBitmasks[];
+};
+
 bool pointLightIntersectsCluster(PointLight light, Cluster cluster);
+bool spotLightIntersectsCluster(SpotLight light, Cluster cluster);
+void binSpotLights(uint clusterIndex);
 
 shared vec3 viewSpacePointLights[64];
 
@@ -81,6 +98,31 @@ void main() {
     }
     lightOffset += batchSize;
   }
+
+  binSpotLights(clusterIndex);
+}
+
+void binSpotLights(uint clusterIndex) {
+  uint bitmaskCount = (spotLightCount + 31) / 32;
+  if (clusterIndex >= clusterCount) {
+    return;
+  }
+
+  for (uint i = 0; i < bitmaskCount; i++) {
+    spotLightBitmasks[clusterIndex * bitmaskCount + i] = 0;
+  }
+
+  Cluster cluster = clusters[clusterIndex];
+  for (uint lightIndex = 0; lightIndex < spotLightCount; lightIndex++) {
+    SpotLight light = spotLights[lightIndex];
+    light.position = (camera.view * vec4(light.position, 1)).xyz;
+    light.direction = normalize((camera.view * vec4(light.direction, 0)).xyz);
+    if (spotLightIntersectsCluster(light, cluster)) {
+      uint bitmaskIndex = lightIndex / 32;
+      uint bitIndex = lightIndex % 32;
+      atomicOr(spotLightBitmasks[bitmaskCount * clusterIndex + bitmaskIndex], 1 << bitIndex);
+    }
+  }
 }
 
 // check if light radius extends into the cluster
@@ -92,3 +134,19 @@ bool pointLightIntersectsCluster(PointLight light, Cluster cluster) {
   vec3 dist = closest - light.position;
   return dot(dist, dist) <= (light.radius * light.radius);
 }
+
+// cone vs the bounding sphere of the cluster AABB
+// https://bartwronski.com/2017/04/13/cull-that-cone/
+// light position and direction have to be in view space
+bool spotLightIntersectsCluster(SpotLight light, Cluster cluster) {
+  vec3 center = (cluster.minPoint.xyz + cluster.maxPoint.xyz) * 0.5;
+  float radius = length(cluster.maxPoint.xyz - center);
+  vec3 v = center - light.position;
+  float vLengthSquared = dot(v, v);
+  float v1Length = dot(v, light.direction);
+  float distanceClosestPoint = cos(light.angle) * sqrt(max(vLengthSquared - v1Length * v1Length, 0.0)) - v1Length * sin(light.angle);
+  bool angleCull = distanceClosestPoint > radius;
+  bool frontCull = v1Length > radius + light.range;
+  bool backCull = v1Length < -radius;
+  return !(angleCull || frontCull || backCull);
+}
@@ -4,6 +4,7 @@
 layout(location = 0) in vec4 in_position;
 layout(location = 1) in vec3 in_normal;
 layout(location = 2) in vec4 in_oldPosition;
+layout(location = 3) flat in vec4 in_jitter;
 
 layout(location = 0) out vec4 out_normal;
 layout(location = 1) out vec2 out_motion;
@@ -13,5 +14,7 @@ void main(void) {
 
   vec2 transformedPos = (in_position.xy / in_position.w) * 0.5 + 0.5;
   vec2 transformedOldPos = (in_oldPosition.xy / in_oldPosition.w) * 0.5 + 0.5;
-  out_motion = transformedPos - transformedOldPos;
+  // Both positions are jittered, only keep the actual movement. Y is flipped after jittering.
+  vec2 jitterOffset = (in_jitter.xy - in_jitter.zw) * vec2(0.5, -0.5);
+  out_motion = transformedPos - transformedOldPos - jitterOffset;
 }
@@ -0,0 +1,14 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec3 in_pos;
+
+layout(push_constant) uniform VeryHighFrequencyUbo {
+  mat4 mvp;
+};
+
+void main(void) {
+  vec4 pos = mvp * vec4(in_pos, 1);
+  pos.y = -pos.y;
+  gl_Position = pos;
+}
@@ -2,12 +2,37 @@
 
 layout(set = 0, binding = 0) uniform sampler2D frame;
 layout(set = 0, binding = 1, rgba8) uniform writeonly image2D outputTexture;
+layout(set = 0, binding = 2) uniform Params {
+  uint tonemappingOperator;
+  float exposure;
+  uint debugOutput;
+};
+
+const uint DEBUG_OUTPUT_NONE = 0u;
+const uint DEBUG_OUTPUT_PASSTHROUGH = 1u;
+const uint DEBUG_OUTPUT_NORMALS = 2u;
+
+const uint TONEMAPPING_ACES = 0u;
+const uint TONEMAPPING_REINHARD = 1u;
+const uint TONEMAPPING_UNCHARTED2 = 2u;
+
+vec3 tonemap(vec3 color);
+vec3 linearToSrgb(vec3 color);
 
 void main() {
     ivec2 textureSize = textureSize(frame, 0);
     vec2 texCoord = vec2((float(gl_GlobalInvocationID.x) + 0.5) / float(textureSize.x), (float(gl_GlobalInvocationID.y) + 0.5) / float(textureSize.y));
     ivec2 storageTexCoord = ivec2(int(gl_GlobalInvocationID.x), int(gl_GlobalInvocationID.y));
 
+    if (debugOutput == DEBUG_OUTPUT_PASSTHROUGH) {
+      imageStore(outputTexture, storageTexCoord, vec4(texture(frame, texCoord).rgb, 1.0));
+      return;
+    } else if (debugOutput == DEBUG_OUTPUT_NORMALS) {
+      vec3 normal = texture(frame, texCoord).xyz;
+      imageStore(outputTexture, storageTexCoord, vec4(dot(normal, normal) > 0.0 ? normalize(normal) * 0.5 + 0.5 : vec3(0.0), 1.0));
+      return;
+    }
+
     /*
       0 -1  0
       -1  5 -1
@@ -33,5 +58,40 @@ void main() {
 
     float sharpeningIntensity = 0.3;
     vec3 finalColor = mix(color, sharpened, sharpeningIntensity);
+    finalColor = linearToSrgb(tonemap(max(finalColor, vec3(0.0)) * exposure));
     imageStore(outputTexture, storageTexCoord, vec4(finalColor, 1.0));
 }
+
+vec3 uncharted2Curve(vec3 x) {
+  const float A = 0.15;
+  const float B = 0.50;
+  const float C = 0.10;
+  const float D = 0.20;
+  const float E = 0.02;
+  const float F = 0.30;
+  return ((x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F)) - E / F;
+}
+
+vec3 tonemap(vec3 color) {
+  if (tonemappingOperator == TONEMAPPING_REINHARD) {
+    return color / (color + vec3(1.0));
+  } else if (tonemappingOperator == TONEMAPPING_UNCHARTED2) {
+    const float whitePoint = 11.2;
+    const float exposureBias = 2.0;
+    return uncharted2Curve(color * exposureBias) / uncharted2Curve(vec3(whitePoint));
+  } else {
+    // Narkowicz's fit of the ACES filmic curve
+    const float a = 2.51;
+    const float b = 0.03;
+    const float c = 2.43;
+    const float d = 0.59;
+    const float e = 0.14;
+    return clamp((color * (a * color + b)) / (color * (c * color + d) + e), 0.0, 1.0);
+  }
+}
+
+vec3 linearToSrgb(vec3 color) {
+  vec3 low = color * 12.92;
+  vec3 high = 1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055;
+  return mix(high, low, lessThanEqual(color, vec3(0.0031308)));
+}
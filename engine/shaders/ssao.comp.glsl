@@ -13,6 +13,12 @@ layout(set = 0, binding = 4, std140) uniform Camera {
   mat4 proj;
 } camera;
 layout(set = 0, binding = 5, r16f) uniform writeonly image2D outputTexture;
+layout(set = 0, binding = 6, std140) uniform Params {
+  float radius;
+  float bias;
+  float intensity;
+  uint kernelSize;
+} params;
 
 // REFERENCE:
 // http://john-chapman-graphics.blogspot.com/2013/01/ssao-tutorial.html
@@ -39,13 +45,12 @@ void main() {
   vec3 biTangent = cross(viewNormal, tangent);
   mat3 TBN = mat3(tangent, biTangent, viewNormal);
 
-  float bias = 0.025;
+  float bias = params.bias;
+  float radius = params.radius;
+  uint kernelSize = min(params.kernelSize, 16u);
 
   float occlusion = 0.0;
 
-  const uint kernelSize = 16;
-  const float radius = 0.5;
-
   for (uint i = 0; i < kernelSize; i++) {
     vec3 samplePos = TBN * samples[i].xyz;
     samplePos = viewPos + samplePos * radius;
@@ -65,7 +70,7 @@ void main() {
     float rangeCheck = smoothstep(0.0, 1.0, radius / abs(viewPos.z - sampleView.z));
     occlusion += (sampleView.z >= samplePos.z + bias ? 1.0 : 0.0) * rangeCheck;
   }
-  occlusion = 1.0 - (occlusion / kernelSize);
+  occlusion = clamp(1.0 - (occlusion / max(float(kernelSize), 1.0)) * params.intensity, 0.0, 1.0);
   ivec2 storageTexCoord = ivec2(int(gl_GlobalInvocationID.x), int(gl_GlobalInvocationID.y));
   imageStore(outputTexture, storageTexCoord, vec4(occlusion, 0.0, 0.0, 0.0));
 }
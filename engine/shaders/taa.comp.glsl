@@ -2,7 +2,7 @@
 
 layout(set = 0, binding = 0) uniform sampler2D frame;
 layout(set = 0, binding = 1) uniform sampler2D history;
-layout(set = 0, binding = 2, rgba8) uniform writeonly image2D outputTexture;
+layout(set = 0, binding = 2, rgba16f) uniform writeonly image2D outputTexture;
 layout(set = 0, binding = 3) uniform sampler2D motion;
 
 const int HISTORY_FRAMES = 8;
@@ -46,17 +46,65 @@ layout(set = 2, binding = 3) uniform PerFrameUbo {
   float clusterZScale;
   uvec3 clusterCount;
   uint pointLightCount;
+  uint debugView;
+  uint spotLightCount;
 };
 
+struct SpotLight {
+  vec3 position;
+  float intensity;
+  vec3 direction;
+  float angle;
+};
+layout(std430, set = 2, binding = 9, std430) readonly buffer spotLightsBuffer {
+  SpotLight spotLights[];
+};
+
+layout (std430, set = 2, binding = 10) buffer spotLightBitmasksBuffer {
+  uint spotLightBitmasks[];
+};
+
+const uint DEBUG_VIEW_NONE = 0u;
+const uint DEBUG_VIEW_NORMALS = 1u;
+const uint DEBUG_VIEW_ALBEDO = 2u;
+const uint DEBUG_VIEW_DEPTH = 3u;
+const uint DEBUG_VIEW_OVERDRAW = 4u;
+const uint DEBUG_VIEW_CLUSTERS = 5u;
+const uint DEBUG_VIEW_SSAO = 6u;
+
 layout(set = 2, binding = 4) uniform sampler2D ssao;
 
+layout(set = 2, binding = 5) uniform sampler2DArray pointShadowMaps;
+layout(std140, set = 2, binding = 6) uniform PointShadowsUbo {
+  mat4 pointShadowViewProj[24];
+  uvec4 pointShadowLightIndices;
+  uint pointShadowLightCount;
+};
+
+layout(set = 2, binding = 7) uniform sampler2DArray cascadeShadowMaps;
+layout(std140, set = 2, binding = 8) uniform CascadesUbo {
+  mat4 cascadeViewProj[4];
+  vec4 cascadeSplits;
+  vec4 directionalLightDirection;
+  vec4 directionalLightColor;
+  uint directionalLightCount;
+};
+
 /*layout(std430, set = 2, binding = 4, std430) readonly buffer clusterAABB {
   Cluster clusters[];
 };*/
 
 float linearizeDepth(float d, float zNear,float zFar);
+float pointShadow(uint lightIndex, vec3 lightToFrag);
+float cascadeShadow(float viewDepth);
 
 void main(void) {
+  if (debugView == DEBUG_VIEW_OVERDRAW) {
+    // Blended additively, every fragment brightens the pixel a bit
+    out_color = vec4(0.1, 0.05, 0.02, 1.0);
+    return;
+  }
+
   vec2 tileSize = vec2(rtSize) / vec2(clusterCount.xy);
 
   float z = linearizeDepth(gl_FragCoord.z, zNear, zFar);
@@ -72,6 +120,24 @@ void main(void) {
 
   uint maxClusterCount = clusterCount.x * clusterCount.y * clusterCount.z;
 
+  if (debugView != DEBUG_VIEW_NONE) {
+    vec3 debugColor = vec3(0.0);
+    // The normals view is sampled from the prepass normals after post processing
+    if (debugView == DEBUG_VIEW_ALBEDO) {
+      debugColor = texture(tex, in_uv).rgb;
+    } else if (debugView == DEBUG_VIEW_DEPTH) {
+      debugColor = vec3(z / zFar);
+    } else if (debugView == DEBUG_VIEW_CLUSTERS) {
+      // Hash the index so neighbouring clusters get clearly different colors
+      uint hash = clusterIndex * 2654435761u;
+      debugColor = vec3(float(hash & 0xFFu), float((hash >> 8) & 0xFFu), float((hash >> 16) & 0xFFu)) / 255.0;
+    } else if (debugView == DEBUG_VIEW_SSAO) {
+      debugColor = texture(ssao, vec2(gl_FragCoord.x / rtSize.x, gl_FragCoord.y / rtSize.y)).rrr;
+    }
+    out_color = vec4(debugColor, 1.0);
+    return;
+  }
+
   /*
   vec3 viewPos = (camera.view * vec4(in_worldPosition, 1)).xyz;
   if (abs(z - viewPos.z) > 0.01) {
@@ -91,6 +157,12 @@ void main(void) {
   lighting += texture(lightmap, in_lightmap_uv).xyz;
   lighting *= texture(ssao, vec2(gl_FragCoord.x / rtSize.x, gl_FragCoord.y / rtSize.y)).rrr;
 
+  if (directionalLightCount != 0) {
+    float viewDepth = -(camera.view * vec4(in_worldPosition, 1.0)).z;
+    float shadow = cascadeShadow(viewDepth);
+    lighting += shadow * directionalLightColor.rgb * max(0.0, dot(in_normal, -directionalLightDirection.xyz));
+  }
+
   uint lightBitmaskCount = (pointLightCount + 31) / 32;
   uint bitmaskOffset = lightBitmaskCount * clusterIndex;
   for (uint i = 0; i < lightBitmaskCount; i++) {
@@ -111,14 +183,79 @@ void main(void) {
         vec3 fragToLight = in_worldPosition - light.position;
         vec3 lightDir = normalize(fragToLight);
         float lightSquaredDist = dot(fragToLight, fragToLight);
-        lighting += max(0.0, dot(in_normal, normalize(lightDir)) * (light.intensity * 1.0 / lightSquaredDist));
+        float shadow = pointShadow(i * 32 + bitIndex, fragToLight);
+        lighting += shadow * max(0.0, dot(in_normal, normalize(lightDir)) * (light.intensity * 1.0 / lightSquaredDist));
       }
     }
   }
+  uint spotLightBitmaskCount = (spotLightCount + 31) / 32;
+  uint spotBitmaskOffset = spotLightBitmaskCount * clusterIndex;
+  for (uint i = 0; i < spotLightBitmaskCount && clusterIndex < maxClusterCount; i++) {
+    uint bitmask = spotLightBitmasks[spotBitmaskOffset + i];
+    while (bitmask != 0) {
+      uint bitIndex = findLSB(bitmask);
+      bitmask &= ~(1 << bitIndex);
+      SpotLight light = spotLights[i * 32 + bitIndex];
+      vec3 fragToLight = light.position - in_worldPosition;
+      vec3 lightDir = normalize(fragToLight);
+      float lightSquaredDist = dot(fragToLight, fragToLight);
+      // Fade out over the outer 10% of the cone
+      float cosAngle = dot(-lightDir, light.direction);
+      float cosOuter = cos(light.angle);
+      float cosInner = cos(light.angle * 0.9);
+      float cone = clamp((cosAngle - cosOuter) / max(cosInner - cosOuter, 0.0001), 0.0, 1.0);
+      lighting += cone * max(0.0, dot(in_normal, lightDir)) * (light.intensity / lightSquaredDist);
+    }
+  }
+
   vec4 tex = texture(tex, in_uv);
   out_color = vec4(lighting.x * tex.x, lighting.y * tex.y, lighting.z * tex.z, 1);
 }
 
+float pointShadow(uint lightIndex, vec3 lightToFrag) {
+  for (uint i = 0; i < pointShadowLightCount; i++) {
+    if (pointShadowLightIndices[i] != lightIndex) {
+      continue;
+    }
+
+    // Pick the cube face the same way the hardware would: +X, -X, +Y, -Y, +Z, -Z
+    vec3 absDir = abs(lightToFrag);
+    uint face;
+    if (absDir.x >= absDir.y && absDir.x >= absDir.z) {
+      face = lightToFrag.x > 0.0 ? 0 : 1;
+    } else if (absDir.y >= absDir.z) {
+      face = lightToFrag.y > 0.0 ? 2 : 3;
+    } else {
+      face = lightToFrag.z > 0.0 ? 4 : 5;
+    }
+    uint layer = i * 6 + face;
+    vec4 shadowPos = pointShadowViewProj[layer] * vec4(in_worldPosition, 1.0);
+    shadowPos.xyz /= shadowPos.w;
+    vec2 shadowUv = vec2(shadowPos.x * 0.5 + 0.5, -shadowPos.y * 0.5 + 0.5);
+    float shadowDepth = texture(pointShadowMaps, vec3(shadowUv, float(layer))).r;
+    const float bias = 0.0005;
+    return shadowPos.z - bias > shadowDepth ? 0.0 : 1.0;
+  }
+  return 1.0;
+}
+
+float cascadeShadow(float viewDepth) {
+  uint cascade = 0;
+  while (cascade < 4 && viewDepth > cascadeSplits[cascade]) {
+    cascade++;
+  }
+  if (cascade == 4) {
+    return 1.0;
+  }
+
+  vec4 shadowPos = cascadeViewProj[cascade] * vec4(in_worldPosition, 1.0);
+  shadowPos.xyz /= shadowPos.w;
+  vec2 shadowUv = vec2(shadowPos.x * 0.5 + 0.5, -shadowPos.y * 0.5 + 0.5);
+  float shadowDepth = texture(cascadeShadowMaps, vec3(shadowUv, float(cascade))).r;
+  const float bias = 0.001;
+  return shadowPos.z - bias > shadowDepth ? 0.0 : 1.0;
+}
+
 float linearizeDepth(float d, float zNear,float zFar)
 {
   return 2.0 * zNear * zFar / (zFar + zNear - d * (zFar - zNear));
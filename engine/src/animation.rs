@@ -0,0 +1,171 @@
+use std::ops::{Add, Mul};
+
+use legion::{Entity, EntityStore, IntoQuery};
+use legion::systems::Builder;
+use legion::world::SubWorld;
+use sourcerenderer_core::{Matrix4, Quaternion, Vec3, Vec4};
+
+use crate::Transform;
+use crate::game::DeltaTime;
+
+pub fn install(systems: &mut Builder) {
+  systems.add_system(animate_system());
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+  Linear,
+  Step,
+  /// Every keyframe stores an in-tangent, the value and an out-tangent
+  CubicSpline
+}
+
+#[derive(Clone, Debug)]
+pub enum AnimationValues {
+  Translation(Vec<Vec3>),
+  /// Quaternions in XYZW order, not necessarily normalized
+  Rotation(Vec<Vec4>),
+  Scale(Vec<Vec3>)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AnimationValue {
+  Translation(Vec3),
+  Rotation(Quaternion),
+  Scale(Vec3)
+}
+
+#[derive(Clone)]
+pub struct AnimationTrack {
+  pub target: Entity,
+  pub interpolation: Interpolation,
+  pub times: Vec<f32>,
+  pub values: AnimationValues
+}
+
+#[derive(Clone)]
+pub struct AnimationClip {
+  pub name: String,
+  pub duration: f32,
+  pub tracks: Vec<AnimationTrack>
+}
+
+/// Joints are regular entities, so the bone hierarchy is expressed through their Parent components.
+pub struct SkinComponent {
+  pub joints: Vec<Entity>,
+  /// One per joint, transforms from mesh space into the space of the joint
+  pub inverse_bind_matrices: Vec<Matrix4>,
+  pub skeleton: Option<Entity>
+}
+
+pub struct AnimationPlayerComponent {
+  pub clips: Vec<AnimationClip>,
+  pub active_clip: Option<usize>,
+  pub time: f32,
+  pub looping: bool
+}
+
+impl AnimationTrack {
+  /// Returns None if the track has no keyframes.
+  pub fn sample(&self, time: f32) -> Option<AnimationValue> {
+    if self.times.is_empty() {
+      return None;
+    }
+
+    let next_index = self.times.iter().position(|keyframe_time| *keyframe_time > time);
+    let (index, next_index) = match next_index {
+      Some(0) => (0, 0),
+      Some(next_index) => (next_index - 1, next_index),
+      None => (self.times.len() - 1, self.times.len() - 1)
+    };
+    let duration = self.times[next_index] - self.times[index];
+    let factor = if duration > 0f32 { (time - self.times[index]) / duration } else { 0f32 };
+
+    Some(match &self.values {
+      AnimationValues::Translation(values) => AnimationValue::Translation(self.interpolate(values, index, next_index, factor, duration)),
+      AnimationValues::Scale(values) => AnimationValue::Scale(self.interpolate(values, index, next_index, factor, duration)),
+      AnimationValues::Rotation(values) => {
+        let rotation = if self.interpolation == Interpolation::Linear {
+          let from = Quaternion::new_normalize(nalgebra::Quaternion::from(values[index]));
+          let to = Quaternion::new_normalize(nalgebra::Quaternion::from(values[next_index]));
+          from.try_slerp(&to, factor, 1.0e-6f32).unwrap_or_else(|| from.nlerp(&to, factor))
+        } else {
+          Quaternion::new_normalize(nalgebra::Quaternion::from(self.interpolate(values, index, next_index, factor, duration)))
+        };
+        AnimationValue::Rotation(rotation)
+      }
+    })
+  }
+
+  fn interpolate<T>(&self, values: &[T], index: usize, next_index: usize, factor: f32, duration: f32) -> T
+    where T: Copy + Add<Output = T> + Mul<f32, Output = T> {
+    match self.interpolation {
+      Interpolation::Step => values[index],
+      Interpolation::Linear => values[index] * (1f32 - factor) + values[next_index] * factor,
+      Interpolation::CubicSpline => {
+        let value = values[index * 3 + 1];
+        let out_tangent = values[index * 3 + 2];
+        let next_in_tangent = values[next_index * 3];
+        let next_value = values[next_index * 3 + 1];
+        let factor2 = factor * factor;
+        let factor3 = factor2 * factor;
+        value * (2f32 * factor3 - 3f32 * factor2 + 1f32)
+          + out_tangent * ((factor3 - 2f32 * factor2 + factor) * duration)
+          + next_value * (-2f32 * factor3 + 3f32 * factor2)
+          + next_in_tangent * ((factor3 - factor2) * duration)
+      }
+    }
+  }
+}
+
+impl AnimationClip {
+  pub fn apply(&self, time: f32, transforms: &mut impl EntityStore) {
+    for track in &self.tracks {
+      let value = track.sample(time);
+      if value.is_none() {
+        continue;
+      }
+      let mut entry = if let Ok(entry) = transforms.entry_mut(track.target) {
+        entry
+      } else {
+        continue;
+      };
+      let transform = if let Ok(transform) = entry.get_component_mut::<Transform>() {
+        transform
+      } else {
+        continue;
+      };
+      match value.unwrap() {
+        AnimationValue::Translation(translation) => { transform.position = translation; }
+        AnimationValue::Rotation(rotation) => { transform.rotation = rotation; }
+        AnimationValue::Scale(scale) => { transform.scale = scale; }
+      }
+    }
+  }
+}
+
+#[system]
+#[write_component(AnimationPlayerComponent)]
+#[write_component(Transform)]
+fn animate(world: &mut SubWorld, #[resource] delta_time: &DeltaTime) {
+  let mut query = <&mut AnimationPlayerComponent>::query();
+  let (mut player_world, mut transform_world) = world.split_for_query(&query);
+  for player in query.iter_mut(&mut player_world) {
+    let clip_index = if let Some(clip_index) = player.active_clip {
+      clip_index
+    } else {
+      continue;
+    };
+    let clip = if let Some(clip) = player.clips.get(clip_index) {
+      clip
+    } else {
+      continue;
+    };
+
+    player.time += delta_time.secs();
+    if player.time > clip.duration {
+      player.time = if player.looping && clip.duration > 0f32 { player.time % clip.duration } else { clip.duration };
+    }
+    clip.apply(player.time, &mut transform_world);
+  }
+}
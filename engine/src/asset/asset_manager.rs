@@ -1,13 +1,15 @@
-use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 use std::sync::{Arc, RwLock, Mutex, Condvar};
-use std::collections::{VecDeque, HashSet};
+use std::collections::{VecDeque, HashSet, HashMap};
 use sourcerenderer_core::platform::{Platform, io::IO};
 use sourcerenderer_core::graphics;
 use sourcerenderer_core::graphics::TextureInfo;
+use sourcerenderer_core::{Matrix4, Quaternion, Vec3, Vec4};
 use std::hash::Hash;
 
 use std::sync::Weak;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
+use std::path::PathBuf;
 use legion::World;
 use std::io::{Cursor, Seek, SeekFrom, Read, Result as IOResult};
 
@@ -39,7 +41,8 @@ pub enum AssetType {
   Sound,
   Level,
   Chunk,
-  Container
+  Container,
+  Skeleton
 }
 
 #[derive(Clone)]
@@ -50,25 +53,127 @@ pub struct MeshRange {
 
 pub struct Texture {
   pub info: TextureInfo,
-  pub data: Box<[Box<[u8]>]>
+  /// One entry per mip level of every array layer, ordered by layer first
+  pub data: Box<[Box<[u8]>]>,
+  /// Animated textures store their frames as array layers, each frame taking up array_length / frame_count layers
+  pub frame_count: u32
 }
 
 pub struct Mesh {
   pub indices: Option<Box<[u8]>>,
   pub vertices: Box<[u8]>,
+  /// One SkinningVertex per vertex for meshes that are deformed by a skeleton
+  pub skinning: Option<Box<[u8]>>,
   pub parts: Box<[MeshRange]>,
   pub bounding_box: Option<BoundingBox>
 }
 
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct SkinningVertex {
+  pub joints: [u16; 4],
+  /// Sum up to 1 unless the vertex isn't influenced by any joint
+  pub weights: [f32; 4]
+}
+
+#[derive(Clone)]
+pub struct SkeletonBone {
+  pub name: String,
+  /// Parents always come before their children
+  pub parent: Option<usize>,
+  /// Bind pose relative to the parent bone
+  pub position: Vec3,
+  pub rotation: Quaternion,
+  /// Transforms from mesh space into the space of the bone
+  pub inverse_bind_matrix: Matrix4
+}
+
+/// The joint indices of SkinningVertex refer to the bones of the skeleton.
+#[derive(Clone)]
+pub struct Skeleton {
+  pub bones: Vec<SkeletonBone>
+}
+
+#[derive(Clone)]
+pub struct ModelLod {
+  pub mesh_path: String,
+  /// The LOD gets used once the projected size of the model drops below this fraction of the screen height.
+  pub screen_size: f32
+}
+
 #[derive(Clone)]
 pub struct Model {
   pub mesh_path: String,
-  pub material_paths: Vec<String>
+  pub material_paths: Vec<String>,
+  /// Coarser versions of the mesh, ordered by decreasing screen size
+  pub lods: Vec<ModelLod>
+}
+
+impl Model {
+  /// Models with multiple material sets store every skin other than the default one as its own model at this path.
+  pub fn skin_path(model_path: &str, skin: u32) -> String {
+    if skin == 0 {
+      model_path.to_owned()
+    } else {
+      format!("{}/skin{}", model_path, skin)
+    }
+  }
+}
+
+#[derive(Clone)]
+pub struct MaterialTexture {
+  pub path: String,
+  /// Index of the UV set that's used to sample the texture
+  pub tex_coord: u32
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AlphaMode {
+  Opaque,
+  /// Pixels with an alpha value below the cutoff get discarded
+  Mask(f32),
+  Blend
 }
 
 #[derive(Clone)]
 pub struct Material {
-  pub albedo_texture_path: String
+  pub albedo_texture_path: String,
+  pub albedo_tex_coord: u32,
+  pub base_color_factor: Vec4,
+  pub metallic_factor: f32,
+  pub roughness_factor: f32,
+  pub metallic_roughness_texture: Option<MaterialTexture>,
+  pub normal_texture: Option<MaterialTexture>,
+  pub occlusion_texture: Option<MaterialTexture>,
+  pub emissive_texture: Option<MaterialTexture>,
+  pub emissive_factor: Vec3,
+  pub alpha_mode: AlphaMode,
+  pub detail_texture: Option<MaterialTexture>,
+  /// Source shader like LightmappedGeneric, lets the renderer pick a matching pipeline
+  pub shader_name: Option<String>,
+  pub surface_prop: Option<String>
+}
+
+impl Material {
+  /// A plain diffuse material without any of the PBR parameters.
+  pub fn new(albedo_texture_path: &str) -> Self {
+    Self {
+      albedo_texture_path: albedo_texture_path.to_string(),
+      albedo_tex_coord: 0,
+      base_color_factor: Vec4::new(1f32, 1f32, 1f32, 1f32),
+      metallic_factor: 0f32,
+      roughness_factor: 1f32,
+      metallic_roughness_texture: None,
+      normal_texture: None,
+      occlusion_texture: None,
+      emissive_texture: None,
+      emissive_factor: Vec3::new(0f32, 0f32, 0f32),
+      alpha_mode: AlphaMode::Opaque,
+      detail_texture: None,
+      shader_name: None,
+      surface_prop: None
+    }
+  }
 }
 
 pub struct AssetFile<P: Platform> {
@@ -125,6 +230,14 @@ pub trait AssetContainer<P: Platform>
     self.load(path).is_some()
   }
   fn load(&self, path: &str) -> Option<AssetFile<P>>;
+  /// Only containers that are backed by files on disk can report this, everything else won't get hot-reloaded.
+  fn modified_time(&self, _path: &str) -> Option<SystemTime> {
+    None
+  }
+  /// The location of the file on disk, if it's stored as a regular file.
+  fn external_path(&self, _path: &str) -> Option<PathBuf> {
+    None
+  }
 }
 
 pub struct AssetLoaderProgress {
@@ -138,12 +251,28 @@ impl AssetLoaderProgress {
   }
 }
 
+#[derive(Debug, Clone)]
+pub struct AssetError {
+  pub path: String,
+  pub reason: String
+}
+
+impl AssetError {
+  pub fn new<R: ToString>(path: &str, reason: R) -> Self {
+    Self {
+      path: path.to_string(),
+      reason: reason.to_string()
+    }
+  }
+}
+
 pub struct AssetLoaderResult {
   pub level: Option<World>,
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
 pub enum AssetLoadPriority {
+  High,
   Normal,
   Low
 }
@@ -151,7 +280,7 @@ pub enum AssetLoadPriority {
 pub trait AssetLoader<P: Platform>
   : Send + Sync {
   fn matches(&self, file: &mut AssetFile<P>) -> bool;
-  fn load(&self, file: AssetFile<P>, manager: &Arc<AssetManager<P>>, priority: AssetLoadPriority, progress: &Arc<AssetLoaderProgress>) -> Result<AssetLoaderResult, ()>;
+  fn load(&self, file: AssetFile<P>, manager: &Arc<AssetManager<P>>, priority: AssetLoadPriority, progress: &Arc<AssetLoaderProgress>) -> Result<AssetLoaderResult, AssetError>;
 }
 
 pub enum Asset {
@@ -159,7 +288,8 @@ pub enum Asset {
   Mesh(Mesh),
   Model(Model),
   Sound,
-  Material(Material)
+  Material(Material),
+  Skeleton(Skeleton)
 }
 
 pub struct AssetManager<P: Platform> {
@@ -169,32 +299,157 @@ pub struct AssetManager<P: Platform> {
   loaders: RwLock<Vec<Box<dyn AssetLoader<P>>>>,
   renderer_sender: Sender<LoadedAsset>,
   renderer_receiver: Receiver<LoadedAsset>,
+  released_sender: Sender<String>,
+  released_receiver: Receiver<String>,
+  memory_budget: AtomicU64,
+  progress_callback: RwLock<Option<Box<dyn Fn(u32, u32) + Send + Sync>>>,
   cond_var: Arc<Condvar>
 }
 
 struct AssetManagerInner {
-  load_queue: VecDeque<AssetLoadRequest>,
+  high_priority_queue: VecDeque<AssetLoadRequest>,
+  normal_priority_queue: VecDeque<AssetLoadRequest>,
+  low_priority_queue: VecDeque<AssetLoadRequest>,
   requested_assets: HashSet<String>,
-  loaded_assets: HashSet<String>
+  loaded_assets: HashSet<String>,
+  errors: Vec<AssetError>,
+  total_requests: u32,
+  finished_requests: u32,
+  /// Highest fraction reported since everything was last done
+  reported_progress: f32,
+  ref_counts: HashMap<String, u32>,
+  skeletons: HashMap<String, Arc<Skeleton>>,
+  #[cfg(feature = "hot-reload")]
+  watched_files: HashMap<String, WatchedFile>
+}
+
+#[cfg(feature = "hot-reload")]
+struct WatchedFile {
+  modified_time: SystemTime,
+  asset_type: AssetType,
+  priority: AssetLoadPriority
+}
+
+impl AssetManagerInner {
+  fn new() -> Self {
+    Self {
+      high_priority_queue: VecDeque::new(),
+      normal_priority_queue: VecDeque::new(),
+      low_priority_queue: VecDeque::new(),
+      loaded_assets: HashSet::new(),
+      requested_assets: HashSet::new(),
+      errors: Vec::new(),
+      total_requests: 0,
+      finished_requests: 0,
+      reported_progress: 0f32,
+      ref_counts: HashMap::new(),
+      skeletons: HashMap::new(),
+      #[cfg(feature = "hot-reload")]
+      watched_files: HashMap::new()
+    }
+  }
+
+  fn push_request(&mut self, request: AssetLoadRequest) {
+    let queue = match request.priority {
+      AssetLoadPriority::High => &mut self.high_priority_queue,
+      AssetLoadPriority::Normal => &mut self.normal_priority_queue,
+      AssetLoadPriority::Low => &mut self.low_priority_queue
+    };
+    queue.push_back(request);
+    self.total_requests += 1;
+  }
+
+  /// Moves an already queued request over to the high priority queue.
+  fn promote_request(&mut self, path: &str) {
+    for queue in [&mut self.normal_priority_queue, &mut self.low_priority_queue] {
+      if let Some(index) = queue.iter().position(|request| request.path == path) {
+        let mut request = queue.remove(index).unwrap();
+        request.priority = AssetLoadPriority::High;
+        self.high_priority_queue.push_back(request);
+        return;
+      }
+    }
+  }
+
+  fn finish_request(&mut self) -> (u32, u32) {
+    self.finished_requests += 1;
+    if self.finished_requests == self.total_requests {
+      // The next batch of requests starts from zero again
+      self.reported_progress = 0f32;
+    }
+    (self.finished_requests, self.total_requests)
+  }
+
+  /// Loaders add requests while loading, which lowers the raw ratio.
+  /// The reported one never goes backwards until everything is done.
+  fn progress_fraction(&mut self) -> f32 {
+    if self.finished_requests == self.total_requests {
+      return 1f32;
+    }
+    let fraction = self.finished_requests as f32 / self.total_requests as f32;
+    self.reported_progress = self.reported_progress.max(fraction);
+    self.reported_progress
+  }
+
+  fn pop_request(&mut self) -> Option<AssetLoadRequest> {
+    self.high_priority_queue.pop_front()
+      .or_else(|| self.normal_priority_queue.pop_front())
+      .or_else(|| self.low_priority_queue.pop_front())
+  }
+
+  /// Queues the watched files that changed since they were loaded, returns whether there were any.
+  #[cfg(feature = "hot-reload")]
+  fn queue_modified_files(&mut self, modified_files: Vec<(String, SystemTime)>) -> bool {
+    let mut reloaded_any = false;
+    for (path, modified_time) in modified_files {
+      let (asset_type, priority) = match self.watched_files.get_mut(&path) {
+        Some(watched_file) if watched_file.modified_time < modified_time => {
+          watched_file.modified_time = modified_time;
+          (watched_file.asset_type, watched_file.priority)
+        }
+        _ => continue
+      };
+      if self.requested_assets.contains(&path) {
+        continue;
+      }
+
+      // The loader sends the assets to the renderer again which replaces the existing ones in place.
+      println!("Reloading modified file: {:?}", path);
+      self.loaded_assets.remove(&path);
+      self.requested_assets.insert(path.clone());
+      self.push_request(AssetLoadRequest {
+        asset_type,
+        path,
+        progress: Arc::new(AssetLoaderProgress {
+          expected: AtomicU32::new(1),
+          finished: AtomicU32::new(0)
+        }),
+        priority
+      });
+      reloaded_any = true;
+    }
+    reloaded_any
+  }
 }
 
 impl<P: Platform> AssetManager<P> {
   pub fn new(device: &Arc<<P::GraphicsBackend as graphics::Backend>::Device>) -> Arc<Self> {
     let (renderer_sender, renderer_receiver) = unbounded();
+    let (released_sender, released_receiver) = unbounded();
 
     let cond_var = Arc::new(Condvar::new());
 
     let manager = Arc::new(Self {
       device: device.clone(),
-      inner: Mutex::new(AssetManagerInner {
-        load_queue: VecDeque::new(),
-        loaded_assets: HashSet::new(),
-        requested_assets: HashSet::new()
-      }),
+      inner: Mutex::new(AssetManagerInner::new()),
       loaders: RwLock::new(Vec::new()),
       containers: RwLock::new(Vec::new()),
       renderer_sender,
       renderer_receiver,
+      released_sender,
+      released_receiver,
+      memory_budget: AtomicU64::new(u64::MAX),
+      progress_callback: RwLock::new(None),
       cond_var
     });
 
@@ -215,6 +470,7 @@ impl<P: Platform> AssetManager<P> {
     let mesh = Mesh {
       vertices: vertex_buffer_data,
       indices: if !index_buffer_data.is_empty() { Some(index_buffer_data) } else { None },
+      skinning: None,
       parts,
       bounding_box: None
     };
@@ -222,16 +478,15 @@ impl<P: Platform> AssetManager<P> {
   }
 
   pub fn add_material(&self, path: &str, albedo: &str) {
-    let material = Material {
-      albedo_texture_path: albedo.to_string()
-    };
+    let material = Material::new(albedo);
     self.add_asset(path, Asset::Material(material), AssetLoadPriority::Normal);
   }
 
   pub fn add_model(&self, path: &str, mesh_path: &str, material_paths: &[&str]) {
     let model = Model {
       mesh_path: mesh_path.to_string(),
-      material_paths: material_paths.iter().map(|mat| (*mat).to_owned()).collect()
+      material_paths: material_paths.iter().map(|mat| (*mat).to_owned()).collect(),
+      lods: Vec::new()
     };
     self.add_asset(path, Asset::Model(model), AssetLoadPriority::Normal);
   }
@@ -240,6 +495,7 @@ impl<P: Platform> AssetManager<P> {
     self.add_asset(path, Asset::Texture(Texture {
       info: info.clone(),
       data: Box::new([texture_data.to_vec().into_boxed_slice()]),
+      frame_count: 1
     }), AssetLoadPriority::Normal);
   }
 
@@ -303,10 +559,20 @@ impl<P: Platform> AssetManager<P> {
           priority
         }).unwrap();
       }
+      Asset::Skeleton(skeleton) => {
+        // Skeletons are only used on the CPU
+        let mut inner = self.inner.lock().unwrap();
+        inner.skeletons.insert(path.to_owned(), Arc::new(skeleton));
+      }
       _ => unimplemented!()
     }
   }
 
+  pub fn get_skeleton(&self, path: &str) -> Option<Arc<Skeleton>> {
+    let inner = self.inner.lock().unwrap();
+    inner.skeletons.get(path).cloned()
+  }
+
   pub fn request_asset(&self, path: &str, asset_type: AssetType, priority: AssetLoadPriority) -> Arc<AssetLoaderProgress> {
     self.request_asset_with_progress(path, asset_type, priority, None)
   }
@@ -320,13 +586,17 @@ impl<P: Platform> AssetManager<P> {
 
     {
       let mut inner = self.inner.lock().unwrap();
+      *inner.ref_counts.entry(path.to_owned()).or_insert(0) += 1;
+      if inner.requested_assets.contains(path) && priority == AssetLoadPriority::High {
+        inner.promote_request(path);
+      }
       if inner.loaded_assets.contains(path) || inner.requested_assets.contains(path) {
         progress.finished.fetch_add(1, Ordering::SeqCst);
         return progress;
       }
       inner.requested_assets.insert(path.to_owned());
 
-      inner.load_queue.push_back(AssetLoadRequest {
+      inner.push_request(AssetLoadRequest {
         asset_type,
         path: path.to_owned(),
         progress: progress.clone(),
@@ -338,10 +608,70 @@ impl<P: Platform> AssetManager<P> {
     progress
   }
 
+  /// Drops a reference that was acquired with request_asset.
+  /// Once nothing references the asset anymore, the renderer is free to evict it when it runs out of memory.
+  pub fn release(&self, path: &str) {
+    let mut inner = self.inner.lock().unwrap();
+    let ref_count = inner.ref_counts.get_mut(path);
+    if ref_count.is_none() {
+      println!("Releasing asset that was never requested: {:?}", path);
+      return;
+    }
+    let ref_count = ref_count.unwrap();
+    *ref_count -= 1;
+    if *ref_count == 0 {
+      inner.ref_counts.remove(path);
+      self.released_sender.send(path.to_owned()).unwrap();
+    }
+  }
+
+  pub fn is_referenced(&self, path: &str) -> bool {
+    let inner = self.inner.lock().unwrap();
+    inner.ref_counts.contains_key(path)
+  }
+
+  pub fn receive_released_asset(&self) -> Option<String> {
+    self.released_receiver.try_recv().ok()
+  }
+
+  /// The amount of GPU memory in bytes that textures can take up before unreferenced ones get evicted.
+  pub fn set_memory_budget(&self, memory_budget: u64) {
+    self.memory_budget.store(memory_budget, Ordering::SeqCst);
+  }
+
+  pub fn memory_budget(&self) -> u64 {
+    self.memory_budget.load(Ordering::SeqCst)
+  }
+
+  /// Gets called with the number of finished and the total number of requests whenever a request is done.
+  /// Assets that get requested by other assets count towards the same total.
+  pub fn on_progress(&self, callback: Box<dyn Fn(u32, u32) + Send + Sync>) {
+    let mut progress_callback = self.progress_callback.write().unwrap();
+    *progress_callback = Some(callback);
+  }
+
+  /// Fraction of the requests that are done, it only goes back down when new requests come in after everything was done.
+  pub fn progress_fraction(&self) -> f32 {
+    let mut inner = self.inner.lock().unwrap();
+    inner.progress_fraction()
+  }
+
+  fn finish_request(&self) {
+    let (finished, total) = {
+      let mut inner = self.inner.lock().unwrap();
+      inner.finish_request()
+    };
+    let progress_callback = self.progress_callback.read().unwrap();
+    if let Some(progress_callback) = progress_callback.as_ref() {
+      progress_callback(finished, total);
+    }
+  }
+
   pub fn load_level(self: &Arc<Self>, path: &str) -> Option<World> {
     let file_opt = self.load_file(path);
     if file_opt.is_none() {
       println!("Could not load file: {:?}", path);
+      self.report_error(AssetError::new(path, "File not found"));
       return None;
     }
     let mut file = file_opt.unwrap();
@@ -350,6 +680,7 @@ impl<P: Platform> AssetManager<P> {
     let loader_opt = AssetManager::find_loader(&mut file, loaders.as_ref());
     if loader_opt.is_none() {
       println!("Could not find loader for file: {:?}", path);
+      self.report_error(AssetError::new(path, "No loader found"));
       return None;
     }
 
@@ -359,8 +690,9 @@ impl<P: Platform> AssetManager<P> {
     });
     let loader = loader_opt.unwrap();
     let assets_opt = loader.load(file, self, AssetLoadPriority::Normal, &progress);
-    if assets_opt.is_err() {
-      println!("Could not load file: {:?}", path);
+    if let Err(error) = assets_opt {
+      println!("Could not load file: {:?}: {}", path, error.reason);
+      self.report_error(error);
       return None;
     }
     let assets = assets_opt.unwrap();
@@ -393,6 +725,56 @@ impl<P: Platform> AssetManager<P> {
     file_opt
   }
 
+  #[cfg(feature = "hot-reload")]
+  fn file_modified_time(&self, path: &str) -> Option<SystemTime> {
+    let containers = self.containers.read().unwrap();
+    containers.iter().find_map(|container| container.modified_time(path))
+  }
+
+  #[cfg(feature = "hot-reload")]
+  fn watch_file(&self, path: &str, asset_type: AssetType, priority: AssetLoadPriority) {
+    if asset_type == AssetType::Container || asset_type == AssetType::Level {
+      return;
+    }
+    let modified_time = self.file_modified_time(path);
+    if let Some(modified_time) = modified_time {
+      let mut inner = self.inner.lock().unwrap();
+      inner.watched_files.insert(path.to_string(), WatchedFile {
+        modified_time,
+        asset_type,
+        priority
+      });
+    }
+  }
+
+  #[cfg(feature = "hot-reload")]
+  fn reload_modified_files(&self) {
+    let watched_paths: Vec<String> = {
+      let inner = self.inner.lock().unwrap();
+      inner.watched_files.keys().cloned().collect()
+    };
+
+    let mut modified_files = Vec::<(String, SystemTime)>::new();
+    for path in watched_paths {
+      if let Some(modified_time) = self.file_modified_time(&path) {
+        modified_files.push((path, modified_time));
+      }
+    }
+
+    let reloaded_any = {
+      let mut inner = self.inner.lock().unwrap();
+      inner.queue_modified_files(modified_files)
+    };
+    if reloaded_any {
+      self.cond_var.notify_all();
+    }
+  }
+
+  pub fn external_file_path(&self, path: &str) -> Option<PathBuf> {
+    let containers = self.containers.read().unwrap();
+    containers.iter().find_map(|container| container.external_path(path))
+  }
+
   pub fn file_exists(&self, path: &str) -> bool {
     let containers = self.containers.read().unwrap();
     for container in containers.iter() {
@@ -431,23 +813,34 @@ impl<P: Platform> AssetManager<P> {
         inner.requested_assets.remove(&path);
       }
       println!("Could not find loader for file: {:?}", path.as_str());
+      self.report_error(AssetError::new(&path, "No loader found"));
       return;
     }
     let loader = loader_opt.unwrap();
 
     let assets_opt = loader.load(file, self, priority, progress);
-    if assets_opt.is_err() {
+    if let Err(error) = assets_opt {
       progress.finished.fetch_add(1, Ordering::SeqCst);
       {
         let mut inner = self.inner.lock().unwrap();
         inner.requested_assets.remove(&path);
       }
-      println!("Could not load file: {:?}", path.as_str());
-      return;
-      // dunno, error i guess
+      println!("Could not load file: {:?}: {}", path.as_str(), error.reason);
+      self.report_error(error);
     }
   }
 
+  fn report_error(&self, error: AssetError) {
+    let mut inner = self.inner.lock().unwrap();
+    inner.errors.push(error);
+  }
+
+  /// Returns all load failures since the last call.
+  pub fn take_errors(&self) -> Vec<AssetError> {
+    let mut inner = self.inner.lock().unwrap();
+    std::mem::take(&mut inner.errors)
+  }
+
   pub fn receive_render_asset(&self) -> Option<LoadedAsset> {
     self.renderer_receiver.try_recv().ok()
   }
@@ -481,7 +874,7 @@ fn asset_manager_thread_fn<P: Platform>(asset_manager: Weak<AssetManager<P>>) {
     let mgr = mgr_opt.unwrap();
     let request = {
       let mut inner = mgr.inner.lock().unwrap();
-      let mut request_opt = inner.load_queue.pop_front();
+      let mut request_opt = inner.pop_request();
       let _ = cond_var.wait_timeout_while(
         inner,
         Duration::from_millis(2000),
@@ -489,23 +882,136 @@ fn asset_manager_thread_fn<P: Platform>(asset_manager: Weak<AssetManager<P>>) {
         if request_opt.is_some() {
           return false;
         }
-        request_opt = inner.load_queue.pop_front();
+        request_opt = inner.pop_request();
         request_opt.is_none()
       }).unwrap();
       match request_opt {
         Some(request) => request,
-        None => continue 'asset_loop
+        None => {
+          #[cfg(feature = "hot-reload")]
+          mgr.reload_modified_files();
+          continue 'asset_loop;
+        }
       }
     };
 
     {
       let file_opt = mgr.load_file(&request.path);
       if file_opt.is_none() {
+        mgr.report_error(AssetError::new(&request.path, "File not found"));
         request.progress.finished.fetch_add(1, Ordering::SeqCst);
+        mgr.finish_request();
         continue 'asset_loop;
       }
       let file = file_opt.unwrap();
+      #[cfg(feature = "hot-reload")]
+      mgr.watch_file(&request.path, request.asset_type, request.priority);
       mgr.load_asset(file, request.priority, &request.progress);
+      mgr.finish_request();
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn request(path: &str, priority: AssetLoadPriority) -> AssetLoadRequest {
+    AssetLoadRequest {
+      path: path.to_string(),
+      asset_type: AssetType::Texture,
+      progress: Arc::new(AssetLoaderProgress {
+        expected: AtomicU32::new(1),
+        finished: AtomicU32::new(0)
+      }),
+      priority
+    }
+  }
+
+  fn pop_path(inner: &mut AssetManagerInner) -> Option<String> {
+    inner.pop_request().map(|request| request.path)
+  }
+
+  #[test]
+  fn high_priority_requests_are_loaded_first() {
+    let mut inner = AssetManagerInner::new();
+    inner.push_request(request("low", AssetLoadPriority::Low));
+    inner.push_request(request("normal", AssetLoadPriority::Normal));
+    inner.push_request(request("high", AssetLoadPriority::High));
+    assert_eq!(pop_path(&mut inner).as_deref(), Some("high"));
+    assert_eq!(pop_path(&mut inner).as_deref(), Some("normal"));
+    assert_eq!(pop_path(&mut inner).as_deref(), Some("low"));
+    assert_eq!(pop_path(&mut inner), None);
+  }
+
+  #[test]
+  fn requests_stay_in_order_within_a_priority() {
+    let mut inner = AssetManagerInner::new();
+    for path in ["a", "b", "c"] {
+      inner.push_request(request(path, AssetLoadPriority::Low));
     }
+    inner.promote_request("c");
+    assert_eq!(pop_path(&mut inner).as_deref(), Some("c"));
+    assert_eq!(pop_path(&mut inner).as_deref(), Some("a"));
+    assert_eq!(pop_path(&mut inner).as_deref(), Some("b"));
+  }
+
+  #[test]
+  fn progress_fraction_never_goes_backwards() {
+    let mut inner = AssetManagerInner::new();
+    assert_eq!(inner.progress_fraction(), 1f32);
+
+    // A level that requests a material
+    inner.push_request(request("level", AssetLoadPriority::Normal));
+    inner.push_request(request("material", AssetLoadPriority::Normal));
+    assert_eq!(inner.progress_fraction(), 0f32);
+    inner.finish_request();
+    assert_eq!(inner.progress_fraction(), 0.5f32);
+
+    // The material requests two textures, the raw ratio drops to 1/4
+    inner.push_request(request("albedo", AssetLoadPriority::Normal));
+    inner.push_request(request("normal", AssetLoadPriority::Normal));
+    let mut last_fraction = inner.progress_fraction();
+    assert_eq!(last_fraction, 0.5f32);
+    for _ in 0..3 {
+      inner.finish_request();
+      let fraction = inner.progress_fraction();
+      assert!(fraction >= last_fraction);
+      last_fraction = fraction;
+    }
+    assert_eq!(last_fraction, 1f32);
+
+    // Requests that come in after everything was done lower it again
+    inner.push_request(request("next_level", AssetLoadPriority::Normal));
+    inner.push_request(request("next_material", AssetLoadPriority::Normal));
+    inner.finish_request();
+    assert!(inner.progress_fraction() < 1f32);
+  }
+
+  #[cfg(feature = "hot-reload")]
+  #[test]
+  fn modified_watched_file_gets_reloaded() {
+    let loaded_time = SystemTime::UNIX_EPOCH + Duration::from_secs(100);
+    let mut inner = AssetManagerInner::new();
+    inner.loaded_assets.insert("materials/wall.vtf".to_string());
+    inner.watched_files.insert("materials/wall.vtf".to_string(), WatchedFile {
+      modified_time: loaded_time,
+      asset_type: AssetType::Texture,
+      priority: AssetLoadPriority::Normal
+    });
+
+    // Unchanged files stay loaded
+    assert!(!inner.queue_modified_files(vec![("materials/wall.vtf".to_string(), loaded_time)]));
+    assert!(inner.pop_request().is_none());
+
+    let rewritten_time = loaded_time + Duration::from_secs(1);
+    assert!(inner.queue_modified_files(vec![("materials/wall.vtf".to_string(), rewritten_time)]));
+    assert!(!inner.loaded_assets.contains("materials/wall.vtf"));
+    let request = inner.pop_request().unwrap();
+    assert_eq!(request.path, "materials/wall.vtf");
+    assert!(request.asset_type == AssetType::Texture);
+
+    // The same change only triggers a single reload
+    assert!(!inner.queue_modified_files(vec![("materials/wall.vtf".to_string(), rewritten_time)]));
   }
 }
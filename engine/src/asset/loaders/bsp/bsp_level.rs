@@ -1,17 +1,17 @@
 
 use nalgebra::Rotation3;
 use sourcerenderer_core::{Platform, Quaternion, Vec4, graphics::{Format, SampleCount, TextureUsage}};
-use crate::asset::{AssetLoader, AssetType, Asset, Model, AssetManager, Mesh};
+use crate::asset::{AssetLoader, AssetType, Asset, Model, AssetManager, Mesh, AssetError};
 use core::slice;
 use std::{path::Path, u8};
 use std::sync::Arc;
-use sourcerenderer_bsp::{DispInfo, DispVert, Face, Map, SurfaceFlags};
+use sourcerenderer_bsp::{DispInfo, DispVert, Entity as BspEntity, EntityClass, Face, Map, SurfaceFlags, game_lumps::StaticPropFlags};
 use std::collections::HashMap;
 use sourcerenderer_core::{Vec3, Vec2};
 use crate::asset::asset_manager::{AssetLoaderResult, AssetFile, AssetFileData, MeshRange, AssetLoaderProgress, AssetLoadPriority, Texture};
-use sourcerenderer_core::graphics::TextureInfo;
+use sourcerenderer_core::graphics::{TextureDimension, TextureInfo};
 use legion::{World, WorldOptions};
-use crate::renderer::StaticRenderableComponent;
+use crate::renderer::{DirectionalLightComponent, PointLightComponent, SpotLightComponent, StaticRenderableComponent};
 use crate::Transform;
 use regex::Regex;
 use crate::asset::loaders::csgo_loader::CSGO_MAP_NAME_PATTERN;
@@ -19,7 +19,7 @@ use std::io::BufReader;
 use std::collections::HashSet;
 use crate::asset::loaders::PakFileContainer;
 use super::BspLumps;
-use crate::asset::loaders::bsp::{lightmap_packer::LightmapPacker, Vertex};
+use crate::asset::loaders::bsp::{lightmap_packer::LightmapPacker, VertexKey};
 use crate::math::BoundingBox;
 
 // REFERENCE
@@ -33,15 +33,71 @@ use crate::math::BoundingBox;
 // VBSP IS CURSED
 
 pub struct BspLevelLoader {
-  map_name_regex: Regex
+  map_name_regex: Regex,
+  smooth_normals: bool
 }
 
 const SCALING_FACTOR: f32 = 0.0236f32;
 
+/// Tool textures that never get rendered. Sky faces are collected separately.
+const IGNORED_SURFACES: SurfaceFlags = SurfaceFlags::from_bits_truncate(
+  SurfaceFlags::NODRAW.bits() | SurfaceFlags::LIGHT.bits() | SurfaceFlags::SKY.bits() | SurfaceFlags::SKY2D.bits() | SurfaceFlags::TRIGGER.bits() | SurfaceFlags::HINT.bits() | SurfaceFlags::SKIP.bits()
+);
+const SKY_SURFACES: SurfaceFlags = SurfaceFlags::from_bits_truncate(SurfaceFlags::SKY.bits() | SurfaceFlags::SKY2D.bits());
+
+/// Smoothing groups and plane normals of all faces that use a vertex, indexed by the BSP vertex index
+type VertexFaceNormals = HashMap<u16, Vec<(u32, Vec3)>>;
+
 impl BspLevelLoader {
   pub fn new() -> Self {
     Self {
-      map_name_regex: Regex::new(CSGO_MAP_NAME_PATTERN).unwrap()
+      map_name_regex: Regex::new(CSGO_MAP_NAME_PATTERN).unwrap(),
+      smooth_normals: false
+    }
+  }
+
+  /// Averages the normals of faces in the same smoothing group and calculates proper displacement normals
+  /// instead of using the flat plane normal everywhere.
+  pub fn with_smooth_normals(mut self, smooth_normals: bool) -> Self {
+    self.smooth_normals = smooth_normals;
+    self
+  }
+
+  fn collect_face_normals(temp: &BspLumps, faces: &[Face]) -> VertexFaceNormals {
+    let mut face_normals = VertexFaceNormals::new();
+    for face in faces {
+      if face.displacement_info != -1 || temp.tex_info[face.texture_info as usize].flags.intersects(IGNORED_SURFACES) {
+        continue;
+      }
+      let normal = temp.planes[face.plane_index as usize].normal;
+      for surf_edge_index in face.first_edge .. face.first_edge + face.edges_count as i32 {
+        let edge_index = temp.surface_edges[surf_edge_index as usize].index;
+        let edge = temp.edges[edge_index.abs() as usize];
+        let vert_index = edge.vertex_index[if edge_index >= 0 { 0 } else { 1 }];
+        face_normals.entry(vert_index).or_default().push((face.smoothing_group, normal));
+      }
+    }
+    face_normals
+  }
+
+  fn smooth_normal(face: &Face, normal: &Vec3, vert_index: u16, face_normals: &VertexFaceNormals) -> Vec3 {
+    if face.smoothing_group == 0 {
+      return *normal;
+    }
+    let mut sum = *normal;
+    let mut added_normals = vec![*normal];
+    for (smoothing_group, other_normal) in face_normals.get(&vert_index).map(|normals| normals.as_slice()).unwrap_or_default() {
+      // Coplanar neighbors would otherwise get more weight than they should
+      if smoothing_group & face.smoothing_group == 0 || added_normals.iter().any(|added| added.dot(other_normal) > 0.999f32) {
+        continue;
+      }
+      sum += other_normal;
+      added_normals.push(*other_normal);
+    }
+    if sum.magnitude_squared() < 1.0e-6f32 {
+      *normal
+    } else {
+      sum.normalize()
     }
   }
 
@@ -49,11 +105,13 @@ impl BspLevelLoader {
                 temp: &BspLumps,
                 face: &Face,
                 brush_vertices: &mut Vec<super::Vertex>,
+                vertex_lookup: &mut HashMap<VertexKey, u32>,
                 brush_indices: &mut HashMap<String, Vec<u32>>,
-                lightmap_packer: &mut LightmapPacker) {
+                lightmap_packer: &mut LightmapPacker,
+                white_lightmap_uv: Vec2,
+                face_normals: Option<&VertexFaceNormals>) {
     let tex_info = &temp.tex_info[face.texture_info as usize];
-    let ignore_flags = SurfaceFlags::NODRAW | SurfaceFlags::LIGHT | SurfaceFlags::SKY | SurfaceFlags::SKY2D | SurfaceFlags::TRIGGER;
-    if tex_info.flags.intersects(ignore_flags) {
+    if tex_info.flags.intersects(IGNORED_SURFACES) {
       return;
     }
 
@@ -73,7 +131,8 @@ impl BspLevelLoader {
 
     let material_brush_indices = &mut brush_indices.entry(tex_name).or_default();
     let plane = &temp.planes[face.plane_index as usize];
-    let root_vertex = brush_vertices.len() as u32;
+    let mut root_vertex = 0u32;
+    let mut previous_vertex = 0u32;
 
     for surf_edge_index in face.first_edge ..face.first_edge  + face.edges_count as i32 {
       let edge_index = temp.surface_edges[surf_edge_index as usize].index;
@@ -85,7 +144,7 @@ impl BspLevelLoader {
       let mut uv = Self::calculate_uv(&position, &tex_info.texture_vecs_s, &tex_info.texture_vecs_t);
       uv.x /= tex_data.width as f32;
       uv.y /= tex_data.height as f32;
-      let mut lightmap_uv = Vec2::default();
+      let mut lightmap_uv = white_lightmap_uv;
       if face.light_offset >= 0 {
         lightmap_uv = Self::calculate_uv(&position, &tex_info.lightmap_vecs_s, &tex_info.lightmap_vecs_t);
         lightmap_uv -= Vec2::new(face.lightmap_texture_mins_in_luxels[0] as f32, face.lightmap_texture_mins_in_luxels[1] as f32);
@@ -99,20 +158,30 @@ impl BspLevelLoader {
         lightmap_uv.y /= lightmap_packer.texture_height() as f32;
       }
 
-      brush_vertices.push(super::Vertex {
+      let normal = if let Some(face_normals) = face_normals {
+        Self::smooth_normal(face, &plane.normal, vert_index, face_normals)
+      } else {
+        plane.normal
+      };
+
+      let index = Self::push_vertex(super::Vertex {
         position: BspLevelLoader::fixup_position(&position),
-        normal: BspLevelLoader::fixup_normal(&plane.normal),
+        normal: BspLevelLoader::fixup_normal(&normal),
         uv,
         lightmap_uv,
         alpha: 1f32
-      });
-
-      if surf_edge_index < face.first_edge + 2 {
-        continue;
+      }, brush_vertices, vertex_lookup);
+
+      // Triangle fan around the first vertex
+      let edge_number = surf_edge_index - face.first_edge;
+      if edge_number == 0 {
+        root_vertex = index;
+      } else if edge_number >= 2 {
+        material_brush_indices.push(root_vertex);
+        material_brush_indices.push(index);
+        material_brush_indices.push(previous_vertex);
       }
-      material_brush_indices.push(root_vertex);
-      material_brush_indices.push(brush_vertices.len() as u32 - 1);
-      material_brush_indices.push(brush_vertices.len() as u32 - 2);
+      previous_vertex = index;
     }
   }
 
@@ -120,12 +189,13 @@ impl BspLevelLoader {
                              temp: &BspLumps,
                              disp_info: &DispInfo,
                              brush_vertices: &mut Vec<super::Vertex>,
+                             vertex_lookup: &mut HashMap<VertexKey, u32>,
                              brush_indices: &mut HashMap<String, Vec<u32>>,
-                             lightmap_packer: &mut LightmapPacker) {
+                             lightmap_packer: &mut LightmapPacker,
+                             white_lightmap_uv: Vec2) {
     let face = &temp.faces[disp_info.map_face as usize];
     let tex_info = &temp.tex_info[face.texture_info as usize];
-    let ignore_flags = SurfaceFlags::NODRAW | SurfaceFlags::LIGHT | SurfaceFlags::SKY | SurfaceFlags::SKY2D | SurfaceFlags::TRIGGER;
-    if tex_info.flags.intersects(ignore_flags) {
+    if tex_info.flags.intersects(IGNORED_SURFACES) {
       return;
     }
 
@@ -170,54 +240,125 @@ impl BspLevelLoader {
 
     let subdivisions = 1 << disp_info.power;
     let size = subdivisions + 1;
+    let mut positions = Vec::<Vec3>::with_capacity((size * size) as usize);
+    for y in 0..size {
+      for x in 0..size {
+        positions.push(Self::calculate_disp_vert(disp_info.disp_vert_start, x, y, size, &corners, first_corner, &temp.disp_verts));
+      }
+    }
+    let normals: Vec<Vec3> = if self.smooth_normals {
+      (0..size * size).map(|i| Self::calculate_disp_normal(i % size, i / size, size, &positions, &plane.normal)).collect()
+    } else {
+      vec![plane.normal; (size * size) as usize]
+    };
+
     for y in 0..subdivisions {
-      let old_len = brush_vertices.len() as u32;
+      let mut row_indices = Vec::<u32>::with_capacity((size * 2) as usize);
       for x in 0..size {
-        let position = Self::calculate_disp_vert(disp_info.disp_vert_start, x, y, size, &corners, first_corner, &temp.disp_verts);
-        let mut uv = Self::calculate_uv(&position, &tex_info.texture_vecs_s, &tex_info.texture_vecs_t);
-        uv.x /= tex_data.width as f32;
-        uv.y /= tex_data.height as f32;
-        brush_vertices.push(super::Vertex {
+        let position = positions[(x + y * size) as usize];
+        let uv = Self::calculate_disp_uv(x, y, size, &corners_uv, first_corner);
+        row_indices.push(Self::push_vertex(super::Vertex {
           position: Self::fixup_position(&position),
-          normal: Self::fixup_normal(&plane.normal),
+          normal: Self::fixup_normal(&normals[(x + y * size) as usize]),
           uv,
-          lightmap_uv: Vec2::new(
-            ((x as f32 / subdivisions as f32) * face.lightmap_texture_size_in_luxels[0] as f32 + 0.5f32 + lightmap_offset_x as f32) / (lightmap_packer.texture_width() as f32),
-            ((y as f32 / subdivisions as f32) * face.lightmap_texture_size_in_luxels[1] as f32 + 0.5f32 + lightmap_offset_y as f32) / (lightmap_packer.texture_height() as f32)
-          ),
+          lightmap_uv: if face.light_offset >= 0 {
+            Vec2::new(
+              ((x as f32 / subdivisions as f32) * face.lightmap_texture_size_in_luxels[0] as f32 + 0.5f32 + lightmap_offset_x as f32) / (lightmap_packer.texture_width() as f32),
+              ((y as f32 / subdivisions as f32) * face.lightmap_texture_size_in_luxels[1] as f32 + 0.5f32 + lightmap_offset_y as f32) / (lightmap_packer.texture_height() as f32)
+            )
+          } else {
+            white_lightmap_uv
+          },
           alpha: &temp.disp_verts[(disp_info.disp_vert_start + x + y * size) as usize].alpha * 255f32
-        });
+        }, brush_vertices, vertex_lookup));
 
-        if brush_vertices.len() - old_len as usize >= 3 {
-          material_brush_indices.push(brush_vertices.len() as u32 - 3);
-          material_brush_indices.push(brush_vertices.len() as u32 - 1);
-          material_brush_indices.push(brush_vertices.len() as u32 - 2);
+        let row_len = row_indices.len();
+        if row_len >= 3 {
+          material_brush_indices.push(row_indices[row_len - 3]);
+          material_brush_indices.push(row_indices[row_len - 1]);
+          material_brush_indices.push(row_indices[row_len - 2]);
         }
 
-        let position = Self::calculate_disp_vert(disp_info.disp_vert_start, x, y + 1, size, &corners, first_corner, &temp.disp_verts);
-        let mut uv = Self::calculate_uv(&position, &tex_info.texture_vecs_s, &tex_info.texture_vecs_t);
-        uv.x /= tex_data.width as f32;
-        uv.y /= tex_data.height as f32;
-        brush_vertices.push(super::Vertex {
+        let position = positions[(x + (y + 1) * size) as usize];
+        let uv = Self::calculate_disp_uv(x, y + 1, size, &corners_uv, first_corner);
+        row_indices.push(Self::push_vertex(super::Vertex {
           position: Self::fixup_position(&position),
-          normal: Self::fixup_normal(&plane.normal),
+          normal: Self::fixup_normal(&normals[(x + (y + 1) * size) as usize]),
           uv,
-          lightmap_uv: Vec2::new(
-            ((x as f32 / subdivisions as f32) * face.lightmap_texture_size_in_luxels[0] as f32 + 0.5f32 + lightmap_offset_x as f32) / (lightmap_packer.texture_width() as f32),
-            (((y + 1) as f32 / subdivisions as f32) * face.lightmap_texture_size_in_luxels[1] as f32 + 0.5f32 + lightmap_offset_y as f32) / (lightmap_packer.texture_height() as f32)
-          ),
+          lightmap_uv: if face.light_offset >= 0 {
+            Vec2::new(
+              ((x as f32 / subdivisions as f32) * face.lightmap_texture_size_in_luxels[0] as f32 + 0.5f32 + lightmap_offset_x as f32) / (lightmap_packer.texture_width() as f32),
+              (((y + 1) as f32 / subdivisions as f32) * face.lightmap_texture_size_in_luxels[1] as f32 + 0.5f32 + lightmap_offset_y as f32) / (lightmap_packer.texture_height() as f32)
+            )
+          } else {
+            white_lightmap_uv
+          },
           alpha: &temp.disp_verts[(disp_info.disp_vert_start + x + (y + 1) * size) as usize].alpha * 255f32
-        });
+        }, brush_vertices, vertex_lookup));
 
-        if brush_vertices.len() - old_len as usize >= 3 {
-          material_brush_indices.push(brush_vertices.len() as u32 - 3);
-          material_brush_indices.push(brush_vertices.len() as u32 - 2);
-          material_brush_indices.push(brush_vertices.len() as u32 - 1);
+        let row_len = row_indices.len();
+        if row_len >= 3 {
+          material_brush_indices.push(row_indices[row_len - 3]);
+          material_brush_indices.push(row_indices[row_len - 2]);
+          material_brush_indices.push(row_indices[row_len - 1]);
         }
       }
     }
   }
 
+  /// Only the positions matter for sky faces, they mark where the skybox shows through.
+  fn build_sky_face(temp: &BspLumps,
+                    face: &Face,
+                    sky_vertices: &mut Vec<super::Vertex>,
+                    vertex_lookup: &mut HashMap<VertexKey, u32>,
+                    sky_indices: &mut Vec<u32>) {
+    let plane = &temp.planes[face.plane_index as usize];
+    let mut root_vertex = 0u32;
+    let mut previous_vertex = 0u32;
+    for surf_edge_index in face.first_edge .. face.first_edge + face.edges_count as i32 {
+      let edge_index = temp.surface_edges[surf_edge_index as usize].index;
+      let edge = temp.edges[edge_index.abs() as usize];
+      let vert_index = edge.vertex_index[if edge_index >= 0 { 0 } else { 1 }];
+      let position = temp.vertices[vert_index as usize].position;
+      let index = Self::push_vertex(super::Vertex {
+        position: Self::fixup_position(&position),
+        normal: Self::fixup_normal(&plane.normal),
+        uv: Vec2::default(),
+        lightmap_uv: Vec2::default(),
+        alpha: 1f32
+      }, sky_vertices, vertex_lookup);
+
+      let edge_number = surf_edge_index - face.first_edge;
+      if edge_number == 0 {
+        root_vertex = index;
+      } else if edge_number >= 2 {
+        sky_indices.push(root_vertex);
+        sky_indices.push(index);
+        sky_indices.push(previous_vertex);
+      }
+      previous_vertex = index;
+    }
+  }
+
+  fn into_bytes<T>(data: Box<[T]>) -> Box<[u8]> {
+    let len = data.len();
+    let ptr = Box::into_raw(data);
+    let data_ptr = unsafe { slice::from_raw_parts_mut(ptr as *mut u8, len * std::mem::size_of::<T>()) as *mut [u8] };
+    unsafe { Box::from_raw(data_ptr) }
+  }
+
+  /// Reuses the index of an identical vertex if there already is one.
+  fn push_vertex(vertex: super::Vertex, brush_vertices: &mut Vec<super::Vertex>, vertex_lookup: &mut HashMap<VertexKey, u32>) -> u32 {
+    let key = VertexKey::from(&vertex);
+    if let Some(index) = vertex_lookup.get(&key) {
+      return *index;
+    }
+    let index = brush_vertices.len() as u32;
+    brush_vertices.push(vertex);
+    vertex_lookup.insert(key, index);
+    index
+  }
+
   fn calculate_disp_vert(offset: i32, x: i32, y: i32, size: i32, corners: &[Vec3; 4], first_corner: i32, disp_verts: &[DispVert]) -> Vec3 {
     let disp_vert = &disp_verts[(offset + x + y * size) as usize];
     let tx = (x as f32) / ((size - 1) as f32);
@@ -235,6 +376,40 @@ impl BspLevelLoader {
     origin + disp_vert.vec * disp_vert.dist
   }
 
+  /// Uses central differences of the neighboring grid positions, one sided ones at the edges.
+  fn calculate_disp_normal(x: i32, y: i32, size: i32, positions: &[Vec3], plane_normal: &Vec3) -> Vec3 {
+    let position = |x: i32, y: i32| positions[(x.clamp(0, size - 1) + y.clamp(0, size - 1) * size) as usize];
+    let tangent_x = position(x + 1, y) - position(x - 1, y);
+    let tangent_y = position(x, y + 1) - position(x, y - 1);
+    let normal = tangent_x.cross(&tangent_y);
+    if normal.magnitude_squared() < 1.0e-6f32 {
+      return *plane_normal;
+    }
+    // The winding depends on which corner the displacement starts at
+    let normal = normal.normalize();
+    if normal.dot(plane_normal) < 0f32 {
+      -normal
+    } else {
+      normal
+    }
+  }
+
+  /// Interpolates the texture coordinates of the undisplaced corners, so the texture doesn't stretch along steep terrain.
+  fn calculate_disp_uv(x: i32, y: i32, size: i32, corners_uv: &[Vec2; 4], first_corner: i32) -> Vec2 {
+    let tx = (x as f32) / ((size - 1) as f32);
+    let ty = (y as f32) / ((size - 1) as f32);
+    let sx = 1f32 - tx;
+    let sy = 1f32 - ty;
+
+    let relevant_corners = [
+      corners_uv[((first_corner) & 3) as usize],
+      corners_uv[((first_corner + 1) & 3) as usize],
+      corners_uv[((first_corner + 2) & 3) as usize],
+      corners_uv[((first_corner + 3) & 3) as usize],
+    ];
+    ty * (sx * relevant_corners[1] + tx * relevant_corners[2]) + sy * (sx * relevant_corners[0] + tx * relevant_corners[3])
+  }
+
   fn calculate_uv(position: &Vec3, texture_vecs_s: &Vec4, texture_vecs_t: &Vec4) -> Vec2 {
     let pos4 = Vec4::new(position.x, position.y, position.z, 1.0f32);
     Vec2::new(
@@ -262,6 +437,91 @@ impl BspLevelLoader {
 
     Quaternion::from_rotation_matrix(&rotation)
   }
+
+  fn parse_floats(entity: &BspEntity, key: &str) -> Vec<f32> {
+    entity.get(key)
+      .map(|value| value.split_whitespace().filter_map(|part| part.parse::<f32>().ok()).collect())
+      .unwrap_or_default()
+  }
+
+  fn light_environment(entity: &BspEntity) -> (DirectionalLightComponent, Transform) {
+    let (color, intensity) = Self::light_color(entity);
+    (DirectionalLightComponent {
+      color,
+      intensity
+    },
+    Transform {
+      position: Vec3::new(0f32, 0f32, 0f32),
+      scale: Vec3::new(1.0f32, 1.0f32, 1.0f32),
+      rotation: Self::light_rotation(entity)
+    })
+  }
+
+  fn point_light(entity: &BspEntity) -> (PointLightComponent, Transform) {
+    let (_color, intensity) = Self::light_color(entity);
+    (PointLightComponent {
+      intensity
+    },
+    Transform {
+      position: Self::light_position(entity),
+      scale: Vec3::new(1.0f32, 1.0f32, 1.0f32),
+      rotation: Quaternion::identity()
+    })
+  }
+
+  fn spot_light(entity: &BspEntity) -> (SpotLightComponent, Transform) {
+    const DEG_TO_RAD: f32 = std::f32::consts::PI / 180f32;
+    let (_color, intensity) = Self::light_color(entity);
+    // _cone is the outer angle of the cone in degrees, 45 is the Hammer default
+    let cone = Self::parse_floats(entity, "_cone").first().copied().unwrap_or(45f32);
+    (SpotLightComponent {
+      intensity,
+      angle: cone * DEG_TO_RAD
+    },
+    Transform {
+      position: Self::light_position(entity),
+      scale: Vec3::new(1.0f32, 1.0f32, 1.0f32),
+      rotation: Self::light_rotation(entity)
+    })
+  }
+
+  fn light_position(entity: &BspEntity) -> Vec3 {
+    let origin = Self::parse_floats(entity, "origin");
+    if origin.len() < 3 {
+      return Vec3::new(0f32, 0f32, 0f32);
+    }
+    Self::fixup_position(&Vec3::new(origin[0], origin[1], origin[2]))
+  }
+
+  fn light_rotation(entity: &BspEntity) -> Quaternion {
+    const DEG_TO_RAD: f32 = std::f32::consts::PI / 180f32;
+    // The pitch key is inverted compared to regular angles, -90 points straight down.
+    let angles = Self::parse_floats(entity, "angles");
+    let yaw = angles.get(1).copied().unwrap_or(0f32);
+    let pitch = Self::parse_floats(entity, "pitch").first().map(|pitch| -*pitch)
+      .or_else(|| angles.first().copied())
+      .unwrap_or(0f32);
+    let source_direction = Vec3::new(
+      (pitch * DEG_TO_RAD).cos() * (yaw * DEG_TO_RAD).cos(),
+      (pitch * DEG_TO_RAD).cos() * (yaw * DEG_TO_RAD).sin(),
+      -(pitch * DEG_TO_RAD).sin()
+    );
+    let direction = Self::fixup_normal(&source_direction).normalize();
+    Quaternion::rotation_between(&Vec3::new(0f32, 0f32, -1f32), &direction)
+      .unwrap_or_else(|| Quaternion::from_axis_angle(&Vec3::y_axis(), std::f32::consts::PI))
+  }
+
+  fn light_color(entity: &BspEntity) -> (Vec3, f32) {
+    // _light is "r g b brightness" with everything in the 0-255 range
+    let light = Self::parse_floats(entity, "_light");
+    let color = if light.len() >= 3 {
+      Vec3::new(light[0], light[1], light[2]) / 255f32
+    } else {
+      Vec3::new(1f32, 1f32, 1f32)
+    };
+    let intensity = light.get(3).map(|brightness| *brightness / 255f32).unwrap_or(1f32);
+    (color, intensity)
+  }
 }
 
 impl<P: Platform> AssetLoader<P> for BspLevelLoader {
@@ -270,36 +530,37 @@ impl<P: Platform> AssetLoader<P> for BspLevelLoader {
     file_name.and_then(|file_name| file_name.to_str()).map_or(false, |file_name| self.map_name_regex.is_match(file_name))
   }
 
-  fn load(&self, asset_file: AssetFile<P>, manager: &Arc<AssetManager<P>>, _priority: AssetLoadPriority, progress: &Arc<AssetLoaderProgress>) -> Result<AssetLoaderResult, ()> {
-    let name = Path::new(&asset_file.path).file_name().unwrap().to_str().unwrap();
+  fn load(&self, asset_file: AssetFile<P>, manager: &Arc<AssetManager<P>>, _priority: AssetLoadPriority, progress: &Arc<AssetLoaderProgress>) -> Result<AssetLoaderResult, AssetError> {
+    let path = asset_file.path.clone();
+    let name = Path::new(&path).file_name().unwrap().to_str().unwrap();
     let file = match asset_file.data {
       AssetFileData::File(file) => file,
       _ => unreachable!("hi")
     };
     let buf_reader = BufReader::new(file);
-    let mut map = Map::read(name, buf_reader).unwrap();
-    let leafs = map.read_leafs().unwrap();
-    let nodes = map.read_nodes().unwrap();
-    let faces = map.read_faces().unwrap();
-    let leaf_faces = map.read_leaf_faces().unwrap();
-    let leaf_brushes = map.read_leaf_brushes().unwrap();
-    let edges = map.read_edges().unwrap();
-    let surface_edges = map.read_surface_edges().unwrap();
-    let vertices = map.read_vertices().unwrap();
-    let planes = map.read_planes().unwrap();
-    let tex_data = map.read_texture_data().unwrap();
-    let tex_info = map.read_texture_info().unwrap();
-    let tex_string_data = map.read_texture_string_data().unwrap();
-    let tex_data_string_table = map.read_texture_data_string_table().unwrap();
-    let brush_models = map.read_brush_models().unwrap();
-    let disp_infos = map.read_disp_infos().unwrap();
-    let disp_verts = map.read_disp_verts().unwrap();
-    let disp_tris = map.read_disp_tris().unwrap();
-    let pakfile = map.read_pakfile().unwrap();
-    let lighting = map.read_lighting().unwrap();
-    let visibility = map.read_visibility().unwrap();
-    let static_props = map.read_static_props().unwrap();
-    let entities = map.read_entities().unwrap();
+    let mut map = Map::read(name, buf_reader).map_err(|e| AssetError::new(&path, e))?;
+    let leafs = map.read_leafs().map_err(|e| AssetError::new(&path, e))?;
+    let nodes = map.read_nodes().map_err(|e| AssetError::new(&path, e))?;
+    let faces = map.read_faces().map_err(|e| AssetError::new(&path, e))?;
+    let leaf_faces = map.read_leaf_faces().map_err(|e| AssetError::new(&path, e))?;
+    let leaf_brushes = map.read_leaf_brushes().map_err(|e| AssetError::new(&path, e))?;
+    let edges = map.read_edges().map_err(|e| AssetError::new(&path, e))?;
+    let surface_edges = map.read_surface_edges().map_err(|e| AssetError::new(&path, e))?;
+    let vertices = map.read_vertices().map_err(|e| AssetError::new(&path, e))?;
+    let planes = map.read_planes().map_err(|e| AssetError::new(&path, e))?;
+    let tex_data = map.read_texture_data().map_err(|e| AssetError::new(&path, e))?;
+    let tex_info = map.read_texture_info().map_err(|e| AssetError::new(&path, e))?;
+    let tex_string_data = map.read_texture_string_data().map_err(|e| AssetError::new(&path, e))?;
+    let tex_data_string_table = map.read_texture_data_string_table().map_err(|e| AssetError::new(&path, e))?;
+    let brush_models = map.read_brush_models().map_err(|e| AssetError::new(&path, e))?;
+    let disp_infos = map.read_disp_infos().map_err(|e| AssetError::new(&path, e))?;
+    let disp_verts = map.read_disp_verts().map_err(|e| AssetError::new(&path, e))?;
+    let disp_tris = map.read_disp_tris().map_err(|e| AssetError::new(&path, e))?;
+    let pakfile = map.read_pakfile().map_err(|e| AssetError::new(&path, e))?;
+    let lighting = map.read_lighting().map_err(|e| AssetError::new(&path, e))?;
+    let visibility = map.read_visibility().map_err(|e| AssetError::new(&path, e))?;
+    let static_props = map.read_static_props().map_err(|e| AssetError::new(&path, e))?;
+    let entities = map.read_entities().map_err(|e| AssetError::new(&path, e))?;
 
     let temp = BspLumps {
       map_name: name.to_string(),
@@ -330,20 +591,34 @@ impl<P: Platform> AssetLoader<P> for BspLevelLoader {
     let mut world = World::new(WorldOptions::default());
     let mut materials_to_load = HashSet::<String>::new();
     let mut lightmap_packer = LightmapPacker::new(2048, 2048);
+    let white_lightmap_uv = lightmap_packer.add_white_block();
+
+    let mut sky_vertices = Vec::<super::Vertex>::new();
+    let mut sky_vertex_lookup = HashMap::<VertexKey, u32>::new();
+    let mut sky_indices = Vec::<u32>::new();
 
     let mut model_index = 0;
     for model in &brush_models {
       let mut brush_vertices = Vec::<super::Vertex>::new();
+      let mut vertex_lookup = HashMap::<VertexKey, u32>::new();
       let mut brush_indices = Vec::<u32>::new();
       let mut per_material_indices = HashMap::<String, Vec<u32>>::new();
       let mut mesh_ranges = Vec::<MeshRange>::new();
 
-      for face in &temp.faces[model.first_face as usize .. (model.first_face + model.num_faces) as usize] {
+      let model_faces = &temp.faces[model.first_face as usize .. (model.first_face + model.num_faces) as usize];
+      let face_normals = if self.smooth_normals {
+        Some(Self::collect_face_normals(&temp, model_faces))
+      } else {
+        None
+      };
+      for face in model_faces {
         if face.displacement_info != -1 {
           let disp_info = &temp.disp_infos[face.displacement_info as usize];
-          self.build_displacement_face(&temp, disp_info, &mut brush_vertices, &mut per_material_indices, &mut lightmap_packer);
+          self.build_displacement_face(&temp, disp_info, &mut brush_vertices, &mut vertex_lookup, &mut per_material_indices, &mut lightmap_packer, white_lightmap_uv);
+        } else if temp.tex_info[face.texture_info as usize].flags.intersects(SKY_SURFACES) {
+          Self::build_sky_face(&temp, face, &mut sky_vertices, &mut sky_vertex_lookup, &mut sky_indices);
         } else {
-          self.build_face(&temp, face, &mut brush_vertices, &mut per_material_indices, &mut lightmap_packer);
+          self.build_face(&temp, face, &mut brush_vertices, &mut vertex_lookup, &mut per_material_indices, &mut lightmap_packer, white_lightmap_uv, face_normals.as_ref());
         }
       }
 
@@ -367,21 +642,13 @@ impl<P: Platform> AssetLoader<P> for BspLevelLoader {
         });
       }
 
-      let vertices_box = brush_vertices.clone().into_boxed_slice();
-      let vertices_count = brush_vertices.len();
-      let ptr = Box::into_raw(vertices_box);
-      let data_ptr = unsafe { slice::from_raw_parts_mut(ptr as *mut u8, vertices_count * std::mem::size_of::<Vertex>()) as *mut [u8] };
-      let vertices_data = unsafe { Box::from_raw(data_ptr) };
-
-      let indices_box = brush_indices.clone().into_boxed_slice();
-      let indices_count = brush_indices.len();
-      let ptr = Box::into_raw(indices_box);
-      let data_ptr = unsafe { slice::from_raw_parts_mut(ptr as *mut u8, indices_count * std::mem::size_of::<u32>()) as *mut [u8] };
-      let indices_data = unsafe { Box::from_raw(data_ptr) };
+      let vertices_data = Self::into_bytes(brush_vertices.clone().into_boxed_slice());
+      let indices_data = Self::into_bytes(brush_indices.clone().into_boxed_slice());
 
       let mesh = Mesh {
         vertices: vertices_data,
         indices: Some(indices_data),
+        skinning: None,
         parts: mesh_ranges.into_boxed_slice(),
         bounding_box: Some(BoundingBox::new(Self::fixup_position(&model.min), Self::fixup_position(&model.max)))
       };
@@ -393,13 +660,15 @@ impl<P: Platform> AssetLoader<P> for BspLevelLoader {
       let model_name = format!("brushes_model_{}", model_index);
       let model = Model {
         mesh_path: mesh_name,
-        material_paths: materials
+        material_paths: materials,
+        lods: Vec::new()
       };
       manager.add_asset(&model_name, Asset::Model(model), AssetLoadPriority::Normal);
 
       world.push(
         (StaticRenderableComponent {
           model_path: model_name,
+          skin: 0,
           receive_shadows: true,
           cast_shadows: true,
           can_move: false
@@ -414,24 +683,52 @@ impl<P: Platform> AssetLoader<P> for BspLevelLoader {
       model_index += 1;
     }
 
+    // Not rendered yet, kept around for a skybox pass
+    if !sky_indices.is_empty() {
+      let sky_indices_count = sky_indices.len() as u32;
+      manager.add_asset("sky_mesh", Asset::Mesh(Mesh {
+        vertices: Self::into_bytes(sky_vertices.into_boxed_slice()),
+        indices: Some(Self::into_bytes(sky_indices.into_boxed_slice())),
+        skinning: None,
+        parts: Box::new([MeshRange {
+          start: 0,
+          count: sky_indices_count
+        }]),
+        bounding_box: None
+      }), AssetLoadPriority::Normal);
+    }
+
     for prop in temp.static_props.props.as_ref() {
+      if prop.flags.contains(StaticPropFlags::NO_DRAW) {
+        continue;
+      }
       let name = &temp.static_props.names.as_ref()[prop.prop_type as usize];
       manager.request_asset_with_progress(name, AssetType::Model, AssetLoadPriority::Normal, Some(&progress));
       world.push(
         (StaticRenderableComponent {
           model_path: name.clone(),
+          skin: prop.skin.max(0) as u32,
           receive_shadows: true,
           cast_shadows: true,
           can_move: false
         },
          Transform {
            position: Self::fixup_position(&prop.origin),
-           scale: Vec3::new(1.0f32, 1.0f32, 1.0f32),
+           scale: Vec3::new(prop.uniform_scale, prop.uniform_scale, prop.uniform_scale),
            rotation: Self::fixup_rotation(&prop.angles),
          })
       );
     }
 
+    for entity in &temp.entities.entities {
+      match entity.class_name() {
+        EntityClass::LightEnvironment => { world.push(Self::light_environment(entity)); }
+        EntityClass::Light => { world.push(Self::point_light(entity)); }
+        EntityClass::LightSpot => { world.push(Self::spot_light(entity)); }
+        _ => {}
+      }
+    }
+
     for material in materials_to_load {
       manager.request_asset(&material, AssetType::Material, AssetLoadPriority::Low);
     }
@@ -440,6 +737,7 @@ impl<P: Platform> AssetLoader<P> for BspLevelLoader {
 
 
     let lightmap_info = TextureInfo {
+      dimension: TextureDimension::Dim2D,
       format: Format::RGBA8,
       width: lightmap_packer.texture_width(),
       height: lightmap_packer.texture_height(),
@@ -447,7 +745,8 @@ impl<P: Platform> AssetLoader<P> for BspLevelLoader {
       mip_levels: 1,
       array_length: 1,
       samples: SampleCount::Samples1,
-      usage: TextureUsage::COPY_DST | TextureUsage::FRAGMENT_SHADER_SAMPLED
+      usage: TextureUsage::COPY_DST | TextureUsage::FRAGMENT_SHADER_SAMPLED,
+      is_cubemap: false
     };
     let samples = lightmap_packer.take_data();
     let samples_len = samples.len();
@@ -457,7 +756,8 @@ impl<P: Platform> AssetLoader<P> for BspLevelLoader {
 
     manager.add_asset("lightmap", Asset::Texture(Texture {
       info: lightmap_info,
-      data: Box::new([data])
+      data: Box::new([data]),
+      frame_count: 1
     }), AssetLoadPriority::Normal);
 
     Ok(AssetLoaderResult {
@@ -465,3 +765,173 @@ impl<P: Platform> AssetLoader<P> for BspLevelLoader {
     })
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use nalgebra::{Vector3, Vector4};
+  use sourcerenderer_bsp::{DispCornerNeighbors, DispNeighbor, DispSubNeighbor, NeighborOrientation, NeighborSpan, Edge, Entities, Plane, SurfaceEdge, TextureData, TextureDataStringTable, TextureInfo, TextureStringData, Vertex as BspVertex, Visibility};
+  use sourcerenderer_bsp::game_lumps::StaticPropDict;
+
+  /// Three faces with the same quad: a brick face, a nodraw face and a sky face.
+  fn quad_lumps() -> BspLumps {
+    let tex_data = |name_string_table_id: i32| TextureData {
+      reflectivity: Vector3::zeros(),
+      name_string_table_id,
+      width: 64,
+      height: 64,
+      view_width: 64,
+      view_height: 64
+    };
+    let tex_info = |flags: SurfaceFlags, texture_data: i32| TextureInfo {
+      texture_vecs_s: Vector4::new(1f32, 0f32, 0f32, 0f32),
+      texture_vecs_t: Vector4::new(0f32, 1f32, 0f32, 0f32),
+      lightmap_vecs_s: Vector4::zeros(),
+      lightmap_vecs_t: Vector4::zeros(),
+      flags,
+      texture_data
+    };
+    let face = |texture_info: i16| Face {
+      plane_index: 0,
+      size: 0,
+      is_on_node: false,
+      first_edge: 0,
+      edges_count: 4,
+      texture_info,
+      displacement_info: -1,
+      surface_fog_volume_id: -1,
+      styles: [0u8; 4],
+      light_offset: -1,
+      area: 64f32 * 64f32,
+      lightmap_texture_mins_in_luxels: [0i32; 2],
+      lightmap_texture_size_in_luxels: [0i32; 2],
+      original_face: -1,
+      primitives_count: 0,
+      first_primitive_id: 0,
+      smoothing_group: 0
+    };
+
+    BspLumps {
+      map_name: "test".to_string(),
+      leafs: Vec::new(),
+      nodes: Vec::new(),
+      leaf_faces: Vec::new(),
+      leaf_brushes: Vec::new(),
+      surface_edges: (0..4).map(|index| SurfaceEdge { index }).collect(),
+      vertices: vec![
+        BspVertex { position: Vector3::new(0f32, 0f32, 0f32) },
+        BspVertex { position: Vector3::new(64f32, 0f32, 0f32) },
+        BspVertex { position: Vector3::new(64f32, 64f32, 0f32) },
+        BspVertex { position: Vector3::new(0f32, 64f32, 0f32) }
+      ],
+      faces: vec![face(0), face(1), face(2)],
+      edges: vec![
+        Edge { vertex_index: [0, 1] },
+        Edge { vertex_index: [1, 2] },
+        Edge { vertex_index: [2, 3] },
+        Edge { vertex_index: [3, 0] }
+      ],
+      planes: vec![Plane { normal: Vector3::new(0f32, 0f32, 1f32), dist: 0f32, edge_type: 0 }],
+      tex_data: vec![tex_data(0), tex_data(1), tex_data(2)],
+      tex_info: vec![tex_info(SurfaceFlags::empty(), 0), tex_info(SurfaceFlags::NODRAW, 1), tex_info(SurfaceFlags::SKY, 2)],
+      tex_string_data: TextureStringData {
+        data: b"BRICK/WALL\0TOOLS/TOOLSNODRAW\0TOOLS/TOOLSSKYBOX\0".to_vec().into_boxed_slice()
+      },
+      tex_data_string_table: vec![TextureDataStringTable(0), TextureDataStringTable(11), TextureDataStringTable(29)],
+      disp_infos: Vec::new(),
+      disp_verts: Vec::new(),
+      disp_tris: Vec::new(),
+      lighting: Vec::new(),
+      visibility: Visibility {
+        num_clusters: 0,
+        byte_offsets: [Vec::new().into_boxed_slice(), Vec::new().into_boxed_slice()]
+      },
+      static_props: StaticPropDict::default(),
+      entities: Entities {
+        entities: Vec::new()
+      }
+    }
+  }
+
+  #[test]
+  fn tool_faces_contribute_no_indices() {
+    let lumps = quad_lumps();
+    let loader = BspLevelLoader::new();
+    let mut lightmap_packer = LightmapPacker::new(64, 64);
+    let white_lightmap_uv = lightmap_packer.add_white_block();
+    let mut vertices = Vec::<super::super::Vertex>::new();
+    let mut vertex_lookup = HashMap::<VertexKey, u32>::new();
+    let mut indices = HashMap::<String, Vec<u32>>::new();
+    for face in &lumps.faces {
+      loader.build_face(&lumps, face, &mut vertices, &mut vertex_lookup, &mut indices, &mut lightmap_packer, white_lightmap_uv, None);
+    }
+    assert_eq!(indices.len(), 1);
+    assert_eq!(indices["brick/wall"].len(), 6);
+    assert_eq!(vertices.len(), 4);
+
+    let mut sky_vertices = Vec::<super::super::Vertex>::new();
+    let mut sky_vertex_lookup = HashMap::<VertexKey, u32>::new();
+    let mut sky_indices = Vec::<u32>::new();
+    BspLevelLoader::build_sky_face(&lumps, &lumps.faces[2], &mut sky_vertices, &mut sky_vertex_lookup, &mut sky_indices);
+    assert_eq!(sky_indices.len(), 6);
+  }
+
+  #[test]
+  fn power_2_displacement_has_a_5x5_grid() {
+    let mut lumps = quad_lumps();
+    lumps.disp_verts = (0..25).map(|_| DispVert { vec: Vector3::zeros(), dist: 0f32, alpha: 0f32 }).collect();
+    let no_neighbor = || DispSubNeighbor {
+      neighbor_index: 0xffff,
+      neighbor_orientation: NeighborOrientation::Unknown,
+      span: NeighborSpan::CornerToCorner,
+      neighbor_span: NeighborSpan::CornerToCorner
+    };
+    let no_edge_neighbors = || DispNeighbor { sub_neighbors: [no_neighbor(), no_neighbor()] };
+    let no_corner_neighbors = || DispCornerNeighbors::read(&mut &[0u8; 10][..]).unwrap();
+    let disp_info = DispInfo {
+      start_position: Vector3::zeros(),
+      disp_vert_start: 0,
+      disp_tri_start: 0,
+      power: 2,
+      min_tess: 0,
+      smoothing_angle: 0f32,
+      contents: 0,
+      map_face: 0,
+      lightmap_alpha_start: 0,
+      lightmap_sample_position_start: 0,
+      edge_neighbors: [no_edge_neighbors(), no_edge_neighbors(), no_edge_neighbors(), no_edge_neighbors()],
+      corner_neighbors: [no_corner_neighbors(), no_corner_neighbors(), no_corner_neighbors(), no_corner_neighbors()],
+      allowed_verts: [0u32; 10]
+    };
+
+    let loader = BspLevelLoader::new();
+    let mut lightmap_packer = LightmapPacker::new(64, 64);
+    let white_lightmap_uv = lightmap_packer.add_white_block();
+    let mut vertices = Vec::<super::super::Vertex>::new();
+    let mut vertex_lookup = HashMap::<VertexKey, u32>::new();
+    let mut indices = HashMap::<String, Vec<u32>>::new();
+    loader.build_displacement_face(&lumps, &disp_info, &mut vertices, &mut vertex_lookup, &mut indices, &mut lightmap_packer, white_lightmap_uv);
+
+    let size = (1 << disp_info.power) + 1;
+    assert_eq!(vertices.len(), (size * size) as usize);
+    // Two triangles for each of the 4x4 quads
+    assert_eq!(indices["brick/wall"].len(), 4 * 4 * 2 * 3);
+  }
+
+  #[test]
+  fn displacement_uvs_interpolate_the_rotated_corners() {
+    let corners_uv = [Vec2::new(0f32, 0f32), Vec2::new(0f32, 1f32), Vec2::new(1f32, 1f32), Vec2::new(1f32, 0f32)];
+    let size = 5;
+    for first_corner in 0..4 {
+      let corner = |offset: i32| corners_uv[((first_corner + offset) & 3) as usize];
+      assert_eq!(BspLevelLoader::calculate_disp_uv(0, 0, size, &corners_uv, first_corner), corner(0));
+      assert_eq!(BspLevelLoader::calculate_disp_uv(size - 1, 0, size, &corners_uv, first_corner), corner(3));
+      assert_eq!(BspLevelLoader::calculate_disp_uv(size - 1, size - 1, size, &corners_uv, first_corner), corner(2));
+      assert_eq!(BspLevelLoader::calculate_disp_uv(0, size - 1, size, &corners_uv, first_corner), corner(1));
+
+      let centre = BspLevelLoader::calculate_disp_uv(size / 2, size / 2, size, &corners_uv, first_corner);
+      let average = (corners_uv[0] + corners_uv[1] + corners_uv[2] + corners_uv[3]) / 4f32;
+      assert!((centre - average).norm() < 0.0001f32, "{:?}", centre);
+    }
+  }
+}
@@ -1,5 +1,6 @@
 use sourcerenderer_bsp::Lighting;
 use std::option::Option::Some;
+use sourcerenderer_core::Vec2;
 
 const MARGIN: u32 = 2;
 const FREE_MIN: u32 = 3 + MARGIN * 2;
@@ -132,6 +133,23 @@ impl LightmapPacker {
     (rect.x, rect.y)
   }
 
+  /// Reserves a small white block for faces without a lightmap and returns the UV of its center texel.
+  pub fn add_white_block(&mut self) -> Vec2 {
+    const SIZE: u32 = 3;
+    let rect = self.find_space(SIZE, SIZE).unwrap();
+    for y in 0 .. SIZE {
+      for x in 0 .. SIZE {
+        let offset = (x + rect.x + (y + rect.y) * self.lightmap_width) as usize;
+        self.data[offset] = 0xFFFFFFFF;
+      }
+    }
+    self.used_area += SIZE * SIZE;
+    Vec2::new(
+      (rect.x as f32 + SIZE as f32 / 2f32) / self.lightmap_width as f32,
+      (rect.y as f32 + SIZE as f32 / 2f32) / self.lightmap_height as f32
+    )
+  }
+
   pub fn take_data(self) -> Box<[u32]> {
     self.data
   }
@@ -6,3 +6,4 @@ mod vertex;
 pub use bsp_level::BspLevelLoader;
 use bsp_lumps::BspLumps;
 pub use vertex::Vertex;
+use vertex::VertexKey;
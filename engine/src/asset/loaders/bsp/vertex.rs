@@ -9,3 +9,19 @@ pub struct Vertex {
   pub lightmap_uv: Vec2,
   pub alpha: f32
 }
+
+/// Compares the exact bit patterns of all attributes, so identical vertices can share an index.
+#[derive(PartialEq, Eq, Hash)]
+pub(super) struct VertexKey([u32; 11]);
+
+impl From<&Vertex> for VertexKey {
+  fn from(vertex: &Vertex) -> Self {
+    Self([
+      vertex.position.x.to_bits(), vertex.position.y.to_bits(), vertex.position.z.to_bits(),
+      vertex.normal.x.to_bits(), vertex.normal.y.to_bits(), vertex.normal.z.to_bits(),
+      vertex.uv.x.to_bits(), vertex.uv.y.to_bits(),
+      vertex.lightmap_uv.x.to_bits(), vertex.lightmap_uv.y.to_bits(),
+      vertex.alpha.to_bits()
+    ])
+  }
+}
@@ -1,4 +1,5 @@
 use std::path::PathBuf;
+use std::time::SystemTime;
 use crate::asset::asset_manager::{AssetContainer, AssetFile, AssetFileData};
 use crate::asset::loaders::csgo_loader::CSGOMapLoaderError::CSGONotFound;
 use regex::Regex;
@@ -37,15 +38,9 @@ impl CSGODirectoryContainer {
       pak_name_regex: Regex::new(CSGO_PAK_NAME_PATTERN).unwrap()
     })
   }
-}
-
-impl<P: Platform> AssetContainer<P> for CSGODirectoryContainer {
-  fn contains(&self, path: &str) -> bool {
-    return self.map_name_regex.is_match(path) || self.primary_pak_name_regex.is_match(path) || self.pak_name_regex.is_match(path);
-  }
 
-  fn load(&self, path: &str) -> Option<AssetFile<P>> {
-    let actual_path = if self.map_name_regex.is_match(path) {
+  fn resolve_path(&self, path: &str) -> Option<PathBuf> {
+    if self.map_name_regex.is_match(path) {
       let mut actual_path = PathBuf::new();
       actual_path.push(&self.path);
       actual_path.push("csgo");
@@ -55,7 +50,7 @@ impl<P: Platform> AssetContainer<P> for CSGODirectoryContainer {
         file_name.push_str(".bsp");
       }
       actual_path.push(file_name);
-      actual_path
+      Some(actual_path)
     } else if self.primary_pak_name_regex.is_match(path) || self.pak_name_regex.is_match(path) {
       let mut actual_path = PathBuf::new();
       actual_path.push(&self.path);
@@ -65,11 +60,20 @@ impl<P: Platform> AssetContainer<P> for CSGODirectoryContainer {
         file_name.push_str(".vpk");
       }
       actual_path.push(file_name);
-      actual_path
+      Some(actual_path)
     } else {
-      return None;
-    };
+      None
+    }
+  }
+}
 
+impl<P: Platform> AssetContainer<P> for CSGODirectoryContainer {
+  fn contains(&self, path: &str) -> bool {
+    return self.map_name_regex.is_match(path) || self.primary_pak_name_regex.is_match(path) || self.pak_name_regex.is_match(path);
+  }
+
+  fn load(&self, path: &str) -> Option<AssetFile<P>> {
+    let actual_path = self.resolve_path(path)?;
     let file = <P::IO as IO>::open_external_asset(&actual_path);
     file.ok().map(|file|
       AssetFile {
@@ -77,4 +81,13 @@ impl<P: Platform> AssetContainer<P> for CSGODirectoryContainer {
       data: AssetFileData::File(file)
     })
   }
+
+  fn modified_time(&self, path: &str) -> Option<SystemTime> {
+    let actual_path = self.resolve_path(path)?;
+    <P::IO as IO>::external_asset_modified_time(&actual_path)
+  }
+
+  fn external_path(&self, path: &str) -> Option<PathBuf> {
+    self.resolve_path(path)
+  }
 }
@@ -1,5 +1,5 @@
-use std::{fs::File, io::{Cursor, Error as IOError, ErrorKind, Result as IOResult}, path::Path, usize};
-use gltf::{Glb, Gltf, buffer::Data as GltfBufferData, image::Data as GltfImageData, import};
+use std::{fs::File, io::{Cursor, Error as IOError, ErrorKind, Read, Result as IOResult}, path::Path, usize};
+use gltf::{Glb, Gltf, buffer::Data as GltfBufferData, image::{Data as GltfImageData, Format as GltfImageFormat}, import};
 use sourcerenderer_core::Platform;
 
 use crate::asset::asset_manager::{AssetContainer, AssetFile, AssetFileData};
@@ -15,12 +15,19 @@ pub struct GltfContainer {
 impl GltfContainer {
   pub fn load(path: &str) -> IOResult<Self> {
     let json_data = {
-      let file = File::open(path)?;
-      let glb = Glb::from_reader(file).map_err(|_e| IOError::new(ErrorKind::Other, "Failed to read Glb"))?;
-      glb.json.into_owned().into_boxed_slice()
+      let mut file = File::open(path)?;
+      let mut data = Vec::<u8>::new();
+      file.read_to_end(&mut data)?;
+      if data.starts_with(b"glTF") {
+        let glb = Glb::from_slice(&data).map_err(|_e| IOError::new(ErrorKind::Other, "Failed to read Glb"))?;
+        glb.json.into_owned().into_boxed_slice()
+      } else {
+        data.into_boxed_slice()
+      }
     };
 
-    let (document, buffers, images) = import(path).unwrap();
+    // Resolves the embedded BIN chunk, external files and base64 data URIs
+    let (document, buffers, images) = import(path).map_err(|e| IOError::new(ErrorKind::Other, format!("Failed to import glTF: {:?}", e)))?;
     let gltf = Gltf {
       document, 
       blob: None
@@ -41,32 +48,81 @@ impl GltfContainer {
       base_path
     })
   }
-}
 
-impl<P: Platform> AssetContainer<P> for GltfContainer {
-  fn load(&self, path: &str) -> Option<crate::asset::asset_manager::AssetFile<P>> {
+  pub fn open(&self, path: &str) -> Option<Cursor<Box<[u8]>>> {
     let scene_base_path = self.base_path.clone() + "scene/";
+    if path == self.base_path.clone() + "scene" {
+      return Some(Cursor::new(self.json_data.clone()));
+    }
     if path.starts_with(&scene_base_path) {
       let scene_name = &path[scene_base_path.len()..];
       for scene in self.gltf.scenes() {
         if scene.name().map_or_else(|| scene.index().to_string(), |name| name.to_string()) == scene_name {
           // We just need the JSON data to load the scene
-          return Some(AssetFile {
-            path: path.to_string(),
-            data: AssetFileData::Memory(Cursor::new(self.json_data.clone()))
-          });
+          return Some(Cursor::new(self.json_data.clone()));
         }
       }
     }
     let buffer_base_path = self.base_path.clone() + "buffer/";
     if path.starts_with(&buffer_base_path) {
       let buffer_index: usize = Path::new(path).file_name().unwrap().to_str().unwrap().parse().unwrap();
-      return Some(AssetFile {
-        path: path.to_string(),
-        data: AssetFileData::Memory(Cursor::new(self.buffers[buffer_index].0.clone().into_boxed_slice()))
-      });
+      return Some(Cursor::new(self.buffers[buffer_index].0.clone().into_boxed_slice()));
+    }
+
+    let image_base_path = self.base_path.clone() + "image/";
+    if path.starts_with(&image_base_path) {
+      let image_index: usize = path[image_base_path.len()..].parse().ok()?;
+      let image = self.images.get(image_index)?;
+      let mut data = Vec::<u8>::with_capacity(8 + (image.width * image.height * 4) as usize);
+      data.extend_from_slice(&image.width.to_le_bytes());
+      data.extend_from_slice(&image.height.to_le_bytes());
+      data.extend(image_to_rgba8(image));
+      return Some(Cursor::new(data.into_boxed_slice()));
     }
 
     None
   }
 }
+
+impl<P: Platform> AssetContainer<P> for GltfContainer {
+  fn load(&self, path: &str) -> Option<AssetFile<P>> {
+    let data = self.open(path)?;
+    Some(AssetFile {
+      path: path.to_string(),
+      data: AssetFileData::Memory(data)
+    })
+  }
+}
+
+/// Images get served as the width and height as little endian u32 followed by the RGBA8 pixels.
+fn image_to_rgba8(image: &GltfImageData) -> Vec<u8> {
+  let (channels, bytes_per_channel) = match image.format {
+    GltfImageFormat::R8 => (1, 1),
+    GltfImageFormat::R8G8 => (2, 1),
+    GltfImageFormat::R8G8B8 | GltfImageFormat::B8G8R8 => (3, 1),
+    GltfImageFormat::R8G8B8A8 | GltfImageFormat::B8G8R8A8 => (4, 1),
+    GltfImageFormat::R16 => (1, 2),
+    GltfImageFormat::R16G16 => (2, 2),
+    GltfImageFormat::R16G16B16 => (3, 2),
+    GltfImageFormat::R16G16B16A16 => (4, 2)
+  };
+  let is_bgr = image.format == GltfImageFormat::B8G8R8 || image.format == GltfImageFormat::B8G8R8A8;
+
+  let mut rgba = Vec::<u8>::with_capacity((image.width * image.height * 4) as usize);
+  for pixel in image.pixels.chunks_exact(channels * bytes_per_channel) {
+    // Only keep the most significant byte of 16 bit channels
+    let channel = |index: usize| pixel[index * bytes_per_channel + bytes_per_channel - 1];
+    let (r, g, b, a) = match channels {
+      1 => (channel(0), channel(0), channel(0), 255u8),
+      2 => (channel(0), channel(0), channel(0), channel(1)),
+      3 => (channel(0), channel(1), channel(2), 255u8),
+      _ => (channel(0), channel(1), channel(2), channel(3))
+    };
+    if is_bgr {
+      rgba.extend_from_slice(&[b, g, r, a]);
+    } else {
+      rgba.extend_from_slice(&[r, g, b, a]);
+    }
+  }
+  rgba
+}
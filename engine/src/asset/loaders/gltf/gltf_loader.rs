@@ -1,41 +1,53 @@
-use std::{collections::HashMap, io::{Cursor, Read, Seek, SeekFrom}, slice, sync::Arc, usize};
+use std::{collections::{HashMap, HashSet}, io::Read, slice, sync::Arc, usize};
 
-use gltf::{Gltf, Material, Node, Primitive, Scene, Semantic, buffer::Source};
+use gltf::{Buffer, Gltf, Material, Node, Primitive, Scene, Semantic, animation::util::ReadOutputs};
 use legion::{Entity, World, WorldOptions};
 use nalgebra::UnitQuaternion;
-use sourcerenderer_core::{Platform, Vec2, Vec3, Vec4};
+use smallvec::SmallVec;
+use sourcerenderer_core::{Matrix4, Platform, Vec2, Vec3, Vec4};
+use sourcerenderer_core::graphics::{Format, SampleCount, TextureDimension, TextureInfo, TextureUsage};
 
-use crate::{Parent, Transform, asset::{Asset, AssetLoadPriority, AssetLoader, AssetLoaderProgress, AssetManager, Mesh, MeshRange, Model, asset_manager::{AssetFile, AssetLoaderResult}, loaders::BspVertex as Vertex}, math::BoundingBox, renderer::StaticRenderableComponent};
+use crate::{Parent, Transform, animation::{AnimationClip, AnimationPlayerComponent, AnimationTrack, AnimationValues, Interpolation, SkinComponent}, asset::{Asset, AssetError, AssetLoadPriority, AssetLoader, AssetLoaderProgress, AssetManager, Material as AssetMaterial, MaterialTexture, Mesh, MeshRange, Model, SkinningVertex, Texture, asset_manager::{AssetFile, AssetLoaderResult}, loaders::BspVertex as Vertex}, math::BoundingBox, renderer::StaticRenderableComponent};
 
 pub struct GltfLoader {}
 
+/// The parts of the asset manager the import needs, so it can run without a graphics device.
+trait GltfAssets {
+  type File: Read;
+
+  fn load_file(&self, path: &str) -> Option<Self::File>;
+  fn add_asset(&self, path: &str, asset: Asset);
+}
+
+impl<P: Platform> GltfAssets for AssetManager<P> {
+  type File = AssetFile<P>;
+
+  fn load_file(&self, path: &str) -> Option<AssetFile<P>> {
+    AssetManager::load_file(self, path)
+  }
+
+  fn add_asset(&self, path: &str, asset: Asset) {
+    AssetManager::add_asset(self, path, asset, AssetLoadPriority::Normal);
+  }
+}
+
 impl GltfLoader {
   pub fn new() -> Self {
     Self {}
   }
 
-  fn visit_node<P: Platform>(node: &Node, world: &mut World, asset_mgr: &AssetManager<P>, parent_entity: Option<Entity>, gltf_file_name: &str, buffer_cache: &mut HashMap<usize, Vec<u8>>) {
-    let (translation, _rotation, scale) = match node.transform() {
-      gltf::scene::Transform::Matrix { matrix: _columns_data } => {
-        unimplemented!()
-
-        /*let mut matrix = Matrix4::default();
-        for i in 0..matrix.len() {
-          let column_slice = &columns_data[0];
-          matrix.column_mut(i).copy_from_slice(column_slice);
-        }
-        matrix*/
-      },
-      gltf::scene::Transform::Decomposed { translation, rotation, scale } =>
-        (Vec3::new(translation[0], translation[1], translation[2]),
-        Vec4::new(rotation[0], rotation[1], rotation[2], rotation[3]),
-        Vec3::new(scale[0], scale[1], scale[2])),
+  fn visit_node<A: GltfAssets>(node: &Node, world: &mut World, asset_mgr: &A, parent_entity: Option<Entity>, gltf_file_name: &str, buffer_cache: &mut HashMap<usize, Vec<u8>>, node_entities: &mut HashMap<usize, Entity>, imported_assets: &mut HashSet<String>) -> Result<(), String> {
+    // The transform is relative to the parent node
+    let transform = match node.transform() {
+      gltf::scene::Transform::Matrix { matrix } => Transform::from_matrix(&Matrix4::from(matrix)),
+      gltf::scene::Transform::Decomposed { translation, rotation, scale } => Transform {
+        position: Vec3::new(translation[0], translation[1], translation[2]),
+        rotation: UnitQuaternion::new_normalize(nalgebra::Quaternion::from(Vec4::new(rotation[0], rotation[1], rotation[2], rotation[3]))),
+        scale: Vec3::new(scale[0], scale[1], scale[2])
+      }
     };
-    let entity = world.push((Transform {
-      position: translation,
-      scale: scale,
-      rotation: UnitQuaternion::identity(),
-    },));
+    let entity = world.push((transform,));
+    node_entities.insert(node.index(), entity);
 
     {
       let mut entry = world.entry(entity).unwrap();
@@ -48,10 +60,20 @@ impl GltfLoader {
       let mut indices = Vec::<u32>::new();
       let mut vertices = Vec::<Vertex>::new();
       let mut parts = Vec::<MeshRange>::with_capacity(mesh.primitives().len());
+      let mut material_paths = Vec::<String>::with_capacity(mesh.primitives().len());
+      let mut skinning = Vec::<SkinningVertex>::new();
+      let mut has_skinning = false;
       let mut bounding_box = BoundingBox::new(Vec3::new(0f32, 0f32, 0f32), Vec3::new(0f32, 0f32, 0f32));
       for primitive in mesh.primitives() {
         let part_start = indices.len();
-        GltfLoader::load_primitive(&primitive, asset_mgr, &mut vertices, &mut indices, gltf_file_name, buffer_cache);
+        GltfLoader::load_primitive(&primitive, asset_mgr, &mut vertices, &mut indices, gltf_file_name, buffer_cache)?;
+        if let Some(primitive_skinning) = GltfLoader::load_skinning(&primitive, asset_mgr, gltf_file_name, buffer_cache)? {
+          skinning.extend(primitive_skinning);
+          has_skinning = true;
+        }
+        // Unskinned primitives in a skinned mesh don't get influenced by any joint
+        skinning.resize(vertices.len(), SkinningVertex::default());
+        material_paths.push(GltfLoader::load_material(&primitive.material(), asset_mgr, gltf_file_name, imported_assets)?);
         let primitive_bounding_box = primitive.bounding_box();
         bounding_box.min.x = f32::min(bounding_box.min.x, primitive_bounding_box.min[0]);
         bounding_box.min.y = f32::min(bounding_box.min.y, primitive_bounding_box.min[1]);
@@ -78,35 +100,33 @@ impl GltfLoader {
       let data_ptr = unsafe { slice::from_raw_parts_mut(ptr as *mut u8, indices_count * std::mem::size_of::<u32>()) as *mut [u8] };
       let indices_data = unsafe { Box::from_raw(data_ptr) };
 
-      let parts_len = parts.len();
-
       let model_name = node.name().map_or_else(|| node.index().to_string(), |name| name.to_string());
       let mesh_path = gltf_file_name.to_string() + "/mesh/" + &model_name;
       asset_mgr.add_asset(&mesh_path, Asset::Mesh(Mesh {
         indices: (indices_count > 0).then(|| indices_data),
         vertices: vertices_data,
+        skinning: has_skinning.then(|| unsafe { slice::from_raw_parts(skinning.as_ptr() as *const u8, std::mem::size_of_val(&skinning[..])) }.to_vec().into_boxed_slice()),
         bounding_box: Some(bounding_box),
         parts: parts.into_boxed_slice()
-      }), AssetLoadPriority::Normal);
+      }));
 
       let model_path = gltf_file_name.to_string() + "/model/" + &model_name;
       asset_mgr.add_asset(&model_path, Asset::Model(Model {
         mesh_path: mesh_path.clone(),
-        material_paths: vec!["IGNORE".to_string(); parts_len],
-      }), AssetLoadPriority::Normal);
+        material_paths,
+        lods: Vec::new()
+      }));
       
       let mut entry = world.entry(entity).unwrap();
       entry.add_component(StaticRenderableComponent {
         model_path: model_path,
+        skin: 0,
         receive_shadows: true,
         cast_shadows: true,
         can_move: false
       });
     };
 
-    if node.skin().is_some() {
-      println!("WARNING: skins are not supported. Node name: {:?}", node.name());
-    }
     if node.camera().is_some() {
       println!("WARNING: cameras are not supported. Node name: {:?}", node.name());
     }
@@ -115,204 +135,734 @@ impl GltfLoader {
     }
 
     for child in node.children() {
-      GltfLoader::visit_node(&child, world, asset_mgr, Some(entity), gltf_file_name, buffer_cache);
+      GltfLoader::visit_node(&child, world, asset_mgr, Some(entity), gltf_file_name, buffer_cache, node_entities, imported_assets)?;
     }
+    Ok(())
   }
 
-  fn load_scene<P: Platform>(scene: &Scene, asset_mgr: &AssetManager<P>, gltf_file_name: &str) -> World {
+  fn load_scene<A: GltfAssets>(gltf: &Gltf, scene: &Scene, asset_mgr: &A, gltf_file_name: &str) -> Result<World, String> {
     let mut world = World::new(WorldOptions::default());
     let nodes = scene.nodes();
     let mut buffer_cache = HashMap::<usize, Vec<u8>>::new();
+    let mut node_entities = HashMap::<usize, Entity>::new();
+    let mut imported_assets = HashSet::<String>::new();
     for node in nodes {
-      GltfLoader::visit_node(&node, &mut world, asset_mgr, None, gltf_file_name, &mut buffer_cache);
+      GltfLoader::visit_node(&node, &mut world, asset_mgr, None, gltf_file_name, &mut buffer_cache, &mut node_entities, &mut imported_assets)?;
     }
-    world
-  }
 
-  fn load_primitive<P: Platform>(primitive: &Primitive, asset_mgr: &AssetManager<P>, vertices: &mut Vec<Vertex>, indices: &mut Vec<u32>, gltf_file_name: &str, buffer_cache: &mut HashMap<usize, Vec<u8>>) {
-    GltfLoader::load_material(&primitive.material(), asset_mgr, gltf_file_name);
+    GltfLoader::load_skins(gltf, &mut world, &node_entities, asset_mgr, gltf_file_name, &mut buffer_cache)?;
 
-    let index_base = vertices.len() as u32;
+    let clips = GltfLoader::load_animations(gltf, &node_entities, asset_mgr, gltf_file_name, &mut buffer_cache)?;
+    if !clips.is_empty() {
+      world.push((AnimationPlayerComponent {
+        clips,
+        active_clip: Some(0),
+        time: 0f32,
+        looping: true
+      },));
+    }
+    Ok(world)
+  }
 
-    {
-      let positions = primitive.get(&Semantic::Positions).unwrap();
-      assert!(positions.sparse().is_none());
-      let positions_view = positions.view().unwrap();
-      let positions_buffer = positions_view.buffer();
-      match positions_buffer.source() {
-        Source::Bin => {},
-        Source::Uri(_) => unimplemented!(),
-      }
+  fn load_buffer<A: GltfAssets>(buffer: &Buffer, asset_mgr: &A, gltf_file_name: &str, buffer_cache: &mut HashMap<usize, Vec<u8>>) -> Result<(), String> {
+    if buffer_cache.contains_key(&buffer.index()) {
+      return Ok(());
+    }
+    let url = format!("{}/buffer/{}", gltf_file_name, buffer.index().to_string());
+    println!("Loading: {}", url);
+    let mut buffer_file = asset_mgr.load_file(&url).ok_or_else(|| format!("Failed to load buffer: {}", url))?;
+
+    let mut data = vec![0u8; buffer.length()];
+    buffer_file.read_exact(&mut data).map_err(|e| format!("Failed to read buffer {}: {}", url, e))?;
+    buffer_cache.insert(buffer.index(), data);
+    Ok(())
+  }
 
-      let normals = primitive.get(&Semantic::Normals).unwrap();
-      assert!(normals.sparse().is_none());
-      let normals_view = normals.view().unwrap();
-      let normals_buffer = normals_view.buffer();
-      match normals_buffer.source() {
-        Source::Bin => {},
-        Source::Uri(_) => unimplemented!(),
+  fn load_skins<A: GltfAssets>(gltf: &Gltf, world: &mut World, node_entities: &HashMap<usize, Entity>, asset_mgr: &A, gltf_file_name: &str, buffer_cache: &mut HashMap<usize, Vec<u8>>) -> Result<(), String> {
+    for node in gltf.nodes() {
+      let skin = if let Some(skin) = node.skin() {
+        skin
+      } else {
+        continue;
+      };
+      let entity = if let Some(entity) = node_entities.get(&node.index()) {
+        *entity
+      } else {
+        continue;
+      };
+
+      let joints: Option<Vec<Entity>> = skin.joints().map(|joint| node_entities.get(&joint.index()).copied()).collect();
+      if joints.is_none() {
+        println!("WARNING: skin uses joints outside of the scene. Node name: {:?}", node.name());
+        continue;
       }
+      let joints = joints.unwrap();
 
-      if !buffer_cache.contains_key(&positions_buffer.index()) {
-        let url = format!("{}/buffer/{}", gltf_file_name, positions_buffer.index().to_string());
-        println!("Loading: {}", url);
-        let mut buffer_file = asset_mgr.load_file(&url).expect("Failed to load buffer");
+      if let Some(view) = skin.inverse_bind_matrices().and_then(|accessor| accessor.view()) {
+        GltfLoader::load_buffer(&view.buffer(), asset_mgr, gltf_file_name, buffer_cache)?;
+      }
+      let buffers = &*buffer_cache;
+      let reader = skin.reader(|buffer| buffers.get(&buffer.index()).map(|data| &data[..]));
+      let inverse_bind_matrices: Vec<Matrix4> = reader.read_inverse_bind_matrices().map_or_else(
+        || vec![Matrix4::identity(); joints.len()],
+        |matrices| matrices.map(Matrix4::from).collect()
+      );
+
+      let skeleton = skin.skeleton().and_then(|skeleton| node_entities.get(&skeleton.index()).copied());
+      let mut entry = world.entry(entity).unwrap();
+      entry.add_component(SkinComponent {
+        joints,
+        inverse_bind_matrices,
+        skeleton
+      });
+    }
+    Ok(())
+  }
 
-        let mut data = vec![0u8; positions_buffer.length()];
-        buffer_file.read_exact(&mut data).unwrap();
-        buffer_cache.insert(positions_buffer.index(), data);
+  /// Returns None if the primitive isn't skinned.
+  fn load_skinning<A: GltfAssets>(primitive: &Primitive, asset_mgr: &A, gltf_file_name: &str, buffer_cache: &mut HashMap<usize, Vec<u8>>) -> Result<Option<Vec<SkinningVertex>>, String> {
+    for semantic in [Semantic::Joints(0), Semantic::Weights(0), Semantic::Joints(1), Semantic::Weights(1)] {
+      if let Some(view) = primitive.get(&semantic).and_then(|accessor| accessor.view()) {
+        GltfLoader::load_buffer(&view.buffer(), asset_mgr, gltf_file_name, buffer_cache)?;
       }
+    }
 
-      if !buffer_cache.contains_key(&normals_buffer.index()) {
-        let url = format!("{}/buffer/{}", gltf_file_name, normals_buffer.index().to_string());
-        println!("Loading: {}", url);
-        let mut buffer_file = asset_mgr.load_file(&url).expect("Failed to load buffer");
+    let buffers = &*buffer_cache;
+    let reader = primitive.reader(|buffer| buffers.get(&buffer.index()).map(|data| &data[..]));
+    let (joints, weights): (Vec<[u16; 4]>, Vec<[f32; 4]>) = match (reader.read_joints(0), reader.read_weights(0)) {
+      (Some(joints), Some(weights)) => (joints.into_u16().collect(), weights.into_f32().collect()),
+      _ => return Ok(None)
+    };
+    let extra_joints: Option<Vec<[u16; 4]>> = reader.read_joints(1).map(|joints| joints.into_u16().collect());
+    let extra_weights: Option<Vec<[f32; 4]>> = reader.read_weights(1).map(|weights| weights.into_f32().collect());
 
-        let mut data = vec![0u8; normals_buffer.length()];
-        buffer_file.read_exact(&mut data).unwrap();
-        buffer_cache.insert(normals_buffer.index(), data);
+    Ok(Some(joints.iter().zip(weights.iter()).enumerate().map(|(index, (joints, weights))| {
+      let mut influences: SmallVec<[(u16, f32); 8]> = joints.iter().copied().zip(weights.iter().copied()).collect();
+      if let (Some(extra_joints), Some(extra_weights)) = (extra_joints.as_ref().and_then(|joints| joints.get(index)), extra_weights.as_ref().and_then(|weights| weights.get(index))) {
+        influences.extend(extra_joints.iter().copied().zip(extra_weights.iter().copied()));
       }
-      
-      let positions_buffer_data = buffer_cache.get(&positions_buffer.index()).unwrap();
-      let mut positions_buffer_cursor = Cursor::new(positions_buffer_data);
-      positions_buffer_cursor.seek(SeekFrom::Start((positions_view.offset() + positions.offset()) as u64)).unwrap();
-
-      let normals_buffer_data = buffer_cache.get(&normals_buffer.index()).unwrap();
-      let mut normals_buffer_cursor = Cursor::new(normals_buffer_data);
-      normals_buffer_cursor.seek(SeekFrom::Start((normals_view.offset() + normals.offset()) as u64)).unwrap();
-
-      assert_eq!(positions.count(), normals.count());
-      for _ in 0..positions.count() {
-        let positions_start = positions_buffer_cursor.seek(SeekFrom::Current(0)).unwrap();
-        let normals_start = normals_buffer_cursor.seek(SeekFrom::Current(0)).unwrap();
-
-        let mut position_data = vec![0; positions.size()];
-        positions_buffer_cursor.read_exact(&mut position_data).unwrap();
-        assert_eq!(position_data.len(), std::mem::size_of::<Vec3>());
-        
-        let mut normal_data = vec![0; normals.size()];
-        normals_buffer_cursor.read_exact(&mut normal_data).unwrap();
-        assert_eq!(normal_data.len(), std::mem::size_of::<Vec3>());
-
-        unsafe { 
-          let position_vec_ptr: *const Vec3 = std::mem::transmute(position_data.as_ptr());
-          let normal_vec_ptr: *const Vec3 = std::mem::transmute(normal_data.as_ptr());
-          let mut normal = *normal_vec_ptr;
-          normal.normalize_mut();
-          vertices.push(Vertex {
-            position: *position_vec_ptr,
-            normal: normal,
-            uv: Vec2::new(0f32, 0f32),
-            lightmap_uv: Vec2::new(0f32, 0f32),
-            alpha: 1.0f32
-          });
-        }
 
-        if let Some(stride) = positions_view.stride() {
-          assert!(stride > positions.size());
-          positions_buffer_cursor.seek(SeekFrom::Start(positions_start + stride as u64)).unwrap();
+      // Keep the 4 strongest influences and renormalize them
+      influences.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+      influences.truncate(4);
+      let weight_sum: f32 = influences.iter().map(|(_, weight)| *weight).sum();
+      let mut vertex = SkinningVertex::default();
+      if weight_sum > 0f32 {
+        for (i, (joint, weight)) in influences.iter().enumerate() {
+          vertex.joints[i] = *joint;
+          vertex.weights[i] = *weight / weight_sum;
         }
+      }
+      vertex
+    }).collect()))
+  }
+
+  /// Only animates nodes that are part of the loaded scene.
+  fn load_animations<A: GltfAssets>(gltf: &Gltf, node_entities: &HashMap<usize, Entity>, asset_mgr: &A, gltf_file_name: &str, buffer_cache: &mut HashMap<usize, Vec<u8>>) -> Result<Vec<AnimationClip>, String> {
+    let mut clips = Vec::<AnimationClip>::new();
+    for animation in gltf.animations() {
+      let mut tracks = Vec::<AnimationTrack>::new();
+      let mut duration = 0f32;
+      for channel in animation.channels() {
+        let target = if let Some(target) = node_entities.get(&channel.target().node().index()) {
+          *target
+        } else {
+          continue;
+        };
 
-        if let Some(stride) = normals_view.stride() {
-          assert!(stride > normals.size());
-          normals_buffer_cursor.seek(SeekFrom::Start(normals_start + stride as u64)).unwrap();
+        let sampler = channel.sampler();
+        for accessor in [sampler.input(), sampler.output()] {
+          if let Some(view) = accessor.view() {
+            GltfLoader::load_buffer(&view.buffer(), asset_mgr, gltf_file_name, buffer_cache)?;
+          }
         }
 
-        assert!(positions_buffer_cursor.seek(SeekFrom::Current(0)).unwrap() <= (positions_view.offset() + positions_view.length()) as u64);
-        assert!(normals_buffer_cursor.seek(SeekFrom::Current(0)).unwrap() <= (normals_view.offset() + normals_view.length()) as u64);
+        let buffers = &*buffer_cache;
+        let reader = channel.reader(|buffer| buffers.get(&buffer.index()).map(|data| &data[..]));
+        let times: Vec<f32> = if let Some(inputs) = reader.read_inputs() {
+          inputs.collect()
+        } else {
+          continue;
+        };
+        let values = match reader.read_outputs() {
+          Some(ReadOutputs::Translations(translations)) => AnimationValues::Translation(translations.map(|t| Vec3::new(t[0], t[1], t[2])).collect()),
+          Some(ReadOutputs::Rotations(rotations)) => AnimationValues::Rotation(rotations.into_f32().map(|r| Vec4::new(r[0], r[1], r[2], r[3])).collect()),
+          Some(ReadOutputs::Scales(scales)) => AnimationValues::Scale(scales.map(|s| Vec3::new(s[0], s[1], s[2])).collect()),
+          Some(ReadOutputs::MorphTargetWeights(_)) => {
+            println!("WARNING: morph target animations are not supported. Animation name: {:?}", animation.name());
+            continue;
+          }
+          None => continue
+        };
+        let interpolation = match sampler.interpolation() {
+          gltf::animation::Interpolation::Linear => Interpolation::Linear,
+          gltf::animation::Interpolation::Step => Interpolation::Step,
+          gltf::animation::Interpolation::CubicSpline => Interpolation::CubicSpline
+        };
+
+        duration = f32::max(duration, times.last().copied().unwrap_or(0f32));
+        tracks.push(AnimationTrack {
+          target,
+          interpolation,
+          times,
+          values
+        });
       }
-    }
 
-    let indices_accessor = primitive.indices();
-    if let Some(indices_accessor) = indices_accessor {
-      assert!(indices_accessor.sparse().is_none());
-      let view = indices_accessor.view().unwrap();
-      let buffer = view.buffer();
+      clips.push(AnimationClip {
+        name: animation.name().map_or_else(|| animation.index().to_string(), |name| name.to_string()),
+        duration,
+        tracks
+      });
+    }
+    Ok(clips)
+  }
 
+  fn load_primitive<A: GltfAssets>(primitive: &Primitive, asset_mgr: &A, vertices: &mut Vec<Vertex>, indices: &mut Vec<u32>, gltf_file_name: &str, buffer_cache: &mut HashMap<usize, Vec<u8>>) -> Result<(), String> {
+    let index_base = vertices.len() as u32;
 
-      if !buffer_cache.contains_key(&buffer.index()) {
-        let url = format!("{}/buffer/{}", gltf_file_name, buffer.index().to_string());
-        println!("Loading: {}", url);
-        let mut buffer_file = asset_mgr.load_file(&url).expect("Failed to load buffer");
+    {
+      let positions = primitive.get(&Semantic::Positions).ok_or_else(|| "Primitive has no positions".to_string())?;
+      let normals = primitive.get(&Semantic::Normals).ok_or_else(|| "Primitive has no normals".to_string())?;
+      if positions.sparse().is_some() || normals.sparse().is_some() {
+        return Err("Sparse vertex accessors are not supported".to_string());
+      }
+      if positions.size() != std::mem::size_of::<Vec3>() || normals.size() != std::mem::size_of::<Vec3>() {
+        return Err("Positions and normals have to be 3 floats".to_string());
+      }
+      if positions.count() != normals.count() {
+        return Err("Positions and normals have different counts".to_string());
+      }
+      let positions_view = positions.view().ok_or_else(|| "Positions have no buffer view".to_string())?;
+      let positions_buffer = positions_view.buffer();
+      let normals_view = normals.view().ok_or_else(|| "Normals have no buffer view".to_string())?;
+      let normals_buffer = normals_view.buffer();
 
-        let mut data = vec![0u8; buffer.length()];
-        buffer_file.read_exact(&mut data).unwrap();
-        buffer_cache.insert(buffer.index(), data);
+      GltfLoader::load_buffer(&positions_buffer, asset_mgr, gltf_file_name, buffer_cache)?;
+      GltfLoader::load_buffer(&normals_buffer, asset_mgr, gltf_file_name, buffer_cache)?;
+
+      let positions_data = GltfLoader::accessor_data(&buffer_cache[&positions_buffer.index()], positions_view.offset(), positions_view.length(), positions_view.stride(), positions.offset(), positions.size(), positions.count())?;
+      let normals_data = GltfLoader::accessor_data(&buffer_cache[&normals_buffer.index()], normals_view.offset(), normals_view.length(), normals_view.stride(), normals.offset(), normals.size(), normals.count())?;
+      for (position_data, normal_data) in positions_data.iter().zip(normals_data.iter()) {
+        let position = Vec3::new(read_f32(position_data, 0), read_f32(position_data, 4), read_f32(position_data, 8));
+        let mut normal = Vec3::new(read_f32(normal_data, 0), read_f32(normal_data, 4), read_f32(normal_data, 8));
+        normal.normalize_mut();
+        vertices.push(Vertex {
+          position,
+          normal,
+          uv: Vec2::new(0f32, 0f32),
+          lightmap_uv: Vec2::new(0f32, 0f32),
+          alpha: 1.0f32
+        });
       }
+    }
 
-      let buffer_data = buffer_cache.get(&buffer.index()).unwrap();
+    let indices_accessor = primitive.indices();
+    if let Some(indices_accessor) = indices_accessor {
+      if indices_accessor.sparse().is_some() {
+        return Err("Sparse index accessors are not supported".to_string());
+      }
+      let index_size = indices_accessor.size();
+      if index_size != 2 && index_size != 4 {
+        return Err(format!("Index size {} is not supported", index_size));
+      }
+      let view = indices_accessor.view().ok_or_else(|| "Indices have no buffer view".to_string())?;
+      let buffer = view.buffer();
 
-      let mut buffer_cursor = Cursor::new(buffer_data);
-      buffer_cursor.seek(SeekFrom::Start((view.offset() + indices_accessor.offset()) as u64)).unwrap();
-    
-      for _ in 0..indices_accessor.count() {
-        let start = buffer_cursor.seek(SeekFrom::Current(0)).unwrap();
+      GltfLoader::load_buffer(&buffer, asset_mgr, gltf_file_name, buffer_cache)?;
 
-        let mut attr_data = vec![0; indices_accessor.size()];
-        buffer_cursor.read_exact(&mut attr_data).unwrap();
+      let indices_data = GltfLoader::accessor_data(&buffer_cache[&buffer.index()], view.offset(), view.length(), view.stride(), indices_accessor.offset(), index_size, indices_accessor.count())?;
+      for index_data in indices_data {
+        let index = if index_size == 4 {
+          u32::from_le_bytes([index_data[0], index_data[1], index_data[2], index_data[3]])
+        } else {
+          u16::from_le_bytes([index_data[0], index_data[1]]) as u32
+        };
+        indices.push(index + index_base);
+      }
+    }
+    Ok(())
+  }
 
-        assert!(indices_accessor.size() <= std::mem::size_of::<u32>());
+  /// Returns the elements of an accessor, or an error if they don't fit into the buffer view.
+  fn accessor_data(buffer_data: &[u8], view_offset: usize, view_length: usize, view_stride: Option<usize>, offset: usize, size: usize, count: usize) -> Result<Vec<&[u8]>, String> {
+    let stride = view_stride.unwrap_or(size);
+    if stride < size {
+      return Err(format!("Buffer view stride {} is smaller than the element size {}", stride, size));
+    }
+    let view_end = view_offset.checked_add(view_length).filter(|end| *end <= buffer_data.len())
+      .ok_or_else(|| "Buffer view exceeds the buffer".to_string())?;
+    (0..count).map(|i| {
+      let start = i.checked_mul(stride).and_then(|element_offset| element_offset.checked_add(offset)).and_then(|element_offset| element_offset.checked_add(view_offset));
+      start.and_then(|start| start.checked_add(size).filter(|end| *end <= view_end).map(|end| &buffer_data[start..end]))
+        .ok_or_else(|| "Accessor exceeds the buffer view".to_string())
+    }).collect()
+  }
 
-        unsafe {
-          if indices_accessor.size() == 4 {
-            let index_ptr: *const u32 = std::mem::transmute(attr_data.as_ptr());
-            indices.push(*index_ptr + index_base);
-          } else if indices_accessor.size() == 2 {
-            let index_ptr: *const u16 = std::mem::transmute(attr_data.as_ptr());
-            indices.push(*index_ptr as u32 + index_base);
-          } else {
-            unimplemented!();
-          }
-        }
+  fn load_material<A: GltfAssets>(material: &Material, asset_mgr: &A, gltf_file_name: &str, imported_assets: &mut HashSet<String>) -> Result<String, String> {
+    let material_name = material.index().map_or_else(|| "default".to_string(), |index| index.to_string());
+    let material_path = gltf_file_name.to_string() + "/material/" + &material_name;
+    if imported_assets.contains(&material_path) {
+      return Ok(material_path);
+    }
 
-        if let Some(stride) = view.stride() {
-          assert!(stride > indices_accessor.size());
-          buffer_cursor.seek(SeekFrom::Start(start + stride as u64)).unwrap();
-        }
+    let pbr = material.pbr_metallic_roughness();
+    let mut asset_material = AssetMaterial::new("NULL");
+    if let Some(base_color) = pbr.base_color_texture() {
+      if let Some(path) = GltfLoader::load_texture(&base_color.texture(), true, asset_mgr, gltf_file_name, imported_assets)? {
+        asset_material.albedo_texture_path = path;
+        asset_material.albedo_tex_coord = base_color.tex_coord();
       }
-      assert!(buffer_cursor.seek(SeekFrom::Current(0)).unwrap() <= (view.offset() + view.length()) as u64);
     }
+    let base_color_factor = pbr.base_color_factor();
+    asset_material.base_color_factor = Vec4::new(base_color_factor[0], base_color_factor[1], base_color_factor[2], base_color_factor[3]);
+    asset_material.metallic_factor = pbr.metallic_factor();
+    asset_material.roughness_factor = pbr.roughness_factor();
+    asset_material.metallic_roughness_texture = match pbr.metallic_roughness_texture() {
+      Some(info) => GltfLoader::load_texture(&info.texture(), false, asset_mgr, gltf_file_name, imported_assets)?
+        .map(|path| MaterialTexture { path, tex_coord: info.tex_coord() }),
+      None => None
+    };
+    asset_material.normal_texture = match material.normal_texture() {
+      Some(info) => GltfLoader::load_texture(&info.texture(), false, asset_mgr, gltf_file_name, imported_assets)?
+        .map(|path| MaterialTexture { path, tex_coord: info.tex_coord() }),
+      None => None
+    };
+    asset_material.occlusion_texture = match material.occlusion_texture() {
+      Some(info) => GltfLoader::load_texture(&info.texture(), false, asset_mgr, gltf_file_name, imported_assets)?
+        .map(|path| MaterialTexture { path, tex_coord: info.tex_coord() }),
+      None => None
+    };
+    asset_material.emissive_texture = match material.emissive_texture() {
+      Some(info) => GltfLoader::load_texture(&info.texture(), true, asset_mgr, gltf_file_name, imported_assets)?
+        .map(|path| MaterialTexture { path, tex_coord: info.tex_coord() }),
+      None => None
+    };
+    let emissive_factor = material.emissive_factor();
+    asset_material.emissive_factor = Vec3::new(emissive_factor[0], emissive_factor[1], emissive_factor[2]);
+
+    asset_mgr.add_asset(&material_path, Asset::Material(asset_material));
+    imported_assets.insert(material_path.clone());
+    Ok(material_path)
   }
 
-  fn load_material<P: Platform>(_material: &Material, _asset_mgr: &AssetManager<P>, _gltf_file_name: &str) {
-    /*let albedo = material.pbr_metallic_roughness().base_color_texture().unwrap();
-    let albedo_source = albedo.texture().source().source();
-    match albedo_source {
-      gltf::image::Source::View { view, .. } => {
+  /// Color textures get sampled as sRGB, data textures like normal maps as linear.
+  /// Returns None if the image is missing, the material then falls back to its default texture.
+  fn load_texture<A: GltfAssets>(texture: &gltf::Texture, srgb: bool, asset_mgr: &A, gltf_file_name: &str, imported_assets: &mut HashSet<String>) -> Result<Option<String>, String> {
+    let gltf_image = texture.source();
+    let texture_path = format!("{}/texture/{}{}", gltf_file_name, gltf_image.index(), if srgb { "_srgb" } else { "" });
+    if imported_assets.contains(&texture_path) {
+      return Ok(Some(texture_path));
+    }
 
+    // The container already decoded the image, no matter if it's embedded, external or a data URI
+    let url = format!("{}/image/{}", gltf_file_name, gltf_image.index());
+    let image_file = asset_mgr.load_file(&url);
+    if image_file.is_none() {
+      println!("Failed to load image: {}", url);
+      return Ok(None);
+    }
+    let mut image_file = image_file.unwrap();
+    let mut size_data = [0u8; 8];
+    image_file.read_exact(&mut size_data).map_err(|e| format!("Failed to read image {}: {}", url, e))?;
+    let width = u32::from_le_bytes([size_data[0], size_data[1], size_data[2], size_data[3]]);
+    let height = u32::from_le_bytes([size_data[4], size_data[5], size_data[6], size_data[7]]);
+    let pixels_size = (width as usize).checked_mul(height as usize).and_then(|pixels| pixels.checked_mul(4))
+      .ok_or_else(|| format!("Image {} is too large: {}x{}", url, width, height))?;
+    let mut pixels = vec![0u8; pixels_size];
+    image_file.read_exact(&mut pixels).map_err(|e| format!("Failed to read image {}: {}", url, e))?;
+
+    asset_mgr.add_asset(&texture_path, Asset::Texture(Texture {
+      info: TextureInfo {
+        dimension: TextureDimension::Dim2D,
+        format: if srgb { Format::SRGBA8 } else { Format::RGBA8 },
+        width,
+        height,
+        depth: 1,
+        mip_levels: 1,
+        array_length: 1,
+        samples: SampleCount::Samples1,
+        usage: TextureUsage::FRAGMENT_SHADER_SAMPLED | TextureUsage::VERTEX_SHADER_SAMPLED | TextureUsage::BLIT_DST,
+        is_cubemap: false
       },
-      gltf::image::Source::Uri { .. } => unimplemented!(),
-    }*/
-    unimplemented!()
+      data: Box::new([pixels.into_boxed_slice()]),
+      frame_count: 1
+    }));
+    imported_assets.insert(texture_path.clone());
+    Ok(Some(texture_path))
+  }
+}
+
+/// "file/scene" loads the default scene, "file/scene/name" a specific one either by name or index.
+/// Returns the name of the glTF file along with the scene.
+fn find_scene<'a>(gltf: &'a Gltf, path: &'a str) -> Option<(&'a str, Scene<'a>)> {
+  let default_scene_suffix = "/scene";
+  let scene_prefix = "/scene/";
+  if path.ends_with(default_scene_suffix) {
+    let gltf_name = &path[0 .. path.len() - default_scene_suffix.len()];
+    gltf.default_scene()
+      .or_else(|| gltf.scenes().next())
+      .map(|scene| (gltf_name, scene))
+  } else if let Some(scene_name_start) = path.find(scene_prefix) {
+    let gltf_name = &path[0..scene_name_start];
+    let scene_name = &path[scene_name_start + scene_prefix.len() ..];
+    gltf.scenes()
+      .find(|scene| scene.name().map_or_else(|| scene.index().to_string(), |name| name.to_string()) == scene_name)
+      .map(|scene| (gltf_name, scene))
+  } else {
+    None
   }
 }
 
+fn read_f32(data: &[u8], offset: usize) -> f32 {
+  f32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
 impl<P: Platform> AssetLoader<P> for GltfLoader {
   fn matches(&self, file: &mut AssetFile<P>) -> bool {
     Gltf::from_reader(file).is_ok()
   }
 
-  fn load(&self, file: AssetFile<P>, manager: &Arc<AssetManager<P>>, _priority: AssetLoadPriority, _progress: &Arc<AssetLoaderProgress>) -> Result<AssetLoaderResult, ()> {
+  fn load(&self, file: AssetFile<P>, manager: &Arc<AssetManager<P>>, _priority: AssetLoadPriority, _progress: &Arc<AssetLoaderProgress>) -> Result<AssetLoaderResult, AssetError> {
     let path = file.path.clone();
-    let gltf = Gltf::from_reader(file).unwrap();
-
-    let scene_prefix = "/scene/";
-    let scene_name_start = path.find(scene_prefix);
-    if let Some(scene_name_start) = scene_name_start {
-      let gltf_name = &path[0..scene_name_start];
-      let scene_name = &path[scene_name_start + scene_prefix.len() ..];
-      for scene in gltf.scenes() {
-        if scene.name().is_some() && scene.name().unwrap() == scene_name {
-          let world = GltfLoader::load_scene(&scene, manager, gltf_name);
-          return Ok(AssetLoaderResult {
-            level: Some(world),
-          });
-        }
-      }
-    }
+    let gltf = Gltf::from_reader(file).map_err(|e| AssetError::new(&path, e))?;
 
+    if let Some((gltf_name, scene)) = find_scene(&gltf, &path) {
+      let world = GltfLoader::load_scene(&gltf, &scene, manager.as_ref(), gltf_name).map_err(|reason| AssetError::new(&path, reason))?;
+      return Ok(AssetLoaderResult {
+        level: Some(world),
+      });
+    }
 
-    unimplemented!()
+    Err(AssetError::new(&path, "Could not find scene"))
   }
 }
 
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::io::Cursor;
+  use std::path::Path;
+  use std::sync::Mutex;
+  use legion::IntoQuery;
+  use sourcerenderer_core::Quaternion;
+  use crate::animation::AnimationValue;
+  use crate::asset::loaders::GltfContainer;
+
+  struct TestAssets {
+    container: GltfContainer,
+    assets: Mutex<HashMap<String, Asset>>
+  }
+
+  impl GltfAssets for TestAssets {
+    type File = Cursor<Box<[u8]>>;
+
+    fn load_file(&self, path: &str) -> Option<Self::File> {
+      self.container.open(path)
+    }
+
+    fn add_asset(&self, path: &str, asset: Asset) {
+      self.assets.lock().unwrap().insert(path.to_string(), asset);
+    }
+  }
+
+  fn f32_bytes(values: &[f32]) -> Vec<u8> {
+    values.iter().flat_map(|value| value.to_le_bytes()).collect()
+  }
+
+  fn i16_bytes(values: &[i16]) -> Vec<u8> {
+    values.iter().flat_map(|value| value.to_le_bytes()).collect()
+  }
+
+  /// Packs the views into a single buffer and returns it along with the JSON of the buffer views.
+  fn pack_views(views: &[Vec<u8>]) -> (Vec<u8>, String) {
+    let mut bin = Vec::<u8>::new();
+    let mut views_json = Vec::<String>::new();
+    for view in views {
+      views_json.push(format!("{{\"buffer\":0,\"byteOffset\":{},\"byteLength\":{}}}", bin.len(), view.len()));
+      bin.extend_from_slice(view);
+      bin.resize((bin.len() + 3) & !3, 0u8);
+    }
+    (bin, views_json.join(","))
+  }
+
+  fn test_dir() -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join("sourcerenderer_gltf_tests");
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+  }
+
+  /// `json` holds all top level properties except the buffers and buffer views.
+  fn document_json(buffer_json: &str, views_json: &str, json: &str) -> String {
+    format!("{{\"asset\":{{\"version\":\"2.0\"}},\"buffers\":[{}],\"bufferViews\":[{}],{}}}", buffer_json, views_json, json)
+  }
+
+  /// Writes a .gltf file with an external .bin buffer.
+  fn write_gltf(name: &str, json: &str, views: &[Vec<u8>]) -> String {
+    let dir = test_dir();
+    let (bin, views_json) = pack_views(views);
+    std::fs::write(dir.join(name.to_string() + ".bin"), &bin).unwrap();
+    let buffer_json = format!("{{\"byteLength\":{},\"uri\":\"{}.bin\"}}", bin.len(), name);
+    let path = dir.join(name.to_string() + ".gltf");
+    std::fs::write(&path, document_json(&buffer_json, &views_json, json)).unwrap();
+    path.to_str().unwrap().to_string()
+  }
+
+  /// Writes a .glb file that embeds the buffer as its BIN chunk.
+  fn write_glb(name: &str, json: &str, views: &[Vec<u8>]) -> String {
+    let (bin, views_json) = pack_views(views);
+    let buffer_json = format!("{{\"byteLength\":{}}}", bin.len());
+    let mut json_chunk = document_json(&buffer_json, &views_json, json).into_bytes();
+    json_chunk.resize((json_chunk.len() + 3) & !3, b' ');
+
+    let mut glb = Vec::<u8>::new();
+    glb.extend_from_slice(b"glTF");
+    glb.extend_from_slice(&2u32.to_le_bytes());
+    glb.extend_from_slice(&((12 + 8 + json_chunk.len() + 8 + bin.len()) as u32).to_le_bytes());
+    for (chunk_type, chunk) in [(b"JSON", &json_chunk), (b"BIN\0", &bin)] {
+      glb.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+      glb.extend_from_slice(chunk_type);
+      glb.extend_from_slice(chunk);
+    }
+    let path = test_dir().join(name.to_string() + ".glb");
+    std::fs::write(&path, glb).unwrap();
+    path.to_str().unwrap().to_string()
+  }
+
+  /// A 1x1 RGBA image with the pixel 0x102030FF.
+  const PNG: [u8; 70] = [
+    0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44, 0x52, 0x00, 0x00, 0x00, 0x01,
+    0x00, 0x00, 0x00, 0x01, 0x08, 0x06, 0x00, 0x00, 0x00, 0x1f, 0x15, 0xc4, 0x89, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x44, 0x41,
+    0x54, 0x78, 0x9c, 0x63, 0x10, 0x50, 0x30, 0xf8, 0x0f, 0x00, 0x02, 0x04, 0x01, 0x60, 0x8d, 0xbc, 0xbb, 0x71, 0x00, 0x00,
+    0x00, 0x00, 0x49, 0x45, 0x4e, 0x44, 0xae, 0x42, 0x60, 0x82
+  ];
+
+  /// Positions, normals and indices of a triangle, used by the first three accessors.
+  const TRIANGLE_ACCESSORS: &str = r#"
+    {"bufferView":0,"componentType":5126,"count":3,"type":"VEC3","min":[0,0,0],"max":[1,1,0]},
+    {"bufferView":1,"componentType":5126,"count":3,"type":"VEC3"},
+    {"bufferView":2,"componentType":5123,"count":3,"type":"SCALAR"}"#;
+
+  fn triangle_views() -> Vec<Vec<u8>> {
+    vec![
+      f32_bytes(&[0f32, 0f32, 0f32, 1f32, 0f32, 0f32, 0f32, 1f32, 0f32]),
+      f32_bytes(&[0f32, 0f32, 1f32, 0f32, 0f32, 1f32, 0f32, 0f32, 1f32]),
+      [0u16, 1u16, 2u16].iter().flat_map(|index| index.to_le_bytes()).collect()
+    ]
+  }
+
+  /// Imports the default scene of the file.
+  fn import(path: &str) -> (World, TestAssets) {
+    let assets = TestAssets {
+      container: GltfContainer::load(path).unwrap(),
+      assets: Mutex::new(HashMap::new())
+    };
+    let scene_path = Path::new(path).file_name().unwrap().to_str().unwrap().to_string() + "/scene";
+    let gltf = Gltf::from_reader(assets.load_file(&scene_path).unwrap()).unwrap();
+    let (gltf_name, scene) = find_scene(&gltf, &scene_path).unwrap();
+    let world = GltfLoader::load_scene(&gltf, &scene, &assets, gltf_name).unwrap();
+    (world, assets)
+  }
+
+  #[test]
+  fn rotation_animation_interpolates_between_keyframes() {
+    // The rotations are normalized shorts: identity and 90 degrees around Y
+    let views = vec![
+      f32_bytes(&[0f32, 1f32]),
+      i16_bytes(&[0, 0, 0, 32767, 0, 23170, 0, 23170])
+    ];
+    let path = write_gltf("animation", r#"
+      "accessors":[
+        {"bufferView":0,"componentType":5126,"count":2,"type":"SCALAR","min":[0],"max":[1]},
+        {"bufferView":1,"componentType":5122,"normalized":true,"count":2,"type":"VEC4"}
+      ],
+      "nodes":[{"name":"spinner"}],
+      "scenes":[{"nodes":[0]}],
+      "scene":0,
+      "animations":[{
+        "name":"spin",
+        "channels":[{"sampler":0,"target":{"node":0,"path":"rotation"}}],
+        "samplers":[{"input":0,"output":1,"interpolation":"LINEAR"}]
+      }]"#, &views);
+    let (world, _assets) = import(&path);
+
+    let mut query = <&AnimationPlayerComponent>::query();
+    let player = query.iter(&world).next().unwrap();
+    let clip = &player.clips[0];
+    assert_eq!(clip.name, "spin");
+    assert_eq!(clip.duration, 1f32);
+    assert_eq!(clip.tracks.len(), 1);
+    let track = &clip.tracks[0];
+    assert_eq!(track.interpolation, Interpolation::Linear);
+    assert!(world.entry_ref(track.target).unwrap().get_component::<Transform>().is_ok());
+
+    let sample_rotation = |time: f32| match track.sample(time) {
+      Some(AnimationValue::Rotation(rotation)) => rotation,
+      value => panic!("Expected a rotation, got {:?}", value)
+    };
+    assert!(sample_rotation(0f32).angle() < 1e-3f32);
+    let expected = Quaternion::from_axis_angle(&Vec3::y_axis(), std::f32::consts::FRAC_PI_4);
+    assert!(sample_rotation(0.5f32).angle_to(&expected) < 1e-3f32);
+  }
+
+  #[test]
+  fn metallic_roughness_material() {
+    std::fs::write(test_dir().join("material.png"), &PNG[..]).unwrap();
+    let path = write_gltf("material", &format!(r#"
+      "accessors":[{}],
+      "meshes":[{{"primitives":[{{"attributes":{{"POSITION":0,"NORMAL":1}},"indices":2,"material":0}}]}}],
+      "materials":[{{
+        "pbrMetallicRoughness":{{
+          "baseColorFactor":[0.5,0.25,1.0,1.0],
+          "baseColorTexture":{{"index":0}},
+          "metallicFactor":0.25,
+          "roughnessFactor":0.75,
+          "metallicRoughnessTexture":{{"index":0,"texCoord":1}}
+        }}
+      }}],
+      "images":[{{"uri":"material.png"}}],
+      "textures":[{{"source":0}}],
+      "nodes":[{{"mesh":0,"name":"triangle"}}],
+      "scenes":[{{"nodes":[0]}}]"#, TRIANGLE_ACCESSORS), &triangle_views());
+    let (_world, assets) = import(&path);
+    let assets = assets.assets.lock().unwrap();
+
+    let material = match assets.get("material.gltf/material/0") {
+      Some(Asset::Material(material)) => material,
+      _ => panic!("Material was not imported")
+    };
+    assert_eq!(material.base_color_factor, Vec4::new(0.5f32, 0.25f32, 1f32, 1f32));
+    assert_eq!(material.metallic_factor, 0.25f32);
+    assert_eq!(material.roughness_factor, 0.75f32);
+    assert_eq!(material.albedo_texture_path, "material.gltf/texture/0_srgb");
+    let metallic_roughness = material.metallic_roughness_texture.as_ref().unwrap();
+    assert_eq!(metallic_roughness.path, "material.gltf/texture/0");
+    assert_eq!(metallic_roughness.tex_coord, 1);
+
+    // The same image gets sampled as sRGB for the base color and as linear for the metallic and roughness values
+    let texture_format = |path: &str| match assets.get(path) {
+      Some(Asset::Texture(texture)) => {
+        assert_eq!(&texture.data[0][..], &[0x10u8, 0x20u8, 0x30u8, 0xFFu8][..]);
+        texture.info.format
+      },
+      _ => panic!("Texture {} was not imported", path)
+    };
+    assert_eq!(texture_format("material.gltf/texture/0_srgb"), Format::SRGBA8);
+    assert_eq!(texture_format("material.gltf/texture/0"), Format::RGBA8);
+  }
+
+  #[test]
+  fn two_bone_skin() {
+    let mut views = triangle_views();
+    views.push(vec![0u8, 1u8, 0u8, 0u8, 0u8, 1u8, 0u8, 0u8, 1u8, 0u8, 0u8, 0u8]);
+    views.push(f32_bytes(&[0.6f32, 0.2f32, 0f32, 0f32, 0.5f32, 0.5f32, 0f32, 0f32, 1f32, 0f32, 0f32, 0f32]));
+    let tip_inverse_bind_matrix = Matrix4::new_translation(&Vec3::new(0f32, -1f32, 0f32));
+    let mut inverse_bind_matrices = Vec::<f32>::new();
+    inverse_bind_matrices.extend_from_slice(Matrix4::identity().as_slice());
+    inverse_bind_matrices.extend_from_slice(tip_inverse_bind_matrix.as_slice());
+    views.push(f32_bytes(&inverse_bind_matrices));
+    let path = write_gltf("skin", &format!(r#"
+      "accessors":[{},
+        {{"bufferView":3,"componentType":5121,"count":3,"type":"VEC4"}},
+        {{"bufferView":4,"componentType":5126,"count":3,"type":"VEC4"}},
+        {{"bufferView":5,"componentType":5126,"count":2,"type":"MAT4"}}
+      ],
+      "meshes":[{{"primitives":[{{"attributes":{{"POSITION":0,"NORMAL":1,"JOINTS_0":3,"WEIGHTS_0":4}},"indices":2}}]}}],
+      "skins":[{{"joints":[1,2],"inverseBindMatrices":5,"skeleton":1}}],
+      "nodes":[
+        {{"mesh":0,"skin":0,"name":"body"}},
+        {{"name":"root","children":[2]}},
+        {{"name":"tip","translation":[0,1,0]}}
+      ],
+      "scenes":[{{"nodes":[0,1]}}]"#, TRIANGLE_ACCESSORS), &views);
+    let (world, assets) = import(&path);
+
+    let mut query = <&SkinComponent>::query();
+    let skin = query.iter(&world).next().unwrap();
+    assert_eq!(skin.joints.len(), 2);
+    assert_eq!(skin.skeleton, Some(skin.joints[0]));
+    assert_eq!(world.entry_ref(skin.joints[1]).unwrap().get_component::<Parent>().unwrap().0, skin.joints[0]);
+    assert_eq!(skin.inverse_bind_matrices, vec![Matrix4::identity(), tip_inverse_bind_matrix]);
+
+    let assets = assets.assets.lock().unwrap();
+    let skinning: Vec<SkinningVertex> = match assets.get("skin.gltf/mesh/body") {
+      Some(Asset::Mesh(mesh)) => mesh.skinning.as_ref().unwrap()
+        .chunks_exact(std::mem::size_of::<SkinningVertex>())
+        .map(|data| unsafe { std::ptr::read_unaligned(data.as_ptr() as *const SkinningVertex) })
+        .collect(),
+      _ => panic!("Mesh was not imported")
+    };
+    assert_eq!(skinning.len(), 3);
+    assert_eq!(skinning[0].joints, [0, 1, 0, 0]);
+    assert!((skinning[0].weights[0] - 0.75f32).abs() < 1e-5f32);
+    assert!((skinning[0].weights[1] - 0.25f32).abs() < 1e-5f32);
+    assert_eq!(skinning[2].joints[0], 1);
+    for vertex in &skinning {
+      assert!((vertex.weights.iter().sum::<f32>() - 1f32).abs() < 1e-5f32);
+    }
+  }
+
+  #[test]
+  fn child_node_keeps_parent_and_local_transform() {
+    let path = write_gltf("hierarchy", &format!(r#"
+      "accessors":[{}],
+      "meshes":[{{"primitives":[{{"attributes":{{"POSITION":0,"NORMAL":1}},"indices":2}}]}}],
+      "nodes":[
+        {{"name":"car","translation":[0,2,0],"children":[1]}},
+        {{"name":"wheel","mesh":0,"translation":[1,0,0],"scale":[2,2,2]}}
+      ],
+      "scenes":[{{"nodes":[0]}}]"#, TRIANGLE_ACCESSORS), &triangle_views());
+    let (world, _assets) = import(&path);
+
+    let mut query = <(&Transform, &Parent, &StaticRenderableComponent)>::query();
+    let (transform, parent, renderable) = query.iter(&world).next().unwrap();
+    assert_eq!(renderable.model_path, "hierarchy.gltf/model/wheel");
+    assert_eq!(transform.position, Vec3::new(1f32, 0f32, 0f32));
+    assert_eq!(transform.scale, Vec3::new(2f32, 2f32, 2f32));
+
+    let parent_entry = world.entry_ref(parent.0).unwrap();
+    assert_eq!(parent_entry.get_component::<Transform>().unwrap().position, Vec3::new(0f32, 2f32, 0f32));
+    assert!(parent_entry.get_component::<Parent>().is_err());
+  }
+
+  #[test]
+  fn finds_scenes_by_name() {
+    let gltf = Gltf::from_slice(br#"{
+      "asset":{"version":"2.0"},
+      "nodes":[{"name":"a"},{"name":"b"}],
+      "scenes":[{"name":"main","nodes":[0]},{"name":"alternative","nodes":[1]}],
+      "scene":0
+    }"#).unwrap();
+
+    let (gltf_name, scene) = find_scene(&gltf, "model.gltf/scene").unwrap();
+    assert_eq!(gltf_name, "model.gltf");
+    assert_eq!(scene.index(), 0);
+    let (gltf_name, scene) = find_scene(&gltf, "model.gltf/scene/alternative").unwrap();
+    assert_eq!(gltf_name, "model.gltf");
+    assert_eq!(scene.index(), 1);
+    assert!(find_scene(&gltf, "model.gltf/scene/missing").is_none());
+    assert!(find_scene(&gltf, "model.gltf/mesh/a").is_none());
+  }
+
+  #[test]
+  fn gltf_and_glb_import_the_same_mesh() {
+    let json = format!(r#"
+      "accessors":[{}],
+      "meshes":[{{"primitives":[{{"attributes":{{"POSITION":0,"NORMAL":1}},"indices":2}}]}}],
+      "nodes":[{{"mesh":0,"name":"triangle"}}],
+      "scenes":[{{"nodes":[0]}}]"#, TRIANGLE_ACCESSORS);
+    let (_world, gltf_assets) = import(&write_gltf("binary", &json, &triangle_views()));
+    let (_world, glb_assets) = import(&write_glb("binary", &json, &triangle_views()));
+    let gltf_assets = gltf_assets.assets.lock().unwrap();
+    let glb_assets = glb_assets.assets.lock().unwrap();
+
+    let (gltf_mesh, glb_mesh) = match (gltf_assets.get("binary.gltf/mesh/triangle"), glb_assets.get("binary.glb/mesh/triangle")) {
+      (Some(Asset::Mesh(gltf_mesh)), Some(Asset::Mesh(glb_mesh))) => (gltf_mesh, glb_mesh),
+      _ => panic!("Meshes were not imported")
+    };
+    assert_eq!(gltf_mesh.vertices.len(), 3 * std::mem::size_of::<Vertex>());
+    assert_eq!(gltf_mesh.vertices, glb_mesh.vertices);
+    assert_eq!(gltf_mesh.indices, glb_mesh.indices);
+  }
+}
@@ -1,17 +1,23 @@
-use crate::{asset::{AssetLoader, AssetLoaderProgress, AssetManager, AssetLoadPriority, Asset}, math::BoundingBox};
+use crate::{asset::{AssetLoader, AssetLoaderProgress, AssetManager, AssetLoadPriority, Asset, AssetError}, math::BoundingBox};
 use std::sync::Arc;
 use std::io::{Seek, SeekFrom, Read, Result as IOResult};
 use crate::asset::asset_manager::{AssetFile, AssetLoaderResult, MeshRange};
-use sourcerenderer_mdl::{BodyPart, Header, Mesh, Model, PrimitiveRead, StringRead};
-use sourcerenderer_vtx::{BodyPartHeader, ModelHeader, ModelLODHeader, MeshHeader, Header as VTXHeader, StripGroupHeader, StripHeader, Vertex as VTXVertex};
-use sourcerenderer_vvd::{Header as VVDHeader, Vertex, VertexFileFixup};
+use sourcerenderer_mdl::{BodyPart, Bone, Header, Mesh, Model, PrimitiveRead, SkinReplacementTable, StringRead, MAX_NAME_LENGTH};
+use sourcerenderer_vtx::{BodyPartHeader, ModelHeader, ModelLODHeader, MeshHeader, Header as VTXHeader, StripGroupHeader, StripHeader, StripFlags, Vertex as VTXVertex};
+use sourcerenderer_vvd::{BoneWeight, Header as VVDHeader, Vertex, VertexFileFixup};
 use crate::asset::loaders::bsp::Vertex as BspVertex;
-use nalgebra::{Vector2, Vector3};
-use crate::asset::{Mesh as AssetMesh, Model as AssetModel, AssetType};
+use nalgebra::{Matrix3x4, Vector2, Vector3};
+use sourcerenderer_core::{Matrix4, Quaternion};
+use crate::asset::{Mesh as AssetMesh, Model as AssetModel, ModelLod, AssetType, Skeleton, SkeletonBone, SkinningVertex};
 use sourcerenderer_core::platform::Platform;
 use std::slice;
+use std::cmp::min;
 
 const SCALING_FACTOR: f32 = 0.0236f32;
+/// Size of mstudiovertex_t, the vertex index of MDL models is a byte offset into an array of those
+const MDL_VERTEX_SIZE: i32 = 48;
+const VTX_MODEL_LOD_HEADER_SIZE: u64 = 12;
+const LOD_REFERENCE_SCREEN_HEIGHT: f32 = 1080f32;
 
 pub struct MDLModelLoader {}
 
@@ -20,48 +26,72 @@ impl<P: Platform> AssetLoader<P> for MDLModelLoader {
     file.path.starts_with("models/") && file.path.ends_with(".mdl")
   }
 
-  fn load(&self, mut file: AssetFile<P>, manager: &Arc<AssetManager<P>>, _priority: AssetLoadPriority, progress: &Arc<AssetLoaderProgress>) -> Result<AssetLoaderResult, ()> {
+  fn load(&self, mut file: AssetFile<P>, manager: &Arc<AssetManager<P>>, _priority: AssetLoadPriority, progress: &Arc<AssetLoaderProgress>) -> Result<AssetLoaderResult, AssetError> {
+    let path = file.path.clone();
     if file.path.contains("autocombine") {
       print!("Model: {} is auto combined", &file.path);
     }
 
-    let mut models = Vec::<Vec<Vec<Mesh>>>::new();
-    let file_start = file.seek(SeekFrom::Current(0)).map_err(|_e| ())?;
-    let header = Header::read(&mut file).map_err(|_e| ())?;
-    file.seek(SeekFrom::Start(file_start + header.body_part_offset as u64)).map_err(|_e| ())?;
+    // The first vertex of every model and its meshes, grouped by body part
+    let mut models = Vec::<Vec<(i32, Vec<Mesh>)>>::new();
+    let file_start = file.seek(SeekFrom::Current(0)).map_err(|e| AssetError::new(&path, e))?;
+    let header = Header::read(&mut file).map_err(|e| AssetError::new(&path, e))?;
+    file.seek(SeekFrom::Start(file_start + header.body_part_offset as u64)).map_err(|e| AssetError::new(&path, e))?;
     for _ in 0..header.body_part_count {
-      let body_part_start = file.seek(SeekFrom::Current(0)).map_err(|_e| ())?;
-      let body_part = BodyPart::read(&mut file).map_err(|_e| ())?;
-      let body_part_next = file.seek(SeekFrom::Current(0)).map_err(|_e| ())?;
-      file.seek(SeekFrom::Start(body_part_start + body_part.model_index as u64)).map_err(|_e| ())?;
-      let mut body_part_models = Vec::<Vec<Mesh>>::with_capacity(body_part.models_count as usize);
+      let body_part_start = file.seek(SeekFrom::Current(0)).map_err(|e| AssetError::new(&path, e))?;
+      let body_part = BodyPart::read(&mut file).map_err(|e| AssetError::new(&path, e))?;
+      let body_part_next = file.seek(SeekFrom::Current(0)).map_err(|e| AssetError::new(&path, e))?;
+      file.seek(SeekFrom::Start(body_part_start + body_part.model_index as u64)).map_err(|e| AssetError::new(&path, e))?;
+      let mut body_part_models = Vec::<(i32, Vec<Mesh>)>::with_capacity(body_part.models_count as usize);
       for _ in 0..body_part.models_count {
-        let model_start = file.seek(SeekFrom::Current(0)).map_err(|_e| ())?;
-        let model = Model::read(&mut file).map_err(|_e| ())?;
-        let model_next = file.seek(SeekFrom::Current(0)).map_err(|_e| ())?;
-        file.seek(SeekFrom::Start(model_start + model.mesh_index as u64)).map_err(|_e| ())?;
+        let model_start = file.seek(SeekFrom::Current(0)).map_err(|e| AssetError::new(&path, e))?;
+        let model = Model::read(&mut file).map_err(|e| AssetError::new(&path, e))?;
+        let model_next = file.seek(SeekFrom::Current(0)).map_err(|e| AssetError::new(&path, e))?;
+        file.seek(SeekFrom::Start(model_start + model.mesh_index as u64)).map_err(|e| AssetError::new(&path, e))?;
         let mut model_meshes = Vec::<Mesh>::with_capacity(model.meshes_count as usize);
         for _ in 0..model.meshes_count {
-          let mesh = Mesh::read(&mut file).map_err(|_e| ())?;
-          let mesh_next = file.seek(SeekFrom::Current(0)).map_err(|_e| ())?;
+          let mesh = Mesh::read(&mut file).map_err(|e| AssetError::new(&path, e))?;
+          let mesh_next = file.seek(SeekFrom::Current(0)).map_err(|e| AssetError::new(&path, e))?;
           model_meshes.push(mesh);
-          file.seek(SeekFrom::Start(mesh_next)).map_err(|_e| ())?;
+          file.seek(SeekFrom::Start(mesh_next)).map_err(|e| AssetError::new(&path, e))?;
         }
-        body_part_models.push(model_meshes);
-        file.seek(SeekFrom::Start(model_next)).map_err(|_e| ())?;
+        body_part_models.push((model.vertex_index / MDL_VERTEX_SIZE, model_meshes));
+        file.seek(SeekFrom::Start(model_next)).map_err(|e| AssetError::new(&path, e))?;
       }
       models.push(body_part_models);
-      file.seek(SeekFrom::Start(body_part_next)).map_err(|_e| ())?;
+      file.seek(SeekFrom::Start(body_part_next)).map_err(|e| AssetError::new(&path, e))?;
     }
 
+    let mut bones = Vec::<SkeletonBone>::with_capacity(header.bone_count as usize);
+    file.seek(SeekFrom::Start(file_start + header.bone_offset as u64)).map_err(|e| AssetError::new(&path, e))?;
+    for _ in 0..header.bone_count {
+      let bone_start = file.seek(SeekFrom::Current(0)).map_err(|e| AssetError::new(&path, e))?;
+      let bone = Bone::read(&mut file).map_err(|e| AssetError::new(&path, e))?;
+      let bone_next = file.seek(SeekFrom::Current(0)).map_err(|e| AssetError::new(&path, e))?;
+
+      file.seek(SeekFrom::Start(bone_start + bone.name_index as u64)).map_err(|e| AssetError::new(&path, e))?;
+      let name = file.read_null_terminated_string_max(MAX_NAME_LENGTH).map_err(|e| AssetError::new(&path, format!("{:?}", e)))?;
+      let parent = if bone.parent >= 0 && (bone.parent as usize) < bones.len() { Some(bone.parent as usize) } else { None };
+      bones.push(SkeletonBone {
+        name,
+        parent,
+        position: fixup_position(&bone.position),
+        rotation: fixup_rotation(&bone.quaternion),
+        inverse_bind_matrix: fixup_bone_matrix(&bone.pose_to_bone)
+      });
+      file.seek(SeekFrom::Start(bone_next)).map_err(|e| AssetError::new(&path, e))?;
+    }
+    // Rigid props only have a single bone, there is nothing to deform for those
+    let is_skinned = bones.len() > 1;
+
     let mut texture_dirs = Vec::<String>::with_capacity(header.texture_dir_count as usize);
-    file.seek(SeekFrom::Start(file_start + header.texture_dir_offset as u64)).map_err(|_e| ())?;
+    file.seek(SeekFrom::Start(file_start + header.texture_dir_offset as u64)).map_err(|e| AssetError::new(&path, e))?;
     for _ in 0..header.texture_dir_count {
-      let offset = file.read_i32().map_err(|_e| ())?;
-      let texture_dir_next = file.seek(SeekFrom::Current(0)).map_err(|_e| ())?;
+      let offset = file.read_i32().map_err(|e| AssetError::new(&path, e))?;
+      let texture_dir_next = file.seek(SeekFrom::Current(0)).map_err(|e| AssetError::new(&path, e))?;
 
-      file.seek(SeekFrom::Start(offset as u64)).map_err(|_e| ())?;
-      let mut dir = file.read_null_terminated_string().map_err(|_e| ())?
+      file.seek(SeekFrom::Start(file_start + offset as u64)).map_err(|e| AssetError::new(&path, e))?;
+      let mut dir = file.read_null_terminated_string_max(MAX_NAME_LENGTH).map_err(|e| AssetError::new(&path, format!("{:?}", e)))?
         .replace('\\', "/")
         .trim_start_matches('/')
         .to_lowercase();
@@ -69,23 +99,23 @@ impl<P: Platform> AssetLoader<P> for MDLModelLoader {
         dir += "/";
       }
       texture_dirs.push(dir);
-      file.seek(SeekFrom::Start(texture_dir_next)).map_err(|_e| ())?;
+      file.seek(SeekFrom::Start(texture_dir_next)).map_err(|e| AssetError::new(&path, e))?;
     }
 
     let mut textures = Vec::<String>::with_capacity(header.texture_count as usize);
-    file.seek(SeekFrom::Start(file_start + header.texture_offset as u64)).map_err(|_e| ())?;
+    file.seek(SeekFrom::Start(file_start + header.texture_offset as u64)).map_err(|e| AssetError::new(&path, e))?;
     for _ in 0..header.texture_count {
-      let texture_start = file.seek(SeekFrom::Current(0)).map_err(|_e| ())?;
-      let texture = sourcerenderer_mdl::Texture::read(&mut file).map_err(|_e| ())?;
-      let texture_next = file.seek(SeekFrom::Current(0)).map_err(|_e| ())?;
+      let texture_start = file.seek(SeekFrom::Current(0)).map_err(|e| AssetError::new(&path, e))?;
+      let texture = sourcerenderer_mdl::Texture::read(&mut file).map_err(|e| AssetError::new(&path, e))?;
+      let texture_next = file.seek(SeekFrom::Current(0)).map_err(|e| AssetError::new(&path, e))?;
 
-      file.seek(SeekFrom::Start(texture_start + texture.name_offset as u64)).map_err(|_e| ())?;
+      file.seek(SeekFrom::Start(texture_start + texture.name_offset as u64)).map_err(|e| AssetError::new(&path, e))?;
       textures.push(
-        file.read_null_terminated_string().map_err(|_e| ())?
+        file.read_null_terminated_string_max(MAX_NAME_LENGTH).map_err(|e| AssetError::new(&path, format!("{:?}", e)))?
         .trim_matches('/')
         .to_lowercase()
       );
-      file.seek(SeekFrom::Start(texture_next)).map_err(|_e| ())?;
+      file.seek(SeekFrom::Start(texture_next)).map_err(|e| AssetError::new(&path, e))?;
     }
 
     let mut texture_paths = Vec::<String>::with_capacity(textures.len());
@@ -110,133 +140,254 @@ impl<P: Platform> AssetLoader<P> for MDLModelLoader {
       manager.request_asset(texture_paths.last().unwrap(), AssetType::Material, AssetLoadPriority::Low);
     }
 
-    let vvd_path = file.path.replace(".mdl", ".vvd");
-    let mut vvd_file = manager.load_file(&vvd_path).unwrap();
-    let vvd_vertices: Box<[Vertex]> = load_geometry(&mut vvd_file).map_err(|_e| ())?;
+    file.seek(SeekFrom::Start(file_start + header.skin_reference_index as u64)).map_err(|e| AssetError::new(&path, e))?;
+    let skins = SkinReplacementTable::read(&mut file, header.skin_reference_family_count, header.skin_reference_count).map_err(|e| AssetError::new(&path, e))?;
 
-    let mut vertices = Vec::<BspVertex>::with_capacity(vvd_vertices.len());
+    let vvd_path = file.path.replace(".mdl", ".vvd");
+    let mut vvd_file = manager.load_file(&vvd_path)
+      .ok_or_else(|| AssetError::new(&path, format!("Could not find vertex data: {:?}", vvd_path)))?;
+    let vvd_vertices: Box<[Vertex]> = load_geometry(&mut vvd_file).map_err(|e| AssetError::new(&path, e))?;
 
     let vtx_path = file.path.replace(".mdl", ".dx90.vtx");
-    let mut vtx_file = manager.load_file(&vtx_path).unwrap();
+    let mut vtx_file = manager.load_file(&vtx_path)
+      .ok_or_else(|| AssetError::new(&path, format!("Could not find mesh data: {:?}", vtx_path)))?;
+    let vtx_start = vtx_file.seek(SeekFrom::Current(0)).map_err(|e| AssetError::new(&path, e))?;
+    let vtx_header = VTXHeader::read(&mut vtx_file).map_err(|e| AssetError::new(&path, e))?;
 
-    let mut ranges = Vec::<MeshRange>::new();
-    let mut materials = Vec::<String>::new();
+    let bounding_box = BoundingBox::new(fixup_position(&header.hull_min), fixup_position(&header.hull_max));
+    let lod0 = Self::load_lod(&mut vtx_file, &path, vtx_start, &vtx_header, 0, &models, &vvd_vertices, is_skinned)?;
+    let mut skin_materials = skin_material_paths(&skins, &lod0.materials, &texture_paths)
+      .map_err(|texture_index| AssetError::new(&path, format!("Texture index {} is out of bounds", texture_index)))?;
+    add_mesh(manager, &vtx_path, lod0, &bounding_box);
+
+    let mut lods = Vec::<ModelLod>::new();
+    for lod_index in 1..vtx_header.lods_count as u32 {
+      let lod = Self::load_lod(&mut vtx_file, &path, vtx_start, &vtx_header, lod_index, &models, &vvd_vertices, is_skinned)?;
+      // Negative switch points mark LODs that are only used for shadows, zero would replace LOD0 right away
+      if lod.switch_point <= 0f32 {
+        continue;
+      }
+      let lod_path = format!("{}/lod{}", &vtx_path, lod_index);
+      let screen_size = switch_point_to_screen_size(lod.switch_point);
+      add_mesh(manager, &lod_path, lod, &bounding_box);
+      lods.push(ModelLod {
+        mesh_path: lod_path,
+        screen_size
+      });
+    }
+
+    if is_skinned {
+      manager.add_asset(&Self::skeleton_path(&file.path), Asset::Skeleton(Skeleton {
+        bones
+      }), AssetLoadPriority::Normal);
+    }
+
+    // The default skin goes last because it finishes the request
+    let default_materials = skin_materials.remove(0);
+    for (skin, materials) in skin_materials.into_iter().enumerate() {
+      manager.add_asset(&AssetModel::skin_path(&file.path, skin as u32 + 1), Asset::Model(AssetModel {
+        mesh_path: vtx_path.clone(),
+        material_paths: materials,
+        lods: lods.clone()
+      }), AssetLoadPriority::Normal);
+    }
+    manager.add_asset_with_progress(&file.path, Asset::Model(AssetModel {
+      mesh_path: vtx_path.clone(),
+      material_paths: default_materials,
+      lods
+    }), Some(progress), AssetLoadPriority::Normal);
+
+    Ok(AssetLoaderResult {
+      level: None
+    })
+  }
+}
+
+impl MDLModelLoader {
+  pub fn new() -> Self {
+    Self {}
+  }
+
+  /// The bones of skinned models get stored as a Skeleton next to the model.
+  pub fn skeleton_path(model_path: &str) -> String {
+    format!("{}/skeleton", model_path)
+  }
+
+  /// Builds the geometry of one LOD of all body parts, models that have fewer LODs fall back to their last one.
+  fn load_lod<P: Platform>(vtx_file: &mut AssetFile<P>, path: &str, vtx_start: u64, vtx_header: &VTXHeader, lod: u32, models: &[Vec<(i32, Vec<Mesh>)>], vvd_vertices: &[Vertex], is_skinned: bool) -> Result<MDLLod, AssetError> {
+    let mut vertices = Vec::<BspVertex>::new();
+    let mut skinning = Vec::<SkinningVertex>::new();
     let mut indices = Vec::<u32>::new();
+    let mut ranges = Vec::<MeshRange>::new();
+    let mut materials = Vec::<i32>::new();
+    let mut switch_point = 0f32;
     let mut strip_group_indices = Vec::<u32>::new();
-    let vtx_start = vtx_file.seek(SeekFrom::Current(0)).map_err(|_e| ())?;
-    let vtx_header = VTXHeader::read(&mut vtx_file).map_err(|_e| ())?;
-    vtx_file.seek(SeekFrom::Start(vtx_start + vtx_header.body_parts_offset as u64)).map_err(|_e| ())?;
+    vtx_file.seek(SeekFrom::Start(vtx_start + vtx_header.body_parts_offset as u64)).map_err(|e| AssetError::new(path, e))?;
     for body_part_index in 0..vtx_header.body_parts_count {
-      let body_part_start = vtx_file.seek(SeekFrom::Current(0)).map_err(|_e| ())?;
-      let body_part = BodyPartHeader::read(&mut vtx_file).map_err(|_e| ())?;
-      let body_part_next = vtx_file.seek(SeekFrom::Current(0)).map_err(|_e| ())?;
-      vtx_file.seek(SeekFrom::Start(body_part_start + body_part.model_offset as u64)).map_err(|_e| ())?;
+      let body_part_start = vtx_file.seek(SeekFrom::Current(0)).map_err(|e| AssetError::new(path, e))?;
+      let body_part = BodyPartHeader::read(vtx_file).map_err(|e| AssetError::new(path, e))?;
+      let body_part_next = vtx_file.seek(SeekFrom::Current(0)).map_err(|e| AssetError::new(path, e))?;
+      vtx_file.seek(SeekFrom::Start(body_part_start + body_part.model_offset as u64)).map_err(|e| AssetError::new(path, e))?;
       for model_index in 0..body_part.models_count {
-        let model_start = vtx_file.seek(SeekFrom::Current(0)).map_err(|_e| ())?;
-        let model = ModelHeader::read(&mut vtx_file).map_err(|_e| ())?;
-        let model_next = vtx_file.seek(SeekFrom::Start(0)).map_err(|_e| ())?;
-        vtx_file.seek(SeekFrom::Start(model_start + model.lod_offset as u64)).map_err(|_e| ())?;
-        for _model_lod_index in 0..model.lods_count {
-          let lod_start = vtx_file.seek(SeekFrom::Current(0)).map_err(|_e| ())?;
-          let model_lod = ModelLODHeader::read(&mut vtx_file).map_err(|_e| ())?;
-          let lod_next = vtx_file.seek(SeekFrom::Current(0)).map_err(|_e| ())?;
-          vtx_file.seek(SeekFrom::Start(lod_start + model_lod.mesh_offset as u64)).map_err(|_e| ())?;
-          for mesh_index in 0..model_lod.meshes_count {
-            let mdl_mesh = &models[body_part_index as usize][model_index as usize][mesh_index as usize];
-            let indices_start = indices.len();
-
-            let mesh_start = vtx_file.seek(SeekFrom::Current(0)).map_err(|_e| ())?;
-            let mesh = MeshHeader::read(&mut vtx_file).map_err(|_e| ())?;
-            let mesh_next = vtx_file.seek(SeekFrom::Current(0)).map_err(|_e| ())?;
-            vtx_file.seek(SeekFrom::Start(mesh_start + mesh.strip_group_header_offset as u64)).map_err(|_e| ())?;
-            for _ in 0..mesh.strip_groups_count {
-              let strip_group_start = vtx_file.seek(SeekFrom::Current(0)).map_err(|_e| ())?;
-              let strip_group = StripGroupHeader::read(&mut vtx_file).map_err(|_e| ())?;
-              let strip_group_next = vtx_file.seek(SeekFrom::Current(0)).map_err(|_e| ())?;
-              vtx_file.seek(SeekFrom::Start(strip_group_start + strip_group.indices_offset as u64)).map_err(|_e| ())?;
-              strip_group_indices.clear();
-              for _ in 0..strip_group.indices_count {
-                strip_group_indices.push(vtx_file.read_u16().map_err(|_e| ())? as u32);
-              }
+        let model_start = vtx_file.seek(SeekFrom::Current(0)).map_err(|e| AssetError::new(path, e))?;
+        let model = ModelHeader::read(vtx_file).map_err(|e| AssetError::new(path, e))?;
+        let model_next = vtx_file.seek(SeekFrom::Current(0)).map_err(|e| AssetError::new(path, e))?;
+        if model.lods_count <= 0 {
+          continue;
+        }
+        let (model_first_vertex, model_meshes) = &models[body_part_index as usize][model_index as usize];
+        let model_lod_index = min(lod, model.lods_count as u32 - 1);
+        vtx_file.seek(SeekFrom::Start(model_start + model.lod_offset as u64 + model_lod_index as u64 * VTX_MODEL_LOD_HEADER_SIZE)).map_err(|e| AssetError::new(path, e))?;
+        let lod_start = vtx_file.seek(SeekFrom::Current(0)).map_err(|e| AssetError::new(path, e))?;
+        let model_lod = ModelLODHeader::read(vtx_file).map_err(|e| AssetError::new(path, e))?;
+        if model_lod_index == lod {
+          switch_point = model_lod.switch_point;
+        }
+        vtx_file.seek(SeekFrom::Start(lod_start + model_lod.mesh_offset as u64)).map_err(|e| AssetError::new(path, e))?;
+        for mesh_index in 0..model_lod.meshes_count {
+          let mdl_mesh = &model_meshes[mesh_index as usize];
+          let indices_start = indices.len();
 
-              let base_index = vertices.len();
-              vtx_file.seek(SeekFrom::Start(strip_group_start + strip_group.strips_offset as u64)).map_err(|_e| ())?;
-              for _ in 0..strip_group.strips_count {
-                let strip = StripHeader::read(&mut vtx_file).map_err(|_e| ())?;
-                for i in 0..strip.indices_count {
-                  let reversed_i = strip.indices_count - 1 - i;
-                  indices.push(base_index as u32 + strip_group_indices[(strip.index_offset + reversed_i) as usize]);
-                }
-              }
+          let mesh_start = vtx_file.seek(SeekFrom::Current(0)).map_err(|e| AssetError::new(path, e))?;
+          let mesh = MeshHeader::read(vtx_file).map_err(|e| AssetError::new(path, e))?;
+          let mesh_next = vtx_file.seek(SeekFrom::Current(0)).map_err(|e| AssetError::new(path, e))?;
+          vtx_file.seek(SeekFrom::Start(mesh_start + mesh.strip_group_header_offset as u64)).map_err(|e| AssetError::new(path, e))?;
+          for _ in 0..mesh.strip_groups_count {
+            let strip_group_start = vtx_file.seek(SeekFrom::Current(0)).map_err(|e| AssetError::new(path, e))?;
+            let strip_group = StripGroupHeader::read(vtx_file).map_err(|e| AssetError::new(path, e))?;
+            let strip_group_next = vtx_file.seek(SeekFrom::Current(0)).map_err(|e| AssetError::new(path, e))?;
+            vtx_file.seek(SeekFrom::Start(strip_group_start + strip_group.indices_offset as u64)).map_err(|e| AssetError::new(path, e))?;
+            strip_group_indices.clear();
+            for _ in 0..strip_group.indices_count {
+              strip_group_indices.push(vtx_file.read_u16().map_err(|e| AssetError::new(path, e))? as u32);
+            }
 
-              vtx_file.seek(SeekFrom::Start(strip_group_start + strip_group.vert_offset as u64)).map_err(|_e| ())?;
-              for _ in 0..strip_group.verts_count {
-                let vtx_vertex = VTXVertex::read(&mut vtx_file).map_err(|_e| ())?;
-                let vert_index = mdl_mesh.vertex_offset + vtx_vertex.orig_mesh_vert_id as i32;
-                if vert_index < 0 || vert_index as usize >= vvd_vertices.len() {
-                  return Err(());
-                }
-                let vertex = vvd_vertices.as_ref()[vert_index as usize].clone();
-                let bsp_vertex = BspVertex {
-                  position: fixup_position(&vertex.vec_position),
-                  normal: fixup_normal(&vertex.vec_normal),
-                  uv: vertex.vec_tex_coord,
-                  lightmap_uv: Vector2::<f32>::new(0f32, 0f32),
-                  alpha: 0.0
-                };
-                vertices.push(bsp_vertex);
-              }
+            let base_index = vertices.len();
+            vtx_file.seek(SeekFrom::Start(strip_group_start + strip_group.strips_offset as u64)).map_err(|e| AssetError::new(path, e))?;
+            for _ in 0..strip_group.strips_count {
+              let strip = StripHeader::read(vtx_file).map_err(|e| AssetError::new(path, e))?;
+              let strip_indices = strip_group_indices.get(strip.index_offset as usize..(strip.index_offset + strip.indices_count) as usize)
+                .ok_or_else(|| AssetError::new(path, "Strip indices are out of bounds"))?;
+              push_strip_triangles(&mut indices, strip_indices, base_index as u32, strip.flags.contains(StripFlags::STRIP_IS_TRISTRIP));
+            }
 
-              vtx_file.seek(SeekFrom::Start(strip_group_next)).map_err(|_e| ())?;
+            vtx_file.seek(SeekFrom::Start(strip_group_start + strip_group.vert_offset as u64)).map_err(|e| AssetError::new(path, e))?;
+            for _ in 0..strip_group.verts_count {
+              let vtx_vertex = VTXVertex::read(vtx_file).map_err(|e| AssetError::new(path, e))?;
+              let vert_index = model_first_vertex + mdl_mesh.vertex_offset + vtx_vertex.orig_mesh_vert_id as i32;
+              if vert_index < 0 || vert_index as usize >= vvd_vertices.len() {
+                return Err(AssetError::new(path, format!("Vertex index {} is out of bounds", vert_index)));
+              }
+              let vertex = &vvd_vertices[vert_index as usize];
+              vertices.push(BspVertex {
+                position: fixup_position(&vertex.vec_position),
+                normal: fixup_normal(&vertex.vec_normal),
+                uv: vertex.vec_tex_coord,
+                lightmap_uv: Vector2::<f32>::new(0f32, 0f32),
+                alpha: 0.0
+              });
+              if is_skinned {
+                skinning.push(fixup_bone_weights(&vertex.bone_weights));
+              }
             }
 
-            materials.push(texture_paths[mdl_mesh.material as usize].clone());
-            ranges.push(MeshRange {
-              start: indices_start as u32,
-              count: (indices.len() - indices_start) as u32
-            });
-            vtx_file.seek(SeekFrom::Start(mesh_next)).map_err(|_e| ())?;
+            vtx_file.seek(SeekFrom::Start(strip_group_next)).map_err(|e| AssetError::new(path, e))?;
           }
-          vtx_file.seek(SeekFrom::Start(lod_next)).map_err(|_e| ())?;
-          break;
+
+          materials.push(mdl_mesh.material);
+          ranges.push(MeshRange {
+            start: indices_start as u32,
+            count: (indices.len() - indices_start) as u32
+          });
+          vtx_file.seek(SeekFrom::Start(mesh_next)).map_err(|e| AssetError::new(path, e))?;
         }
-        vtx_file.seek(SeekFrom::Start(model_next)).map_err(|_e| ())?;
+        vtx_file.seek(SeekFrom::Start(model_next)).map_err(|e| AssetError::new(path, e))?;
       }
-      vtx_file.seek(SeekFrom::Start(body_part_next)).map_err(|_e| ())?;
+      vtx_file.seek(SeekFrom::Start(body_part_next)).map_err(|e| AssetError::new(path, e))?;
     }
 
-    let indices_box = indices.clone().into_boxed_slice();
-    let indices_count = indices.len();
-    let ptr = Box::into_raw(indices_box);
-    let data_ptr = unsafe { slice::from_raw_parts_mut(ptr as *mut u8, indices_count * std::mem::size_of::<u32>()) as *mut [u8] };
-    let indices_data = unsafe { Box::from_raw(data_ptr) };
-    let vertices_box = vertices.clone().into_boxed_slice();
-    let vertices_count = vertices.len();
-    let ptr = Box::into_raw(vertices_box);
-    let data_ptr = unsafe { slice::from_raw_parts_mut(ptr as *mut u8, vertices_count * std::mem::size_of::<BspVertex>()) as *mut [u8] };
-    let vertices_data = unsafe { Box::from_raw(data_ptr) };
-
-    manager.add_asset(&vtx_path, Asset::Mesh(AssetMesh {
-      indices: Some(indices_data),
-      vertices: vertices_data,
-      parts: ranges.into_boxed_slice(),
-      bounding_box: Some(BoundingBox::new(fixup_position(&header.hull_min), fixup_position(&header.hull_max)))
-    }), AssetLoadPriority::Normal);
+    Ok(MDLLod {
+      vertices,
+      skinning: is_skinned.then(|| skinning),
+      indices,
+      ranges,
+      materials,
+      switch_point
+    })
+  }
+}
 
-    manager.add_asset_with_progress(&file.path, Asset::Model(AssetModel {
-      mesh_path: vtx_path.clone(),
-      material_paths: materials
-    }), Some(progress), AssetLoadPriority::Normal);
+/// Geometry of one LOD, the materials are indices into the texture table of the MDL.
+struct MDLLod {
+  vertices: Vec<BspVertex>,
+  skinning: Option<Vec<SkinningVertex>>,
+  indices: Vec<u32>,
+  ranges: Vec<MeshRange>,
+  materials: Vec<i32>,
+  switch_point: f32
+}
 
-    Ok(AssetLoaderResult {
-      level: None
-    })
+fn add_mesh<P: Platform>(manager: &Arc<AssetManager<P>>, path: &str, lod: MDLLod, bounding_box: &BoundingBox) {
+  let indices_box = lod.indices.into_boxed_slice();
+  let indices_count = indices_box.len();
+  let ptr = Box::into_raw(indices_box);
+  let data_ptr = unsafe { slice::from_raw_parts_mut(ptr as *mut u8, indices_count * std::mem::size_of::<u32>()) as *mut [u8] };
+  let indices_data = unsafe { Box::from_raw(data_ptr) };
+  let vertices_box = lod.vertices.into_boxed_slice();
+  let vertices_count = vertices_box.len();
+  let ptr = Box::into_raw(vertices_box);
+  let data_ptr = unsafe { slice::from_raw_parts_mut(ptr as *mut u8, vertices_count * std::mem::size_of::<BspVertex>()) as *mut [u8] };
+  let vertices_data = unsafe { Box::from_raw(data_ptr) };
+
+  manager.add_asset(path, Asset::Mesh(AssetMesh {
+    indices: Some(indices_data),
+    vertices: vertices_data,
+    skinning: lod.skinning.map(|skinning| unsafe { slice::from_raw_parts(skinning.as_ptr() as *const u8, std::mem::size_of_val(&skinning[..])) }.to_vec().into_boxed_slice()),
+    parts: lod.ranges.into_boxed_slice(),
+    bounding_box: Some(bounding_box.clone())
+  }), AssetLoadPriority::Normal);
+}
+
+/// Resolves the material of every mesh for each skin family, the default skin comes first.
+/// Fails with the texture index that's out of bounds.
+fn skin_material_paths(skins: &SkinReplacementTable, mesh_materials: &[i32], texture_paths: &[String]) -> Result<Vec<Vec<String>>, usize> {
+  let mut skin_materials = Vec::<Vec<String>>::with_capacity(skins.families_count().max(1));
+  for family in 0..skins.families_count().max(1) {
+    let mut materials = Vec::<String>::with_capacity(mesh_materials.len());
+    for material in mesh_materials {
+      let texture_index = skins.get(family, *material as usize).map_or(*material as usize, |texture| texture as usize);
+      let texture_path = texture_paths.get(texture_index).ok_or(texture_index)?;
+      materials.push(texture_path.clone());
+    }
+    skin_materials.push(materials);
   }
+  Ok(skin_materials)
 }
 
-impl MDLModelLoader {
-  pub fn new() -> Self {
-    Self {}
+/// Source switches to a LOD once 100 divided by the projected size of the model in pixels exceeds the switch point.
+/// The renderer works with fractions of the screen height instead, so this assumes a 1080 pixel high screen.
+fn switch_point_to_screen_size(switch_point: f32) -> f32 {
+  (100f32 / switch_point) / LOD_REFERENCE_SCREEN_HEIGHT
+}
+
+/// Appends the triangles of a VTX strip as a triangle list with flipped winding, degenerate triangles of strips are dropped.
+fn push_strip_triangles(indices: &mut Vec<u32>, strip_indices: &[u32], base_index: u32, is_tri_strip: bool) {
+  if !is_tri_strip {
+    indices.extend(strip_indices.iter().rev().map(|index| base_index + *index));
+    return;
+  }
+
+  for (i, triangle) in strip_indices.windows(3).enumerate() {
+    if triangle[0] == triangle[1] || triangle[1] == triangle[2] || triangle[0] == triangle[2] {
+      continue;
+    }
+    // Every other triangle of a strip has the opposite winding
+    let (a, b, c) = if i % 2 == 0 {
+      (triangle[2], triangle[1], triangle[0])
+    } else {
+      (triangle[2], triangle[0], triangle[1])
+    };
+    indices.extend_from_slice(&[base_index + a, base_index + b, base_index + c]);
   }
 }
 
@@ -276,3 +427,80 @@ fn fixup_position(position: &Vector3<f32>) -> Vector3<f32> {
 fn fixup_normal(normal: &Vector3<f32>) -> Vector3<f32> {
   Vector3::<f32>::new(normal.x, normal.z, -normal.y)
 }
+
+/// The axis swap of fixup_normal is a rotation, so rotating the axis of the quaternion is enough.
+fn fixup_rotation(rotation: &nalgebra::Quaternion<f32>) -> Quaternion {
+  Quaternion::new_normalize(nalgebra::Quaternion::from_parts(rotation.w, fixup_normal(&rotation.imag())))
+}
+
+/// Changes the basis of both mesh and bone space to the one of the engine.
+fn fixup_bone_matrix(pose_to_bone: &Matrix3x4<f32>) -> Matrix4 {
+  let mut basis = Matrix4::identity();
+  basis.fixed_slice_mut::<3, 3>(0, 0).copy_from(&nalgebra::Matrix3::new(
+    1f32, 0f32, 0f32,
+    0f32, 0f32, 1f32,
+    0f32, -1f32, 0f32
+  ));
+  let mut source_matrix = Matrix4::identity();
+  source_matrix.fixed_slice_mut::<3, 4>(0, 0).copy_from(pose_to_bone);
+  let mut matrix = basis * source_matrix * basis.transpose();
+  let translation = matrix.fixed_slice::<3, 1>(0, 3) * SCALING_FACTOR;
+  matrix.fixed_slice_mut::<3, 1>(0, 3).copy_from(&translation);
+  matrix
+}
+
+fn fixup_bone_weights(bone_weights: &BoneWeight) -> SkinningVertex {
+  let mut skinning = SkinningVertex::default();
+  for i in 0..min(bone_weights.bones_count as usize, bone_weights.bone.len()) {
+    skinning.joints[i] = bone_weights.bone[i].max(0) as u16;
+    skinning.weights[i] = bone_weights.weight[i];
+  }
+  skinning
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::Cursor;
+
+  use super::*;
+
+  fn skin_table(families: &[&[u16]]) -> SkinReplacementTable {
+    let data: Vec<u8> = families.iter().flat_map(|family| family.iter()).flat_map(|texture| texture.to_le_bytes()).collect();
+    SkinReplacementTable::read(&mut Cursor::new(data), families.len() as i32, families[0].len() as i32).unwrap()
+  }
+
+  #[test]
+  fn second_skin_swaps_materials() {
+    let skins = skin_table(&[&[0, 1], &[2, 1]]);
+    let texture_paths = vec!["materials/a.vmt".to_string(), "materials/b.vmt".to_string(), "materials/c.vmt".to_string()];
+    let skin_materials = skin_material_paths(&skins, &[1, 0], &texture_paths).unwrap();
+    assert_eq!(skin_materials, vec![
+      vec!["materials/b.vmt".to_string(), "materials/a.vmt".to_string()],
+      vec!["materials/b.vmt".to_string(), "materials/c.vmt".to_string()]
+    ]);
+  }
+
+  #[test]
+  fn skin_with_missing_texture_fails() {
+    let skins = skin_table(&[&[0], &[3]]);
+    let texture_paths = vec!["materials/a.vmt".to_string()];
+    assert_eq!(skin_material_paths(&skins, &[0], &texture_paths), Err(3));
+  }
+
+  #[test]
+  fn strips_and_lists_become_triangle_lists() {
+    let mut indices = Vec::<u32>::new();
+    push_strip_triangles(&mut indices, &[0, 1, 2, 2, 3, 0], 10, false);
+    assert_eq!(indices, vec![10, 13, 12, 12, 11, 10]);
+
+    let mut indices = Vec::<u32>::new();
+    push_strip_triangles(&mut indices, &[0, 1, 2, 3, 4], 10, true);
+    assert_eq!(indices.len(), 3 * 3);
+    assert_eq!(&indices[..6], &[12, 11, 10, 13, 11, 12]);
+
+    // Degenerate triangles that join two strips are dropped
+    let mut indices = Vec::<u32>::new();
+    push_strip_triangles(&mut indices, &[0, 1, 2, 2, 3, 4], 0, true);
+    assert_eq!(indices.len(), 2 * 3);
+  }
+}
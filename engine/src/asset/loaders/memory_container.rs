@@ -0,0 +1,47 @@
+use crate::asset::asset_manager::{AssetContainer, AssetFile, AssetFileData};
+use std::io::Cursor;
+use std::collections::HashMap;
+use sourcerenderer_core::Platform;
+
+/// Serves files straight from memory, useful for synthetic assets that don't exist on disk.
+pub struct MemoryContainer {
+  files: HashMap<String, Box<[u8]>>
+}
+
+impl MemoryContainer {
+  pub fn new() -> Self {
+    Self {
+      files: HashMap::new()
+    }
+  }
+
+  pub fn insert(&mut self, path: &str, data: Vec<u8>) {
+    self.files.insert(path.to_string(), data.into_boxed_slice());
+  }
+
+  pub fn open(&self, path: &str) -> Option<Cursor<Box<[u8]>>> {
+    self.files.get(path).map(|data| Cursor::new(data.clone()))
+  }
+}
+
+impl From<HashMap<String, Vec<u8>>> for MemoryContainer {
+  fn from(files: HashMap<String, Vec<u8>>) -> Self {
+    Self {
+      files: files.into_iter().map(|(path, data)| (path, data.into_boxed_slice())).collect()
+    }
+  }
+}
+
+impl<P: Platform> AssetContainer<P> for MemoryContainer {
+  fn contains(&self, path: &str) -> bool {
+    self.files.contains_key(path)
+  }
+
+  fn load(&self, path: &str) -> Option<AssetFile<P>> {
+    let data = self.open(path)?;
+    Some(AssetFile {
+      path: path.to_string(),
+      data: AssetFileData::Memory(data)
+    })
+  }
+}
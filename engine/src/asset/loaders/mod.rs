@@ -4,6 +4,7 @@ mod vpk_container;
 mod vtf_loader;
 mod vmt_loader;
 mod pakfile_container;
+mod memory_container;
 mod mdl_loader;
 mod gltf;
 
@@ -14,6 +15,7 @@ pub use self::vpk_container::VPKContainer;
 pub use self::vpk_container::VPKContainerLoader;
 pub use self::vtf_loader::VTFTextureLoader;
 pub use self::pakfile_container::PakFileContainer;
+pub use self::memory_container::MemoryContainer;
 pub use self::vmt_loader::VMTMaterialLoader;
 pub use self::mdl_loader::MDLModelLoader;
 pub use self::gltf::GltfContainer;
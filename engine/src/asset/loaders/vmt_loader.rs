@@ -1,10 +1,13 @@
-use crate::asset::{AssetLoader, Asset, AssetType, AssetManager};
+use crate::asset::{AssetLoader, Asset, AssetType, AssetManager, AssetError, AlphaMode, MaterialTexture};
 use crate::asset::asset_manager::{AssetLoaderResult, AssetFile, AssetFileData, AssetLoaderProgress, AssetLoadPriority};
-use sourcerenderer_core::Platform;
-use sourcerenderer_vmt::VMTMaterial;
-use std::io::{BufReader, Seek, SeekFrom};
+use sourcerenderer_core::{Platform, Vec3, Vec4};
+use sourcerenderer_vmt::{VMTMaterial, BUMP_MAP, DETAIL, TRANSLUCENT, ALPHA_TEST, ALPHA_TEST_REFERENCE, SURFACE_PROP, COLOR};
+use std::io::{BufReader, Read, Seek, SeekFrom};
 use crate::asset::Material;
 use std::sync::Arc;
+use std::collections::HashSet;
+
+const MAX_PATCH_DEPTH: usize = 8;
 
 pub struct VMTMaterialLoader {
 
@@ -21,61 +24,45 @@ impl<P: Platform> AssetLoader<P> for VMTMaterialLoader {
     file.path.starts_with("materials/") && file.path.ends_with(".vmt")
   }
 
-  fn load(&self, asset_file: AssetFile<P>, manager: &Arc<AssetManager<P>>, priority: AssetLoadPriority, progress: &Arc<AssetLoaderProgress>) -> Result<AssetLoaderResult, ()> {
+  fn load(&self, asset_file: AssetFile<P>, manager: &Arc<AssetManager<P>>, priority: AssetLoadPriority, progress: &Arc<AssetLoaderProgress>) -> Result<AssetLoaderResult, AssetError> {
     let path = asset_file.path.clone();
-    let mut vmt_material = match asset_file.data {
-      AssetFileData::File(file) => {
-        let mut bufreader = BufReader::new(file);
-        let current = bufreader.seek(SeekFrom::Current(0)).unwrap();
-        let len = bufreader.seek(SeekFrom::End(0)).unwrap();
-        bufreader.seek(SeekFrom::Start(current)).unwrap();
-        VMTMaterial::new(&mut bufreader, len as u32)
-      }
-      AssetFileData::Memory(mut cursor) => {
-        let current = cursor.seek(SeekFrom::Current(0)).unwrap();
-        let len = cursor.seek(SeekFrom::End(0)).unwrap();
-        cursor.seek(SeekFrom::Start(current)).unwrap();
-        VMTMaterial::new(&mut cursor, len as u32)
-      }
-    }.map_err(|_| ())?;
-
-    if vmt_material.is_patch() {
-      let base_path = vmt_material.get_patch_base().unwrap().replace('\\', "/").to_lowercase();
-      let base_file = manager.load_file(&base_path);
-      if base_file.is_none() {
-        return Err(());
-      }
-      let base_file = base_file.unwrap();
-      let mut base_material = match base_file.data {
-        AssetFileData::File(file) => {
-          let mut bufreader = BufReader::new(file);
-          let current = bufreader.seek(SeekFrom::Current(0)).unwrap();
-          let len = bufreader.seek(SeekFrom::End(0)).unwrap();
-          bufreader.seek(SeekFrom::Start(current)).unwrap();
-          VMTMaterial::new(&mut bufreader, len as u32)
-        }
-        AssetFileData::Memory(mut cursor) => {
-          let current = cursor.seek(SeekFrom::Current(0)).unwrap();
-          let len = cursor.seek(SeekFrom::End(0)).unwrap();
-          cursor.seek(SeekFrom::Start(current)).unwrap();
-          VMTMaterial::new(&mut cursor, len as u32)
-        }
-      }.map_err(|_| ())?;
-      base_material.apply_patch(&vmt_material);
-      vmt_material = base_material
-    }
+    let vmt_material = read_material_file(asset_file)?;
+    let vmt_material = resolve_patches(vmt_material, &path, |base_path| manager.load_file(base_path).map(read_material_file))?;
 
     let albedo_opt = vmt_material.get_base_texture_name();
     if albedo_opt.is_none() {
-      return Err(());
+      return Err(AssetError::new(&path, "Material has no base texture"));
+    }
+    let albedo_path = texture_path(albedo_opt.unwrap());
+    let mut material = Material::new(&albedo_path);
+    manager.request_asset_with_progress(&albedo_path, AssetType::Texture, priority, Some(progress));
+
+    let load_texture = |key: &str| {
+      vmt_material.get_value(key).map(|texture| {
+        let path = texture_path(texture);
+        manager.request_asset_with_progress(&path, AssetType::Texture, priority, Some(progress));
+        MaterialTexture {
+          path,
+          tex_coord: 0
+        }
+      })
+    };
+    material.normal_texture = load_texture(BUMP_MAP);
+    material.detail_texture = load_texture(DETAIL);
+
+    if let Some(color) = vmt_material.get_value(COLOR).and_then(parse_color) {
+      material.base_color_factor = Vec4::new(color.x, color.y, color.z, 1f32);
     }
-    let albedo = albedo_opt.unwrap();
-    let albedo_path = "materials/".to_string() + albedo.to_lowercase().replace('\\', "/").as_str().trim_matches('/').trim_end_matches(".vtf") + ".vtf";
-    let material = Material {
-      albedo_texture_path: albedo_path.clone()
+    material.alpha_mode = if is_flag_set(&vmt_material, TRANSLUCENT) {
+      AlphaMode::Blend
+    } else if is_flag_set(&vmt_material, ALPHA_TEST) {
+      AlphaMode::Mask(vmt_material.get_value(ALPHA_TEST_REFERENCE).and_then(|value| value.trim().parse::<f32>().ok()).unwrap_or(0.5f32))
+    } else {
+      AlphaMode::Opaque
     };
+    material.shader_name = Some(vmt_material.get_shader().to_string());
+    material.surface_prop = vmt_material.get_value(SURFACE_PROP).map(|surface_prop| surface_prop.to_lowercase());
 
-    manager.request_asset_with_progress(&albedo_path, AssetType::Texture, priority, Some(progress));
     manager.add_asset_with_progress(&path, Asset::Material(material), Some(progress), priority);
 
     Ok(AssetLoaderResult {
@@ -83,3 +70,151 @@ impl<P: Platform> AssetLoader<P> for VMTMaterialLoader {
     })
   }
 }
+
+/// Follows the includes of patch materials down to the base material and applies the patches on top of it.
+/// `load_material` returns None if there is no material at the given path.
+fn resolve_patches<F>(material: VMTMaterial, path: &str, mut load_material: F) -> Result<VMTMaterial, AssetError>
+  where F: FnMut(&str) -> Option<Result<VMTMaterial, AssetError>> {
+  let mut material = material;
+  let mut patches = Vec::<VMTMaterial>::new();
+  let mut visited = HashSet::<String>::new();
+  visited.insert(path.to_string());
+  while material.is_patch() {
+    if patches.len() >= MAX_PATCH_DEPTH {
+      return Err(AssetError::new(path, format!("Patch materials are nested deeper than {} levels", MAX_PATCH_DEPTH)));
+    }
+    let base_path = material.get_patch_base()
+      .ok_or_else(|| AssetError::new(path, "Patch material has no include"))?
+      .replace('\\', "/")
+      .to_lowercase();
+    let base_path = if base_path.ends_with(".vmt") { base_path } else { base_path + ".vmt" };
+    if !visited.insert(base_path.clone()) {
+      return Err(AssetError::new(path, format!("Patch materials include each other: {:?}", base_path)));
+    }
+
+    let base_material = load_material(&base_path)
+      .ok_or_else(|| AssetError::new(path, format!("Could not find patched material: {:?}", base_path)))??;
+    patches.push(std::mem::replace(&mut material, base_material));
+  }
+
+  // The patch closest to the base material goes first
+  for patch in patches.iter().rev() {
+    material.apply_patch(patch);
+  }
+  Ok(material)
+}
+
+fn read_material_file<P: Platform>(file: AssetFile<P>) -> Result<VMTMaterial, AssetError> {
+  let path = file.path;
+  match file.data {
+    AssetFileData::File(file) => read_material(BufReader::new(file)),
+    AssetFileData::Memory(cursor) => read_material(cursor)
+  }.map_err(|reason| AssetError::new(&path, reason))
+}
+
+fn texture_path(texture: &str) -> String {
+  "materials/".to_string() + texture.to_lowercase().replace('\\', "/").as_str().trim_matches('/').trim_end_matches(".vtf") + ".vtf"
+}
+
+fn is_flag_set(material: &VMTMaterial, key: &str) -> bool {
+  material.get_value(key)
+    .and_then(|value| value.trim().parse::<f32>().ok())
+    .map_or(false, |value| value != 0f32)
+}
+
+/// Colors are either written as floats in square brackets or as bytes in curly braces.
+fn parse_color(value: &str) -> Option<Vec3> {
+  let value = value.trim();
+  let (components, scale) = if value.starts_with('[') && value.ends_with(']') {
+    (&value[1 .. value.len() - 1], 1f32)
+  } else if value.starts_with('{') && value.ends_with('}') {
+    (&value[1 .. value.len() - 1], 255f32)
+  } else {
+    return None;
+  };
+  let components: Vec<f32> = components.split_whitespace().filter_map(|component| component.parse::<f32>().ok()).collect();
+  if components.len() != 3 {
+    return None;
+  }
+  Some(Vec3::new(components[0], components[1], components[2]) / scale)
+}
+
+fn read_material<R: Read + Seek>(mut reader: R) -> Result<VMTMaterial, String> {
+  let current = reader.seek(SeekFrom::Current(0)).map_err(|e| e.to_string())?;
+  let len = reader.seek(SeekFrom::End(0)).map_err(|e| e.to_string())?;
+  reader.seek(SeekFrom::Start(current)).map_err(|e| e.to_string())?;
+  VMTMaterial::new(&mut reader, len as u32).map_err(|e| format!("Failed to parse VMT: {:?}", e))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::asset::loaders::MemoryContainer;
+  use std::collections::HashMap;
+
+  #[test]
+  fn reads_material_from_memory_container() {
+    let mut files = HashMap::new();
+    files.insert("materials/concrete/wall01.vmt".to_string(), b"\"LightmappedGeneric\"\n{\n  \"$basetexture\" \"Concrete\\Wall01\"\n  \"$surfaceprop\" \"concrete\"\n}\n".to_vec());
+    let container = MemoryContainer::from(files);
+
+    assert!(container.open("materials/concrete/missing.vmt").is_none());
+    let material = read_material(container.open("materials/concrete/wall01.vmt").unwrap()).unwrap();
+    assert_eq!(material.get_shader().to_lowercase(), "lightmappedgeneric");
+    assert_eq!(texture_path(material.get_base_texture_name().unwrap()), "materials/concrete/wall01.vtf");
+    assert_eq!(material.get_value(SURFACE_PROP), Some("concrete"));
+  }
+
+  fn memory_container(files: &[(&str, String)]) -> MemoryContainer {
+    let mut container = MemoryContainer::new();
+    for (path, data) in files {
+      container.insert(path, data.as_bytes().to_vec());
+    }
+    container
+  }
+
+  fn resolve(container: &MemoryContainer, path: &str) -> Result<VMTMaterial, AssetError> {
+    let load_material = |path: &str| container.open(path).map(|data| read_material(data).map_err(|reason| AssetError::new(path, reason)));
+    resolve_patches(load_material(path).unwrap()?, path, load_material)
+  }
+
+  fn patch_material(include: &str) -> String {
+    format!("\"patch\"\n{{\n  \"include\" \"{}\"\n  \"replace\"\n  {{\n    \"$surfaceprop\" \"metal\"\n  }}\n}}\n", include)
+  }
+
+  #[test]
+  fn nested_patches_get_applied_on_top_of_the_base() {
+    let container = memory_container(&[
+      ("materials/brick/wall_outer.vmt", patch_material("materials/brick/wall_inner.vmt")),
+      ("materials/brick/wall_inner.vmt", "\"patch\"\n{\n  \"include\" \"materials\\brick\\wall\"\n  \"insert\"\n  {\n    \"$detail\" \"detail/noise\"\n  }\n  \"replace\"\n  {\n    \"$surfaceprop\" \"glass\"\n  }\n}\n".to_string()),
+      ("materials/brick/wall.vmt", "\"LightmappedGeneric\"\n{\n  \"$basetexture\" \"brick/wall01\"\n  \"$surfaceprop\" \"brick\"\n}\n".to_string())
+    ]);
+    let material = resolve(&container, "materials/brick/wall_outer.vmt").unwrap();
+    assert_eq!(material.get_shader(), "lightmappedgeneric");
+    assert_eq!(material.get_base_texture_name(), Some("brick/wall01"));
+    assert_eq!(material.get_value(DETAIL), Some("detail/noise"));
+    // The outermost patch gets applied last
+    assert_eq!(material.get_value(SURFACE_PROP), Some("metal"));
+  }
+
+  #[test]
+  fn patch_cycles_and_deep_chains_are_errors() {
+    let container = memory_container(&[
+      ("materials/a.vmt", patch_material("materials/b.vmt")),
+      ("materials/b.vmt", patch_material("materials/a.vmt"))
+    ]);
+    let error = resolve(&container, "materials/a.vmt").err().unwrap();
+    assert!(error.reason.contains("include each other"), "{}", error.reason);
+
+    let chain: Vec<(String, String)> = (0 .. MAX_PATCH_DEPTH + 2)
+      .map(|i| (format!("materials/chain{}.vmt", i), patch_material(&format!("materials/chain{}.vmt", i + 1))))
+      .collect();
+    let files: Vec<(&str, String)> = chain.iter().map(|(path, data)| (path.as_str(), data.clone())).collect();
+    let error = resolve(&memory_container(&files), "materials/chain0.vmt").err().unwrap();
+    assert!(error.reason.contains("nested deeper"), "{}", error.reason);
+
+    let container = memory_container(&[("materials/a.vmt", patch_material("materials/missing.vmt"))]);
+    let error = resolve(&container, "materials/a.vmt").err().unwrap();
+    assert!(error.reason.contains("Could not find patched material"), "{}", error.reason);
+  }
+}
@@ -1,9 +1,10 @@
 use std::io::{Cursor, Error as IOError, ErrorKind};
 
 use sourcerenderer_vpk::{Package, PackageError};
-use crate::asset::{AssetLoader, AssetManager, AssetLoaderProgress};
+use crate::asset::{AssetLoader, AssetManager, AssetLoaderProgress, AssetError};
 use crate::asset::asset_manager::{AssetLoaderResult, AssetFile, AssetFileData, AssetContainer, AssetLoadPriority};
 use sourcerenderer_core::Platform;
+use sourcerenderer_core::platform::io::IO;
 use regex::Regex;
 use std::path::Path;
 use std::sync::Arc;
@@ -17,13 +18,22 @@ pub struct VPKContainer<P: Platform> {
 
 pub fn new_vpk_container<P: Platform>(asset_manager: &Arc<AssetManager<P>>, asset_file: AssetFile<P>) -> Result<Box<dyn AssetContainer<P>>, PackageError> {
   let path = asset_file.path.clone();
-  let asset_manager = Arc::downgrade(asset_manager);
 
-  Package::read(&path, asset_file, move |path| {
-    let mgr = asset_manager.upgrade()
-      .ok_or(IOError::new(ErrorKind::Other, "Manager dropped"))?;
-    mgr.load_file(path)
-      .ok_or(IOError::new(ErrorKind::NotFound, "File not found"))
+  // Archives get opened the first time an entry in them is read. That happens while the asset manager
+  // is iterating over its containers, so going through AssetManager::load_file would lock them again.
+  let archive_directory = asset_manager.external_file_path(&path)
+    .and_then(|dir_path| dir_path.parent().map(|parent| parent.to_path_buf()));
+
+  Package::read(&path, asset_file, move |archive_path| {
+    let archive_directory = archive_directory.as_ref()
+      .ok_or_else(|| IOError::new(ErrorKind::NotFound, "Package is not stored on disk"))?;
+    let file_name = Path::new(archive_path).file_name()
+      .ok_or_else(|| IOError::new(ErrorKind::InvalidInput, "Invalid archive path"))?;
+    let file = <P::IO as IO>::open_external_asset(archive_directory.join(file_name))?;
+    Ok(AssetFile {
+      path: archive_path.to_string(),
+      data: AssetFileData::File(file)
+    })
   }).map(|package|
     Box::new(VPKContainer::<P> {
       package
@@ -64,8 +74,10 @@ impl<P: Platform> AssetLoader<P> for VPKContainerLoader {
     file_name.and_then(|file_name| file_name.to_str()).map_or(false, |file_name| self.pak_name_regex.is_match(file_name))
   }
 
-  fn load(&self, file: AssetFile<P>, manager: &Arc<AssetManager<P>>, _priority: AssetLoadPriority, progress: &Arc<AssetLoaderProgress>) -> Result<AssetLoaderResult, ()> {
-    let container = new_vpk_container::<P>(manager, file).unwrap();
+  fn load(&self, file: AssetFile<P>, manager: &Arc<AssetManager<P>>, _priority: AssetLoadPriority, progress: &Arc<AssetLoaderProgress>) -> Result<AssetLoaderResult, AssetError> {
+    let path = file.path.clone();
+    let container = new_vpk_container::<P>(manager, file)
+      .map_err(|e| AssetError::new(&path, format!("Failed to read package: {:?}", e)))?;
     manager.add_container_with_progress(container, Some(progress));
     Ok(AssetLoaderResult {
       level: None
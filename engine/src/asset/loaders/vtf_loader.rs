@@ -1,12 +1,13 @@
-use crate::asset::{AssetLoader, Asset, AssetManager};
+use crate::asset::{AssetLoader, Asset, AssetManager, AssetError};
 use sourcerenderer_core::Platform;
 use crate::asset::asset_manager::{AssetFile, AssetLoaderResult, AssetFileData, AssetLoaderProgress, AssetLoadPriority, Texture};
-use std::io::{Cursor, BufReader};
-use sourcerenderer_vtf::{VtfTexture, ImageFormat as VTFTextureFormat};
+use std::io::{Cursor, BufReader, Read, Seek};
+use sourcerenderer_vtf::{VtfTexture, ImageFormat as VTFTextureFormat, TextureFlags};
 use std::fs::File;
-use sourcerenderer_core::graphics::{SampleCount, TextureInfo, TextureUsage};
+use sourcerenderer_core::graphics::{SampleCount, TextureDimension, TextureInfo, TextureUsage};
 use sourcerenderer_core::graphics::Format;
 use std::sync::Arc;
+use std::cmp::max;
 
 pub struct VTFTextureLoader {
 
@@ -34,44 +35,12 @@ impl<P: Platform> AssetLoader<P> for VTFTextureLoader {
     }
   }
 
-  fn load(&self, file: AssetFile<P>, manager: &Arc<AssetManager<P>>, priority: AssetLoadPriority, progress: &Arc<AssetLoaderProgress>) -> Result<AssetLoaderResult, ()> {
+  fn load(&self, file: AssetFile<P>, manager: &Arc<AssetManager<P>>, priority: AssetLoadPriority, progress: &Arc<AssetLoaderProgress>) -> Result<AssetLoaderResult, AssetError> {
     let path = file.path.clone();
     let texture = match file.data {
-      AssetFileData::File(file) => {
-        let mut texture = VtfTexture::new(BufReader::new(file)).unwrap();
-        let mipmap = &texture.read_mip_map(texture.header().mipmap_count as u32 - 1).unwrap();
-        Texture {
-          info: TextureInfo {
-            format: convert_vtf_texture_format(mipmap.format),
-            width: mipmap.width,
-            height: mipmap.height,
-            depth: 1,
-            mip_levels: 1,
-            array_length: 1,
-            samples: SampleCount::Samples1,
-            usage: TextureUsage::FRAGMENT_SHADER_SAMPLED | TextureUsage::VERTEX_SHADER_SAMPLED | TextureUsage::FRAGMENT_SHADER_SAMPLED | TextureUsage::BLIT_DST
-          },
-          data: Box::new([mipmap.frames[0].faces[0].slices[0].data.clone()]),
-        }
-      }
-      AssetFileData::Memory(cursor) => {
-        let mut texture = VtfTexture::new(BufReader::new(cursor)).unwrap();
-        let mipmap = texture.read_mip_map(texture.header().mipmap_count as u32 - 1).unwrap();
-        Texture {
-          info: TextureInfo {
-            format: convert_vtf_texture_format(mipmap.format),
-            width: mipmap.width,
-            height: mipmap.height,
-            depth: 1,
-            mip_levels: 1,
-            array_length: 1,
-            samples: SampleCount::Samples1,
-            usage: TextureUsage::FRAGMENT_SHADER_SAMPLED | TextureUsage::VERTEX_SHADER_SAMPLED | TextureUsage::FRAGMENT_SHADER_SAMPLED | TextureUsage::BLIT_DST
-          },
-          data: Box::new([mipmap.frames[0].faces[0].slices[0].data.clone()]),
-        }
-      }
-    };
+      AssetFileData::File(file) => load_texture(BufReader::new(file)),
+      AssetFileData::Memory(cursor) => load_texture(BufReader::new(cursor))
+    }.map_err(|reason| AssetError::new(&path, reason))?;
 
     manager.add_asset_with_progress(&path, Asset::Texture(texture), Some(progress), priority);
 
@@ -81,13 +50,326 @@ impl<P: Platform> AssetLoader<P> for VTFTextureLoader {
   }
 }
 
-fn convert_vtf_texture_format(texture_format: VTFTextureFormat) -> Format {
+fn load_texture<R: Read + Seek>(reader: R) -> Result<Texture, String> {
+  let mut texture = VtfTexture::new(reader).map_err(|e| format!("Failed to read VTF header: {}", e))?;
+  let mipmap_count = texture.header().mipmap_count as u32;
+  if mipmap_count == 0 {
+    return Err("VTF has no mip maps".to_string());
+  }
+  // Normal maps hold vectors, so they stay linear even if they were tagged otherwise
+  let flags = texture.header().flags;
+  let srgb = flags.contains(TextureFlags::SRGB) && !flags.contains(TextureFlags::NORMAL);
+  let format = convert_vtf_texture_format(texture.header().high_res_image_format, srgb)
+    .ok_or_else(|| format!("VTF format {:?} is not supported", texture.header().high_res_image_format))?;
+
+  // The sphere map that older environment maps store as the 7th face is skipped.
+  let is_cubemap = texture.header().faces_count() >= 6;
+  let faces_count = if is_cubemap { 6 } else { 1 };
+  let frame_count = max(1, texture.header().frames as u32);
+
+  // Array layers are laid out as [frame][face], each with all of its mips
+  let layer_count = frame_count * faces_count;
+
+  // VTFs store the smallest mip level first
+  let mut width = 0;
+  let mut height = 0;
+  let mut layers: Vec<Vec<Box<[u8]>>> = (0..layer_count).map(|_| Vec::with_capacity(mipmap_count as usize)).collect();
+  for level in (0..mipmap_count).rev() {
+    let mipmap = texture.read_mip_map(level).ok_or_else(|| format!("Failed to read VTF mip map {}", mipmap_count - 1 - level))?;
+    if level == mipmap_count - 1 {
+      width = mipmap.width;
+      height = mipmap.height;
+    }
+    let image_format = texture.header().high_res_image_format;
+    let face_size = mipmap.width;
+    if is_cubemap && mipmap.width != mipmap.height {
+      return Err("VTF cube map faces aren't square".to_string());
+    }
+    let mut faces = Vec::<Box<[u8]>>::with_capacity(layer_count as usize);
+    for frame in mipmap.frames {
+      let frame_faces = frame.faces.into_iter()
+        .take(faces_count as usize)
+        .map(|face| face.slices.into_iter().next()
+          .map(|slice| convert_vtf_texture_data(image_format, slice.data))
+          .ok_or_else(|| "VTF face is empty".to_string()))
+        .collect::<Result<Vec<Box<[u8]>>, String>>()?;
+      if !is_cubemap {
+        faces.extend(frame_faces);
+        continue;
+      }
+      if frame_faces.len() < 6 {
+        break;
+      }
+      faces.extend(CUBEMAP_FACES.iter().map(|(face, rotation)| rotate_face(image_format, &frame_faces[*face], face_size, *rotation)));
+    }
+    for (layer, face) in layers.iter_mut().zip(faces) {
+      layer.push(face);
+    }
+  }
+  if layers.iter().any(|layer| layer.len() != mipmap_count as usize) {
+    return Err("VTF is missing frames or faces".to_string());
+  }
+  let data: Vec<Box<[u8]>> = layers.into_iter().flatten().collect();
+
+  Ok(Texture {
+    info: TextureInfo {
+      dimension: TextureDimension::Dim2D,
+      format,
+      width,
+      height,
+      depth: 1,
+      mip_levels: mipmap_count,
+      array_length: layer_count,
+      samples: SampleCount::Samples1,
+      usage: TextureUsage::FRAGMENT_SHADER_SAMPLED | TextureUsage::VERTEX_SHADER_SAMPLED | TextureUsage::BLIT_DST,
+      is_cubemap
+    },
+    data: data.into_boxed_slice(),
+    frame_count
+  })
+}
+
+/// Rotation of a square image, the position in the rotated image is rotated the other way to find the original texel.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum FaceRotation {
+  None,
+  Clockwise90,
+  Clockwise180,
+  CounterClockwise90
+}
+
+impl FaceRotation {
+  fn source_texel(self, x: u32, y: u32, size: u32) -> (u32, u32) {
+    match self {
+      FaceRotation::None => (x, y),
+      FaceRotation::Clockwise90 => (y, size - 1 - x),
+      FaceRotation::Clockwise180 => (size - 1 - x, size - 1 - y),
+      FaceRotation::CounterClockwise90 => (size - 1 - y, x)
+    }
+  }
+}
+
+/// The VTF face that makes up each cube face in the engine's +X, -X, +Y, -Y, +Z, -Z order.
+/// Source is Z up, so the engine direction (x, y, z) is (x, -z, y) in Source and the faces need to be rotated to line up again.
+const CUBEMAP_FACES: [(usize, FaceRotation); 6] = [
+  (0, FaceRotation::Clockwise90),
+  (1, FaceRotation::CounterClockwise90),
+  (4, FaceRotation::None),
+  (5, FaceRotation::Clockwise180),
+  (3, FaceRotation::None),
+  (2, FaceRotation::Clockwise180)
+];
+
+/// Rotates a square face that was already converted by `convert_vtf_texture_data`.
+/// Block compressed faces get their blocks and the texels inside of every block moved.
+fn rotate_face(texture_format: VTFTextureFormat, data: &[u8], size: u32, rotation: FaceRotation) -> Box<[u8]> {
+  if rotation == FaceRotation::None || size <= 1 {
+    return data.to_vec().into_boxed_slice();
+  }
+
+  let (texel_size, block_size) = match texture_format {
+    VTFTextureFormat::DXT1 | VTFTextureFormat::DXT1OneBitAlpha | VTFTextureFormat::ATI1N => (8, 4),
+    VTFTextureFormat::DXT3 | VTFTextureFormat::DXT5 | VTFTextureFormat::ATI2N => (16, 4),
+    _ => (data.len() / (size * size) as usize, 1)
+  };
+  let blocks = size.div_ceil(block_size);
+  let mut rotated = vec![0u8; data.len()];
+  for y in 0..blocks {
+    for x in 0..blocks {
+      let (source_x, source_y) = rotation.source_texel(x, y, blocks);
+      let source_offset = (source_x + source_y * blocks) as usize * texel_size;
+      let offset = (x + y * blocks) as usize * texel_size;
+      let block = &mut rotated[offset..offset + texel_size];
+      block.copy_from_slice(&data[source_offset..source_offset + texel_size]);
+      if block_size > 1 {
+        rotate_block(texture_format, block, size.min(block_size), rotation);
+      }
+    }
+  }
+  rotated.into_boxed_slice()
+}
+
+/// Moves the per texel indices inside of a 4x4 block, only the top left `size` x `size` texels are used by small mip maps.
+fn rotate_block(texture_format: VTFTextureFormat, block: &mut [u8], size: u32, rotation: FaceRotation) {
+  // Offset of the 8 byte sub block, the first byte of its indices and the bits per index
+  let sub_blocks: &[(usize, usize, u32)] = match texture_format {
+    VTFTextureFormat::DXT1 | VTFTextureFormat::DXT1OneBitAlpha => &[(0, 4, 2)],
+    VTFTextureFormat::DXT3 => &[(0, 0, 4), (8, 4, 2)],
+    VTFTextureFormat::DXT5 => &[(0, 2, 3), (8, 4, 2)],
+    VTFTextureFormat::ATI1N => &[(0, 2, 3)],
+    VTFTextureFormat::ATI2N => &[(0, 2, 3), (8, 2, 3)],
+    _ => &[]
+  };
+  for &(offset, indices_start, bits) in sub_blocks {
+    let indices_bytes = &mut block[offset + indices_start..offset + 8];
+    let mut raw = [0u8; 8];
+    raw[..indices_bytes.len()].copy_from_slice(indices_bytes);
+    let indices = u64::from_le_bytes(raw);
+    let mask = (1u64 << bits) - 1;
+    let mut rotated_indices = indices;
+    for y in 0..size {
+      for x in 0..size {
+        let (source_x, source_y) = rotation.source_texel(x, y, size);
+        let source_shift = (source_x + source_y * 4) * bits;
+        let shift = (x + y * 4) * bits;
+        rotated_indices = (rotated_indices & !(mask << shift)) | (((indices >> source_shift) & mask) << shift);
+      }
+    }
+    let rotated_bytes = rotated_indices.to_le_bytes();
+    let len = indices_bytes.len();
+    indices_bytes.copy_from_slice(&rotated_bytes[..len]);
+  }
+}
+
+fn convert_vtf_texture_format(texture_format: VTFTextureFormat, srgb: bool) -> Option<Format> {
+  match (texture_format, srgb) {
+    (VTFTextureFormat::DXT1, false) => Some(Format::DXT1),
+    (VTFTextureFormat::DXT1, true) => Some(Format::DXT1Srgb),
+    (VTFTextureFormat::DXT1OneBitAlpha, false) => Some(Format::DXT1Alpha),
+    (VTFTextureFormat::DXT1OneBitAlpha, true) => Some(Format::DXT1AlphaSrgb),
+    (VTFTextureFormat::DXT3, false) => Some(Format::DXT3),
+    (VTFTextureFormat::DXT3, true) => Some(Format::DXT3Srgb),
+    (VTFTextureFormat::DXT5, false) => Some(Format::DXT5),
+    (VTFTextureFormat::DXT5, true) => Some(Format::DXT5Srgb),
+    (VTFTextureFormat::BGRA8888, false) => Some(Format::BGRA8UNorm),
+    (VTFTextureFormat::BGRA8888, true) => Some(Format::BGRA8Srgb),
+    (VTFTextureFormat::ATI1N, _) => Some(Format::BC4),
+    (VTFTextureFormat::ATI2N, _) => Some(Format::BC5),
+    (VTFTextureFormat::RGBA8888, _)
+    | (VTFTextureFormat::BGR888, _)
+    | (VTFTextureFormat::I8, _)
+    | (VTFTextureFormat::A8, _) => Some(if srgb { Format::SRGBA8 } else { Format::RGBA8 }),
+    _ => None
+  }
+}
+
+/// Expands the formats that Vulkan drivers commonly can't sample from to RGBA8.
+fn convert_vtf_texture_data(texture_format: VTFTextureFormat, data: Box<[u8]>) -> Box<[u8]> {
   match texture_format {
-    VTFTextureFormat::DXT1 => Format::DXT1,
-    VTFTextureFormat::DXT1OneBitAlpha => Format::DXT1Alpha,
-    VTFTextureFormat::DXT3 => Format::DXT3,
-    VTFTextureFormat::DXT5 => Format::DXT5,
-    VTFTextureFormat::RGBA8888 => Format::RGBA8,
-    _ => panic!("VTF format {:?} is not supported", texture_format)
-  }
-}
\ No newline at end of file
+    VTFTextureFormat::BGR888 => data.chunks_exact(3).flat_map(|pixel| [pixel[2], pixel[1], pixel[0], 255u8]).collect(),
+    VTFTextureFormat::I8 => data.iter().flat_map(|intensity| [*intensity, *intensity, *intensity, 255u8]).collect(),
+    VTFTextureFormat::A8 => data.iter().flat_map(|alpha| [0u8, 0u8, 0u8, *alpha]).collect(),
+    _ => data
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn rgba_vtf_bytes(width: u16, height: u16) -> Vec<u8> {
+    let pixels: Vec<u8> = (0..(width as usize * height as usize)).flat_map(|i| [i as u8, 0u8, 0u8, 255u8]).collect();
+    vtf_bytes(width, height, VTFTextureFormat::RGBA8888, TextureFlags::empty(), 1, &pixels)
+  }
+
+  /// A version 7.2 VTF with a single mip map, `image_data` holds all frames of it.
+  fn vtf_bytes(width: u16, height: u16, format: VTFTextureFormat, flags: TextureFlags, frames: u16, image_data: &[u8]) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend_from_slice(b"VTF\0");
+    data.extend_from_slice(&7u32.to_le_bytes());
+    data.extend_from_slice(&2u32.to_le_bytes());
+    data.extend_from_slice(&80u32.to_le_bytes()); // header size
+    data.extend_from_slice(&width.to_le_bytes());
+    data.extend_from_slice(&height.to_le_bytes());
+    data.extend_from_slice(&flags.bits().to_le_bytes());
+    data.extend_from_slice(&frames.to_le_bytes());
+    data.extend_from_slice(&0u16.to_le_bytes()); // first frame
+    data.extend_from_slice(&[0u8; 4]);
+    data.extend_from_slice(&[0u8; 12]); // reflectivity
+    data.extend_from_slice(&[0u8; 4]);
+    data.extend_from_slice(&1f32.to_le_bytes()); // bumpmap scale
+    data.extend_from_slice(&(format as u32).to_le_bytes());
+    data.push(1); // mip map count
+    data.extend_from_slice(&u32::MAX.to_le_bytes()); // no thumbnail
+    data.push(0);
+    data.push(0);
+    data.extend_from_slice(&1u16.to_le_bytes()); // depth
+    data.resize(80, 0);
+    data.extend_from_slice(image_data);
+    data
+  }
+
+  #[test]
+  fn corrupt_vtf_fails_without_affecting_later_loads() {
+    let mut truncated = rgba_vtf_bytes(4, 4);
+    truncated.truncate(90);
+    let error = load_texture(Cursor::new(truncated)).err().expect("Truncated VTF loaded successfully");
+    assert!(error.contains("mip map"));
+
+    let garbage = b"VTF\0garbage".to_vec();
+    let error = load_texture(Cursor::new(garbage)).err().expect("Corrupt VTF loaded successfully");
+    assert!(error.contains("header"));
+
+    let texture = load_texture(Cursor::new(rgba_vtf_bytes(4, 4))).unwrap();
+    assert_eq!((texture.info.width, texture.info.height), (4, 4));
+    assert_eq!(texture.info.format, Format::RGBA8);
+    assert_eq!(texture.data.len(), 1);
+    assert_eq!(texture.data[0].len(), 4 * 4 * 4);
+  }
+
+  #[test]
+  fn ati2n_loads_as_bc5() {
+    // 8x8 is 2x2 blocks of 16 bytes each
+    let blocks: Vec<u8> = (0..64u8).collect();
+    let texture = load_texture(Cursor::new(vtf_bytes(8, 8, VTFTextureFormat::ATI2N, TextureFlags::empty(), 1, &blocks))).unwrap();
+    assert_eq!(texture.info.format, Format::BC5);
+    assert_eq!((texture.info.width, texture.info.height, texture.info.mip_levels), (8, 8, 1));
+    assert_eq!(texture.data.len(), 1);
+    assert_eq!(&texture.data[0][..], &blocks[..]);
+  }
+
+  #[test]
+  fn frames_become_array_layers() {
+    let frames: Vec<u8> = (0..32u8).collect();
+    let texture = load_texture(Cursor::new(vtf_bytes(2, 2, VTFTextureFormat::RGBA8888, TextureFlags::empty(), 2, &frames))).unwrap();
+    assert_eq!(texture.frame_count, 2);
+    assert_eq!(texture.info.array_length, 2);
+    assert!(!texture.info.is_cubemap);
+    // [frame][face][mip] with a single face and mip
+    assert_eq!(texture.data.len(), 2);
+    assert_eq!(&texture.data[0][..], &frames[..16]);
+    assert_eq!(&texture.data[1][..], &frames[16..]);
+  }
+
+  #[test]
+  fn normal_maps_stay_linear() {
+    let pixels = [128u8, 128u8, 255u8, 255u8];
+    let srgb = load_texture(Cursor::new(vtf_bytes(1, 1, VTFTextureFormat::RGBA8888, TextureFlags::SRGB, 1, &pixels))).unwrap();
+    assert_eq!(srgb.info.format, Format::SRGBA8);
+
+    let normal = load_texture(Cursor::new(vtf_bytes(1, 1, VTFTextureFormat::RGBA8888, TextureFlags::SRGB | TextureFlags::NORMAL, 1, &pixels))).unwrap();
+    assert_eq!(normal.info.format, Format::RGBA8);
+    assert_eq!(&normal.data[0][..], &pixels[..]);
+  }
+
+  #[test]
+  fn cube_faces_get_reordered_and_rotated_to_engine_space() {
+    // 7 faces of 2x2 texels, the 7th is the sphere map of version 7.2 environment maps
+    let faces: Vec<u8> = (0..7u8).flat_map(|face| (0..4u8).flat_map(move |texel| [face, texel, 0u8, 255u8])).collect();
+    let texture = load_texture(Cursor::new(vtf_bytes(2, 2, VTFTextureFormat::RGBA8888, TextureFlags::ENV_MAP, 1, &faces))).unwrap();
+    assert!(texture.info.is_cubemap);
+    assert_eq!(texture.info.array_length, 6);
+    assert_eq!(texture.data.len(), 6);
+
+    let first_texels: Vec<(u8, u8)> = texture.data.iter().map(|face| (face[0], face[1])).collect();
+    // +X and -X are rotated by 90 degrees, -Y and -Z by 180 degrees
+    assert_eq!(first_texels, vec![(0, 2), (1, 1), (4, 0), (5, 3), (3, 0), (2, 3)]);
+  }
+
+  #[test]
+  fn rotating_compressed_faces_moves_blocks_and_texels() {
+    // An 8x8 DXT1 face is 2x2 blocks, only the texel indices differ between the blocks
+    let block = |first_index: u8| [0xffu8, 0xff, 0, 0, first_index, 0, 0, 0];
+    let face: Vec<u8> = (0..4u8).flat_map(block).collect();
+    let rotated = rotate_face(VTFTextureFormat::DXT1, &face, 8, FaceRotation::Clockwise180);
+    // The last block ends up first and its first texel ends up last
+    assert_eq!(&rotated[..4], &[0xff, 0xff, 0, 0]);
+    assert_eq!(&rotated[4..8], &[0, 0, 0, 3 << 6]);
+
+    let mut rotated = face.clone().into_boxed_slice();
+    for _ in 0..4 {
+      rotated = rotate_face(VTFTextureFormat::DXT1, &rotated, 8, FaceRotation::Clockwise90);
+    }
+    assert_eq!(&rotated[..], &face[..]);
+  }
+}
@@ -8,8 +8,15 @@ pub use asset_manager::AssetKey;
 pub use asset_manager::AssetManager;
 pub use asset_manager::Mesh;
 pub use asset_manager::MeshRange;
+pub use asset_manager::SkinningVertex;
+pub use asset_manager::Skeleton;
+pub use asset_manager::SkeletonBone;
 pub use asset_manager::Texture;
 pub use asset_manager::Model;
+pub use asset_manager::ModelLod;
 pub use asset_manager::Material;
+pub use asset_manager::MaterialTexture;
+pub use asset_manager::AlphaMode;
 pub use asset_manager::AssetLoaderProgress;
 pub use asset_manager::AssetLoadPriority;
+pub use asset_manager::AssetError;
@@ -1,7 +1,54 @@
 use legion::Entity;
+use sourcerenderer_core::Matrix4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+  /// Horizontal field of view in radians
+  Perspective { fov: f32 },
+  /// Height of the visible area in world units, the width follows from the aspect ratio
+  Orthographic { height: f32 }
+}
+
+impl Projection {
+  pub fn matrix(&self, aspect_ratio: f32, z_near: f32, z_far: f32) -> Matrix4 {
+    match *self {
+      Projection::Perspective { fov } => {
+        let vertical_fov = 2f32 * ((fov / 2f32).tan() * (1f32 / aspect_ratio)).atan();
+        Matrix4::new_perspective(aspect_ratio, vertical_fov, z_near, z_far)
+      }
+      Projection::Orthographic { height } => {
+        let half_height = height / 2f32;
+        let half_width = half_height * aspect_ratio;
+        Matrix4::new_orthographic(-half_width, half_width, -half_height, half_height, z_near, z_far)
+      }
+    }
+  }
+}
 
 pub struct Camera {
-  pub fov: f32
+  pub projection: Projection
 }
 
 pub struct ActiveCamera(pub Entity);
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use sourcerenderer_core::Vec4;
+
+  #[test]
+  fn orthographic_matrix_matches_reference() {
+    let matrix = Projection::Orthographic { height: 10f32 }.matrix(2f32, 1f32, 101f32);
+    // 20 x 10 units wide, depth maps from -1 at the near plane to 1 at the far plane
+    let expected = Matrix4::new(
+      0.1f32, 0f32, 0f32, 0f32,
+      0f32, 0.2f32, 0f32, 0f32,
+      0f32, 0f32, -0.02f32, -1.02f32,
+      0f32, 0f32, 0f32, 1f32
+    );
+    assert!((matrix - expected).amax() < 0.0001f32);
+
+    let corner = matrix * Vec4::new(10f32, 5f32, -101f32, 1f32);
+    assert!((corner - Vec4::new(1f32, 1f32, 1f32, 1f32)).amax() < 0.0001f32);
+  }
+}
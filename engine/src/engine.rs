@@ -6,7 +6,7 @@ use sourcerenderer_core::graphics::*;
 use sourcerenderer_core::platform::Window;
 
 use crate::{asset::AssetManager, renderer::RendererInterface};
-use crate::renderer::Renderer;
+use crate::renderer::{Renderer, RendererSettings};
 use crate::game::Game;
 
 const TICK_RATE: u32 = 5;
@@ -24,14 +24,18 @@ impl<P: Platform> Engine<P> {
   }
 
   pub fn run(platform: Box<P>) -> Self {
+    Self::run_with_settings(platform, RendererSettings::default())
+  }
+
+  pub fn run_with_settings(platform: Box<P>, renderer_settings: RendererSettings) -> Self {
     let instance = platform.create_graphics(true).expect("Failed to initialize graphics");
     let surface = platform.window().create_surface(instance.clone());
 
     let mut adapters = instance.clone().list_adapters();
     let device = Arc::new(adapters.remove(0).create_device(&surface));
-    let swapchain = Arc::new(platform.window().create_swapchain(false, &device, &surface));
+    let swapchain = Arc::new(platform.window().create_swapchain(PresentMode::Immediate, &device, &surface));
     let asset_manager = AssetManager::<P>::new(&device);
-    let renderer = Renderer::<P>::run(platform.window(), &instance, &device, &swapchain, &asset_manager);
+    let renderer = Renderer::<P>::run(platform.window(), &instance, &device, &swapchain, &asset_manager, renderer_settings);
     let game = Game::<P>::run(&renderer, &asset_manager, TICK_RATE);
     Self {
       renderer,
@@ -15,10 +15,23 @@ pub fn install<P: Platform>(_world: &mut World, systems: &mut Builder) {
 }
 
 pub struct FPSCameraComponent {
+  pub move_speed: f32,
+  pub sprint_multiplier: f32
+}
+
+impl Default for FPSCameraComponent {
+  fn default() -> Self {
+    Self {
+      move_speed: 3f32,
+      sprint_multiplier: 2.5f32
+    }
+  }
 }
 
 pub struct FPSCamera {
   sensitivity: f32,
+  stick_sensitivity: f32,
+  stick_deadzone: f32,
   pitch: f32,
   yaw: f32,
   last_touch_position: Vec2
@@ -28,14 +41,40 @@ impl FPSCamera {
   pub fn new() -> Self {
     FPSCamera {
       sensitivity: 10.0f32,
+      stick_sensitivity: 2.5f32,
+      stick_deadzone: 0.15f32,
       pitch: 0f32,
       yaw: 0f32,
       last_touch_position: Vec2::new(0f32, 0f32)
     }
   }
+
+  pub fn set_stick_sensitivity(&mut self, stick_sensitivity: f32) {
+    self.stick_sensitivity = stick_sensitivity;
+  }
+
+  pub fn set_stick_deadzone(&mut self, stick_deadzone: f32) {
+    self.stick_deadzone = stick_deadzone;
+  }
+
+  /// Applies the deadzone to both sticks before the input reaches the camera rotation and movement.
+  pub fn apply_stick_deadzone(&self, input: &mut InputState) {
+    input.set_left_stick(apply_deadzone(input.left_stick(), self.stick_deadzone));
+    input.set_right_stick(apply_deadzone(input.right_stick(), self.stick_deadzone));
+  }
 }
 
-pub fn fps_camera_rotation<P: Platform>(input: &InputState, fps_camera: &mut FPSCamera, _delta_time: f32) -> Quaternion {
+/// Ignores small stick deflections and rescales the rest so the output still covers the full range.
+fn apply_deadzone(stick: Vec2, deadzone: f32) -> Vec2 {
+  let length = stick.norm();
+  if length <= deadzone {
+    return Vec2::new(0f32, 0f32);
+  }
+  let scaled_length = ((length - deadzone) / (1f32 - deadzone)).min(1f32);
+  stick * (scaled_length / length)
+}
+
+pub fn fps_camera_rotation(input: &InputState, fps_camera: &mut FPSCamera, delta_time: f32) -> Quaternion {
   let mouse_delta = input.mouse_position();
   let touch_position = input.finger_position(0);
   let touch_delta = if fps_camera.last_touch_position.x.abs() > 0.1f32 && fps_camera.last_touch_position.y.abs() > 0.1f32
@@ -48,8 +87,15 @@ pub fn fps_camera_rotation<P: Platform>(input: &InputState, fps_camera: &mut FPS
   fps_camera.yaw -= mouse_delta.x as f32 / 20_000f32 * fps_camera.sensitivity;
   fps_camera.pitch -= touch_delta.y / 20_000f32 * fps_camera.sensitivity;
   fps_camera.yaw -= touch_delta.x / 20_000f32 * fps_camera.sensitivity;
+  // The stick controls the rotation speed rather than the rotation itself
+  let right_stick = input.right_stick();
+  fps_camera.pitch -= right_stick.y * fps_camera.stick_sensitivity * delta_time;
+  fps_camera.yaw -= right_stick.x * fps_camera.stick_sensitivity * delta_time;
 
+  // Looking past straight up or down would flip the camera
   fps_camera.pitch = fps_camera.pitch.max(-std::f32::consts::FRAC_PI_2 + 0.01f32).min(std::f32::consts::FRAC_PI_2 - 0.01f32);
+  // Keep yaw small so it doesn't lose precision after a lot of turning
+  fps_camera.yaw = fps_camera.yaw.rem_euclid(2f32 * std::f32::consts::PI);
 
   fps_camera.last_touch_position = touch_position;
   Quaternion::from_euler_angles(fps_camera.pitch, fps_camera.yaw, 0f32)
@@ -63,7 +109,13 @@ fn retrieve_fps_camera_rotation<P: Platform>(#[resource] late_latch_camera: &Arc
 
 #[system(for_each)]
 #[filter(component::<Camera>() & component::<FPSCameraComponent>())]
-fn fps_camera_movement<P: Platform>(#[resource] input: &InputState, transform: &mut Transform, #[resource] tick_rate: &TickRate) {
+fn fps_camera_movement<P: Platform>(#[resource] input: &InputState, transform: &mut Transform, fps_camera: &FPSCameraComponent, #[resource] tick_rate: &TickRate) {
+  transform.position += fps_camera_movement_delta(input, &transform.rotation, fps_camera, tick_rate.0);
+}
+
+/// Returns how far the camera moves in one tick.
+fn fps_camera_movement_delta(input: &InputState, rotation: &Quaternion, fps_camera: &FPSCameraComponent, tick_rate: u32) -> Vec3 {
+  // The camera looks along -Z with +X to its right
   let mut movement_vector = Vec3::new(0f32, 0f32, 0f32);
   if input.is_key_down(Key::W) {
     movement_vector.z -= 1f32;
@@ -77,23 +129,150 @@ fn fps_camera_movement<P: Platform>(#[resource] input: &InputState, transform: &
   if input.is_key_down(Key::D) {
     movement_vector.x += 1f32;
   }
-  if input.is_key_down(Key::Q) {
+  if input.is_key_down(Key::Q) || input.is_key_down(Key::Space) {
     movement_vector.y += 1f32;
   }
-  if input.is_key_down(Key::E) {
+  if input.is_key_down(Key::E) || input.is_key_down(Key::LCtrl) {
     movement_vector.y -= 1f32;
   }
+  let left_stick = input.left_stick();
+  movement_vector.x += left_stick.x;
+  movement_vector.z += left_stick.y;
+  movement_vector.y += input.right_trigger() - input.left_trigger();
+
+  // Keys are always fully pressed, sticks and triggers can move slower
+  let analog_scale = Vec2::new(movement_vector.x, movement_vector.z).norm().max(movement_vector.y.abs()).min(1f32);
 
   if movement_vector.x.abs() > 0.00001f32 || movement_vector.z.abs() > 0.00001f32 {
     let y = movement_vector.y;
     movement_vector = movement_vector.normalize();
     movement_vector = Vec3::new(movement_vector.x, 0.0f32, movement_vector.z).normalize();
-    movement_vector = transform.rotation.transform_vector(&movement_vector);
+    movement_vector = rotation.transform_vector(&movement_vector);
     movement_vector.y = y;
   }
 
   if movement_vector.x.abs() > 0.00001f32 || movement_vector.y.abs() > 0.00001f32 || movement_vector.z.abs() > 0.00001f32 {
+    let mut speed = fps_camera.move_speed * analog_scale;
+    if input.is_key_down(Key::LShift) {
+      speed *= fps_camera.sprint_multiplier;
+    }
     movement_vector = movement_vector.normalize();
-    transform.position += movement_vector * 8f32 / (tick_rate.0 as f32);
+    return movement_vector * speed / (tick_rate as f32);
+  }
+  Vec3::new(0f32, 0f32, 0f32)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use sourcerenderer_core::Vec2I;
+
+  #[test]
+  fn sprinting_scales_the_movement() {
+    let fps_camera = FPSCameraComponent::default();
+    let rotation = Quaternion::identity();
+    let mut input = InputState::new();
+    input.set_key_down(Key::W, true);
+    let walk = fps_camera_movement_delta(&input, &rotation, &fps_camera, 20);
+    assert!((walk - Vec3::new(0f32, 0f32, -3f32 / 20f32)).norm() < 0.0001f32);
+
+    input.set_key_down(Key::LShift, true);
+    let sprint = fps_camera_movement_delta(&input, &rotation, &fps_camera, 20);
+    assert!((sprint - walk * fps_camera.sprint_multiplier).norm() < 0.0001f32);
+  }
+
+  #[test]
+  fn right_stick_rotates_after_deadzone() {
+    let mut fps_camera = FPSCamera::new();
+    fps_camera.set_stick_deadzone(0.2f32);
+    fps_camera.set_stick_sensitivity(2f32);
+
+    let mut input = InputState::new();
+    input.set_right_stick(Vec2::new(0.1f32, -0.1f32));
+    fps_camera.apply_stick_deadzone(&mut input);
+    fps_camera_rotation(&input, &mut fps_camera, 1f32);
+    assert_eq!((fps_camera.yaw, fps_camera.pitch), (0f32, 0f32));
+
+    // 0.6 is halfway between the deadzone and a full deflection
+    let mut input = InputState::new();
+    input.set_right_stick(Vec2::new(0.6f32, 0f32));
+    fps_camera.apply_stick_deadzone(&mut input);
+    fps_camera_rotation(&input, &mut fps_camera, 0.1f32);
+    let yaw = 2f32 * std::f32::consts::PI - 0.5f32 * 2f32 * 0.1f32;
+    assert!((fps_camera.yaw - yaw).abs() < 0.0001f32);
+
+    let mut input = InputState::new();
+    input.set_right_stick(Vec2::new(0f32, 0.6f32));
+    input.set_left_stick(Vec2::new(0.1f32, 0f32));
+    fps_camera.apply_stick_deadzone(&mut input);
+    assert_eq!(input.left_stick(), Vec2::new(0f32, 0f32));
+    fps_camera_rotation(&input, &mut fps_camera, 0.1f32);
+    assert!((fps_camera.pitch + 0.1f32).abs() < 0.0001f32);
+  }
+
+  #[test]
+  fn diagonal_movement_is_normalized() {
+    let fps_camera = FPSCameraComponent::default();
+    let mut input = InputState::new();
+    input.set_key_down(Key::W, true);
+    input.set_key_down(Key::D, true);
+    let delta = fps_camera_movement_delta(&input, &Quaternion::identity(), &fps_camera, 20);
+    assert!((delta.norm() - 3f32 / 20f32).abs() < 0.0001f32);
+  }
+
+  #[test]
+  fn a_strafes_left_and_d_strafes_right() {
+    let fps_camera = FPSCameraComponent::default();
+    let mut input = InputState::new();
+    input.set_key_down(Key::A, true);
+    // Facing -Z, the right side of the camera is +X
+    let left = fps_camera_movement_delta(&input, &Quaternion::identity(), &fps_camera, 1);
+    assert!((left - Vec3::new(-3f32, 0f32, 0f32)).norm() < 0.0001f32);
+
+    // Facing +Z, the right side of the camera is -X
+    let facing_z = Quaternion::from_euler_angles(0f32, std::f32::consts::PI, 0f32);
+    let left = fps_camera_movement_delta(&input, &facing_z, &fps_camera, 1);
+    assert!((left - Vec3::new(3f32, 0f32, 0f32)).norm() < 0.0001f32);
+
+    input.set_key_down(Key::A, false);
+    input.set_key_down(Key::D, true);
+    let right = fps_camera_movement_delta(&input, &facing_z, &fps_camera, 1);
+    assert!((right - Vec3::new(-3f32, 0f32, 0f32)).norm() < 0.0001f32);
+  }
+
+  #[test]
+  fn space_and_ctrl_move_vertically() {
+    let fps_camera = FPSCameraComponent::default();
+    // Looking down doesn't change which way is up
+    let looking_down = Quaternion::from_euler_angles(-1f32, 0f32, 0f32);
+    let mut input = InputState::new();
+    input.set_key_down(Key::Space, true);
+    let up = fps_camera_movement_delta(&input, &looking_down, &fps_camera, 1);
+    assert!((up - Vec3::new(0f32, 3f32, 0f32)).norm() < 0.0001f32);
+
+    input.set_key_down(Key::Space, false);
+    input.set_key_down(Key::LCtrl, true);
+    let down = fps_camera_movement_delta(&input, &looking_down, &fps_camera, 1);
+    assert!((down - Vec3::new(0f32, -3f32, 0f32)).norm() < 0.0001f32);
+  }
+
+  #[test]
+  fn pitch_stays_clamped_below_straight_up_and_down() {
+    let mut fps_camera = FPSCamera::new();
+    let mut input = InputState::new();
+    // Moving the mouse up is a negative delta in screen space
+    input.set_mouse_pos(Vec2I::new(0, -20_000));
+    for _ in 0..100 {
+      fps_camera_rotation(&input, &mut fps_camera, 1f32 / 60f32);
+    }
+    assert!(fps_camera.pitch < std::f32::consts::FRAC_PI_2);
+    assert!(fps_camera.pitch > std::f32::consts::FRAC_PI_2 - 0.02f32);
+
+    input.set_mouse_pos(Vec2I::new(0, 20_000));
+    for _ in 0..100 {
+      fps_camera_rotation(&input, &mut fps_camera, 1f32 / 60f32);
+    }
+    assert!(fps_camera.pitch > -std::f32::consts::FRAC_PI_2);
+    assert!(fps_camera.pitch < -std::f32::consts::FRAC_PI_2 + 0.02f32);
   }
 }
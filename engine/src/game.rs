@@ -52,7 +52,7 @@ impl<P: Platform> Game<P> {
   pub fn run(renderer: &Arc<Renderer<P>>,
                           asset_manager: &Arc<AssetManager<P>>,
                           tick_rate: u32) -> Arc<Self> {
-    asset_manager.add_loader(Box::new(BspLevelLoader::new()));
+    asset_manager.add_loader(Box::new(BspLevelLoader::new().with_smooth_normals(true)));
     asset_manager.add_loader(Box::new(VPKContainerLoader::new()));
     asset_manager.add_loader(Box::new(VTFTextureLoader::new()));
     asset_manager.add_loader(Box::new(VMTMaterialLoader::new()));
@@ -101,6 +101,7 @@ impl<P: Platform> Game<P> {
 
       crate::spinning_cube::install(&mut world, &mut resources, &mut fixed_schedule, &c_asset_manager);
       fps_camera::install::<P>(&mut world, &mut fixed_schedule);
+      crate::animation::install(&mut fixed_schedule);
       transform::interpolation::install(&mut fixed_schedule, &mut schedule);
       transform::install(&mut fixed_schedule);
       c_renderer.install(&mut world, &mut resources, &mut schedule);
@@ -162,7 +163,8 @@ impl<P: Platform> Game<P> {
     game
   }
 
-  pub fn update_input_state(&self, input_state: InputState) {
+  pub fn update_input_state(&self, mut input_state: InputState) {
+    self.fps_camera.lock().unwrap().apply_stick_deadzone(&mut input_state);
     {
       let mut input_guard = self.input_state.lock().unwrap();
       let now = SystemTime::now();
@@ -172,7 +174,7 @@ impl<P: Platform> Game<P> {
         let delta = now.duration_since(input_guard.1).unwrap();
         {
           let mut fps_camera = self.fps_camera.lock().unwrap();
-          self.late_latch_camera.update_rotation(fps_camera_rotation::<P>(&input_state, &mut fps_camera, delta.as_secs_f32()));
+          self.late_latch_camera.update_rotation(fps_camera_rotation(&input_state, &mut fps_camera, delta.as_secs_f32()));
         }
       }
 
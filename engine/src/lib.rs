@@ -26,6 +26,7 @@ pub use self::engine::Engine;
 pub use transform::Transform;
 pub use transform::Parent;
 pub use camera::Camera;
+pub use camera::Projection;
 pub use camera::ActiveCamera;
 
 #[cfg(feature = "threading")]
@@ -36,6 +37,7 @@ mod spinning_cube;
 pub mod transform;
 mod camera;
 pub mod fps_camera;
+pub mod animation;
 mod math;
 
 pub mod renderer;
@@ -12,7 +12,8 @@ pub struct Frustum {
   near_half_width: f32,
   near_half_height: f32,
   z_near: f32,
-  z_far: f32
+  z_far: f32,
+  planes: Option<[Vec4; 6]>
 }
 
 impl Frustum {
@@ -23,16 +24,87 @@ impl Frustum {
       near_half_width,
       near_half_height,
       z_near: -z_near,
-      z_far: -z_far
+      z_far: -z_far,
+      planes: None
     }
   }
 
-  pub fn intersects(&self, bounding_box: &BoundingBox, mvp: &Matrix4) -> bool {
+  /// Builds a box shaped frustum in view space for an orthographic camera looking along -Z.
+  pub fn new_orthographic(z_near: f32, z_far: f32, height: f32, aspect_ratio: f32) -> Self {
+    let half_height = height / 2f32;
+    let half_width = half_height * aspect_ratio;
+    let planes = [
+      Vec4::new(1f32, 0f32, 0f32, half_width), // left
+      Vec4::new(-1f32, 0f32, 0f32, half_width), // right
+      Vec4::new(0f32, 1f32, 0f32, half_height), // bottom
+      Vec4::new(0f32, -1f32, 0f32, half_height), // top
+      Vec4::new(0f32, 0f32, -1f32, -z_near), // near
+      Vec4::new(0f32, 0f32, 1f32, z_far) // far
+    ];
+    Self {
+      near_half_width: half_width,
+      near_half_height: half_height,
+      z_near: -z_near,
+      z_far: -z_far,
+      planes: Some(planes)
+    }
+  }
+
+  /// Builds the frustum from an arbitrary (perspective or orthographic) view projection matrix.
+  pub fn from_matrix(view_projection: &Matrix4) -> Self {
+    let row = |index: usize| -> Vec4 {
+      let row = view_projection.row(index);
+      Vec4::new(row[0], row[1], row[2], row[3])
+    };
+    let planes = [
+      row(3) + row(0), // left
+      row(3) - row(0), // right
+      row(3) + row(1), // bottom
+      row(3) - row(1), // top
+      row(3) + row(2), // near, also covers projections with a depth range of 0..1
+      row(3) - row(2)  // far
+    ];
+    Self {
+      near_half_width: 0f32,
+      near_half_height: 0f32,
+      z_near: 0f32,
+      z_far: 0f32,
+      planes: Some(planes)
+    }
+  }
+
+  fn intersects_planes(planes: &[Vec4; 6], bounding_box: &BoundingBox, transform: &Matrix4) -> bool {
+    let center = (bounding_box.min + bounding_box.max) * 0.5f32;
+    let half_extents = (bounding_box.max - bounding_box.min) * 0.5f32;
+    let transformed_center = (transform * Vec4::new(center.x, center.y, center.z, 1f32)).xyz();
+    let transformed_axes = [
+      (transform * Vec4::new(half_extents.x, 0f32, 0f32, 0f32)).xyz(),
+      (transform * Vec4::new(0f32, half_extents.y, 0f32, 0f32)).xyz(),
+      (transform * Vec4::new(0f32, 0f32, half_extents.z, 0f32)).xyz()
+    ];
+    for plane in planes.iter() {
+      let normal = plane.xyz();
+      let radius = transformed_axes.iter().map(|axis| normal.dot(axis).abs()).sum::<f32>();
+      if normal.dot(&transformed_center) + plane.w < -radius {
+        return false;
+      }
+    }
+    true
+  }
+
+  /// Transform moves the bounding box into the space the frustum was built in.
+  /// That's the model view matrix for frustums from new and new_orthographic, which live in view space,
+  /// and just the model matrix for frustums from from_matrix because their planes already include the view.
+  pub fn intersects(&self, bounding_box: &BoundingBox, transform: &Matrix4) -> bool {
+    if let Some(planes) = &self.planes {
+      return Frustum::intersects_planes(planes, bounding_box, transform);
+    }
+
     let corners = [
-      (mvp * Vec4::new(bounding_box.min.x, bounding_box.min.y, bounding_box.min.z, 1f32)).xyz(),
-      (mvp * Vec4::new(bounding_box.max.x, bounding_box.min.y, bounding_box.min.z, 1f32)).xyz(),
-      (mvp * Vec4::new(bounding_box.min.x, bounding_box.max.y, bounding_box.min.z, 1f32)).xyz(),
-      (mvp * Vec4::new(bounding_box.min.x, bounding_box.min.y, bounding_box.max.z, 1f32)).xyz()
+      (transform * Vec4::new(bounding_box.min.x, bounding_box.min.y, bounding_box.min.z, 1f32)).xyz(),
+      (transform * Vec4::new(bounding_box.max.x, bounding_box.min.y, bounding_box.min.z, 1f32)).xyz(),
+      (transform * Vec4::new(bounding_box.min.x, bounding_box.max.y, bounding_box.min.z, 1f32)).xyz(),
+      (transform * Vec4::new(bounding_box.min.x, bounding_box.min.y, bounding_box.max.z, 1f32)).xyz()
     ];
 
     let axes = [
@@ -285,3 +357,39 @@ impl Frustum {
 // https://bruop.github.io/improved_frustum_culling/
 // http://davidlively.com/programming/graphics/frustum-calculation-and-culling-hopefully-demystified/
 // https://gist.github.com/BruOp/60e862049ac6409d2fd4ec6fa5806b30
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn small_box_at(x: f32, y: f32, z: f32) -> (BoundingBox, Matrix4) {
+    let bounding_box = BoundingBox::new(Vec3::new(-0.1f32, -0.1f32, -0.1f32), Vec3::new(0.1f32, 0.1f32, 0.1f32));
+    (bounding_box, Matrix4::new_translation(&Vec3::new(x, y, z)))
+  }
+
+  #[test]
+  fn orthographic_frustum_has_parallel_sides() {
+    // 20 x 10 units wide no matter how far away
+    let frustum = Frustum::new_orthographic(1f32, 101f32, 10f32, 2f32);
+    let inside = [(9.5f32, 4.5f32, -2f32), (9.5f32, 4.5f32, -100f32), (-9.5f32, -4.5f32, -50f32)];
+    for &(x, y, z) in &inside {
+      let (bounding_box, transform) = small_box_at(x, y, z);
+      assert!(frustum.intersects(&bounding_box, &transform), "{} {} {} should be visible", x, y, z);
+    }
+    let outside = [(11f32, 0f32, -100f32), (0f32, -6f32, -2f32), (0f32, 0f32, -0.5f32), (0f32, 0f32, -102f32), (0f32, 0f32, 5f32)];
+    for &(x, y, z) in &outside {
+      let (bounding_box, transform) = small_box_at(x, y, z);
+      assert!(!frustum.intersects(&bounding_box, &transform), "{} {} {} should be culled", x, y, z);
+    }
+  }
+
+  #[test]
+  fn orthographic_matrix_frustum_culls_outside_points() {
+    let projection = Matrix4::new_orthographic(-10f32, 10f32, -5f32, 5f32, 1f32, 101f32);
+    let frustum = Frustum::from_matrix(&projection);
+    let (bounding_box, transform) = small_box_at(9.5f32, 4.5f32, -100f32);
+    assert!(frustum.intersects(&bounding_box, &transform));
+    let (bounding_box, transform) = small_box_at(11f32, 0f32, -100f32);
+    assert!(!frustum.intersects(&bounding_box, &transform));
+  }
+}
@@ -6,6 +6,8 @@ use std::sync::atomic::{AtomicU32, Ordering};
 use crossbeam_utils::atomic::AtomicCell;
 use nalgebra::Point3;
 
+use crate::Projection;
+
 #[derive(Clone)]
 #[repr(C)]
 struct PrimaryCameraBuffer {
@@ -24,7 +26,7 @@ pub struct LateLatchCamera<B: Backend> {
   position: AtomicCell<Vec3>, // AtomicCell uses a mutex for big structs, replace it by something like a lock less ring buffer
   rotation: AtomicCell<Quaternion>,
   aspect_ratio: f32,
-  fov: f32,
+  projection: AtomicCell<Projection>,
   z_near: f32,
   z_far: f32
 }
@@ -43,7 +45,7 @@ impl<B: Backend> LateLatchCamera<B> {
       position: AtomicCell::new(Vec3::new(0f32, 0f32, 0f32)),
       rotation: AtomicCell::new(Quaternion::identity()),
       aspect_ratio,
-      fov,
+      projection: AtomicCell::new(Projection::Perspective { fov }),
       z_near: 0.1f32,
       z_far: 100f32
     };
@@ -59,14 +61,24 @@ impl<B: Backend> LateLatchCamera<B> {
     self.z_far
   }
 
-  pub fn fov(&self) -> f32 {
-    self.fov
+  pub fn projection(&self) -> Projection {
+    self.projection.load()
+  }
+
+  pub fn update_projection_mode(&self, projection: Projection) {
+    if self.projection.swap(projection) != projection {
+      self.update_projection(self.proj());
+    }
   }
 
   pub fn aspect_ratio(&self) -> f32 {
     self.aspect_ratio
   }
 
+  pub fn position(&self) -> Vec3 {
+    self.position.load()
+  }
+
   pub fn rotation(&self) -> Quaternion {
     self.rotation.load()
   }
@@ -114,8 +126,7 @@ impl<B: Backend> LateLatchCamera<B> {
   }
 
   pub fn proj(&self) -> Matrix4 {
-    let vertical_fov = 2f32 * ((self.fov / 2f32).tan() * (1f32 / self.aspect_ratio)).atan();
-    Matrix4::new_perspective(self.aspect_ratio, vertical_fov, self.z_near, self.z_far)
+    self.projection.load().matrix(self.aspect_ratio, self.z_near, self.z_far)
   }
 
   pub fn get_camera(&self) -> Matrix4 {
@@ -1,11 +1,18 @@
+use std::path::PathBuf;
+
 use legion::Entity;
-use sourcerenderer_core::Matrix4;
+use sourcerenderer_core::{Matrix4, Vec3};
+
+use crate::Projection;
+
+use super::{DebugView, TonemappingOperator};
 
 pub enum RendererCommand {
   RegisterStatic {
     entity: Entity,
     transform: Matrix4,
     model_path: String,
+    skin: u32,
     receive_shadows: bool,
     cast_shadows: bool,
     can_move: bool
@@ -17,7 +24,26 @@ pub enum RendererCommand {
     intensity: f32
   },
   UnregisterPointLight(Entity),
+  RegisterDirectionalLight {
+    entity: Entity,
+    transform: Matrix4,
+    color: Vec3,
+    intensity: f32
+  },
+  UnregisterDirectionalLight(Entity),
+  RegisterSpotLight {
+    entity: Entity,
+    transform: Matrix4,
+    intensity: f32,
+    angle: f32
+  },
+  UnregisterSpotLight(Entity),
   UpdateTransform{ entity: Entity, transform_mat: Matrix4 },
-  UpdateCameraTransform { camera_transform_mat: Matrix4, fov: f32 },
+  UpdateCameraTransform { camera_transform_mat: Matrix4, projection: Projection },
+  SetTonemapping { operator: TonemappingOperator, exposure: f32 },
+  SetDebugView(DebugView),
+  SetIndirectDraws(bool),
+  RequestScreenshot(PathBuf),
+  SetCameraSmoothing(f32),
   EndFrame
 }
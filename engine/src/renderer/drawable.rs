@@ -4,10 +4,14 @@ use legion::Entity;
 use std::{sync::Arc, usize};
 use std::f32;
 use sourcerenderer_core::graphics::Backend;
+use crate::Projection;
 use crate::renderer::renderer_assets::*;
 
 pub(super) struct RendererStaticDrawable<B: Backend> {
   pub(super) entity: Entity,
+  /// The path the model was requested with, model_path points at the skin instead
+  pub(super) asset_path: String,
+  pub(super) model_path: String,
   pub(super) transform: Matrix4,
   pub(super) old_transform: Matrix4,
   pub(super) model: Arc<RendererModel<B>>,
@@ -22,7 +26,7 @@ pub(crate) struct View {
   pub(super) proj_matrix: Matrix4,
   pub(super) old_camera_matrix: Matrix4,
   pub(super) camera_transform: Matrix4,
-  pub(super) camera_fov: f32,
+  pub(super) camera_projection: Projection,
   pub(super) near_plane: f32,
   pub(super) far_plane: f32,
   pub(super) drawable_parts: Vec<DrawablePart>
@@ -35,7 +39,7 @@ impl Default for View {
       old_camera_matrix: Matrix4::identity(),
       view_matrix: Matrix4::identity(),
       proj_matrix: Matrix4::identity(),
-      camera_fov: f32::consts::PI / 2f32,
+      camera_projection: Projection::Perspective { fov: f32::consts::PI / 2f32 },
       near_plane: 0.1f32,
       far_plane: 100f32,
       drawable_parts: Vec::new()
@@ -46,5 +50,6 @@ impl Default for View {
 #[derive(Clone)]
 pub struct DrawablePart {
   pub(super) drawable_index: usize,
-  pub(super) part_index: usize
+  pub(super) part_index: usize,
+  pub(super) lod: usize
 }
@@ -4,8 +4,8 @@ use legion::{Entity, IntoQuery, maybe_changed, EntityStore};
 use legion::systems::Builder;
 use legion::component;
 use legion::world::SubWorld;
-use crate::{ActiveCamera, Camera};
-use sourcerenderer_core::{Matrix4, Platform};
+use crate::{ActiveCamera, Camera, Projection};
+use sourcerenderer_core::{Matrix4, Platform, Vec3};
 use crate::transform::interpolation::InterpolatedTransform;
 
 pub trait RendererInterface {
@@ -13,7 +13,11 @@ pub trait RendererInterface {
   fn unregister_static_renderable(&self, entity: Entity);
   fn register_point_light(&self, entity: Entity, transform: &InterpolatedTransform, point_light: &PointLightComponent);
   fn unregister_point_light(&self, entity: Entity);
-  fn update_camera_transform(&self, camera_transform_mat: Matrix4, fov: f32);
+  fn register_directional_light(&self, entity: Entity, transform: &InterpolatedTransform, directional_light: &DirectionalLightComponent);
+  fn unregister_directional_light(&self, entity: Entity);
+  fn register_spot_light(&self, entity: Entity, transform: &InterpolatedTransform, spot_light: &SpotLightComponent);
+  fn unregister_spot_light(&self, entity: Entity);
+  fn update_camera_transform(&self, camera_transform_mat: Matrix4, projection: Projection);
   fn update_transform(&self, entity: Entity, transform: Matrix4);
   fn end_frame(&self);
   fn is_saturated(&self) -> bool;
@@ -23,6 +27,8 @@ pub trait RendererInterface {
 #[derive(Clone, Debug, PartialEq)]
 pub struct StaticRenderableComponent {
   pub model_path: String,
+  /// Selects one of the material sets of the model, falls back to the default one if the model doesn't have it
+  pub skin: u32,
   pub receive_shadows: bool,
   pub cast_shadows: bool,
   pub can_move: bool
@@ -33,6 +39,20 @@ pub struct PointLightComponent {
   pub intensity: f32
 }
 
+/// Shines along the negative Z axis of the entity transform.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DirectionalLightComponent {
+  pub color: Vec3,
+  pub intensity: f32
+}
+
+/// Shines along the negative Z axis of the entity transform, angle is the half angle of the cone in radians.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpotLightComponent {
+  pub intensity: f32,
+  pub angle: f32
+}
+
 #[derive(Clone, Default, Debug)]
 pub struct ActiveStaticRenderables(HashSet<Entity>);
 #[derive(Clone, Default, Debug)]
@@ -41,15 +61,25 @@ pub struct RegisteredStaticRenderables(HashSet<Entity>);
 pub struct ActivePointLights(HashSet<Entity>);
 #[derive(Clone, Default, Debug)]
 pub struct RegisteredPointLights(HashSet<Entity>);
+#[derive(Clone, Default, Debug)]
+pub struct ActiveDirectionalLights(HashSet<Entity>);
+#[derive(Clone, Default, Debug)]
+pub struct RegisteredDirectionalLights(HashSet<Entity>);
+#[derive(Clone, Default, Debug)]
+pub struct ActiveSpotLights(HashSet<Entity>);
+#[derive(Clone, Default, Debug)]
+pub struct RegisteredSpotLights(HashSet<Entity>);
 
 pub fn install<P: Platform, R: RendererInterface + Send + Sync + 'static>(systems: &mut Builder, renderer: R) {
-  systems.add_system(renderer_system::<P, R>(renderer, ActiveStaticRenderables(HashSet::new()), RegisteredStaticRenderables(HashSet::new()), ActivePointLights(HashSet::new()), RegisteredPointLights(HashSet::new())));
+  systems.add_system(renderer_system::<P, R>(renderer, ActiveStaticRenderables(HashSet::new()), RegisteredStaticRenderables(HashSet::new()), ActivePointLights(HashSet::new()), RegisteredPointLights(HashSet::new()), ActiveDirectionalLights(HashSet::new()), RegisteredDirectionalLights(HashSet::new()), ActiveSpotLights(HashSet::new()), RegisteredSpotLights(HashSet::new())));
 }
 
 #[system]
 #[read_component(StaticRenderableComponent)]
 #[read_component(InterpolatedTransform)]
 #[read_component(PointLightComponent)]
+#[read_component(DirectionalLightComponent)]
+#[read_component(SpotLightComponent)]
 #[read_component(Camera)]
 fn renderer<P: Platform, R: RendererInterface + 'static>(world: &mut SubWorld,
             #[state] renderer: &R,
@@ -57,6 +87,10 @@ fn renderer<P: Platform, R: RendererInterface + 'static>(world: &mut SubWorld,
             #[state] registered_static_renderables: &mut RegisteredStaticRenderables,
             #[state] active_point_lights: &mut ActivePointLights,
             #[state] registered_point_lights: &mut RegisteredPointLights,
+            #[state] active_directional_lights: &mut ActiveDirectionalLights,
+            #[state] registered_directional_lights: &mut RegisteredDirectionalLights,
+            #[state] active_spot_lights: &mut ActiveSpotLights,
+            #[state] registered_spot_lights: &mut RegisteredSpotLights,
             #[resource] active_camera: &ActiveCamera) {
   if renderer.is_saturated() {
     return;
@@ -66,7 +100,7 @@ fn renderer<P: Platform, R: RendererInterface + 'static>(world: &mut SubWorld,
   let transform_component = camera_entry.as_ref().and_then(|entry| entry.get_component::<InterpolatedTransform>().ok());
   let camera_component = camera_entry.as_ref().and_then(|entry| entry.get_component::<Camera>().ok());
   if let (Some(camera_component), Some(transform_component)) = (camera_component, transform_component) {
-    renderer.update_camera_transform(transform_component.0, camera_component.fov);
+    renderer.update_camera_transform(transform_component.0, camera_component.projection);
   }
 
   let mut static_components_query = <(Entity, &StaticRenderableComponent, &InterpolatedTransform)>::query();
@@ -131,5 +165,67 @@ fn renderer<P: Platform, R: RendererInterface + 'static>(world: &mut SubWorld,
     }
   });
 
+  let mut directional_lights_query = <(Entity, &DirectionalLightComponent, &InterpolatedTransform)>::query();
+  for (entity, component, transform) in directional_lights_query.iter(world) {
+    if active_directional_lights.0.contains(entity) {
+      continue;
+    }
+
+    if !registered_directional_lights.0.contains(entity) {
+      renderer.register_directional_light(*entity, transform, &component);
+
+      registered_directional_lights.0.insert(*entity);
+    }
+
+    active_directional_lights.0.insert(*entity);
+  }
+
+  let mut directional_lights_update_transforms_query = <(Entity, &InterpolatedTransform)>::query()
+    .filter(component::<DirectionalLightComponent>() & maybe_changed::<InterpolatedTransform>());
+
+  for (entity, transform) in directional_lights_update_transforms_query.iter(world) {
+    renderer.update_transform(*entity, transform.0);
+  }
+
+  registered_directional_lights.0.retain(|entity| {
+    if !active_directional_lights.0.contains(entity) {
+      renderer.unregister_directional_light(*entity);
+      false
+    } else {
+      true
+    }
+  });
+
+  let mut spot_lights_query = <(Entity, &SpotLightComponent, &InterpolatedTransform)>::query();
+  for (entity, component, transform) in spot_lights_query.iter(world) {
+    if active_spot_lights.0.contains(entity) {
+      continue;
+    }
+
+    if !registered_spot_lights.0.contains(entity) {
+      renderer.register_spot_light(*entity, transform, &component);
+
+      registered_spot_lights.0.insert(*entity);
+    }
+
+    active_spot_lights.0.insert(*entity);
+  }
+
+  let mut spot_lights_update_transforms_query = <(Entity, &InterpolatedTransform)>::query()
+    .filter(component::<SpotLightComponent>() & maybe_changed::<InterpolatedTransform>());
+
+  for (entity, transform) in spot_lights_update_transforms_query.iter(world) {
+    renderer.update_transform(*entity, transform.0);
+  }
+
+  registered_spot_lights.0.retain(|entity| {
+    if !active_spot_lights.0.contains(entity) {
+      renderer.unregister_spot_light(*entity);
+      false
+    } else {
+      true
+    }
+  });
+
   renderer.end_frame();
 }
@@ -7,6 +7,23 @@ pub struct PointLight {
   pub intensity: f32
 }
 
+#[derive(Debug, Clone)]
+pub struct DirectionalLight {
+  pub direction: Vec3,
+  pub color: Vec3,
+  pub intensity: f32
+}
+
+#[repr(C)]
+#[derive(Debug, Clone)]
+pub struct SpotLight {
+  pub position: Vec3,
+  pub intensity: f32,
+  pub direction: Vec3,
+  /// Half angle of the cone in radians
+  pub angle: f32
+}
+
 #[repr(C)]
 #[derive(Debug, Clone)]
 pub struct CullingPointLight {
@@ -13,18 +13,25 @@ mod render_path;
 mod camera;
 pub(crate) mod passes;
 mod renderer_assets;
+mod occlusion;
 
 #[cfg(feature = "threading")]
 pub use self::renderer::Renderer;
 
 pub use self::ecs::StaticRenderableComponent;
 pub use self::ecs::PointLightComponent;
+pub use self::ecs::DirectionalLightComponent;
+pub use self::ecs::SpotLightComponent;
 pub use self::drawable::DrawablePart;
 pub use self::camera::LateLatchCamera;
 use self::drawable::View;
 pub use self::ecs::RendererInterface;
 pub use self::command::RendererCommand;
 pub use self::light::PointLight;
+pub use self::light::DirectionalLight;
+pub use self::light::SpotLight;
+pub use self::render_path::{DebugView, RendererSettings, TonemappingOperator};
+pub use self::passes::desktop::desktop_renderer::AntiAliasing;
 use self::drawable::RendererStaticDrawable;
 use self::renderer_scene::RendererScene;
 
@@ -0,0 +1,122 @@
+use sourcerenderer_core::{Matrix4, Vec4};
+
+use crate::math::BoundingBox;
+
+struct DepthMip {
+  width: u32,
+  height: u32,
+  depth: Box<[f32]>
+}
+
+/// A max depth pyramid built from the depth buffer of a previous frame.
+/// Drawables get tested against it using the view projection matrix of that frame.
+pub struct HierarchicalDepthBuffer {
+  view_projection: Matrix4,
+  mips: Vec<DepthMip>
+}
+
+impl HierarchicalDepthBuffer {
+  pub fn new(width: u32, height: u32, depth: &[f32], view_projection: Matrix4) -> Self {
+    assert_eq!(depth.len(), (width * height) as usize);
+    let mut mips = vec![DepthMip {
+      width,
+      height,
+      depth: depth.to_vec().into_boxed_slice()
+    }];
+
+    loop {
+      let last = mips.last().unwrap();
+      if last.width == 1 && last.height == 1 {
+        break;
+      }
+
+      let mip_width = (last.width / 2).max(1);
+      let mip_height = (last.height / 2).max(1);
+      let mut mip_depth = vec![0f32; (mip_width * mip_height) as usize];
+      for y in 0..mip_height {
+        // The last row and column also cover the remaining texel of odd sized mips
+        let y_start = (y * 2).min(last.height - 1);
+        let y_end = if y == mip_height - 1 { last.height } else { y * 2 + 2 };
+        for x in 0..mip_width {
+          let x_start = (x * 2).min(last.width - 1);
+          let x_end = if x == mip_width - 1 { last.width } else { x * 2 + 2 };
+          let mut max_depth = 0f32;
+          for src_y in y_start..y_end {
+            for src_x in x_start..x_end {
+              max_depth = max_depth.max(last.depth[(src_y * last.width + src_x) as usize]);
+            }
+          }
+          mip_depth[(y * mip_width + x) as usize] = max_depth;
+        }
+      }
+      mips.push(DepthMip {
+        width: mip_width,
+        height: mip_height,
+        depth: mip_depth.into_boxed_slice()
+      });
+    }
+
+    Self {
+      view_projection,
+      mips
+    }
+  }
+
+  pub fn is_occluded(&self, bounding_box: &BoundingBox, model_matrix: &Matrix4) -> bool {
+    let model_view_projection = self.view_projection * model_matrix;
+    let mut min_x = f32::MAX;
+    let mut min_y = f32::MAX;
+    let mut max_x = f32::MIN;
+    let mut max_y = f32::MIN;
+    let mut min_depth = f32::MAX;
+    for i in 0..8 {
+      let corner = Vec4::new(
+        if i & 1 == 0 { bounding_box.min.x } else { bounding_box.max.x },
+        if i & 2 == 0 { bounding_box.min.y } else { bounding_box.max.y },
+        if i & 4 == 0 { bounding_box.min.z } else { bounding_box.max.z },
+        1f32
+      );
+      let clip = model_view_projection * corner;
+      if clip.w <= 0f32 {
+        // The box intersects the camera plane
+        return false;
+      }
+      let ndc = clip.xyz() / clip.w;
+      min_x = min_x.min(ndc.x);
+      min_y = min_y.min(ndc.y);
+      max_x = max_x.max(ndc.x);
+      max_y = max_y.max(ndc.y);
+      min_depth = min_depth.min(ndc.z);
+    }
+
+    if min_depth < 0f32 || max_x < -1f32 || min_x > 1f32 || max_y < -1f32 || min_y > 1f32 {
+      // Outside of the area covered by the depth buffer, leave that to frustum culling
+      return false;
+    }
+
+    // The vertex shaders flip Y
+    let base = &self.mips[0];
+    let x_start = (min_x.max(-1f32) * 0.5f32 + 0.5f32) * base.width as f32;
+    let x_end = (max_x.min(1f32) * 0.5f32 + 0.5f32) * base.width as f32;
+    let y_start = (0.5f32 - max_y.min(1f32) * 0.5f32) * base.height as f32;
+    let y_end = (0.5f32 - min_y.max(-1f32) * 0.5f32) * base.height as f32;
+
+    // Pick the coarsest mip at which the box covers at most two texels in each direction
+    let size = (x_end - x_start).max(y_end - y_start).max(1f32);
+    let level = (size.log2().ceil() as usize).min(self.mips.len() - 1);
+    let mip = &self.mips[level];
+    let scale = (1u32 << level) as f32;
+    let mip_x_start = ((x_start / scale) as u32).min(mip.width - 1);
+    let mip_x_end = ((x_end / scale) as u32).min(mip.width - 1);
+    let mip_y_start = ((y_start / scale) as u32).min(mip.height - 1);
+    let mip_y_end = ((y_end / scale) as u32).min(mip.height - 1);
+
+    let mut max_depth = 0f32;
+    for y in mip_y_start..=mip_y_end {
+      for x in mip_x_start..=mip_x_end {
+        max_depth = max_depth.max(mip.depth[(y * mip.width + x) as usize]);
+      }
+    }
+    min_depth > max_depth
+  }
+}
@@ -0,0 +1,349 @@
+use std::{io::Read, path::Path, sync::Arc};
+
+use sourcerenderer_core::{Platform, Vec2, graphics::{AddressMode, Backend as GraphicsBackend, Barrier, BindingFrequency, BufferUsage, CommandBuffer, Device, Filter, Format, PipelineBinding, SampleCount, SamplerInfo, ShaderType, Swapchain, Texture, TextureDimension, TextureInfo, TextureShaderResourceView, TextureShaderResourceViewInfo, TextureUnorderedAccessView, TextureUnorderedAccessViewInfo, TextureUsage}, platform::io::IO};
+
+const BLOOM_MIP_COUNT: u32 = 5;
+
+#[derive(Debug, Clone, Copy)]
+pub struct BloomSettings {
+  pub threshold: f32,
+  pub intensity: f32
+}
+
+impl Default for BloomSettings {
+  fn default() -> Self {
+    Self {
+      // Only pixels that are brighter than white bloom, the input is HDR.
+      threshold: 1.0f32,
+      intensity: 0.5f32
+    }
+  }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct DownsampleParams {
+  threshold: f32
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct BlurParams {
+  direction: Vec2
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct CompositeParams {
+  intensity: f32,
+  mip_count: u32
+}
+
+struct MipChain<B: GraphicsBackend> {
+  texture: Arc<B::Texture>,
+  uavs: Vec<Arc<B::TextureUnorderedAccessView>>,
+  srvs: Vec<Arc<B::TextureShaderResourceView>>
+}
+
+impl<B: GraphicsBackend> MipChain<B> {
+  fn new(device: &Arc<B::Device>, width: u32, height: u32, mip_count: u32, name: &str) -> Self {
+    let texture = device.create_texture(&TextureInfo {
+      dimension: TextureDimension::Dim2D,
+      format: Format::RGBA16Float,
+      width,
+      height,
+      depth: 1,
+      mip_levels: mip_count,
+      array_length: 1,
+      samples: SampleCount::Samples1,
+      usage: TextureUsage::COMPUTE_SHADER_SAMPLED | TextureUsage::COMPUTE_SHADER_STORAGE_WRITE,
+      is_cubemap: false,
+    }, Some(name));
+    let uavs = (0..mip_count).map(|mip| {
+      device.create_unordered_access_view(&texture, &TextureUnorderedAccessViewInfo {
+        base_mip_level: mip,
+        mip_level_length: 1,
+        base_array_level: 0,
+        array_level_length: 1,
+      })
+    }).collect();
+    let srvs = (0..mip_count).map(|mip| {
+      device.create_shader_resource_view(&texture, &TextureShaderResourceViewInfo {
+        base_mip_level: mip,
+        mip_level_length: 1,
+        base_array_level: 0,
+        array_level_length: 1,
+      })
+    }).collect();
+    Self {
+      texture,
+      uavs,
+      srvs
+    }
+  }
+}
+
+/// Everything that depends on the size of the swapchain.
+struct BloomTargets<B: GraphicsBackend> {
+  downsampled: MipChain<B>,
+  blurred_horizontal: MipChain<B>,
+  bloom: MipChain<B>,
+  bloom_srv: Arc<B::TextureShaderResourceView>,
+  output_uav: Arc<B::TextureUnorderedAccessView>,
+  output_srv: Arc<B::TextureShaderResourceView>,
+  mip_count: u32
+}
+
+pub struct BloomPass<B: GraphicsBackend> {
+  downsample_pipeline: Arc<B::ComputePipeline>,
+  blur_pipeline: Arc<B::ComputePipeline>,
+  composite_pipeline: Arc<B::ComputePipeline>,
+  sampler: Arc<B::Sampler>,
+  // The mip chains are split so no dispatch reads from the texture it writes to.
+  downsampled: MipChain<B>,
+  blurred_horizontal: MipChain<B>,
+  bloom: MipChain<B>,
+  bloom_srv: Arc<B::TextureShaderResourceView>,
+  output_uav: Arc<B::TextureUnorderedAccessView>,
+  output_srv: Arc<B::TextureShaderResourceView>,
+  mip_count: u32,
+  settings: BloomSettings
+}
+
+impl<B: GraphicsBackend> BloomPass<B> {
+  pub fn new<P: Platform>(device: &Arc<B::Device>, swapchain: &Arc<B::Swapchain>, init_cmd_buffer: &mut B::CommandBuffer) -> Self {
+    let load_shader = |name: &str| {
+      let mut file = <P::IO as IO>::open_asset(Path::new("shaders").join(Path::new(name))).unwrap();
+      let mut bytes: Vec<u8> = Vec::new();
+      file.read_to_end(&mut bytes).unwrap();
+      device.create_shader(ShaderType::ComputeShader, &bytes, Some(name))
+    };
+    let downsample_pipeline = device.create_compute_pipeline(&load_shader("bloom_downsample.comp.spv"));
+    let blur_pipeline = device.create_compute_pipeline(&load_shader("bloom_blur.comp.spv"));
+    let composite_pipeline = device.create_compute_pipeline(&load_shader("bloom_composite.comp.spv"));
+
+    let sampler = device.create_sampler(&SamplerInfo {
+      mag_filter: Filter::Linear,
+      min_filter: Filter::Linear,
+      mip_filter: Filter::Linear,
+      address_mode_u: AddressMode::ClampToEdge,
+      address_mode_v: AddressMode::ClampToEdge,
+      address_mode_w: AddressMode::ClampToEdge,
+      mip_bias: 0.0,
+      max_anisotropy: 0.0,
+      compare_op: None,
+      min_lod: 0.0,
+      max_lod: BLOOM_MIP_COUNT as f32,
+    });
+
+    let BloomTargets { downsampled, blurred_horizontal, bloom, bloom_srv, output_uav, output_srv, mip_count } = Self::create_targets(device, swapchain, init_cmd_buffer);
+
+    Self {
+      downsample_pipeline,
+      blur_pipeline,
+      composite_pipeline,
+      sampler,
+      downsampled,
+      blurred_horizontal,
+      bloom,
+      bloom_srv,
+      output_uav,
+      output_srv,
+      mip_count,
+      settings: BloomSettings::default()
+    }
+  }
+
+  fn create_targets(device: &Arc<B::Device>, swapchain: &Arc<B::Swapchain>, init_cmd_buffer: &mut B::CommandBuffer) -> BloomTargets<B> {
+    // The chain starts at half resolution.
+    let width = (swapchain.width() / 2).max(1);
+    let height = (swapchain.height() / 2).max(1);
+    let max_mip_count = 32 - width.min(height).leading_zeros();
+    let mip_count = BLOOM_MIP_COUNT.min(max_mip_count);
+    let downsampled = MipChain::<B>::new(device, width, height, mip_count, "BloomDownsampled");
+    let blurred_horizontal = MipChain::<B>::new(device, width, height, mip_count, "BloomBlurredHorizontal");
+    let bloom = MipChain::<B>::new(device, width, height, mip_count, "Bloom");
+    let bloom_srv = device.create_shader_resource_view(&bloom.texture, &TextureShaderResourceViewInfo {
+      base_mip_level: 0,
+      mip_level_length: mip_count,
+      base_array_level: 0,
+      array_level_length: 1,
+    });
+
+    let output = device.create_texture(&TextureInfo {
+      dimension: TextureDimension::Dim2D,
+      format: Format::RGBA16Float,
+      width: swapchain.width(),
+      height: swapchain.height(),
+      depth: 1,
+      mip_levels: 1,
+      array_length: 1,
+      samples: SampleCount::Samples1,
+      usage: TextureUsage::COMPUTE_SHADER_SAMPLED | TextureUsage::COMPUTE_SHADER_STORAGE_WRITE,
+      is_cubemap: false,
+    }, Some("BloomOutput"));
+    let output_uav = device.create_unordered_access_view(&output, &TextureUnorderedAccessViewInfo {
+      base_mip_level: 0,
+      mip_level_length: 1,
+      base_array_level: 0,
+      array_level_length: 1,
+    });
+    let output_srv = device.create_shader_resource_view(&output, &TextureShaderResourceViewInfo {
+      base_mip_level: 0,
+      mip_level_length: 1,
+      base_array_level: 0,
+      array_level_length: 1,
+    });
+
+    let barriers: Vec<Barrier<B>> = [&downsampled.texture, &blurred_horizontal.texture, &bloom.texture, &output].iter().map(|texture| {
+      Barrier::TextureBarrier {
+        old_primary_usage: TextureUsage::UNINITIALIZED,
+        new_primary_usage: TextureUsage::COMPUTE_SHADER_SAMPLED,
+        old_usages: TextureUsage::empty(),
+        new_usages: TextureUsage::empty(),
+        texture: *texture,
+      }
+    }).collect();
+    init_cmd_buffer.barrier(&barriers);
+
+    BloomTargets {
+      downsampled,
+      blurred_horizontal,
+      bloom,
+      bloom_srv,
+      output_uav,
+      output_srv,
+      mip_count
+    }
+  }
+
+  pub fn resize(&mut self, device: &Arc<B::Device>, swapchain: &Arc<B::Swapchain>, init_cmd_buffer: &mut B::CommandBuffer) {
+    let targets = Self::create_targets(device, swapchain, init_cmd_buffer);
+    self.downsampled = targets.downsampled;
+    self.blurred_horizontal = targets.blurred_horizontal;
+    self.bloom = targets.bloom;
+    self.bloom_srv = targets.bloom_srv;
+    self.output_uav = targets.output_uav;
+    self.output_srv = targets.output_srv;
+    self.mip_count = targets.mip_count;
+  }
+
+  pub fn settings(&self) -> &BloomSettings {
+    &self.settings
+  }
+
+  pub fn set_settings(&mut self, settings: &BloomSettings) {
+    self.settings = *settings;
+  }
+
+  pub fn set_threshold(&mut self, threshold: f32) {
+    self.settings.threshold = threshold;
+  }
+
+  pub fn set_intensity(&mut self, intensity: f32) {
+    self.settings.intensity = intensity;
+  }
+
+  fn begin_write(cmd_buffer: &mut B::CommandBuffer, texture: &Arc<B::Texture>) {
+    cmd_buffer.barrier(&[
+      Barrier::TextureBarrier {
+        old_primary_usage: TextureUsage::COMPUTE_SHADER_SAMPLED,
+        new_primary_usage: TextureUsage::COMPUTE_SHADER_STORAGE_WRITE,
+        old_usages: TextureUsage::COMPUTE_SHADER_SAMPLED,
+        new_usages: TextureUsage::COMPUTE_SHADER_STORAGE_WRITE,
+        texture,
+      }
+    ]);
+    cmd_buffer.flush_barriers();
+  }
+
+  fn end_write(cmd_buffer: &mut B::CommandBuffer, texture: &Arc<B::Texture>) {
+    cmd_buffer.barrier(&[
+      Barrier::TextureBarrier {
+        old_primary_usage: TextureUsage::COMPUTE_SHADER_STORAGE_WRITE,
+        new_primary_usage: TextureUsage::COMPUTE_SHADER_SAMPLED,
+        old_usages: TextureUsage::COMPUTE_SHADER_STORAGE_WRITE,
+        new_usages: TextureUsage::COMPUTE_SHADER_SAMPLED,
+        texture,
+      }
+    ]);
+  }
+
+  pub fn execute(&mut self, cmd_buffer: &mut B::CommandBuffer, input_image: &Arc<B::TextureShaderResourceView>) {
+    cmd_buffer.barrier(&[
+      Barrier::TextureBarrier {
+        old_primary_usage: TextureUsage::COMPUTE_SHADER_STORAGE_WRITE,
+        new_primary_usage: TextureUsage::COMPUTE_SHADER_SAMPLED,
+        old_usages: TextureUsage::COMPUTE_SHADER_STORAGE_WRITE,
+        new_usages: TextureUsage::COMPUTE_SHADER_SAMPLED,
+        texture: input_image.texture(),
+      }
+    ]);
+
+    let bloom_info = self.bloom.texture.get_info();
+    for mip in 0..self.mip_count as usize {
+      let width = (bloom_info.width >> mip).max(1);
+      let height = (bloom_info.height >> mip).max(1);
+
+      // Only the first step filters out dark pixels, the later ones just downsample.
+      Self::begin_write(cmd_buffer, &self.downsampled.texture);
+      let (source, threshold) = if mip == 0 {
+        (input_image, self.settings.threshold)
+      } else {
+        (&self.bloom.srvs[mip - 1], 0f32)
+      };
+      let downsample_params = cmd_buffer.upload_dynamic_data(&[DownsampleParams {
+        threshold
+      }], BufferUsage::COMPUTE_SHADER_CONSTANT);
+      cmd_buffer.set_pipeline(PipelineBinding::Compute(&self.downsample_pipeline));
+      cmd_buffer.bind_texture_view(BindingFrequency::PerDraw, 0, source, &self.sampler);
+      cmd_buffer.bind_storage_texture(BindingFrequency::PerDraw, 1, &self.downsampled.uavs[mip]);
+      cmd_buffer.bind_uniform_buffer(BindingFrequency::PerDraw, 2, &downsample_params);
+      cmd_buffer.finish_binding();
+      cmd_buffer.dispatch(width, height, 1);
+      Self::end_write(cmd_buffer, &self.downsampled.texture);
+
+      Self::begin_write(cmd_buffer, &self.blurred_horizontal.texture);
+      let horizontal_params = cmd_buffer.upload_dynamic_data(&[BlurParams {
+        direction: Vec2::new(1f32, 0f32)
+      }], BufferUsage::COMPUTE_SHADER_CONSTANT);
+      cmd_buffer.set_pipeline(PipelineBinding::Compute(&self.blur_pipeline));
+      cmd_buffer.bind_texture_view(BindingFrequency::PerDraw, 0, &self.downsampled.srvs[mip], &self.sampler);
+      cmd_buffer.bind_storage_texture(BindingFrequency::PerDraw, 1, &self.blurred_horizontal.uavs[mip]);
+      cmd_buffer.bind_uniform_buffer(BindingFrequency::PerDraw, 2, &horizontal_params);
+      cmd_buffer.finish_binding();
+      cmd_buffer.dispatch(width, height, 1);
+      Self::end_write(cmd_buffer, &self.blurred_horizontal.texture);
+
+      Self::begin_write(cmd_buffer, &self.bloom.texture);
+      let vertical_params = cmd_buffer.upload_dynamic_data(&[BlurParams {
+        direction: Vec2::new(0f32, 1f32)
+      }], BufferUsage::COMPUTE_SHADER_CONSTANT);
+      cmd_buffer.set_pipeline(PipelineBinding::Compute(&self.blur_pipeline));
+      cmd_buffer.bind_texture_view(BindingFrequency::PerDraw, 0, &self.blurred_horizontal.srvs[mip], &self.sampler);
+      cmd_buffer.bind_storage_texture(BindingFrequency::PerDraw, 1, &self.bloom.uavs[mip]);
+      cmd_buffer.bind_uniform_buffer(BindingFrequency::PerDraw, 2, &vertical_params);
+      cmd_buffer.finish_binding();
+      cmd_buffer.dispatch(width, height, 1);
+      Self::end_write(cmd_buffer, &self.bloom.texture);
+    }
+
+    Self::begin_write(cmd_buffer, self.output_uav.texture());
+    let composite_params = cmd_buffer.upload_dynamic_data(&[CompositeParams {
+      intensity: self.settings.intensity,
+      mip_count: self.mip_count
+    }], BufferUsage::COMPUTE_SHADER_CONSTANT);
+    cmd_buffer.set_pipeline(PipelineBinding::Compute(&self.composite_pipeline));
+    cmd_buffer.bind_texture_view(BindingFrequency::PerDraw, 0, input_image, &self.sampler);
+    cmd_buffer.bind_texture_view(BindingFrequency::PerDraw, 1, &self.bloom_srv, &self.sampler);
+    cmd_buffer.bind_storage_texture(BindingFrequency::PerDraw, 2, &self.output_uav);
+    cmd_buffer.bind_uniform_buffer(BindingFrequency::PerDraw, 3, &composite_params);
+    cmd_buffer.finish_binding();
+    let info = self.output_uav.texture().get_info();
+    cmd_buffer.dispatch(info.width, info.height, 1);
+  }
+
+  pub fn output_srv(&self) -> &Arc<B::TextureShaderResourceView> {
+    &self.output_srv
+  }
+}
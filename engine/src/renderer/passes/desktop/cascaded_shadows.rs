@@ -0,0 +1,385 @@
+use std::{io::Read, path::Path, sync::Arc};
+
+use nalgebra::Point3;
+use sourcerenderer_core::{Matrix4, Platform, Vec2, Vec2I, Vec2UI, Vec3, Vec4, graphics::{AddressMode, AttachmentInfo, Backend as GraphicsBackend, Barrier, BlendInfo, BufferUsage, CommandBuffer, CompareFunc, CullMode, DepthStencilAttachmentRef, DepthStencilInfo, Device, FillMode, Filter, Format, FrontFace, GraphicsPipelineInfo, IndexFormat, InputAssemblerElement, InputRate, LoadOp, LogicOp, PipelineBinding, PrimitiveType, RasterizerInfo, RenderPassAttachment, RenderPassAttachmentView, RenderPassBeginInfo, RenderPassInfo, RenderpassRecordingMode, SampleCount, SamplerInfo, Scissor, ShaderInputElement, ShaderType, StencilInfo, StoreOp, SubpassInfo, TextureDepthStencilViewInfo, TextureDimension, TextureInfo, TextureShaderResourceViewInfo, TextureUsage, VertexLayoutInfo, Viewport}, platform::io::IO};
+
+use crate::{math::Frustum, renderer::{RendererScene, drawable::View}};
+
+const CASCADE_COUNT: usize = 4;
+const SHADOW_MAP_SIZE: u32 = 1024;
+const SHADOW_DISTANCE: f32 = 60f32;
+// Blend between logarithmic and uniform splits, 1.0 is fully logarithmic.
+const SPLIT_LAMBDA: f32 = 0.75f32;
+// How far behind a cascade shadow casters still get rendered.
+const CASTER_DISTANCE: f32 = 50f32;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct CascadeData {
+  view_projections: [Matrix4; CASCADE_COUNT],
+  splits: Vec4,
+  light_direction: Vec4,
+  light_color: Vec4,
+  light_count: u32
+}
+
+pub struct CascadedShadowPass<B: GraphicsBackend> {
+  shadow_map: Arc<B::Texture>,
+  cascade_dsvs: Vec<Arc<B::TextureDepthStencilView>>,
+  shadow_map_srv: Arc<B::TextureShaderResourceView>,
+  sampler: Arc<B::Sampler>,
+  pipeline: Arc<B::GraphicsPipeline>
+}
+
+impl<B: GraphicsBackend> CascadedShadowPass<B> {
+  pub fn new<P: Platform>(device: &Arc<B::Device>, init_cmd_buffer: &mut B::CommandBuffer) -> Self {
+    let shadow_map = device.create_texture(&TextureInfo {
+      dimension: TextureDimension::Dim2D,
+      format: Format::D32,
+      width: SHADOW_MAP_SIZE,
+      height: SHADOW_MAP_SIZE,
+      depth: 1,
+      mip_levels: 1,
+      array_length: CASCADE_COUNT as u32,
+      samples: SampleCount::Samples1,
+      usage: TextureUsage::DEPTH_WRITE | TextureUsage::FRAGMENT_SHADER_SAMPLED,
+      is_cubemap: false,
+    }, Some("ShadowCascades"));
+    let cascade_dsvs = (0..CASCADE_COUNT as u32).map(|layer| {
+      device.create_depth_stencil_view(&shadow_map, &TextureDepthStencilViewInfo {
+        base_mip_level: 0,
+        mip_level_length: 1,
+        base_array_level: layer,
+        array_level_length: 1,
+      })
+    }).collect();
+    let shadow_map_srv = device.create_shader_resource_view(&shadow_map, &TextureShaderResourceViewInfo {
+      base_mip_level: 0,
+      mip_level_length: 1,
+      base_array_level: 0,
+      array_level_length: CASCADE_COUNT as u32,
+    });
+
+    let sampler = device.create_sampler(&SamplerInfo {
+      mag_filter: Filter::Nearest,
+      min_filter: Filter::Nearest,
+      mip_filter: Filter::Nearest,
+      address_mode_u: AddressMode::ClampToEdge,
+      address_mode_v: AddressMode::ClampToEdge,
+      address_mode_w: AddressMode::ClampToEdge,
+      mip_bias: 0.0f32,
+      max_anisotropy: 0.0f32,
+      compare_op: None,
+      min_lod: 0.0f32,
+      max_lod: 1.0f32,
+    });
+
+    let vertex_shader = {
+      let mut file = <P::IO as IO>::open_asset(Path::new("shaders").join(Path::new("shadow.vert.spv"))).unwrap();
+      let mut bytes: Vec<u8> = Vec::new();
+      file.read_to_end(&mut bytes).unwrap();
+      device.create_shader(ShaderType::VertexShader, &bytes, Some("shadow.vert.spv"))
+    };
+    let pipeline_info: GraphicsPipelineInfo<B> = GraphicsPipelineInfo {
+      vs: vertex_shader,
+      fs: None,
+      gs: None,
+      tcs: None,
+      tes: None,
+      primitive_type: PrimitiveType::Triangles,
+      vertex_layout: VertexLayoutInfo {
+        input_assembler: vec![
+          InputAssemblerElement {
+            binding: 0,
+            stride: 44,
+            input_rate: InputRate::PerVertex
+          }
+        ],
+        shader_inputs: vec![
+          ShaderInputElement {
+            input_assembler_binding: 0,
+            location_vk_mtl: 0,
+            semantic_name_d3d: String::from(""),
+            semantic_index_d3d: 0,
+            offset: 0,
+            format: Format::RGB32Float
+          }
+        ]
+      },
+      rasterizer: RasterizerInfo {
+        fill_mode: FillMode::Fill,
+        cull_mode: CullMode::Back,
+        front_face: FrontFace::CounterClockwise,
+        sample_count: SampleCount::Samples1
+      },
+      depth_stencil: DepthStencilInfo {
+        depth_test_enabled: true,
+        depth_write_enabled: true,
+        depth_func: CompareFunc::Less,
+        stencil_enable: false,
+        stencil_read_mask: 0u8,
+        stencil_write_mask: 0u8,
+        stencil_front: StencilInfo::default(),
+        stencil_back: StencilInfo::default()
+      },
+      blend: BlendInfo {
+        alpha_to_coverage_enabled: false,
+        logic_op_enabled: false,
+        logic_op: LogicOp::And,
+        constants: [0f32, 0f32, 0f32, 0f32],
+        attachments: vec![]
+      }
+    };
+    let pipeline = device.create_graphics_pipeline(&pipeline_info, &RenderPassInfo {
+      attachments: vec![
+        AttachmentInfo {
+          format: Format::D32,
+          samples: SampleCount::Samples1,
+          load_op: LoadOp::Clear,
+          store_op: StoreOp::Store,
+          stencil_load_op: LoadOp::DontCare,
+          stencil_store_op: StoreOp::DontCare
+        }
+      ],
+      subpasses: vec![
+        SubpassInfo {
+          input_attachments: vec![],
+          output_color_attachments: vec![],
+          depth_stencil_attachment: Some(DepthStencilAttachmentRef {
+            index: 0,
+            read_only: false
+          })
+        }
+      ],
+    }, 0);
+
+    init_cmd_buffer.barrier(&[
+      Barrier::TextureBarrier {
+        old_primary_usage: TextureUsage::UNINITIALIZED,
+        new_primary_usage: TextureUsage::FRAGMENT_SHADER_SAMPLED,
+        old_usages: TextureUsage::empty(),
+        new_usages: TextureUsage::empty(),
+        texture: &shadow_map,
+      }
+    ]);
+
+    Self {
+      shadow_map,
+      cascade_dsvs,
+      shadow_map_srv,
+      sampler,
+      pipeline
+    }
+  }
+
+  fn cascade_view_projection(view: &View, inverse_view: &Matrix4, light_direction: &Vec3, near: f32, far: f32) -> Matrix4 {
+    let tan_half_x = 1f32 / view.proj_matrix[(0, 0)];
+    let tan_half_y = 1f32 / view.proj_matrix[(1, 1)];
+
+    let mut corners = [Vec3::new(0f32, 0f32, 0f32); 8];
+    for (index, corner) in corners.iter_mut().enumerate() {
+      let depth = if index < 4 { near } else { far };
+      let sign_x = if index & 1 == 0 { -1f32 } else { 1f32 };
+      let sign_y = if index & 2 == 0 { -1f32 } else { 1f32 };
+      let x = sign_x * tan_half_x * depth;
+      let y = sign_y * tan_half_y * depth;
+      *corner = (inverse_view * Vec4::new(x, y, -depth, 1f32)).xyz();
+    }
+
+    // Fit a sphere around the slice so the projection doesn't change size when the camera rotates.
+    let center = corners.iter().fold(Vec3::new(0f32, 0f32, 0f32), |sum, corner| sum + corner) / corners.len() as f32;
+    let radius = corners.iter()
+      .map(|corner| (corner - center).norm())
+      .fold(0f32, f32::max);
+    let radius = (radius * 16f32).ceil() / 16f32;
+
+    let up = if light_direction.y.abs() > 0.99f32 { Vec3::new(0f32, 0f32, 1f32) } else { Vec3::new(0f32, 1f32, 0f32) };
+    let eye = center - light_direction * (radius + CASTER_DISTANCE);
+    let light_view = Matrix4::look_at_rh(
+      &Point3::new(eye.x, eye.y, eye.z),
+      &Point3::new(center.x, center.y, center.z),
+      &up
+    );
+    let proj = Matrix4::new_orthographic(-radius, radius, -radius, radius, 0f32, radius * 2f32 + CASTER_DISTANCE);
+    // new_orthographic produces a -1..1 depth range, Vulkan clips everything outside of 0..1.
+    let depth_fixup = Matrix4::new(
+      1f32, 0f32, 0f32, 0f32,
+      0f32, 1f32, 0f32, 0f32,
+      0f32, 0f32, 0.5f32, 0.5f32,
+      0f32, 0f32, 0f32, 1f32
+    );
+    let mut view_projection = depth_fixup * proj * light_view;
+
+    // Snap the origin to whole texels to avoid shimmering edges while moving.
+    let origin = view_projection * Vec4::new(0f32, 0f32, 0f32, 1f32);
+    let texel_scale = SHADOW_MAP_SIZE as f32 * 0.5f32;
+    let offset_x = (origin.x * texel_scale).round() / texel_scale - origin.x;
+    let offset_y = (origin.y * texel_scale).round() / texel_scale - origin.y;
+    view_projection[(0, 3)] += offset_x;
+    view_projection[(1, 3)] += offset_y;
+    view_projection
+  }
+
+  pub(super) fn execute(
+    &mut self,
+    cmd_buffer: &mut B::CommandBuffer,
+    scene: &RendererScene<B>,
+    view: &View
+  ) -> Arc<B::Buffer> {
+    let mut cascade_data = CascadeData {
+      view_projections: [Matrix4::identity(); CASCADE_COUNT],
+      splits: Vec4::new(0f32, 0f32, 0f32, 0f32),
+      light_direction: Vec4::new(0f32, 0f32, -1f32, 0f32),
+      light_color: Vec4::new(0f32, 0f32, 0f32, 0f32),
+      light_count: 0
+    };
+
+    // Only the first directional light casts shadows.
+    let light = scene.directional_lights().first();
+    let inverse_view = view.view_matrix.try_inverse();
+    if light.is_none() || inverse_view.is_none() {
+      return cmd_buffer.upload_dynamic_data(&[cascade_data], BufferUsage::FRAGMENT_SHADER_CONSTANT);
+    }
+    let light = light.unwrap();
+    let inverse_view = inverse_view.unwrap();
+
+    let shadow_distance = view.far_plane.min(SHADOW_DISTANCE);
+    let splits = cascade_splits(view.near_plane, shadow_distance);
+    cascade_data.splits = Vec4::new(splits[0], splits[1], splits[2], splits[3]);
+    cascade_data.light_direction = Vec4::new(light.direction.x, light.direction.y, light.direction.z, 0f32);
+    let color = light.color * light.intensity;
+    cascade_data.light_color = Vec4::new(color.x, color.y, color.z, 1f32);
+    cascade_data.light_count = 1;
+
+    cmd_buffer.barrier(&[
+      Barrier::TextureBarrier {
+        old_primary_usage: TextureUsage::FRAGMENT_SHADER_SAMPLED,
+        new_primary_usage: TextureUsage::DEPTH_WRITE,
+        old_usages: TextureUsage::empty(),
+        new_usages: TextureUsage::empty(),
+        texture: &self.shadow_map,
+      }
+    ]);
+    cmd_buffer.flush_barriers();
+
+    let static_drawables = scene.static_drawables();
+    let mut cascade_near = view.near_plane;
+    for (cascade, cascade_far) in splits.iter().enumerate() {
+      let view_projection = Self::cascade_view_projection(view, &inverse_view, &light.direction, cascade_near, *cascade_far);
+      cascade_data.view_projections[cascade] = view_projection;
+      cascade_near = *cascade_far;
+
+      cmd_buffer.begin_render_pass_1(&RenderPassBeginInfo {
+        attachments: &[
+          RenderPassAttachment {
+            view: RenderPassAttachmentView::DepthStencil(&self.cascade_dsvs[cascade]),
+            load_op: LoadOp::Clear,
+            store_op: StoreOp::Store
+          }
+        ],
+        subpasses: &[
+          SubpassInfo {
+            input_attachments: vec![],
+            output_color_attachments: vec![],
+            depth_stencil_attachment: Some(DepthStencilAttachmentRef {
+              index: 0,
+              read_only: false
+            })
+          }
+        ]
+      }, RenderpassRecordingMode::Commands);
+
+      cmd_buffer.set_pipeline(PipelineBinding::Graphics(&self.pipeline));
+      cmd_buffer.set_viewports(&[Viewport {
+        position: Vec2::new(0.0f32, 0.0f32),
+        extent: Vec2::new(SHADOW_MAP_SIZE as f32, SHADOW_MAP_SIZE as f32),
+        min_depth: 0.0f32,
+        max_depth: 1.0f32
+      }]);
+      cmd_buffer.set_scissors(&[Scissor {
+        position: Vec2I::new(0, 0),
+        extent: Vec2UI::new(SHADOW_MAP_SIZE, SHADOW_MAP_SIZE),
+      }]);
+      cmd_buffer.finish_binding();
+
+      let frustum = Frustum::from_matrix(&view_projection);
+      let casters = static_drawables.iter().filter(|drawable| {
+        drawable.cast_shadows && drawable.model.mesh.bounding_box.as_ref()
+          .map_or(true, |bounding_box| frustum.intersects(bounding_box, &drawable.transform))
+      });
+      for drawable in casters {
+        cmd_buffer.upload_dynamic_data_inline(&[view_projection * drawable.transform], ShaderType::VertexShader);
+
+        let mesh = &drawable.model.mesh;
+        cmd_buffer.set_vertex_buffer(&mesh.vertices);
+        if mesh.indices.is_some() {
+          cmd_buffer.set_index_buffer(mesh.indices.as_ref().unwrap(), IndexFormat::U32);
+        }
+        for range in &mesh.parts {
+          if mesh.indices.is_some() {
+            cmd_buffer.draw_indexed(1, 0, range.count, range.start, 0);
+          } else {
+            cmd_buffer.draw(range.count, range.start);
+          }
+        }
+      }
+      cmd_buffer.end_render_pass();
+    }
+
+    cmd_buffer.barrier(&[
+      Barrier::TextureBarrier {
+        old_primary_usage: TextureUsage::DEPTH_WRITE,
+        new_primary_usage: TextureUsage::FRAGMENT_SHADER_SAMPLED,
+        old_usages: TextureUsage::DEPTH_WRITE,
+        new_usages: TextureUsage::FRAGMENT_SHADER_SAMPLED,
+        texture: &self.shadow_map,
+      }
+    ]);
+
+    cmd_buffer.upload_dynamic_data(&[cascade_data], BufferUsage::FRAGMENT_SHADER_CONSTANT)
+  }
+
+  pub fn shadow_map_srv(&self) -> &Arc<B::TextureShaderResourceView> {
+    &self.shadow_map_srv
+  }
+
+  pub fn sampler(&self) -> &Arc<B::Sampler> {
+    &self.sampler
+  }
+}
+
+/// Far distance of every cascade in view space.
+fn cascade_splits(near: f32, far: f32) -> [f32; CASCADE_COUNT] {
+  let mut splits = [0f32; CASCADE_COUNT];
+  for (index, split) in splits.iter_mut().enumerate() {
+    let fraction = (index + 1) as f32 / CASCADE_COUNT as f32;
+    let log_split = near * (far / near).powf(fraction);
+    let uniform_split = near + (far - near) * fraction;
+    *split = SPLIT_LAMBDA * log_split + (1f32 - SPLIT_LAMBDA) * uniform_split;
+  }
+  splits
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn cascade_splits_increase_up_to_the_far_plane() {
+    for &(near, far) in &[(0.1f32, SHADOW_DISTANCE), (0.01f32, 10f32), (1f32, 1000f32)] {
+      let splits = cascade_splits(near, far);
+      assert!(splits[0] > near);
+      for pair in splits.windows(2) {
+        assert!(pair[0] < pair[1], "{:?} are not increasing", splits);
+      }
+      assert!((splits[CASCADE_COUNT - 1] - far).abs() < far * 0.0001f32);
+    }
+  }
+
+  #[test]
+  fn cascade_splits_favor_the_near_range() {
+    let splits = cascade_splits(0.1f32, SHADOW_DISTANCE);
+    let uniform_first_split = 0.1f32 + (SHADOW_DISTANCE - 0.1f32) / CASCADE_COUNT as f32;
+    assert!(splits[0] < uniform_first_split);
+  }
+}
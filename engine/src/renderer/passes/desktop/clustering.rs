@@ -1,5 +1,4 @@
-use nalgebra::Vector3;
-use sourcerenderer_core::{Vec2UI, Vec4, graphics::{Backend as GraphicsBackend, Barrier, BindingFrequency, BufferInfo, BufferUsage, CommandBuffer, Device, MemoryUsage, PipelineBinding, ShaderType}};
+use sourcerenderer_core::{Vec2UI, Vec4, graphics::{Backend as GraphicsBackend, Barrier, BindingFrequency, Buffer, BufferInfo, BufferUsage, CommandBuffer, Device, MemoryUsage, PipelineBinding, ShaderType}};
 use sourcerenderer_core::Platform;
 use std::sync::Arc;
 use std::path::Path;
@@ -15,9 +14,33 @@ struct ShaderScreenToView {
   z_far: f32
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClusterConfig {
+  pub tiles_x: u32,
+  pub tiles_y: u32,
+  pub slices_z: u32
+}
+
+impl Default for ClusterConfig {
+  fn default() -> Self {
+    Self {
+      tiles_x: 16,
+      tiles_y: 9,
+      slices_z: 24
+    }
+  }
+}
+
+impl ClusterConfig {
+  pub fn cluster_count(&self) -> u32 {
+    self.tiles_x * self.tiles_y * self.slices_z
+  }
+}
+
 pub struct ClusteringPass<B: GraphicsBackend> {
   pipeline: Arc<B::ComputePipeline>,
-  clusters_buffer: Arc<B::Buffer>
+  clusters_buffer: Arc<B::Buffer>,
+  config: ClusterConfig
 }
 
 impl<B: GraphicsBackend> ClusteringPass<B> {
@@ -29,15 +52,34 @@ impl<B: GraphicsBackend> ClusteringPass<B> {
       device.create_shader(ShaderType::ComputeShader, &bytes, Some("clustering.comp.spv"))
     };
     let clustering_pipeline = device.create_compute_pipeline(&clustering_shader);
-    let buffer = device.create_buffer(&BufferInfo {
-        size: std::mem::size_of::<Vec4>() * 2 * 16 * 9 * 24,
-        usage: BufferUsage::COMPUTE_SHADER_STORAGE_WRITE | BufferUsage::COMPUTE_SHADER_STORAGE_READ,
-    }, MemoryUsage::GpuOnly, Some("Clusters"));
+    let config = ClusterConfig::default();
+    let buffer = Self::create_clusters_buffer(device, &config);
 
     Self {
       pipeline: clustering_pipeline,
-      clusters_buffer: buffer
+      clusters_buffer: buffer,
+      config
+    }
+  }
+
+  fn create_clusters_buffer(device: &Arc<B::Device>, config: &ClusterConfig) -> Arc<B::Buffer> {
+    device.create_buffer(&BufferInfo {
+        size: std::mem::size_of::<Vec4>() * 2 * config.cluster_count() as usize,
+        usage: BufferUsage::COMPUTE_SHADER_STORAGE_WRITE | BufferUsage::COMPUTE_SHADER_STORAGE_READ,
+    }, MemoryUsage::GpuOnly, Some("Clusters"))
+  }
+
+  pub fn config(&self) -> &ClusterConfig {
+    &self.config
+  }
+
+  pub fn set_config(&mut self, device: &Arc<B::Device>, config: &ClusterConfig) {
+    assert!(config.tiles_x != 0 && config.tiles_y != 0 && config.slices_z != 0, "Cluster grid dimensions must not be 0");
+    if *config == self.config {
+      return;
     }
+    self.clusters_buffer = Self::create_clusters_buffer(device, config);
+    self.config = *config;
   }
 
   pub fn execute(
@@ -48,9 +90,10 @@ impl<B: GraphicsBackend> ClusteringPass<B> {
     far_plane: f32,
     camera_buffer: &Arc<B::Buffer>
   ) {
-    let cluster_count = Vector3::<u32>::new(16, 9, 24);
+    let config = self.config;
+    debug_assert!(self.clusters_buffer.get_length() >= std::mem::size_of::<Vec4>() * 2 * config.cluster_count() as usize);
     let screen_to_view = ShaderScreenToView {
-      tile_size: Vec2UI::new(((rt_size.x as f32) / cluster_count.x as f32).ceil() as u32, ((rt_size.y as f32) / cluster_count.y as f32).ceil() as u32),
+      tile_size: Vec2UI::new(((rt_size.x as f32) / config.tiles_x as f32).ceil() as u32, ((rt_size.y as f32) / config.tiles_y as f32).ceil() as u32),
       rt_dimensions: rt_size,
       z_near: near_plane,
       z_far: far_plane
@@ -83,7 +126,7 @@ impl<B: GraphicsBackend> ClusteringPass<B> {
     command_buffer.bind_storage_buffer(BindingFrequency::PerDraw, 1, &screen_to_view_cbuffer);
     command_buffer.bind_uniform_buffer(BindingFrequency::PerDraw, 2, camera_buffer);
     command_buffer.finish_binding();
-    command_buffer.dispatch(cluster_count.x, cluster_count.y, cluster_count.z);
+    command_buffer.dispatch(config.tiles_x, config.tiles_y, config.slices_z);
   }
 
   pub fn clusters_buffer(&self) -> &Arc<B::Buffer> {
@@ -1,10 +1,64 @@
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc};
 
-use sourcerenderer_core::{Matrix4, Platform, Vec2UI, atomic_refcell::AtomicRefCell, graphics::{Backend, Barrier, CommandBuffer, Device, Queue, Swapchain, SwapchainError, TextureRenderTargetView, TextureUsage}};
+use sourcerenderer_core::{Matrix4, Platform, Vec2, Vec2UI, atomic_refcell::AtomicRefCell, graphics::{Backend, Barrier, CommandBuffer, Device, Fence, QueryPool, Queue, SampleCount, Swapchain, SwapchainError, TextureRenderTargetView, TextureShaderResourceView, TextureUsage, TimestampStage}};
 
-use crate::{renderer::{LateLatchCamera, drawable::View, passes::late_latching::LateLatchingPass, renderer_assets::RendererTexture, render_path::RenderPath, renderer_scene::RendererScene}};
+use crate::{renderer::{DebugView, LateLatchCamera, occlusion::HierarchicalDepthBuffer, TonemappingOperator, drawable::View, passes::late_latching::LateLatchingPass, renderer_assets::RendererTexture, render_path::RenderPath, renderer_scene::RendererScene}};
 
-use super::{clustering::ClusteringPass, geometry::GeometryPass, light_binning::LightBinningPass, prepass::Prepass, sharpen::SharpenPass, ssao::SsaoPass, taa::TAAPass};
+use super::{bloom::BloomPass, cascaded_shadows::CascadedShadowPass, clustering::{ClusterConfig, ClusteringPass}, fxaa::FxaaPass, geometry::GeometryPass, hi_z::HierarchicalZPass, light_binning::LightBinningPass, point_shadows::PointShadowPass, prepass::Prepass, screenshot::ScreenshotPass, sharpen::{DebugOutput, SharpenPass}, ssao::SsaoPass, taa::TAAPass};
+
+const TIMESTAMP_POOL_COUNT: usize = 3;
+const FRAME_FENCE_COUNT: usize = 3;
+const PASS_COUNT: usize = 12;
+
+/// GPU time of each pass in nanoseconds, lags a few frames behind.
+#[derive(Clone, Default, Debug)]
+pub struct DesktopRendererStats {
+  pub late_latching: u64,
+  pub clustering: u64,
+  pub light_binning: u64,
+  pub point_shadows: u64,
+  pub cascaded_shadows: u64,
+  pub prepass: u64,
+  pub ssao: u64,
+  pub hi_z: u64,
+  pub geometry: u64,
+  pub anti_aliasing: u64,
+  pub bloom: u64,
+  pub sharpen: u64
+}
+
+impl DesktopRendererStats {
+  fn from_timestamps(timestamps: &[u64; PASS_COUNT + 1]) -> Self {
+    let pass_time = |index: usize| timestamps[index + 1].saturating_sub(timestamps[index]);
+    Self {
+      late_latching: pass_time(0),
+      clustering: pass_time(1),
+      light_binning: pass_time(2),
+      point_shadows: pass_time(3),
+      cascaded_shadows: pass_time(4),
+      prepass: pass_time(5),
+      ssao: pass_time(6),
+      hi_z: pass_time(7),
+      geometry: pass_time(8),
+      anti_aliasing: pass_time(9),
+      bloom: pass_time(10),
+      sharpen: pass_time(11)
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AntiAliasing {
+  Taa,
+  Fxaa,
+  None
+}
+
+enum AntiAliasingPass<B: Backend> {
+  Taa(TAAPass<B>),
+  Fxaa(FxaaPass<B>),
+  None
+}
 
 pub struct DesktopRenderer<B: Backend> {
   swapchain: Arc<B::Swapchain>,
@@ -12,48 +66,127 @@ pub struct DesktopRenderer<B: Backend> {
   late_latching_pass: LateLatchingPass<B>,
   clustering_pass: ClusteringPass<B>,
   light_binning_pass: LightBinningPass<B>,
+  point_shadows: PointShadowPass<B>,
+  cascaded_shadows: CascadedShadowPass<B>,
   prepass: Prepass<B>,
   geometry: GeometryPass<B>,
-  taa: TAAPass<B>,
+  anti_aliasing: AntiAliasingPass<B>,
+  bloom: BloomPass<B>,
   sharpen: SharpenPass<B>,
   ssao: SsaoPass<B>,
+  hi_z: HierarchicalZPass<B>,
+  screenshot: ScreenshotPass<B>,
+  timestamp_pools: Vec<Arc<B::QueryPool>>,
+  frame_fences: Vec<Arc<B::Fence>>,
+  submitted_frame_fences: [bool; FRAME_FENCE_COUNT],
+  stats: DesktopRendererStats,
   frame: u64
 }
 
 impl<B: Backend> DesktopRenderer<B> {
-  pub fn new<P: Platform>(device: &Arc<B::Device>, swapchain: &Arc<B::Swapchain>) -> Self {
+  pub fn new<P: Platform>(device: &Arc<B::Device>, swapchain: &Arc<B::Swapchain>, anti_aliasing: AntiAliasing, msaa: SampleCount) -> Self {
     let mut init_cmd_buffer = device.graphics_queue().create_command_buffer();
 
     let late_latching = LateLatchingPass::<B>::new::<P>(device);
     let clustering = ClusteringPass::<B>::new::<P>(device);
-    let light_binning = LightBinningPass::<B>::new::<P>(device);
+    let light_binning = LightBinningPass::<B>::new::<P>(device, clustering.config());
+    let point_shadows = PointShadowPass::<B>::new::<P>(device, &mut init_cmd_buffer);
+    let cascaded_shadows = CascadedShadowPass::<B>::new::<P>(device, &mut init_cmd_buffer);
     let prepass = Prepass::<B>::new::<P>(device, swapchain, &mut init_cmd_buffer);
-    let geometry = GeometryPass::<B>::new::<P>(device, swapchain, &mut init_cmd_buffer);
-    let taa = TAAPass::<B>::new::<P>(device, swapchain, &mut init_cmd_buffer);
+    let geometry = GeometryPass::<B>::new::<P>(device, swapchain, msaa, &mut init_cmd_buffer);
+    let anti_aliasing = match anti_aliasing {
+      AntiAliasing::Taa => AntiAliasingPass::Taa(TAAPass::<B>::new::<P>(device, swapchain, &mut init_cmd_buffer)),
+      AntiAliasing::Fxaa => AntiAliasingPass::Fxaa(FxaaPass::<B>::new::<P>(device, swapchain, &mut init_cmd_buffer)),
+      AntiAliasing::None => AntiAliasingPass::None
+    };
+    let bloom = BloomPass::<B>::new::<P>(device, swapchain, &mut init_cmd_buffer);
     let sharpen = SharpenPass::<B>::new::<P>(device, swapchain, &mut init_cmd_buffer);
     let ssao = SsaoPass::<B>::new::<P>(device, Vec2UI::new(swapchain.width(), swapchain.height()), &mut init_cmd_buffer);
+    let hi_z = HierarchicalZPass::<B>::new::<P>(device);
 
     device.graphics_queue().submit(init_cmd_buffer.finish(), None, &[], &[]);
 
+    let timestamp_pools = (0..TIMESTAMP_POOL_COUNT)
+      .map(|_| device.create_timestamp_query_pool(PASS_COUNT as u32 + 1))
+      .collect();
+    let frame_fences = (0..FRAME_FENCE_COUNT)
+      .map(|_| device.create_fence())
+      .collect();
+
     Self {
       swapchain: swapchain.clone(),
       device: device.clone(),
       clustering_pass: clustering,
       late_latching_pass: late_latching,
       light_binning_pass: light_binning,
+      point_shadows,
+      cascaded_shadows,
       prepass,
       geometry,
-      taa,
+      anti_aliasing,
+      bloom,
       sharpen,
       ssao,
+      hi_z,
+      screenshot: ScreenshotPass::new(),
+      timestamp_pools,
+      frame_fences,
+      submitted_frame_fences: [false; FRAME_FENCE_COUNT],
+      stats: DesktopRendererStats::default(),
       frame: 0
     }
   }
+
+  pub fn stats(&self) -> &DesktopRendererStats {
+    &self.stats
+  }
+
+  pub fn set_cluster_config(&mut self, config: &ClusterConfig) {
+    self.clustering_pass.set_config(&self.device, config);
+    self.light_binning_pass.set_cluster_config(&self.device, config);
+  }
 }
 
 impl<B: Backend> RenderPath<B> for DesktopRenderer<B> {
-  fn on_swapchain_changed(&mut self, _swapchain: &std::sync::Arc<B::Swapchain>) {
-    todo!()
+  fn on_swapchain_changed(&mut self, swapchain: &std::sync::Arc<B::Swapchain>) {
+    // The device is idle at this point, so the old targets can be dropped right away.
+    self.swapchain = swapchain.clone();
+    let mut init_cmd_buffer = self.device.graphics_queue().create_command_buffer();
+    self.prepass.resize(&self.device, swapchain, &mut init_cmd_buffer);
+    self.geometry.resize(&self.device, swapchain, &mut init_cmd_buffer);
+    match &mut self.anti_aliasing {
+      AntiAliasingPass::Taa(taa) => taa.resize(&self.device, swapchain, &mut init_cmd_buffer),
+      AntiAliasingPass::Fxaa(fxaa) => fxaa.resize(&self.device, swapchain, &mut init_cmd_buffer),
+      AntiAliasingPass::None => {}
+    }
+    self.bloom.resize(&self.device, swapchain, &mut init_cmd_buffer);
+    self.sharpen.resize(&self.device, swapchain, &mut init_cmd_buffer);
+    self.ssao.resize(&self.device, Vec2UI::new(swapchain.width(), swapchain.height()), &mut init_cmd_buffer);
+    self.device.graphics_queue().submit(init_cmd_buffer.finish(), None, &[], &[]);
+  }
+
+  fn set_tonemapping_operator(&mut self, operator: TonemappingOperator) {
+    self.sharpen.set_tonemapping_operator(operator);
+  }
+
+  fn set_exposure(&mut self, exposure: f32) {
+    self.sharpen.set_exposure(exposure);
+  }
+
+  fn set_debug_view(&mut self, debug_view: DebugView) {
+    self.geometry.set_debug_view(debug_view);
+  }
+
+  fn set_indirect_draws(&mut self, indirect_draws: bool) {
+    self.geometry.set_indirect_draws(indirect_draws);
+  }
+
+  fn occlusion_buffer(&self) -> Option<&HierarchicalDepthBuffer> {
+    self.hi_z.depth_buffer()
+  }
+
+  fn request_screenshot(&mut self, path: PathBuf) {
+    self.screenshot.request(path);
   }
 
   fn render(&mut self,
@@ -64,18 +197,130 @@ impl<B: Backend> RenderPath<B> for DesktopRenderer<B> {
     let graphics_queue = self.device.graphics_queue();
     let mut cmd_buf = graphics_queue.create_command_buffer();
 
+    // The pool was last used TIMESTAMP_POOL_COUNT frames ago, keep the old stats if the GPU isn't done with it yet.
+    let timestamp_pool = self.timestamp_pools[self.frame as usize % TIMESTAMP_POOL_COUNT].clone();
+    let mut timestamps = [0u64; PASS_COUNT + 1];
+    if self.device.get_query_results(&timestamp_pool, 0, &mut timestamps) {
+      self.stats = DesktopRendererStats::from_timestamps(&timestamps);
+    }
+    cmd_buf.reset_query_pool(&timestamp_pool, 0, timestamp_pool.query_count());
+    // The fence slot is reused every FRAME_FENCE_COUNT frames, wait for the GPU to finish the old frame first
+    let fence_index = self.frame as usize % FRAME_FENCE_COUNT;
+    let frame_fence = self.frame_fences[fence_index].clone();
+    if self.submitted_frame_fences[fence_index] {
+      frame_fence.await_signal();
+    }
+    self.hi_z.read_back(self.frame);
+    self.screenshot.write_pending();
+    frame_fence.reset();
+    self.submitted_frame_fences[fence_index] = false;
+    cmd_buf.write_timestamp(&timestamp_pool, 0, TimestampStage::Bottom);
+
     let view_ref = view.borrow();
     let scene_ref = scene.borrow();
-    self.late_latching_pass.execute(&mut cmd_buf, primary_camera.buffer());
+    let jitter_point = match &self.anti_aliasing {
+      AntiAliasingPass::Taa(taa) => taa.jitter_point(self.frame),
+      AntiAliasingPass::Fxaa(_) | AntiAliasingPass::None => Vec2::new(0f32, 0f32)
+    };
+    cmd_buf.begin_label("Late latching", [0.5f32, 0.5f32, 0.5f32, 1f32]);
+    self.late_latching_pass.execute(&mut cmd_buf, primary_camera.buffer(), jitter_point);
+    cmd_buf.end_label();
+    cmd_buf.write_timestamp(&timestamp_pool, 1, TimestampStage::Bottom);
+    cmd_buf.begin_label("Clustering", [0.2f32, 0.4f32, 0.8f32, 1f32]);
     self.clustering_pass.execute(&mut cmd_buf, Vec2UI::new(self.swapchain.width(), self.swapchain.height()), 0.1f32, 10f32, self.late_latching_pass.camera_buffer());
-    self.light_binning_pass.execute(&mut cmd_buf, &scene_ref, self.clustering_pass.clusters_buffer(), self.late_latching_pass.camera_buffer());
-    self.prepass.execute(&mut cmd_buf, &self.device, &scene_ref, &view_ref, Matrix4::identity(), self.frame, self.late_latching_pass.camera_buffer(), self.late_latching_pass.camera_buffer_history());
+    cmd_buf.end_label();
+    cmd_buf.write_timestamp(&timestamp_pool, 2, TimestampStage::Bottom);
+    cmd_buf.begin_label("Light binning", [0.9f32, 0.8f32, 0.2f32, 1f32]);
+    self.light_binning_pass.execute(&mut cmd_buf, &self.device, &scene_ref, self.clustering_pass.config(), self.clustering_pass.clusters_buffer(), self.late_latching_pass.camera_buffer());
+    cmd_buf.end_label();
+    cmd_buf.write_timestamp(&timestamp_pool, 3, TimestampStage::Bottom);
+    cmd_buf.begin_label("Point shadows", [0.1f32, 0.1f32, 0.4f32, 1f32]);
+    let point_shadow_buffer = self.point_shadows.execute(&mut cmd_buf, &scene_ref, &view_ref);
+    cmd_buf.end_label();
+    cmd_buf.write_timestamp(&timestamp_pool, 4, TimestampStage::Bottom);
+    cmd_buf.begin_label("Shadow cascades", [0.1f32, 0.2f32, 0.5f32, 1f32]);
+    let cascade_buffer = self.cascaded_shadows.execute(&mut cmd_buf, &scene_ref, &view_ref);
+    cmd_buf.end_label();
+    cmd_buf.write_timestamp(&timestamp_pool, 5, TimestampStage::Bottom);
+    cmd_buf.begin_label("Depth prepass", [0.3f32, 0.3f32, 0.3f32, 1f32]);
+    self.prepass.execute(&mut cmd_buf, &self.device, &scene_ref, &view_ref, Matrix4::identity(), self.late_latching_pass.camera_buffer(), self.late_latching_pass.camera_buffer_history());
+    cmd_buf.end_label();
+    cmd_buf.write_timestamp(&timestamp_pool, 6, TimestampStage::Bottom);
+    cmd_buf.begin_label("SSAO", [0.6f32, 0.6f32, 0.6f32, 1f32]);
     self.ssao.execute(&mut cmd_buf, self.prepass.normals_srv(), self.prepass.depth_srv(), self.late_latching_pass.camera_buffer());
-    self.geometry.execute(&mut cmd_buf, &self.device, &scene_ref, &view_ref, lightmap, Matrix4::identity(), self.frame, self.prepass.depth_dsv(), self.light_binning_pass.light_bitmask_buffer(), self.late_latching_pass.camera_buffer(), self.ssao.ssao_srv());
-    self.taa.execute(&mut cmd_buf, self.geometry.output_srv(), self.prepass.motion_srv());
-    self.sharpen.execute(&mut cmd_buf, self.taa.taa_srv());
+    cmd_buf.end_label();
+    cmd_buf.write_timestamp(&timestamp_pool, 7, TimestampStage::Bottom);
+    cmd_buf.begin_label("Hi-Z", [0.4f32, 0.2f32, 0.2f32, 1f32]);
+    self.hi_z.execute(&mut cmd_buf, self.frame, self.prepass.depth_srv(), primary_camera.proj() * primary_camera.view(), &frame_fence);
+    cmd_buf.end_label();
+    cmd_buf.write_timestamp(&timestamp_pool, 8, TimestampStage::Bottom);
+    cmd_buf.begin_label("Geometry", [0.2f32, 0.8f32, 0.2f32, 1f32]);
+    self.geometry.execute(&mut cmd_buf, &self.device, &scene_ref, &view_ref, lightmap, Matrix4::identity(), jitter_point, self.clustering_pass.config(), self.prepass.depth_dsv(), self.light_binning_pass.light_bitmask_buffer(), self.light_binning_pass.spot_light_bitmask_buffer(), self.late_latching_pass.camera_buffer(), self.ssao.ssao_srv(), self.point_shadows.shadow_map_srv(), self.point_shadows.sampler(), &point_shadow_buffer, self.cascaded_shadows.shadow_map_srv(), self.cascaded_shadows.sampler(), &cascade_buffer);
+    cmd_buf.end_label();
+    cmd_buf.write_timestamp(&timestamp_pool, 9, TimestampStage::Bottom);
+    let anti_aliased_srv = match &mut self.anti_aliasing {
+      AntiAliasingPass::Taa(taa) => {
+        cmd_buf.begin_label("TAA", [0.8f32, 0.2f32, 0.8f32, 1f32]);
+        taa.execute(&mut cmd_buf, self.geometry.output_srv(), self.prepass.motion_srv());
+        cmd_buf.end_label();
+        taa.taa_srv().clone()
+      },
+      AntiAliasingPass::Fxaa(fxaa) => {
+        cmd_buf.begin_label("FXAA", [0.8f32, 0.2f32, 0.8f32, 1f32]);
+        fxaa.execute(&mut cmd_buf, self.geometry.output_srv());
+        // Nothing reads the motion vectors but the prepass expects them in the same state TAA leaves them in.
+        cmd_buf.barrier(&[
+          Barrier::TextureBarrier {
+            old_primary_usage: TextureUsage::RENDER_TARGET,
+            new_primary_usage: TextureUsage::COMPUTE_SHADER_SAMPLED,
+            old_usages: TextureUsage::RENDER_TARGET,
+            new_usages: TextureUsage::COMPUTE_SHADER_SAMPLED,
+            texture: self.prepass.motion_srv().texture(),
+          }
+        ]);
+        cmd_buf.end_label();
+        fxaa.fxaa_srv().clone()
+      }
+      AntiAliasingPass::None => {
+        // Bloom expects its input in the state the anti-aliasing passes leave their output in.
+        cmd_buf.barrier(&[
+          Barrier::TextureBarrier {
+            old_primary_usage: TextureUsage::RENDER_TARGET,
+            new_primary_usage: TextureUsage::COMPUTE_SHADER_STORAGE_WRITE,
+            old_usages: TextureUsage::RENDER_TARGET,
+            new_usages: TextureUsage::COMPUTE_SHADER_STORAGE_WRITE,
+            texture: self.geometry.output_srv().texture(),
+          },
+          Barrier::TextureBarrier {
+            old_primary_usage: TextureUsage::RENDER_TARGET,
+            new_primary_usage: TextureUsage::COMPUTE_SHADER_SAMPLED,
+            old_usages: TextureUsage::RENDER_TARGET,
+            new_usages: TextureUsage::COMPUTE_SHADER_SAMPLED,
+            texture: self.prepass.motion_srv().texture(),
+          }
+        ]);
+        self.geometry.output_srv().clone()
+      }
+    };
+    cmd_buf.write_timestamp(&timestamp_pool, 10, TimestampStage::Bottom);
+    cmd_buf.begin_label("Bloom", [0.9f32, 0.9f32, 0.5f32, 1f32]);
+    self.bloom.execute(&mut cmd_buf, &anti_aliased_srv);
+    cmd_buf.end_label();
+    cmd_buf.write_timestamp(&timestamp_pool, 11, TimestampStage::Bottom);
+    cmd_buf.begin_label("Sharpening", [0.8f32, 0.4f32, 0.2f32, 1f32]);
+    self.sharpen.execute(&mut cmd_buf, self.bloom.output_srv());
+    // Debug views replace the final image so they aren't smeared by TAA, bloom or tonemapping
+    match self.geometry.debug_view() {
+      DebugView::None => {}
+      DebugView::Normals => self.sharpen.execute_debug_output(&mut cmd_buf, self.prepass.normals_srv(), DebugOutput::Normals),
+      _ => self.sharpen.execute_debug_output(&mut cmd_buf, self.geometry.output_srv(), DebugOutput::Passthrough)
+    }
+    cmd_buf.end_label();
+    cmd_buf.write_timestamp(&timestamp_pool, 12, TimestampStage::Bottom);
 
-    self.taa.swap_history_resources();
+    if let AntiAliasingPass::Taa(taa) = &mut self.anti_aliasing {
+      taa.swap_history_resources();
+    }
     self.late_latching_pass.swap_history_resources();
 
     cmd_buf.barrier(&[
@@ -92,26 +337,25 @@ impl<B: Backend> RenderPath<B> for DesktopRenderer<B> {
     let prepare_sem = self.device.create_semaphore();
     let cmd_buf_sem = self.device.create_semaphore();
     self.frame += 1;
-    let back_buffer_res = self.swapchain.prepare_back_buffer(&prepare_sem);
-    if back_buffer_res.is_none() {
-      return Err(SwapchainError::Other);
-    }
-
-    let back_buffer = back_buffer_res.unwrap();
+    let back_buffer = self.swapchain.prepare_back_buffer(&prepare_sem)?;
 
     cmd_buf.barrier(
       &[
+        // The submission only waits for the back buffer at the copy stage, so the layout transition has to wait there too.
         Barrier::TextureBarrier {
           old_primary_usage: TextureUsage::UNINITIALIZED,
           new_primary_usage: TextureUsage::COPY_DST,
-          old_usages: TextureUsage::empty(),
-          new_usages: TextureUsage::empty(),
+          old_usages: TextureUsage::COPY_DST,
+          new_usages: TextureUsage::COPY_DST,
           texture: back_buffer.texture(),
         }
       ]
     );
     cmd_buf.flush_barriers();
+    cmd_buf.begin_label("Blit to back buffer", [0.5f32, 0.5f32, 0.5f32, 1f32]);
     cmd_buf.blit(self.sharpen.sharpened_texture(), 0, 0, back_buffer.texture(), 0, 0);
+    self.screenshot.execute(&mut cmd_buf, &self.device, self.sharpen.sharpened_texture(), &frame_fence);
+    cmd_buf.end_label();
     cmd_buf.barrier(
       &[
         Barrier::TextureBarrier {
@@ -124,7 +368,8 @@ impl<B: Backend> RenderPath<B> for DesktopRenderer<B> {
       ]
     );
 
-    graphics_queue.submit(cmd_buf.finish(), None, &[&prepare_sem], &[&cmd_buf_sem]);
+    graphics_queue.submit(cmd_buf.finish(), Some(&frame_fence), &[(&prepare_sem, TextureUsage::COPY_DST)], &[&cmd_buf_sem]);
+    self.submitted_frame_fences[fence_index] = true;
     graphics_queue.present(&self.swapchain, &[&cmd_buf_sem]);
     return Ok(());
   }
@@ -0,0 +1,124 @@
+use sourcerenderer_core::{graphics::{AddressMode, Backend as GraphicsBackend, Barrier, BindingFrequency, CommandBuffer, Device, Filter, Format, PipelineBinding, SamplerInfo, ShaderType, Swapchain, Texture, TextureDimension, TextureInfo, TextureShaderResourceView, TextureShaderResourceViewInfo, TextureUnorderedAccessView, TextureUnorderedAccessViewInfo, TextureUsage}};
+use sourcerenderer_core::Platform;
+use std::sync::Arc;
+use std::path::Path;
+use std::io::Read;
+use sourcerenderer_core::platform::io::IO;
+
+pub struct FxaaPass<B: GraphicsBackend> {
+  pipeline: Arc<B::ComputePipeline>,
+  sampler: Arc<B::Sampler>,
+  fxaa_uav: Arc<B::TextureUnorderedAccessView>,
+  fxaa_srv: Arc<B::TextureShaderResourceView>
+}
+
+impl<B: GraphicsBackend> FxaaPass<B> {
+  pub fn new<P: Platform>(device: &Arc<B::Device>, swapchain: &Arc<B::Swapchain>, init_cmd_buffer: &mut B::CommandBuffer) -> Self {
+    let fxaa_compute_shader = {
+      let mut file = <P::IO as IO>::open_asset(Path::new("shaders").join(Path::new("fxaa.comp.spv"))).unwrap();
+      let mut bytes: Vec<u8> = Vec::new();
+      file.read_to_end(&mut bytes).unwrap();
+      device.create_shader(ShaderType::ComputeShader, &bytes, Some("fxaa.comp.spv"))
+    };
+    let pipeline = device.create_compute_pipeline(&fxaa_compute_shader);
+
+    let sampler = device.create_sampler(&SamplerInfo {
+      mag_filter: Filter::Linear,
+      min_filter: Filter::Linear,
+      mip_filter: Filter::Linear,
+      address_mode_u: AddressMode::ClampToEdge,
+      address_mode_v: AddressMode::ClampToEdge,
+      address_mode_w: AddressMode::ClampToEdge,
+      mip_bias: 0.0,
+      max_anisotropy: 0.0,
+      compare_op: None,
+      min_lod: 0.0,
+      max_lod: 1.0,
+    });
+
+    let (uav, srv) = Self::create_targets(device, swapchain, init_cmd_buffer);
+
+    Self {
+      pipeline,
+      sampler,
+      fxaa_uav: uav,
+      fxaa_srv: srv
+    }
+  }
+
+  fn create_targets(device: &Arc<B::Device>, swapchain: &Arc<B::Swapchain>, init_cmd_buffer: &mut B::CommandBuffer) -> (Arc<B::TextureUnorderedAccessView>, Arc<B::TextureShaderResourceView>) {
+    let texture = device.create_texture(&TextureInfo {
+      dimension: TextureDimension::Dim2D,
+      format: Format::RGBA16Float,
+      width: swapchain.width(),
+      height: swapchain.height(),
+      depth: 1,
+      mip_levels: 1,
+      array_length: 1,
+      samples: sourcerenderer_core::graphics::SampleCount::Samples1,
+      usage: TextureUsage::COMPUTE_SHADER_SAMPLED | TextureUsage::COMPUTE_SHADER_STORAGE_WRITE,
+      is_cubemap: false,
+    }, Some("FXAAOutput"));
+    let uav = device.create_unordered_access_view(&texture, &TextureUnorderedAccessViewInfo {
+      base_mip_level: 0,
+      mip_level_length: 1,
+      base_array_level: 0,
+      array_level_length: 1,
+    });
+    let srv = device.create_shader_resource_view(&texture, &TextureShaderResourceViewInfo {
+      base_mip_level: 0,
+      mip_level_length: 1,
+      base_array_level: 0,
+      array_level_length: 1,
+    });
+
+    init_cmd_buffer.barrier(&[
+      Barrier::TextureBarrier {
+        old_primary_usage: TextureUsage::UNINITIALIZED,
+        new_primary_usage: TextureUsage::COMPUTE_SHADER_SAMPLED,
+        old_usages: TextureUsage::empty(),
+        new_usages: TextureUsage::empty(),
+        texture: &texture,
+      }
+    ]);
+
+    (uav, srv)
+  }
+
+  pub fn resize(&mut self, device: &Arc<B::Device>, swapchain: &Arc<B::Swapchain>, init_cmd_buffer: &mut B::CommandBuffer) {
+    let (uav, srv) = Self::create_targets(device, swapchain, init_cmd_buffer);
+    self.fxaa_uav = uav;
+    self.fxaa_srv = srv;
+  }
+
+  pub fn execute(&mut self, cmd_buffer: &mut B::CommandBuffer, input_image: &Arc<B::TextureShaderResourceView>) {
+    cmd_buffer.barrier(&[
+      Barrier::TextureBarrier {
+        old_primary_usage: TextureUsage::RENDER_TARGET,
+        new_primary_usage: TextureUsage::COMPUTE_SHADER_SAMPLED,
+        old_usages: TextureUsage::RENDER_TARGET,
+        new_usages: TextureUsage::COMPUTE_SHADER_SAMPLED,
+        texture: input_image.texture(),
+      },
+      Barrier::TextureBarrier {
+        old_primary_usage: TextureUsage::COMPUTE_SHADER_SAMPLED,
+        new_primary_usage: TextureUsage::COMPUTE_SHADER_STORAGE_WRITE,
+        old_usages: TextureUsage::COMPUTE_SHADER_SAMPLED,
+        new_usages: TextureUsage::COMPUTE_SHADER_STORAGE_WRITE,
+        texture: self.fxaa_uav.texture()
+      },
+    ]);
+
+    cmd_buffer.set_pipeline(PipelineBinding::Compute(&self.pipeline));
+    cmd_buffer.bind_texture_view(BindingFrequency::PerDraw, 0, input_image, &self.sampler);
+    cmd_buffer.bind_storage_texture(BindingFrequency::PerDraw, 1, &self.fxaa_uav);
+    cmd_buffer.finish_binding();
+
+    let info = self.fxaa_uav.texture().get_info();
+    cmd_buffer.dispatch(info.width, info.height, 1);
+  }
+
+  pub fn fxaa_srv(&self) -> &Arc<B::TextureShaderResourceView> {
+    &self.fxaa_srv
+  }
+}
@@ -1,12 +1,14 @@
 use nalgebra::Vector2;
-use sourcerenderer_core::{Matrix4, graphics::{AddressMode, AttachmentBlendInfo, AttachmentInfo, Backend as GraphicsBackend, Barrier, BindingFrequency, BlendInfo, BufferUsage, CommandBuffer, CompareFunc, CullMode, DepthStencilAttachmentRef, DepthStencilInfo, Device, FillMode, Filter, Format, FrontFace, GraphicsPipelineInfo, InputAssemblerElement, InputRate, LoadOp, LogicOp, OutputAttachmentRef, PipelineBinding, PrimitiveType, Queue, RasterizerInfo, RenderPassAttachment, RenderPassAttachmentView, RenderPassBeginInfo, RenderPassInfo, RenderpassRecordingMode, SampleCount, SamplerInfo, Scissor, ShaderInputElement, ShaderType, StencilInfo, StoreOp, SubpassInfo, Swapchain, Texture, TextureDepthStencilView, TextureInfo, TextureRenderTargetView, TextureRenderTargetViewInfo, TextureShaderResourceView, TextureShaderResourceViewInfo, TextureUsage, VertexLayoutInfo, Viewport}};
-use std::sync::Arc;
-use crate::renderer::{drawable::View, renderer_scene::RendererScene};
+use sourcerenderer_core::{Matrix4, graphics::{AddressMode, AttachmentBlendInfo, AttachmentInfo, Backend as GraphicsBackend, Barrier, BindingFrequency, BlendFactor, BlendInfo, BlendOp, BufferUsage, CommandBuffer, CompareFunc, CullMode, DepthStencilAttachmentRef, DrawIndexedIndirectCommand, DepthStencilInfo, Device, FillMode, Filter, Format, FrontFace, GraphicsPipelineInfo, IndexFormat, InputAssemblerElement, InputRate, LoadOp, LogicOp, OutputAttachmentRef, PipelineBinding, PrimitiveType, Queue, RasterizerInfo, RenderPassAttachment, RenderPassAttachmentView, RenderPassBeginInfo, RenderPassInfo, RenderpassRecordingMode, SampleCount, SamplerInfo, Scissor, ShaderInputElement, ShaderType, StencilInfo, StoreOp, SubpassInfo, Swapchain, Texture, TextureDepthStencilView, TextureDepthStencilViewInfo, TextureDimension, TextureInfo, TextureRenderTargetView, TextureRenderTargetViewInfo, TextureShaderResourceView, TextureShaderResourceViewInfo, TextureUsage, VertexLayoutInfo, Viewport}};
+use std::{collections::HashMap, sync::Arc};
+use crate::renderer::{DebugView, drawable::{DrawablePart, RendererStaticDrawable, View}, renderer_scene::RendererScene};
+
+use super::clustering::ClusterConfig;
 use sourcerenderer_core::{Platform, Vec2, Vec2I, Vec2UI};
-use crate::renderer::passes::desktop::taa::scaled_halton_point;
 use std::path::Path;
 use std::io::Read;
 use crate::renderer::renderer_assets::*;
+use crate::asset::MeshRange;
 use sourcerenderer_core::platform::io::IO;
 use rayon::prelude::*;
 
@@ -21,41 +23,188 @@ struct FrameData {
   cluster_z_bias: f32,
   cluster_z_scale: f32,
   cluster_count: nalgebra::Vector3::<u32>,
-  point_light_count: u32
+  point_light_count: u32,
+  debug_view: u32,
+  spot_light_count: u32
+}
+
+/// Multisampled color and depth targets that get rendered to instead of the output when MSAA is enabled.
+struct MultisampledTargets<B: GraphicsBackend> {
+  rtv: Arc<B::TextureRenderTargetView>,
+  dsv: Arc<B::TextureDepthStencilView>
+}
+
+// Lighting isn't clamped, the sharpening pass tonemaps it at the very end.
+const OUTPUT_FORMAT: Format = Format::RGBA16Float;
+
+/// Everything that has the size of the swapchain.
+struct GeometryTargets<B: GraphicsBackend> {
+  rtv: Arc<B::TextureRenderTargetView>,
+  srv: Arc<B::TextureShaderResourceView>,
+  msaa_targets: Option<MultisampledTargets<B>>
 }
 
 pub struct GeometryPass<B: GraphicsBackend> {
   rtv: Arc<B::TextureRenderTargetView>,
   srv: Arc<B::TextureShaderResourceView>,
+  msaa_targets: Option<MultisampledTargets<B>>,
   sampler: Arc<B::Sampler>,
-  pipeline: Arc<B::GraphicsPipeline>
+  pipeline: Arc<B::GraphicsPipeline>,
+  overdraw_pipeline: Arc<B::GraphicsPipeline>,
+  instanced_pipeline: Arc<B::GraphicsPipeline>,
+  msaa: SampleCount,
+  debug_view: DebugView,
+  indirect_draws: bool
 }
 
-impl<B: GraphicsBackend> GeometryPass<B> {
-  pub fn new<P: Platform>(device: &Arc<B::Device>, swapchain: &Arc<B::Swapchain>, init_cmd_buffer: &mut B::CommandBuffer) -> Self {
-    let output = device.create_texture(&TextureInfo {
-      format: Format::RGBA8,
-      width: swapchain.width(),
-      height: swapchain.height(),
-      depth: 1,
-      mip_levels: 1,
-      array_length: 1,
-      samples: SampleCount::Samples1,
-      usage: TextureUsage::COMPUTE_SHADER_SAMPLED | TextureUsage::RENDER_TARGET | TextureUsage::COPY_SRC,
-    }, Some("GeometryPassOutput"));
-    let rtv = device.create_render_target_view(&output, &TextureRenderTargetViewInfo {
-      base_mip_level: 0,
-      mip_level_length: 1,
-      base_array_level: 0,
-      array_level_length: 1,
+/// A run of parts that share a drawable and a material and can be submitted using a single indirect draw.
+pub(super) struct IndirectDrawBatch {
+  pub(super) drawable_index: usize,
+  pub(super) part_index: usize,
+  pub(super) lod: usize,
+  pub(super) first_command: u32,
+  pub(super) command_count: u32
+}
+
+/// Builds the indirect draw commands for the given visible parts.
+/// Parts of meshes without an index buffer are skipped and have to be drawn directly.
+pub(super) fn build_indirect_draws<B: GraphicsBackend>(parts: &[DrawablePart], static_drawables: &[RendererStaticDrawable<B>]) -> (Vec<DrawIndexedIndirectCommand>, Vec<IndirectDrawBatch>) {
+  collect_indirect_draws(parts, |part| {
+    let model = &static_drawables[part.drawable_index].model;
+    let mesh = model.lod_mesh(part.lod);
+    mesh.indices.as_ref().map(|_| (&mesh.parts[part.part_index], &model.materials[part.part_index]))
+  })
+}
+
+/// `part_info` returns the index range and the material of a part or None if its mesh has no index buffer.
+fn collect_indirect_draws<'a, M: PartialEq, F: Fn(&DrawablePart) -> Option<(&'a MeshRange, M)>>(parts: &[DrawablePart], part_info: F) -> (Vec<DrawIndexedIndirectCommand>, Vec<IndirectDrawBatch>) {
+  let mut commands = Vec::<DrawIndexedIndirectCommand>::with_capacity(parts.len());
+  let mut batches = Vec::<IndirectDrawBatch>::new();
+  let mut batch_material: Option<M> = None;
+  for part in parts {
+    let (range, material) = match part_info(part) {
+      Some(info) => info,
+      None => continue
+    };
+
+    let can_merge = batches.last().map_or(false, |batch| {
+      batch.drawable_index == part.drawable_index
+        && batch.lod == part.lod
+        && batch.first_command + batch.command_count == commands.len() as u32
+        && batch_material.as_ref() == Some(&material)
     });
-    let srv = device.create_shader_resource_view(&output, &TextureShaderResourceViewInfo {
-      base_mip_level: 0,
-      mip_level_length: 1,
-      base_array_level: 0,
-      array_level_length: 1,
+    if can_merge {
+      batches.last_mut().unwrap().command_count += 1;
+    } else {
+      batches.push(IndirectDrawBatch {
+        drawable_index: part.drawable_index,
+        part_index: part.part_index,
+        lod: part.lod,
+        first_command: commands.len() as u32,
+        command_count: 1
+      });
+      batch_material = Some(material);
+    }
+    commands.push(DrawIndexedIndirectCommand {
+      index_count: range.count,
+      instance_count: 1,
+      first_index: range.start,
+      vertex_offset: 0,
+      first_instance: 0
     });
+  }
+  (commands, batches)
+}
+
+/// A part of a model that gets drawn once for all of its visible instances.
+pub(super) struct InstancedDrawBatch {
+  pub(super) drawable_index: usize,
+  pub(super) part_index: usize,
+  pub(super) lod: usize,
+  pub(super) first_instance: u32,
+  pub(super) instance_count: u32
+}
+
+/// Groups the visible parts of drawables that share a model so every part of the model is drawn once for all instances.
+/// Returns the instance transforms, the instanced batches and the parts that have to be drawn individually.
+pub(super) fn build_instanced_draws<B: GraphicsBackend>(parts: &[DrawablePart], scene: &RendererScene<B>) -> (Vec<Matrix4>, Vec<InstancedDrawBatch>, Vec<DrawablePart>) {
+  let static_drawables = scene.static_drawables();
+  collect_instanced_draws(
+    parts,
+    |drawable_index| if scene.is_instanced(drawable_index) { Some(static_drawables[drawable_index].model_path.as_str()) } else { None },
+    |drawable_index| static_drawables[drawable_index].transform
+  )
+}
 
+/// `instanced_model` returns the model path of drawables that can be instanced.
+fn collect_instanced_draws<'a, F: Fn(usize) -> Option<&'a str>, T: Fn(usize) -> Matrix4>(parts: &[DrawablePart], instanced_model: F, transform: T) -> (Vec<Matrix4>, Vec<InstancedDrawBatch>, Vec<DrawablePart>) {
+  let mut instances = HashMap::<(&str, usize, usize), Vec<usize>>::new();
+  let mut instance_order = Vec::<(&str, usize, usize)>::new();
+  let mut regular_parts = Vec::<DrawablePart>::new();
+  for part in parts {
+    let model_path = match instanced_model(part.drawable_index) {
+      Some(model_path) => model_path,
+      None => {
+        regular_parts.push(part.clone());
+        continue;
+      }
+    };
+
+    let key = (model_path, part.lod, part.part_index);
+    instances.entry(key).or_insert_with(|| {
+      instance_order.push(key);
+      Vec::new()
+    }).push(part.drawable_index);
+  }
+
+  let mut transforms = Vec::<Matrix4>::new();
+  let mut batches = Vec::<InstancedDrawBatch>::new();
+  for key in instance_order {
+    let drawable_indices = &instances[&key];
+    if drawable_indices.len() == 1 {
+      // Only one of the instances is visible
+      regular_parts.push(DrawablePart {
+        drawable_index: drawable_indices[0],
+        part_index: key.2,
+        lod: key.1
+      });
+      continue;
+    }
+
+    batches.push(InstancedDrawBatch {
+      drawable_index: drawable_indices[0],
+      part_index: key.2,
+      lod: key.1,
+      first_instance: transforms.len() as u32,
+      instance_count: drawable_indices.len() as u32
+    });
+    transforms.extend(drawable_indices.iter().map(|drawable_index| transform(*drawable_index)));
+  }
+  (transforms, batches, regular_parts)
+}
+
+fn bind_drawable_part<B: GraphicsBackend>(command_buffer: &mut B::CommandBuffer, drawable: &RendererStaticDrawable<B>, lod: usize, part_index: usize, lightmap: &Arc<RendererTexture<B>>, sampler: &Arc<B::Sampler>) {
+  let model = &drawable.model;
+  let mesh = model.lod_mesh(lod);
+
+  command_buffer.set_vertex_buffer(&mesh.vertices);
+  if mesh.indices.is_some() {
+    command_buffer.set_index_buffer(mesh.indices.as_ref().unwrap(), IndexFormat::U32);
+  }
+
+  let material = &model.materials[part_index];
+  let texture = material.albedo.borrow();
+  let albedo_view = texture.view.borrow();
+  command_buffer.bind_texture_view(BindingFrequency::PerMaterial, 0, &albedo_view, sampler);
+
+  let lightmap_ref = lightmap.view.borrow();
+  command_buffer.bind_texture_view(BindingFrequency::PerMaterial, 1, &lightmap_ref, sampler);
+  command_buffer.finish_binding();
+}
+
+impl<B: GraphicsBackend> GeometryPass<B> {
+  pub fn new<P: Platform>(device: &Arc<B::Device>, swapchain: &Arc<B::Swapchain>, msaa: SampleCount, init_cmd_buffer: &mut B::CommandBuffer) -> Self {
+    let GeometryTargets { rtv, srv, msaa_targets } = Self::create_targets(device, swapchain, msaa, init_cmd_buffer);
 
     let sampler = device.create_sampler(&SamplerInfo {
       mag_filter: Filter::Linear,
@@ -80,6 +229,13 @@ impl<B: GraphicsBackend> GeometryPass<B> {
     device.create_shader(ShaderType::VertexShader, &bytes, Some("textured.vert.spv"))
   };
 
+  let instanced_vertex_shader = {
+    let mut file = <P::IO as IO>::open_asset(Path::new("shaders").join(Path::new("textured_instanced.vert.spv"))).unwrap();
+    let mut bytes: Vec<u8> = Vec::new();
+    file.read_to_end(&mut bytes).unwrap();
+    device.create_shader(ShaderType::VertexShader, &bytes, Some("textured_instanced.vert.spv"))
+  };
+
   let fragment_shader = {
     let mut file = <P::IO as IO>::open_asset(Path::new("shaders").join(Path::new("textured.frag.spv"))).unwrap();
     let mut bytes: Vec<u8> = Vec::new();
@@ -87,8 +243,8 @@ impl<B: GraphicsBackend> GeometryPass<B> {
     device.create_shader(ShaderType::FragmentShader, &bytes, Some("textured.frag.spv"))
   };
 
-  let pipeline_info: GraphicsPipelineInfo<B> = GraphicsPipelineInfo {
-    vs: vertex_shader,
+  let mut pipeline_info: GraphicsPipelineInfo<B> = GraphicsPipelineInfo {
+    vs: vertex_shader.clone(),
     fs: Some(fragment_shader),
     gs: None,
     tcs: None,
@@ -149,11 +305,12 @@ impl<B: GraphicsBackend> GeometryPass<B> {
       fill_mode: FillMode::Fill,
       cull_mode: CullMode::Back,
       front_face: FrontFace::CounterClockwise,
-      sample_count: SampleCount::Samples1
+      sample_count: msaa
     },
     depth_stencil: DepthStencilInfo {
       depth_test_enabled: true,
-      depth_write_enabled: false,
+      // The multisampled path can't reuse the single sampled prepass depth
+      depth_write_enabled: msaa_targets.is_some(),
       depth_func: CompareFunc::LessEqual,
       stencil_enable: false,
       stencil_read_mask: 0u8,
@@ -171,11 +328,11 @@ impl<B: GraphicsBackend> GeometryPass<B> {
       ]
     }
   };
-  let pipeline = device.create_graphics_pipeline(&pipeline_info, &RenderPassInfo {
+  let render_pass_info = RenderPassInfo {
     attachments: vec![
       AttachmentInfo {
-        format: output.get_info().format,
-        samples: output.get_info().samples,
+        format: OUTPUT_FORMAT,
+        samples: msaa,
         load_op: LoadOp::DontCare,
         store_op: StoreOp::DontCare,
         stencil_load_op: LoadOp::DontCare,
@@ -183,7 +340,7 @@ impl<B: GraphicsBackend> GeometryPass<B> {
       },
       AttachmentInfo {
         format: Format::D24S8,
-        samples: SampleCount::Samples1,
+        samples: msaa,
         load_op: LoadOp::DontCare,
         store_op: StoreOp::DontCare,
         stencil_load_op: LoadOp::DontCare,
@@ -201,30 +358,168 @@ impl<B: GraphicsBackend> GeometryPass<B> {
         ],
         depth_stencil_attachment: Some(DepthStencilAttachmentRef {
           index: 1,
-          read_only: true,
+          read_only: msaa_targets.is_none(),
         }),
       }
     ]
-  }, 0);
-
-  init_cmd_buffer.barrier(&[
-    Barrier::TextureBarrier {
-      old_primary_usage: TextureUsage::UNINITIALIZED,
-      new_primary_usage: TextureUsage::COMPUTE_SHADER_SAMPLED,
-      old_usages: TextureUsage::empty(),
-      new_usages: TextureUsage::empty(),
-      texture: rtv.texture(),
+  };
+  let pipeline = device.create_graphics_pipeline(&pipeline_info, &render_pass_info, 0);
+
+  pipeline_info.vs = instanced_vertex_shader;
+  let instanced_pipeline = device.create_graphics_pipeline(&pipeline_info, &render_pass_info, 0);
+  pipeline_info.vs = vertex_shader;
+
+  // Counts every fragment by adding them up regardless of depth
+  pipeline_info.depth_stencil.depth_test_enabled = false;
+  pipeline_info.blend.attachments = vec![
+    AttachmentBlendInfo {
+      blend_enabled: true,
+      src_color_blend_factor: BlendFactor::One,
+      dst_color_blend_factor: BlendFactor::One,
+      color_blend_op: BlendOp::Add,
+      src_alpha_blend_factor: BlendFactor::One,
+      dst_alpha_blend_factor: BlendFactor::One,
+      alpha_blend_op: BlendOp::Add,
+      ..Default::default()
     }
-  ]);
+  ];
+  let overdraw_pipeline = device.create_graphics_pipeline(&pipeline_info, &render_pass_info, 0);
 
     Self {
       srv,
       rtv,
+      msaa_targets,
       sampler,
-      pipeline
+      pipeline,
+      overdraw_pipeline,
+      instanced_pipeline,
+      msaa,
+      debug_view: DebugView::None,
+      indirect_draws: false
+    }
+  }
+
+  fn create_targets(device: &Arc<B::Device>, swapchain: &Arc<B::Swapchain>, msaa: SampleCount, init_cmd_buffer: &mut B::CommandBuffer) -> GeometryTargets<B> {
+    let output = device.create_texture(&TextureInfo {
+      dimension: TextureDimension::Dim2D,
+      format: OUTPUT_FORMAT,
+      width: swapchain.width(),
+      height: swapchain.height(),
+      depth: 1,
+      mip_levels: 1,
+      array_length: 1,
+      samples: SampleCount::Samples1,
+      usage: TextureUsage::COMPUTE_SHADER_SAMPLED | TextureUsage::RENDER_TARGET | TextureUsage::COPY_SRC | TextureUsage::RESOLVE_DST,
+      is_cubemap: false,
+    }, Some("GeometryPassOutput"));
+    let rtv = device.create_render_target_view(&output, &TextureRenderTargetViewInfo {
+      base_mip_level: 0,
+      mip_level_length: 1,
+      base_array_level: 0,
+      array_level_length: 1,
+    });
+    let srv = device.create_shader_resource_view(&output, &TextureShaderResourceViewInfo {
+      base_mip_level: 0,
+      mip_level_length: 1,
+      base_array_level: 0,
+      array_level_length: 1,
+    });
+
+    let msaa_targets = if msaa != SampleCount::Samples1 {
+      let color = device.create_texture(&TextureInfo {
+        dimension: TextureDimension::Dim2D,
+        format: OUTPUT_FORMAT,
+        width: swapchain.width(),
+        height: swapchain.height(),
+        depth: 1,
+        mip_levels: 1,
+        array_length: 1,
+        samples: msaa,
+        usage: TextureUsage::RENDER_TARGET | TextureUsage::RESOLVE_SRC,
+        is_cubemap: false,
+      }, Some("GeometryPassMSAAColor"));
+      let depth = device.create_texture(&TextureInfo {
+        dimension: TextureDimension::Dim2D,
+        format: Format::D24S8,
+        width: swapchain.width(),
+        height: swapchain.height(),
+        depth: 1,
+        mip_levels: 1,
+        array_length: 1,
+        samples: msaa,
+        usage: TextureUsage::DEPTH_WRITE,
+        is_cubemap: false,
+      }, Some("GeometryPassMSAADepth"));
+      init_cmd_buffer.barrier(&[
+        Barrier::TextureBarrier {
+          old_primary_usage: TextureUsage::UNINITIALIZED,
+          new_primary_usage: TextureUsage::RESOLVE_SRC,
+          old_usages: TextureUsage::empty(),
+          new_usages: TextureUsage::empty(),
+          texture: &color,
+        },
+        Barrier::TextureBarrier {
+          old_primary_usage: TextureUsage::UNINITIALIZED,
+          new_primary_usage: TextureUsage::DEPTH_WRITE,
+          old_usages: TextureUsage::empty(),
+          new_usages: TextureUsage::empty(),
+          texture: &depth,
+        }
+      ]);
+      Some(MultisampledTargets {
+        rtv: device.create_render_target_view(&color, &TextureRenderTargetViewInfo {
+          base_mip_level: 0,
+          mip_level_length: 1,
+          base_array_level: 0,
+          array_level_length: 1,
+        }),
+        dsv: device.create_depth_stencil_view(&depth, &TextureDepthStencilViewInfo {
+          base_mip_level: 0,
+          mip_level_length: 1,
+          base_array_level: 0,
+          array_level_length: 1,
+        })
+      })
+    } else {
+      None
+    };
+
+    init_cmd_buffer.barrier(&[
+      Barrier::TextureBarrier {
+        old_primary_usage: TextureUsage::UNINITIALIZED,
+        new_primary_usage: TextureUsage::COMPUTE_SHADER_SAMPLED,
+        old_usages: TextureUsage::empty(),
+        new_usages: TextureUsage::empty(),
+        texture: &output,
+      }
+    ]);
+
+    GeometryTargets {
+      rtv,
+      srv,
+      msaa_targets
     }
   }
 
+  pub fn resize(&mut self, device: &Arc<B::Device>, swapchain: &Arc<B::Swapchain>, init_cmd_buffer: &mut B::CommandBuffer) {
+    let targets = Self::create_targets(device, swapchain, self.msaa, init_cmd_buffer);
+    self.rtv = targets.rtv;
+    self.srv = targets.srv;
+    self.msaa_targets = targets.msaa_targets;
+  }
+
+  pub fn set_debug_view(&mut self, debug_view: DebugView) {
+    self.debug_view = debug_view;
+  }
+
+  pub fn debug_view(&self) -> DebugView {
+    self.debug_view
+  }
+
+  pub fn set_indirect_draws(&mut self, indirect_draws: bool) {
+    self.indirect_draws = indirect_draws;
+  }
+
   pub(super) fn execute(
     &mut self,
     cmd_buffer: &mut B::CommandBuffer,
@@ -233,18 +528,45 @@ impl<B: GraphicsBackend> GeometryPass<B> {
     view: &View,
     lightmap: &Arc<RendererTexture<B>>,
     swapchain_transform: Matrix4,
-    frame: u64,
+    jitter_point: Vec2,
+    cluster_config: &ClusterConfig,
     prepass_depth: &Arc<B::TextureDepthStencilView>,
     light_bitmask_buffer: &Arc<B::Buffer>,
+    spot_light_bitmask_buffer: &Arc<B::Buffer>,
     camera_buffer: &Arc<B::Buffer>,
-    ssao: &Arc<B::TextureShaderResourceView>
+    ssao: &Arc<B::TextureShaderResourceView>,
+    point_shadow_map: &Arc<B::TextureShaderResourceView>,
+    point_shadow_sampler: &Arc<B::Sampler>,
+    point_shadow_buffer: &Arc<B::Buffer>,
+    cascade_shadow_map: &Arc<B::TextureShaderResourceView>,
+    cascade_shadow_sampler: &Arc<B::Sampler>,
+    cascade_buffer: &Arc<B::Buffer>
   ) {
     let static_drawables = scene.static_drawables();
 
+    if let Some(msaa_targets) = self.msaa_targets.as_ref() {
+      cmd_buffer.barrier(&[
+        Barrier::TextureBarrier {
+          old_primary_usage: TextureUsage::RESOLVE_SRC,
+          new_primary_usage: TextureUsage::RENDER_TARGET,
+          old_usages: TextureUsage::empty(),
+          new_usages: TextureUsage::empty(),
+          texture: msaa_targets.rtv.texture(),
+        },
+        Barrier::TextureBarrier {
+          old_primary_usage: TextureUsage::DEPTH_WRITE,
+          new_primary_usage: TextureUsage::DEPTH_WRITE,
+          old_usages: TextureUsage::DEPTH_WRITE,
+          new_usages: TextureUsage::DEPTH_WRITE,
+          texture: msaa_targets.dsv.texture(),
+        },
+      ]);
+    }
+
     cmd_buffer.barrier(&[
       Barrier::TextureBarrier {
         old_primary_usage: TextureUsage::COMPUTE_SHADER_SAMPLED,
-        new_primary_usage: TextureUsage::RENDER_TARGET,
+        new_primary_usage: if self.msaa_targets.is_some() { TextureUsage::RESOLVE_DST } else { TextureUsage::RENDER_TARGET },
         old_usages: TextureUsage::empty(),
         new_usages: TextureUsage::empty(),
         texture: self.rtv.texture(),
@@ -263,6 +585,13 @@ impl<B: GraphicsBackend> GeometryPass<B> {
         new_usages: BufferUsage::FRAGMENT_SHADER_STORAGE_READ,
         buffer: light_bitmask_buffer,
       },
+      Barrier::BufferBarrier {
+        old_primary_usage: BufferUsage::COMPUTE_SHADER_STORAGE_WRITE,
+        new_primary_usage: BufferUsage::FRAGMENT_SHADER_STORAGE_READ,
+        old_usages: BufferUsage::COMPUTE_SHADER_STORAGE_WRITE,
+        new_usages: BufferUsage::FRAGMENT_SHADER_STORAGE_READ,
+        buffer: spot_light_bitmask_buffer,
+      },
       Barrier::TextureBarrier {
         old_primary_usage: TextureUsage::COMPUTE_SHADER_STORAGE_WRITE,
         new_primary_usage: TextureUsage::FRAGMENT_SHADER_SAMPLED,
@@ -272,16 +601,20 @@ impl<B: GraphicsBackend> GeometryPass<B> {
       },
     ]);
 
+    let (rtv, dsv, depth_load_op) = match self.msaa_targets.as_ref() {
+      Some(msaa_targets) => (&msaa_targets.rtv, &msaa_targets.dsv, LoadOp::Clear),
+      None => (&self.rtv, prepass_depth, LoadOp::Load)
+    };
     cmd_buffer.begin_render_pass_1(&RenderPassBeginInfo {
       attachments: &[
         RenderPassAttachment {
-          view: RenderPassAttachmentView::RenderTarget(&self.rtv),
+          view: RenderPassAttachmentView::RenderTarget(rtv),
           load_op: LoadOp::Clear,
           store_op: StoreOp::Store,
         },
         RenderPassAttachment {
-          view: RenderPassAttachmentView::DepthStencil(prepass_depth),
-          load_op: LoadOp::Load,
+          view: RenderPassAttachmentView::DepthStencil(dsv),
+          load_op: depth_load_op,
           store_op: StoreOp::Store
         }
       ],
@@ -296,41 +629,68 @@ impl<B: GraphicsBackend> GeometryPass<B> {
           ],
           depth_stencil_attachment: Some(DepthStencilAttachmentRef {
             index: 1,
-            read_only: true,
+            read_only: self.msaa_targets.is_none(),
           }),
         }
       ]
     }, RenderpassRecordingMode::CommandBuffers);
 
     let rtv_info = self.rtv.texture().get_info();
-    let cluster_count = nalgebra::Vector3::<u32>::new(16, 9, 24);
+    let cluster_count = nalgebra::Vector3::<u32>::new(cluster_config.tiles_x, cluster_config.tiles_y, cluster_config.slices_z);
     let near = view.near_plane;
     let far = view.far_plane;
     let cluster_z_scale = (cluster_count.z as f32) / (far / near).log2();
     let cluster_z_bias = -(cluster_count.z as f32) * (near).log2() / (far / near).log2();
     let per_frame = FrameData {
       swapchain_transform: swapchain_transform,
-      halton_point: scaled_halton_point(rtv_info.width, rtv_info.height, (frame % 8) as u32),
+      halton_point: jitter_point,
       z_near: view.near_plane,
       z_far: view.far_plane,
       rt_size: Vector2::<u32>::new(rtv_info.width, rtv_info.height),
       cluster_z_bias,
       cluster_z_scale,
       cluster_count,
-      point_light_count: scene.point_lights().len() as u32
+      point_light_count: scene.point_lights().len() as u32,
+      // Has to match the debug view constants in textured.frag.glsl
+      debug_view: match self.debug_view {
+        DebugView::None => 0,
+        DebugView::Normals => 1,
+        DebugView::Albedo => 2,
+        DebugView::Depth => 3,
+        DebugView::Overdraw => 4,
+        DebugView::Clusters => 5,
+        DebugView::SsaoOnly => 6
+      },
+      spot_light_count: scene.spot_lights().len() as u32
+    };
+    let pipeline = if self.debug_view == DebugView::Overdraw {
+      &self.overdraw_pipeline
+    } else {
+      &self.pipeline
     };
     let per_frame_buffer = cmd_buffer.upload_dynamic_data(&[per_frame], BufferUsage::FRAGMENT_SHADER_CONSTANT | BufferUsage::VERTEX_SHADER_CONSTANT | BufferUsage::COMPUTE_SHADER_CONSTANT);
     let point_light_buffer = cmd_buffer.upload_dynamic_data(scene.point_lights(), BufferUsage::FRAGMENT_SHADER_STORAGE_READ | BufferUsage::VERTEX_SHADER_STORAGE_READ);
+    let spot_light_buffer = cmd_buffer.upload_dynamic_data(scene.spot_lights(), BufferUsage::FRAGMENT_SHADER_STORAGE_READ);
+
+    let use_instancing = self.debug_view != DebugView::Overdraw;
+    let (instance_transforms, instanced_batches, regular_parts) = if use_instancing {
+      build_instanced_draws(&view.drawable_parts, scene)
+    } else {
+      (Vec::new(), Vec::new(), view.drawable_parts.clone())
+    };
+    let instance_buffer = if !instance_transforms.is_empty() {
+      Some(cmd_buffer.upload_dynamic_data(&instance_transforms[..], BufferUsage::VERTEX_SHADER_STORAGE_READ))
+    } else {
+      None
+    };
 
     let inheritance = cmd_buffer.inheritance();
-    const CHUNK_SIZE: usize = 128;
-    let chunks = view.drawable_parts.par_chunks(CHUNK_SIZE);
-    let inner_cmd_buffers: Vec::<B::CommandBufferSubmission> = chunks.map(|chunk| {
+    let begin_inner_command_buffer = |pipeline: &Arc<B::GraphicsPipeline>| {
       let mut command_buffer = device.graphics_queue().create_inner_command_buffer(inheritance);
 
       command_buffer.bind_uniform_buffer(BindingFrequency::PerFrame, 3, &per_frame_buffer);
 
-      command_buffer.set_pipeline(PipelineBinding::Graphics(&self.pipeline));
+      command_buffer.set_pipeline(PipelineBinding::Graphics(pipeline));
       command_buffer.set_viewports(&[Viewport {
         position: Vec2::new(0.0f32, 0.0f32),
         extent: Vec2::new(rtv_info.width as f32, rtv_info.height as f32),
@@ -347,31 +707,55 @@ impl<B: GraphicsBackend> GeometryPass<B> {
       command_buffer.bind_storage_buffer(BindingFrequency::PerFrame, 1, &point_light_buffer);
       command_buffer.bind_storage_buffer(BindingFrequency::PerFrame, 2, light_bitmask_buffer);
       command_buffer.bind_texture_view(BindingFrequency::PerFrame, 4, ssao, &self.sampler);
-      for part in chunk.into_iter() {
-        let drawable = &static_drawables[part.drawable_index];
+      command_buffer.bind_texture_view(BindingFrequency::PerFrame, 5, point_shadow_map, point_shadow_sampler);
+      command_buffer.bind_uniform_buffer(BindingFrequency::PerFrame, 6, point_shadow_buffer);
+      command_buffer.bind_texture_view(BindingFrequency::PerFrame, 7, cascade_shadow_map, cascade_shadow_sampler);
+      command_buffer.bind_uniform_buffer(BindingFrequency::PerFrame, 8, cascade_buffer);
+      command_buffer.bind_storage_buffer(BindingFrequency::PerFrame, 9, &spot_light_buffer);
+      command_buffer.bind_storage_buffer(BindingFrequency::PerFrame, 10, spot_light_bitmask_buffer);
 
-        /*let model_constant_buffer = command_buffer.upload_dynamic_data(&[drawable.transform], BufferUsage::CONSTANT);
-        command_buffer.bind_uniform_buffer(BindingFrequency::PerDraw, 0, &model_constant_buffer);*/
-        command_buffer.upload_dynamic_data_inline(&[drawable.transform], ShaderType::VertexShader);
+      command_buffer
+    };
 
-        let model = &drawable.model;
-        let mesh = &model.mesh;
+    // Spread the draws evenly across the worker threads but don't bother with tiny command buffers
+    const MIN_CHUNK_SIZE: usize = 64;
+    let chunk_size = (regular_parts.len() / rayon::current_num_threads()).max(MIN_CHUNK_SIZE);
+    let chunks = regular_parts.par_chunks(chunk_size);
+    let mut inner_cmd_buffers: Vec::<B::CommandBufferSubmission> = chunks.map(|chunk| {
+      let mut command_buffer = begin_inner_command_buffer(pipeline);
 
-        command_buffer.set_vertex_buffer(&mesh.vertices);
-        if mesh.indices.is_some() {
-          command_buffer.set_index_buffer(mesh.indices.as_ref().unwrap());
+      if self.indirect_draws {
+        let (draw_commands, batches) = build_indirect_draws(chunk, static_drawables);
+        if !draw_commands.is_empty() {
+          let draw_buffer = command_buffer.upload_dynamic_data(&draw_commands[..], BufferUsage::INDIRECT);
+          let stride = std::mem::size_of::<DrawIndexedIndirectCommand>() as u32;
+          for batch in &batches {
+            let drawable = &static_drawables[batch.drawable_index];
+            command_buffer.upload_dynamic_data_inline(&[drawable.transform], ShaderType::VertexShader);
+            bind_drawable_part(&mut command_buffer, drawable, batch.lod, batch.part_index, lightmap, &self.sampler);
+            command_buffer.draw_indexed_indirect(&draw_buffer, batch.first_command * stride, batch.command_count, stride);
+          }
         }
 
-        let range = &mesh.parts[part.part_index];
-        let material = &model.materials[part.part_index];
-        let texture = material.albedo.borrow();
-        let albedo_view = texture.view.borrow();
-        command_buffer.bind_texture_view(BindingFrequency::PerMaterial, 0, &albedo_view, &self.sampler);
+        for part in chunk.iter().filter(|part| static_drawables[part.drawable_index].model.lod_mesh(part.lod).indices.is_none()) {
+          let drawable = &static_drawables[part.drawable_index];
+          command_buffer.upload_dynamic_data_inline(&[drawable.transform], ShaderType::VertexShader);
+          bind_drawable_part(&mut command_buffer, drawable, part.lod, part.part_index, lightmap, &self.sampler);
+          let range = &drawable.model.lod_mesh(part.lod).parts[part.part_index];
+          command_buffer.draw(range.count, range.start);
+        }
+        return command_buffer.finish();
+      }
 
-        let lightmap_ref = lightmap.view.borrow();
-        command_buffer.bind_texture_view(BindingFrequency::PerMaterial, 1, &lightmap_ref, &self.sampler);
-        command_buffer.finish_binding();
+      for part in chunk.into_iter() {
+        let drawable = &static_drawables[part.drawable_index];
+        /*let model_constant_buffer = command_buffer.upload_dynamic_data(&[drawable.transform], BufferUsage::CONSTANT);
+        command_buffer.bind_uniform_buffer(BindingFrequency::PerDraw, 0, &model_constant_buffer);*/
+        command_buffer.upload_dynamic_data_inline(&[drawable.transform], ShaderType::VertexShader);
+        bind_drawable_part(&mut command_buffer, drawable, part.lod, part.part_index, lightmap, &self.sampler);
 
+        let mesh = drawable.model.lod_mesh(part.lod);
+        let range = &mesh.parts[part.part_index];
         if mesh.indices.is_some() {
           command_buffer.draw_indexed(1, 0, range.count, range.start, 0);
         } else {
@@ -381,11 +765,117 @@ impl<B: GraphicsBackend> GeometryPass<B> {
       command_buffer.finish()
     }).collect();
 
+    if let Some(instance_buffer) = instance_buffer.as_ref() {
+      let instanced_chunks = instanced_batches.par_chunks(MIN_CHUNK_SIZE);
+      let instanced_cmd_buffers: Vec::<B::CommandBufferSubmission> = instanced_chunks.map(|chunk| {
+        let mut command_buffer = begin_inner_command_buffer(&self.instanced_pipeline);
+        command_buffer.bind_storage_buffer(BindingFrequency::PerDraw, 0, instance_buffer);
+        for batch in chunk {
+          let drawable = &static_drawables[batch.drawable_index];
+          bind_drawable_part(&mut command_buffer, drawable, batch.lod, batch.part_index, lightmap, &self.sampler);
+          let range = &drawable.model.lod_mesh(batch.lod).parts[batch.part_index];
+          command_buffer.draw_indexed(batch.instance_count, batch.first_instance, range.count, range.start, 0);
+        }
+        command_buffer.finish()
+      }).collect();
+      inner_cmd_buffers.extend(instanced_cmd_buffers);
+    }
+
     cmd_buffer.execute_inner(inner_cmd_buffers);
     cmd_buffer.end_render_pass();
+
+    if let Some(msaa_targets) = self.msaa_targets.as_ref() {
+      cmd_buffer.barrier(&[
+        Barrier::TextureBarrier {
+          old_primary_usage: TextureUsage::RENDER_TARGET,
+          new_primary_usage: TextureUsage::RESOLVE_SRC,
+          old_usages: TextureUsage::RENDER_TARGET,
+          new_usages: TextureUsage::RESOLVE_SRC,
+          texture: msaa_targets.rtv.texture(),
+        }
+      ]);
+      cmd_buffer.flush_barriers();
+      cmd_buffer.resolve(msaa_targets.rtv.texture(), self.rtv.texture());
+
+      // The following passes expect the output to come straight out of the render pass
+      cmd_buffer.barrier(&[
+        Barrier::TextureBarrier {
+          old_primary_usage: TextureUsage::RESOLVE_DST,
+          new_primary_usage: TextureUsage::RENDER_TARGET,
+          old_usages: TextureUsage::RESOLVE_DST,
+          new_usages: TextureUsage::RENDER_TARGET,
+          texture: self.rtv.texture(),
+        }
+      ]);
+    }
   }
 
   pub fn output_srv(&self) -> &Arc<B::TextureShaderResourceView> {
     &self.srv
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn part(drawable_index: usize, part_index: usize) -> DrawablePart {
+    DrawablePart { drawable_index, part_index, lod: 0 }
+  }
+
+  #[test]
+  fn consecutive_parts_with_the_same_material_share_a_batch() {
+    let ranges = [MeshRange { start: 0, count: 6 }, MeshRange { start: 6, count: 3 }, MeshRange { start: 9, count: 12 }];
+    // Drawable 1 has no index buffer
+    let materials = [vec!["a", "a", "b"], vec!["a"], vec!["a"]];
+    let parts = [part(0, 0), part(0, 1), part(0, 2), part(1, 0), part(2, 0)];
+    let (commands, batches) = collect_indirect_draws(&parts, |part| {
+      if part.drawable_index == 1 {
+        None
+      } else {
+        Some((&ranges[part.part_index], materials[part.drawable_index][part.part_index]))
+      }
+    });
+
+    let batches: Vec<(usize, usize, u32, u32)> = batches.iter().map(|batch| (batch.drawable_index, batch.part_index, batch.first_command, batch.command_count)).collect();
+    assert_eq!(batches, vec![(0, 0, 0, 2), (0, 2, 2, 1), (2, 0, 3, 1)]);
+
+    let commands: Vec<(u32, u32, u32)> = commands.iter().map(|command| (command.first_index, command.index_count, command.instance_count)).collect();
+    assert_eq!(commands, vec![(0, 6, 1), (6, 3, 1), (9, 12, 1), (0, 6, 1)]);
+  }
+
+  #[test]
+  fn visible_instances_of_a_model_share_a_batch() {
+    let transform = |drawable_index: usize| {
+      let mut transform = Matrix4::identity();
+      transform[(0, 3)] = drawable_index as f32;
+      transform
+    };
+    // Drawable 5 isn't instanced
+    let models = ["tree", "rock", "tree", "tree", "rock"];
+    let instanced_model = |drawable_index: usize| models.get(drawable_index).copied();
+
+    // Three visible trees
+    let parts = [part(0, 0), part(2, 0), part(3, 0)];
+    let (transforms, batches, regular_parts) = collect_instanced_draws(&parts, instanced_model, transform);
+    assert_eq!(batches.len(), 1);
+    assert_eq!((batches[0].first_instance, batches[0].instance_count), (0, 3));
+    assert_eq!(transforms, vec![transform(0), transform(2), transform(3)]);
+    assert!(regular_parts.is_empty());
+
+    // A single visible rock gets drawn regularly, just like the drawable that isn't instanced
+    let parts = [part(0, 0), part(1, 0), part(2, 0), part(5, 0)];
+    let (_, batches, regular_parts) = collect_instanced_draws(&parts, instanced_model, transform);
+    assert_eq!(batches.len(), 1);
+    let regular_drawables: Vec<usize> = regular_parts.iter().map(|part| part.drawable_index).collect();
+    assert_eq!(regular_drawables, vec![5, 1]);
+
+    // Both rocks and both trees
+    let parts = [part(0, 0), part(1, 0), part(2, 0), part(4, 0)];
+    let (transforms, batches, regular_parts) = collect_instanced_draws(&parts, instanced_model, transform);
+    let batches: Vec<(usize, u32, u32)> = batches.iter().map(|batch| (batch.drawable_index, batch.first_instance, batch.instance_count)).collect();
+    assert_eq!(batches, vec![(0, 0, 2), (1, 2, 2)]);
+    assert_eq!(transforms, vec![transform(0), transform(2), transform(1), transform(4)]);
+    assert!(regular_parts.is_empty());
+  }
+}
@@ -0,0 +1,108 @@
+use std::{io::Read, path::Path, sync::Arc};
+
+use sourcerenderer_core::{Matrix4, Platform, graphics::{AddressMode, Backend as GraphicsBackend, Barrier, BindingFrequency, Buffer, BufferInfo, BufferUsage, CommandBuffer, Device, Fence, Filter, MemoryUsage, PipelineBinding, SamplerInfo, ShaderType}, platform::io::IO};
+
+use crate::renderer::occlusion::HierarchicalDepthBuffer;
+
+// Has to match the defines in hi_z.comp.glsl
+const HI_Z_WIDTH: u32 = 128;
+const HI_Z_HEIGHT: u32 = 64;
+const HI_Z_TEXEL_COUNT: usize = (HI_Z_WIDTH * HI_Z_HEIGHT) as usize;
+const READBACK_COUNT: usize = 3;
+
+struct HiZReadback<B: GraphicsBackend> {
+  buffer: Arc<B::Buffer>,
+  fence: Option<Arc<B::Fence>>,
+  view_projection: Matrix4
+}
+
+/// Downsamples the prepass depth to a small max depth buffer and reads it back
+/// so the following frames can do occlusion culling on the CPU.
+pub struct HierarchicalZPass<B: GraphicsBackend> {
+  pipeline: Arc<B::ComputePipeline>,
+  sampler: Arc<B::Sampler>,
+  readbacks: Vec<HiZReadback<B>>,
+  depth_buffer: Option<HierarchicalDepthBuffer>
+}
+
+impl<B: GraphicsBackend> HierarchicalZPass<B> {
+  pub fn new<P: Platform>(device: &Arc<B::Device>) -> Self {
+    let shader = {
+      let mut file = <P::IO as IO>::open_asset(Path::new("shaders").join(Path::new("hi_z.comp.spv"))).unwrap();
+      let mut bytes: Vec<u8> = Vec::new();
+      file.read_to_end(&mut bytes).unwrap();
+      device.create_shader(ShaderType::ComputeShader, &bytes, Some("hi_z.comp.spv"))
+    };
+    let pipeline = device.create_compute_pipeline(&shader);
+
+    let sampler = device.create_sampler(&SamplerInfo {
+      mag_filter: Filter::Nearest,
+      min_filter: Filter::Nearest,
+      mip_filter: Filter::Nearest,
+      address_mode_u: AddressMode::ClampToEdge,
+      address_mode_v: AddressMode::ClampToEdge,
+      address_mode_w: AddressMode::ClampToEdge,
+      mip_bias: 0.0,
+      max_anisotropy: 0.0,
+      compare_op: None,
+      min_lod: 0.0,
+      max_lod: 1.0,
+    });
+
+    let readbacks = (0..READBACK_COUNT).map(|_| HiZReadback {
+      buffer: device.create_buffer(&BufferInfo {
+        size: HI_Z_TEXEL_COUNT * std::mem::size_of::<f32>(),
+        usage: BufferUsage::COMPUTE_SHADER_STORAGE_WRITE | BufferUsage::HOST_READ
+      }, MemoryUsage::GpuToCpu, Some("HiZReadback")),
+      fence: None,
+      view_projection: Matrix4::identity()
+    }).collect();
+
+    Self {
+      pipeline,
+      sampler,
+      readbacks,
+      depth_buffer: None
+    }
+  }
+
+  /// Picks up the depth of an older frame if the GPU is done with it.
+  pub fn read_back(&mut self, frame: u64) {
+    let readback = &mut self.readbacks[frame as usize % READBACK_COUNT];
+    let is_done = readback.fence.as_ref().map_or(false, |fence| fence.is_signaled());
+    if !is_done {
+      return;
+    }
+    readback.fence = None;
+
+    let depth = readback.buffer.map::<[f32; HI_Z_TEXEL_COUNT]>().expect("Failed to map Hi-Z readback buffer");
+    self.depth_buffer = Some(HierarchicalDepthBuffer::new(HI_Z_WIDTH, HI_Z_HEIGHT, &depth[..], readback.view_projection));
+  }
+
+  pub fn execute(&mut self, cmd_buffer: &mut B::CommandBuffer, frame: u64, depth_srv: &Arc<B::TextureShaderResourceView>, view_projection: Matrix4, frame_fence: &Arc<B::Fence>) {
+    let readback = &mut self.readbacks[frame as usize % READBACK_COUNT];
+    readback.fence = Some(frame_fence.clone());
+    readback.view_projection = view_projection;
+
+    cmd_buffer.set_pipeline(PipelineBinding::Compute(&self.pipeline));
+    cmd_buffer.bind_texture_view(BindingFrequency::PerDraw, 0, depth_srv, &self.sampler);
+    cmd_buffer.bind_storage_buffer(BindingFrequency::PerDraw, 1, &readback.buffer);
+    cmd_buffer.finish_binding();
+    cmd_buffer.dispatch(HI_Z_WIDTH, HI_Z_HEIGHT, 1);
+    // Makes the shader writes visible to the CPU once the frame fence is signalled
+    cmd_buffer.barrier(&[
+      Barrier::BufferBarrier {
+        old_primary_usage: BufferUsage::COMPUTE_SHADER_STORAGE_WRITE,
+        new_primary_usage: BufferUsage::HOST_READ,
+        old_usages: BufferUsage::COMPUTE_SHADER_STORAGE_WRITE,
+        new_usages: BufferUsage::HOST_READ,
+        buffer: &readback.buffer
+      }
+    ]);
+    cmd_buffer.flush_barriers();
+  }
+
+  pub fn depth_buffer(&self) -> Option<&HierarchicalDepthBuffer> {
+    self.depth_buffer.as_ref()
+  }
+}
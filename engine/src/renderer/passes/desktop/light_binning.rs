@@ -1,5 +1,4 @@
-use nalgebra::Vector3;
-use sourcerenderer_core::{Vec3, graphics::{Backend as GraphicsBackend, Barrier, BindingFrequency, BufferInfo, BufferUsage, CommandBuffer, Device, MemoryUsage, PipelineBinding, ShaderType}};
+use sourcerenderer_core::{Vec3, graphics::{Backend as GraphicsBackend, Barrier, BindingFrequency, Buffer, BufferInfo, BufferUsage, CommandBuffer, Device, MemoryUsage, PipelineBinding, ShaderType}};
 use sourcerenderer_core::Platform;
 use std::sync::Arc;
 use std::path::Path;
@@ -8,11 +7,14 @@ use sourcerenderer_core::platform::io::IO;
 
 use crate::renderer::RendererScene;
 
+use super::clustering::ClusterConfig;
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 pub struct SetupInfo {
   cluster_count: u32,
-  point_light_count: u32
+  point_light_count: u32,
+  spot_light_count: u32
 }
 
 #[repr(C)]
@@ -22,19 +24,33 @@ pub struct CullingPointLight {
   radius: f32
 }
 
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct CullingSpotLight {
+  position: Vec3,
+  range: f32,
+  direction: Vec3,
+  angle: f32
+}
+
 const LIGHT_CUTOFF: f32 = 0.05f32;
+const BITMASK_LIGHT_GRANULARITY: u32 = 32;
 
+// Directional lights reach every cluster so they don't get binned, the geometry pass applies them everywhere.
 pub struct LightBinningPass<B: GraphicsBackend> {
   light_bitmask_buffer: Arc<B::Buffer>,
-  light_binning_pipeline: Arc<B::ComputePipeline>
+  spot_light_bitmask_buffer: Arc<B::Buffer>,
+  light_binning_pipeline: Arc<B::ComputePipeline>,
+  cluster_count: u32,
+  point_light_capacity: u32,
+  spot_light_capacity: u32
 }
 
 impl<B: GraphicsBackend> LightBinningPass<B> {
-  pub fn new<P: Platform>(device: &Arc<B::Device>) -> Self {
-    let buffer = device.create_buffer(&BufferInfo {
-      size: std::mem::size_of::<u32>() * 16 * 9 * 24,
-      usage: BufferUsage::COMPUTE_SHADER_STORAGE_WRITE | BufferUsage::FRAGMENT_SHADER_STORAGE_READ | BufferUsage::FRAGMENT_SHADER_CONSTANT
-    }, MemoryUsage::GpuOnly, Some("LightBitmaskBuffer"));
+  pub fn new<P: Platform>(device: &Arc<B::Device>, cluster_config: &ClusterConfig) -> Self {
+    let cluster_count = cluster_config.cluster_count();
+    let buffer = Self::create_bitmask_buffer(device, cluster_count, BITMASK_LIGHT_GRANULARITY, "LightBitmaskBuffer");
+    let spot_buffer = Self::create_bitmask_buffer(device, cluster_count, BITMASK_LIGHT_GRANULARITY, "SpotLightBitmaskBuffer");
 
     let shader = {
       let mut file = <P::IO as IO>::open_asset(Path::new("shaders").join(Path::new("light_binning.comp.spv"))).unwrap();
@@ -46,23 +62,78 @@ impl<B: GraphicsBackend> LightBinningPass<B> {
 
     Self {
       light_bitmask_buffer: buffer,
-      light_binning_pipeline: pipeline
+      spot_light_bitmask_buffer: spot_buffer,
+      light_binning_pipeline: pipeline,
+      cluster_count,
+      point_light_capacity: BITMASK_LIGHT_GRANULARITY,
+      spot_light_capacity: BITMASK_LIGHT_GRANULARITY
     }
   }
 
-  pub fn execute(&mut self, cmd_buffer: &mut B::CommandBuffer, scene: &RendererScene<B>, clusters_buffer: &Arc<B::Buffer>, camera_buffer: &Arc<B::Buffer>) {
-    let cluster_count = Vector3::<u32>::new(16, 9, 24);
+  /// Every cluster gets one bit per light, rounded up to whole u32s.
+  fn bitmask_buffer_size(cluster_count: u32, light_count: u32) -> usize {
+    let words_per_cluster = (light_count.max(1) + 31) / 32;
+    std::mem::size_of::<u32>() * cluster_count as usize * words_per_cluster as usize
+  }
+
+  fn light_capacity(light_count: u32) -> u32 {
+    (light_count + BITMASK_LIGHT_GRANULARITY - 1) / BITMASK_LIGHT_GRANULARITY * BITMASK_LIGHT_GRANULARITY
+  }
+
+  fn create_bitmask_buffer(device: &Arc<B::Device>, cluster_count: u32, light_capacity: u32, name: &str) -> Arc<B::Buffer> {
+    device.create_buffer(&BufferInfo {
+      size: Self::bitmask_buffer_size(cluster_count, light_capacity),
+      usage: BufferUsage::COMPUTE_SHADER_STORAGE_WRITE | BufferUsage::FRAGMENT_SHADER_STORAGE_READ | BufferUsage::FRAGMENT_SHADER_CONSTANT
+    }, MemoryUsage::GpuOnly, Some(name))
+  }
+
+  pub fn set_cluster_config(&mut self, device: &Arc<B::Device>, cluster_config: &ClusterConfig) {
+    let cluster_count = cluster_config.cluster_count();
+    if cluster_count == self.cluster_count {
+      return;
+    }
+    self.light_bitmask_buffer = Self::create_bitmask_buffer(device, cluster_count, self.point_light_capacity, "LightBitmaskBuffer");
+    self.spot_light_bitmask_buffer = Self::create_bitmask_buffer(device, cluster_count, self.spot_light_capacity, "SpotLightBitmaskBuffer");
+    self.cluster_count = cluster_count;
+  }
+
+  pub fn execute(&mut self, cmd_buffer: &mut B::CommandBuffer, device: &Arc<B::Device>, scene: &RendererScene<B>, cluster_config: &ClusterConfig, clusters_buffer: &Arc<B::Buffer>, camera_buffer: &Arc<B::Buffer>) {
+    let cluster_count = cluster_config.cluster_count();
+    assert_eq!(cluster_count, self.cluster_count, "Light binning wasn't reconfigured for the new cluster grid");
+
+    let point_light_count = scene.point_lights().len() as u32;
+    if point_light_count > self.point_light_capacity {
+      self.point_light_capacity = Self::light_capacity(point_light_count);
+      self.light_bitmask_buffer = Self::create_bitmask_buffer(device, cluster_count, self.point_light_capacity, "LightBitmaskBuffer");
+    }
+    debug_assert!(self.light_bitmask_buffer.get_length() >= Self::bitmask_buffer_size(cluster_count, point_light_count));
+
+    let spot_light_count = scene.spot_lights().len() as u32;
+    if spot_light_count > self.spot_light_capacity {
+      self.spot_light_capacity = Self::light_capacity(spot_light_count);
+      self.spot_light_bitmask_buffer = Self::create_bitmask_buffer(device, cluster_count, self.spot_light_capacity, "SpotLightBitmaskBuffer");
+    }
+    debug_assert!(self.spot_light_bitmask_buffer.get_length() >= Self::bitmask_buffer_size(cluster_count, spot_light_count));
+
     let setup_info = SetupInfo {
-      point_light_count: scene.point_lights().len() as u32,
-      cluster_count: cluster_count.x * cluster_count.y * cluster_count.z
+      point_light_count,
+      spot_light_count,
+      cluster_count
     };
     let point_lights: Vec<CullingPointLight> = scene.point_lights().iter().map(|l| CullingPointLight {
       position: l.position,
       radius: (l.intensity / LIGHT_CUTOFF).sqrt()
     }).collect();
+    let spot_lights: Vec<CullingSpotLight> = scene.spot_lights().iter().map(|l| CullingSpotLight {
+      position: l.position,
+      range: (l.intensity / LIGHT_CUTOFF).sqrt(),
+      direction: l.direction,
+      angle: l.angle
+    }).collect();
 
     let light_info_buffer = cmd_buffer.upload_dynamic_data(&[setup_info], BufferUsage::COMPUTE_SHADER_STORAGE_READ);
     let point_lights_buffer = cmd_buffer.upload_dynamic_data(&point_lights[..], BufferUsage::COMPUTE_SHADER_STORAGE_READ);
+    let spot_lights_buffer = cmd_buffer.upload_dynamic_data(&spot_lights[..], BufferUsage::COMPUTE_SHADER_STORAGE_READ);
 
     cmd_buffer.barrier(&[
       Barrier::BufferBarrier {
@@ -86,6 +157,13 @@ impl<B: GraphicsBackend> LightBinningPass<B> {
         old_usages: BufferUsage::FRAGMENT_SHADER_STORAGE_READ,
         new_usages: BufferUsage::COMPUTE_SHADER_STORAGE_WRITE,
         buffer: &self.light_bitmask_buffer,
+      },
+      Barrier::BufferBarrier {
+        old_primary_usage: BufferUsage::FRAGMENT_SHADER_STORAGE_READ,
+        new_primary_usage: BufferUsage::COMPUTE_SHADER_STORAGE_WRITE,
+        old_usages: BufferUsage::FRAGMENT_SHADER_STORAGE_READ,
+        new_usages: BufferUsage::COMPUTE_SHADER_STORAGE_WRITE,
+        buffer: &self.spot_light_bitmask_buffer,
       }
     ]);
     
@@ -96,11 +174,17 @@ impl<B: GraphicsBackend> LightBinningPass<B> {
     cmd_buffer.bind_storage_buffer(BindingFrequency::PerDraw, 2, &light_info_buffer);
     cmd_buffer.bind_storage_buffer(BindingFrequency::PerDraw, 3, &point_lights_buffer);
     cmd_buffer.bind_storage_buffer(BindingFrequency::PerDraw, 4, &self.light_bitmask_buffer);
+    cmd_buffer.bind_storage_buffer(BindingFrequency::PerDraw, 5, &spot_lights_buffer);
+    cmd_buffer.bind_storage_buffer(BindingFrequency::PerDraw, 6, &self.spot_light_bitmask_buffer);
     cmd_buffer.finish_binding();
-    cmd_buffer.dispatch((cluster_count.x * cluster_count.y * cluster_count.z + 63) / 64, 1, 1);
+    cmd_buffer.dispatch((cluster_count + 63) / 64, 1, 1);
   }
 
   pub fn light_bitmask_buffer(&self) -> &Arc<B::Buffer> {
     &self.light_bitmask_buffer
   }
+
+  pub fn spot_light_bitmask_buffer(&self) -> &Arc<B::Buffer> {
+    &self.spot_light_bitmask_buffer
+  }
 }
\ No newline at end of file
@@ -1,8 +1,14 @@
 pub(crate) mod prepass;
 pub(crate) mod geometry;
 pub(crate) mod taa;
+pub(crate) mod fxaa;
 pub(crate) mod sharpen;
+pub(crate) mod bloom;
 pub(crate) mod clustering;
 pub(crate) mod light_binning;
 pub(crate) mod ssao;
+pub(crate) mod hi_z;
+pub(crate) mod screenshot;
+pub(crate) mod point_shadows;
+pub(crate) mod cascaded_shadows;
 pub(crate) mod desktop_renderer;
@@ -0,0 +1,311 @@
+use std::{io::Read, path::Path, sync::Arc};
+
+use nalgebra::Point3;
+use sourcerenderer_core::{Matrix4, Platform, Vec2, Vec2I, Vec2UI, Vec3, graphics::{AddressMode, AttachmentInfo, Backend as GraphicsBackend, Barrier, BlendInfo, BufferUsage, CommandBuffer, CompareFunc, CullMode, DepthStencilAttachmentRef, DepthStencilInfo, Device, FillMode, Filter, Format, FrontFace, GraphicsPipelineInfo, IndexFormat, InputAssemblerElement, InputRate, LoadOp, LogicOp, PipelineBinding, PrimitiveType, RasterizerInfo, RenderPassAttachment, RenderPassAttachmentView, RenderPassBeginInfo, RenderPassInfo, RenderpassRecordingMode, SampleCount, SamplerInfo, Scissor, ShaderInputElement, ShaderType, StencilInfo, StoreOp, SubpassInfo, TextureDepthStencilViewInfo, TextureDimension, TextureInfo, TextureShaderResourceViewInfo, TextureUsage, VertexLayoutInfo, Viewport}, platform::io::IO};
+
+use crate::{math::Frustum, renderer::{RendererScene, drawable::View}};
+
+const MAX_SHADOWED_POINT_LIGHTS: usize = 4;
+const SHADOW_MAP_SIZE: u32 = 512;
+const SHADOW_NEAR_PLANE: f32 = 0.05f32;
+const SHADOW_FAR_PLANE: f32 = 50f32;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct PointShadowData {
+  view_projections: [Matrix4; MAX_SHADOWED_POINT_LIGHTS * 6],
+  light_indices: [u32; MAX_SHADOWED_POINT_LIGHTS],
+  light_count: u32
+}
+
+pub struct PointShadowPass<B: GraphicsBackend> {
+  shadow_map: Arc<B::Texture>,
+  face_dsvs: Vec<Arc<B::TextureDepthStencilView>>,
+  shadow_map_srv: Arc<B::TextureShaderResourceView>,
+  sampler: Arc<B::Sampler>,
+  pipeline: Arc<B::GraphicsPipeline>
+}
+
+impl<B: GraphicsBackend> PointShadowPass<B> {
+  pub fn new<P: Platform>(device: &Arc<B::Device>, init_cmd_buffer: &mut B::CommandBuffer) -> Self {
+    // Every light gets the 6 faces of a cube map as consecutive layers of one array texture.
+    let shadow_map = device.create_texture(&TextureInfo {
+      dimension: TextureDimension::Dim2D,
+      format: Format::D32,
+      width: SHADOW_MAP_SIZE,
+      height: SHADOW_MAP_SIZE,
+      depth: 1,
+      mip_levels: 1,
+      array_length: MAX_SHADOWED_POINT_LIGHTS as u32 * 6,
+      samples: SampleCount::Samples1,
+      usage: TextureUsage::DEPTH_WRITE | TextureUsage::FRAGMENT_SHADER_SAMPLED,
+      is_cubemap: false,
+    }, Some("PointShadows"));
+    let face_dsvs = (0..MAX_SHADOWED_POINT_LIGHTS as u32 * 6).map(|layer| {
+      device.create_depth_stencil_view(&shadow_map, &TextureDepthStencilViewInfo {
+        base_mip_level: 0,
+        mip_level_length: 1,
+        base_array_level: layer,
+        array_level_length: 1,
+      })
+    }).collect();
+    let shadow_map_srv = device.create_shader_resource_view(&shadow_map, &TextureShaderResourceViewInfo {
+      base_mip_level: 0,
+      mip_level_length: 1,
+      base_array_level: 0,
+      array_level_length: MAX_SHADOWED_POINT_LIGHTS as u32 * 6,
+    });
+
+    let sampler = device.create_sampler(&SamplerInfo {
+      mag_filter: Filter::Nearest,
+      min_filter: Filter::Nearest,
+      mip_filter: Filter::Nearest,
+      address_mode_u: AddressMode::ClampToEdge,
+      address_mode_v: AddressMode::ClampToEdge,
+      address_mode_w: AddressMode::ClampToEdge,
+      mip_bias: 0.0f32,
+      max_anisotropy: 0.0f32,
+      compare_op: None,
+      min_lod: 0.0f32,
+      max_lod: 1.0f32,
+    });
+
+    let vertex_shader = {
+      let mut file = <P::IO as IO>::open_asset(Path::new("shaders").join(Path::new("shadow.vert.spv"))).unwrap();
+      let mut bytes: Vec<u8> = Vec::new();
+      file.read_to_end(&mut bytes).unwrap();
+      device.create_shader(ShaderType::VertexShader, &bytes, Some("shadow.vert.spv"))
+    };
+    let pipeline_info: GraphicsPipelineInfo<B> = GraphicsPipelineInfo {
+      vs: vertex_shader,
+      fs: None,
+      gs: None,
+      tcs: None,
+      tes: None,
+      primitive_type: PrimitiveType::Triangles,
+      vertex_layout: VertexLayoutInfo {
+        input_assembler: vec![
+          InputAssemblerElement {
+            binding: 0,
+            stride: 44,
+            input_rate: InputRate::PerVertex
+          }
+        ],
+        shader_inputs: vec![
+          ShaderInputElement {
+            input_assembler_binding: 0,
+            location_vk_mtl: 0,
+            semantic_name_d3d: String::from(""),
+            semantic_index_d3d: 0,
+            offset: 0,
+            format: Format::RGB32Float
+          }
+        ]
+      },
+      rasterizer: RasterizerInfo {
+        fill_mode: FillMode::Fill,
+        cull_mode: CullMode::Back,
+        front_face: FrontFace::CounterClockwise,
+        sample_count: SampleCount::Samples1
+      },
+      depth_stencil: DepthStencilInfo {
+        depth_test_enabled: true,
+        depth_write_enabled: true,
+        depth_func: CompareFunc::Less,
+        stencil_enable: false,
+        stencil_read_mask: 0u8,
+        stencil_write_mask: 0u8,
+        stencil_front: StencilInfo::default(),
+        stencil_back: StencilInfo::default()
+      },
+      blend: BlendInfo {
+        alpha_to_coverage_enabled: false,
+        logic_op_enabled: false,
+        logic_op: LogicOp::And,
+        constants: [0f32, 0f32, 0f32, 0f32],
+        attachments: vec![]
+      }
+    };
+    let pipeline = device.create_graphics_pipeline(&pipeline_info, &RenderPassInfo {
+      attachments: vec![
+        AttachmentInfo {
+          format: Format::D32,
+          samples: SampleCount::Samples1,
+          load_op: LoadOp::Clear,
+          store_op: StoreOp::Store,
+          stencil_load_op: LoadOp::DontCare,
+          stencil_store_op: StoreOp::DontCare
+        }
+      ],
+      subpasses: vec![
+        SubpassInfo {
+          input_attachments: vec![],
+          output_color_attachments: vec![],
+          depth_stencil_attachment: Some(DepthStencilAttachmentRef {
+            index: 0,
+            read_only: false
+          })
+        }
+      ],
+    }, 0);
+
+    init_cmd_buffer.barrier(&[
+      Barrier::TextureBarrier {
+        old_primary_usage: TextureUsage::UNINITIALIZED,
+        new_primary_usage: TextureUsage::FRAGMENT_SHADER_SAMPLED,
+        old_usages: TextureUsage::empty(),
+        new_usages: TextureUsage::empty(),
+        texture: &shadow_map,
+      }
+    ]);
+
+    Self {
+      shadow_map,
+      face_dsvs,
+      shadow_map_srv,
+      sampler,
+      pipeline
+    }
+  }
+
+  fn face_view_projections(position: &Vec3) -> [Matrix4; 6] {
+    let proj = Matrix4::new_perspective(1f32, std::f32::consts::FRAC_PI_2, SHADOW_NEAR_PLANE, SHADOW_FAR_PLANE);
+    let eye = Point3::<f32>::new(position.x, position.y, position.z);
+    // Same face order as a cube map: +X, -X, +Y, -Y, +Z, -Z
+    let faces = [
+      (Vec3::new(1f32, 0f32, 0f32), Vec3::new(0f32, 1f32, 0f32)),
+      (Vec3::new(-1f32, 0f32, 0f32), Vec3::new(0f32, 1f32, 0f32)),
+      (Vec3::new(0f32, 1f32, 0f32), Vec3::new(0f32, 0f32, 1f32)),
+      (Vec3::new(0f32, -1f32, 0f32), Vec3::new(0f32, 0f32, 1f32)),
+      (Vec3::new(0f32, 0f32, 1f32), Vec3::new(0f32, 1f32, 0f32)),
+      (Vec3::new(0f32, 0f32, -1f32), Vec3::new(0f32, 1f32, 0f32)),
+    ];
+    let mut view_projections = [Matrix4::identity(); 6];
+    for (view_projection, (forward, up)) in view_projections.iter_mut().zip(faces.iter()) {
+      *view_projection = proj * Matrix4::look_at_rh(&eye, &(eye + *forward), up);
+    }
+    view_projections
+  }
+
+  pub(super) fn execute(
+    &mut self,
+    cmd_buffer: &mut B::CommandBuffer,
+    scene: &RendererScene<B>,
+    view: &View
+  ) -> Arc<B::Buffer> {
+    let static_drawables = scene.static_drawables();
+    let point_lights = scene.point_lights();
+
+    // Only the lights closest to the camera cast shadows to keep the cost bounded.
+    let camera_position = Vec3::new(view.camera_transform[(0, 3)], view.camera_transform[(1, 3)], view.camera_transform[(2, 3)]);
+    let mut light_indices: Vec<usize> = (0..point_lights.len()).collect();
+    light_indices.sort_by(|a, b| {
+      let a_dist = (point_lights[*a].position - camera_position).norm_squared();
+      let b_dist = (point_lights[*b].position - camera_position).norm_squared();
+      a_dist.partial_cmp(&b_dist).unwrap_or(std::cmp::Ordering::Equal)
+    });
+    light_indices.truncate(MAX_SHADOWED_POINT_LIGHTS);
+
+    let mut shadow_data = PointShadowData {
+      view_projections: [Matrix4::identity(); MAX_SHADOWED_POINT_LIGHTS * 6],
+      light_indices: [0u32; MAX_SHADOWED_POINT_LIGHTS],
+      light_count: light_indices.len() as u32
+    };
+
+    cmd_buffer.barrier(&[
+      Barrier::TextureBarrier {
+        old_primary_usage: TextureUsage::FRAGMENT_SHADER_SAMPLED,
+        new_primary_usage: TextureUsage::DEPTH_WRITE,
+        old_usages: TextureUsage::empty(),
+        new_usages: TextureUsage::empty(),
+        texture: &self.shadow_map,
+      }
+    ]);
+    cmd_buffer.flush_barriers();
+
+    for (shadow_index, light_index) in light_indices.iter().enumerate() {
+      shadow_data.light_indices[shadow_index] = *light_index as u32;
+      let view_projections = Self::face_view_projections(&point_lights[*light_index].position);
+      for (face, view_projection) in view_projections.iter().enumerate() {
+        let layer = shadow_index * 6 + face;
+        shadow_data.view_projections[layer] = *view_projection;
+
+        cmd_buffer.begin_render_pass_1(&RenderPassBeginInfo {
+          attachments: &[
+            RenderPassAttachment {
+              view: RenderPassAttachmentView::DepthStencil(&self.face_dsvs[layer]),
+              load_op: LoadOp::Clear,
+              store_op: StoreOp::Store
+            }
+          ],
+          subpasses: &[
+            SubpassInfo {
+              input_attachments: vec![],
+              output_color_attachments: vec![],
+              depth_stencil_attachment: Some(DepthStencilAttachmentRef {
+                index: 0,
+                read_only: false
+              })
+            }
+          ]
+        }, RenderpassRecordingMode::Commands);
+
+        cmd_buffer.set_pipeline(PipelineBinding::Graphics(&self.pipeline));
+        cmd_buffer.set_viewports(&[Viewport {
+          position: Vec2::new(0.0f32, 0.0f32),
+          extent: Vec2::new(SHADOW_MAP_SIZE as f32, SHADOW_MAP_SIZE as f32),
+          min_depth: 0.0f32,
+          max_depth: 1.0f32
+        }]);
+        cmd_buffer.set_scissors(&[Scissor {
+          position: Vec2I::new(0, 0),
+          extent: Vec2UI::new(SHADOW_MAP_SIZE, SHADOW_MAP_SIZE),
+        }]);
+        cmd_buffer.finish_binding();
+
+        let frustum = Frustum::from_matrix(view_projection);
+        let casters = static_drawables.iter().filter(|drawable| {
+          drawable.cast_shadows && drawable.model.mesh.bounding_box.as_ref()
+            .map_or(true, |bounding_box| frustum.intersects(bounding_box, &drawable.transform))
+        });
+        for drawable in casters {
+          cmd_buffer.upload_dynamic_data_inline(&[*view_projection * drawable.transform], ShaderType::VertexShader);
+
+          let mesh = &drawable.model.mesh;
+          cmd_buffer.set_vertex_buffer(&mesh.vertices);
+          if mesh.indices.is_some() {
+            cmd_buffer.set_index_buffer(mesh.indices.as_ref().unwrap(), IndexFormat::U32);
+          }
+          for range in &mesh.parts {
+            if mesh.indices.is_some() {
+              cmd_buffer.draw_indexed(1, 0, range.count, range.start, 0);
+            } else {
+              cmd_buffer.draw(range.count, range.start);
+            }
+          }
+        }
+        cmd_buffer.end_render_pass();
+      }
+    }
+
+    cmd_buffer.barrier(&[
+      Barrier::TextureBarrier {
+        old_primary_usage: TextureUsage::DEPTH_WRITE,
+        new_primary_usage: TextureUsage::FRAGMENT_SHADER_SAMPLED,
+        old_usages: TextureUsage::DEPTH_WRITE,
+        new_usages: TextureUsage::FRAGMENT_SHADER_SAMPLED,
+        texture: &self.shadow_map,
+      }
+    ]);
+
+    cmd_buffer.upload_dynamic_data(&[shadow_data], BufferUsage::FRAGMENT_SHADER_CONSTANT)
+  }
+
+  pub fn shadow_map_srv(&self) -> &Arc<B::TextureShaderResourceView> {
+    &self.shadow_map_srv
+  }
+
+  pub fn sampler(&self) -> &Arc<B::Sampler> {
+    &self.sampler
+  }
+}
@@ -1,7 +1,7 @@
 use sourcerenderer_core::graphics::{Barrier, OutputAttachmentRef, Queue, RenderPassAttachment, RenderPassAttachmentView, RenderPassBeginInfo, RenderpassRecordingMode, Texture, TextureDepthStencilView, TextureDepthStencilViewInfo, TextureRenderTargetView, TextureRenderTargetViewInfo, TextureShaderResourceView, TextureShaderResourceViewInfo};
-use sourcerenderer_core::graphics::{AttachmentBlendInfo, AttachmentInfo, Backend as GraphicsBackend, BindingFrequency, BlendInfo, BufferUsage, CommandBuffer, CompareFunc, CullMode, DepthStencilAttachmentRef, DepthStencilInfo, Device, FillMode, Format, FrontFace, GraphicsPipelineInfo, InputAssemblerElement, InputRate, LoadOp, LogicOp, PipelineBinding, PrimitiveType, RasterizerInfo, RenderPassInfo, SampleCount, Scissor, ShaderInputElement, ShaderType, StencilInfo, StoreOp, SubpassInfo, Swapchain, TextureInfo, TextureUsage, VertexLayoutInfo, Viewport};
+use sourcerenderer_core::graphics::{AttachmentBlendInfo, AttachmentInfo, Backend as GraphicsBackend, BindingFrequency, BlendInfo, BufferUsage, CommandBuffer, CompareFunc, CullMode, DepthStencilAttachmentRef, DepthStencilInfo, Device, FillMode, Format, FrontFace, GraphicsPipelineInfo, IndexFormat, InputAssemblerElement, InputRate, LoadOp, LogicOp, PipelineBinding, PrimitiveType, RasterizerInfo, RenderPassInfo, SampleCount, Scissor, ShaderInputElement, ShaderType, StencilInfo, StoreOp, SubpassInfo, Swapchain, TextureDimension, TextureInfo, TextureUsage, VertexLayoutInfo, Viewport};
 use std::sync::Arc;
-use crate::renderer::{RendererScene, drawable::View, passes::desktop::taa::scaled_halton_point};
+use crate::renderer::{RendererScene, drawable::View};
 use sourcerenderer_core::{Matrix4, Platform, Vec2, Vec2I, Vec2UI};
 use std::path::Path;
 use std::io::Read;
@@ -23,8 +23,17 @@ struct PrepassModelCB {
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 struct FrameData {
-  swapchain_transform: Matrix4,
-  halton_point: Vec2
+  swapchain_transform: Matrix4
+}
+
+/// Everything that has the size of the swapchain.
+struct PrepassTargets<B: GraphicsBackend> {
+  depth_buffer: Arc<B::TextureDepthStencilView>,
+  depth_srv: Arc<B::TextureShaderResourceView>,
+  motion: Arc<B::TextureRenderTargetView>,
+  motion_srv: Arc<B::TextureShaderResourceView>,
+  normals: Arc<B::TextureRenderTargetView>,
+  normals_srv: Arc<B::TextureShaderResourceView>
 }
 
 pub struct Prepass<B: GraphicsBackend> {
@@ -39,75 +48,7 @@ pub struct Prepass<B: GraphicsBackend> {
 
 impl<B: GraphicsBackend> Prepass<B> {
   pub fn new<P: Platform>(device: &Arc<B::Device>, swapchain: &Arc<B::Swapchain>, init_cmd_buffer: &mut B::CommandBuffer) -> Self {
-    let depth_buffer = device.create_texture(&TextureInfo {
-      format: Format::D24S8,
-      width: swapchain.width(),
-      height: swapchain.height(),
-      depth: 1,
-      mip_levels: 1,
-      array_length: 1,
-      samples: SampleCount::Samples1,
-      usage: TextureUsage::DEPTH_READ | TextureUsage::DEPTH_WRITE | TextureUsage::COMPUTE_SHADER_SAMPLED,
-    }, Some("PrepassDepth"));
-    let dsv = device.create_depth_stencil_view(&depth_buffer, &TextureDepthStencilViewInfo {
-      base_mip_level: 0,
-      mip_level_length: 1,
-      base_array_level: 0,
-      array_level_length: 1,
-    });
-    let depth_srv = device.create_shader_resource_view(&depth_buffer, &TextureShaderResourceViewInfo {
-      base_mip_level: 0,
-      mip_level_length: 1,
-      base_array_level: 0,
-      array_level_length: 1,
-    });
-
-    let motion = device.create_texture(&TextureInfo {
-      format: Format::RG32Float,
-      width: swapchain.width(),
-      height: swapchain.height(),
-      depth: 1,
-      mip_levels: 1,
-      array_length: 1,
-      samples: SampleCount::Samples1,
-      usage: TextureUsage::RENDER_TARGET | TextureUsage::COMPUTE_SHADER_SAMPLED,
-    }, Some("Motion"));
-    let motion_view = device.create_render_target_view(&motion, &TextureRenderTargetViewInfo {
-      base_mip_level: 0,
-      mip_level_length: 1,
-      base_array_level: 0,
-      array_level_length: 1,
-    });
-    let motion_srv = device.create_shader_resource_view(&motion, &TextureShaderResourceViewInfo {
-      base_mip_level: 0,
-      mip_level_length: 1,
-      base_array_level: 0,
-      array_level_length: 1,
-    });
-
-    let normals = device.create_texture(&TextureInfo {
-      format: Format::RGBA32Float,
-      width: swapchain.width(),
-      height: swapchain.height(),
-      depth: 1,
-      mip_levels: 1,
-      array_length: 1,
-      samples: SampleCount::Samples1,
-      usage: TextureUsage::RENDER_TARGET | TextureUsage::COMPUTE_SHADER_SAMPLED | TextureUsage::FRAGMENT_SHADER_SAMPLED,
-    }, Some("Normals"));
-    let normals_view = device.create_render_target_view(&normals, &TextureRenderTargetViewInfo {
-      base_mip_level: 0,
-      mip_level_length: 1,
-      base_array_level: 0,
-      array_level_length: 1,
-    });
-    let normals_srv = device.create_shader_resource_view(&normals, &TextureShaderResourceViewInfo {
-      base_mip_level: 0,
-      mip_level_length: 1,
-      base_array_level: 0,
-      array_level_length: 1,
-    });
-
+    let PrepassTargets { depth_buffer, depth_srv, motion, motion_srv, normals, normals_srv } = Self::create_targets(device, swapchain, init_cmd_buffer);
 
     let vertex_shader = {
       let mut file = <P::IO as IO>::open_asset(Path::new("shaders").join(Path::new("prepass.vert.spv"))).unwrap();
@@ -231,6 +172,94 @@ impl<B: GraphicsBackend> Prepass<B> {
       ],
     }, 0);
 
+  
+    Self {
+      depth_buffer,
+      motion,
+      motion_srv,
+      depth_srv,
+      normals,
+      normals_srv,
+      pipeline
+    }
+  }
+
+  fn create_targets(device: &Arc<B::Device>, swapchain: &Arc<B::Swapchain>, init_cmd_buffer: &mut B::CommandBuffer) -> PrepassTargets<B> {
+    let depth_buffer = device.create_texture(&TextureInfo {
+      dimension: TextureDimension::Dim2D,
+      format: Format::D24S8,
+      width: swapchain.width(),
+      height: swapchain.height(),
+      depth: 1,
+      mip_levels: 1,
+      array_length: 1,
+      samples: SampleCount::Samples1,
+      usage: TextureUsage::DEPTH_READ | TextureUsage::DEPTH_WRITE | TextureUsage::COMPUTE_SHADER_SAMPLED,
+      is_cubemap: false,
+    }, Some("PrepassDepth"));
+    let dsv = device.create_depth_stencil_view(&depth_buffer, &TextureDepthStencilViewInfo {
+      base_mip_level: 0,
+      mip_level_length: 1,
+      base_array_level: 0,
+      array_level_length: 1,
+    });
+    let depth_srv = device.create_shader_resource_view(&depth_buffer, &TextureShaderResourceViewInfo {
+      base_mip_level: 0,
+      mip_level_length: 1,
+      base_array_level: 0,
+      array_level_length: 1,
+    });
+
+    let motion = device.create_texture(&TextureInfo {
+      dimension: TextureDimension::Dim2D,
+      format: Format::RG32Float,
+      width: swapchain.width(),
+      height: swapchain.height(),
+      depth: 1,
+      mip_levels: 1,
+      array_length: 1,
+      samples: SampleCount::Samples1,
+      usage: TextureUsage::RENDER_TARGET | TextureUsage::COMPUTE_SHADER_SAMPLED,
+      is_cubemap: false,
+    }, Some("Motion"));
+    let motion_view = device.create_render_target_view(&motion, &TextureRenderTargetViewInfo {
+      base_mip_level: 0,
+      mip_level_length: 1,
+      base_array_level: 0,
+      array_level_length: 1,
+    });
+    let motion_srv = device.create_shader_resource_view(&motion, &TextureShaderResourceViewInfo {
+      base_mip_level: 0,
+      mip_level_length: 1,
+      base_array_level: 0,
+      array_level_length: 1,
+    });
+
+    let normals = device.create_texture(&TextureInfo {
+      dimension: TextureDimension::Dim2D,
+      format: Format::RGBA32Float,
+      width: swapchain.width(),
+      height: swapchain.height(),
+      depth: 1,
+      mip_levels: 1,
+      array_length: 1,
+      samples: SampleCount::Samples1,
+      usage: TextureUsage::RENDER_TARGET | TextureUsage::COMPUTE_SHADER_SAMPLED | TextureUsage::FRAGMENT_SHADER_SAMPLED,
+      is_cubemap: false,
+    }, Some("Normals"));
+    let normals_view = device.create_render_target_view(&normals, &TextureRenderTargetViewInfo {
+      base_mip_level: 0,
+      mip_level_length: 1,
+      base_array_level: 0,
+      array_level_length: 1,
+    });
+    let normals_srv = device.create_shader_resource_view(&normals, &TextureShaderResourceViewInfo {
+      base_mip_level: 0,
+      mip_level_length: 1,
+      base_array_level: 0,
+      array_level_length: 1,
+    });
+
     init_cmd_buffer.barrier(&[
       Barrier::TextureBarrier {
         old_primary_usage: TextureUsage::UNINITIALIZED,
@@ -254,18 +283,27 @@ impl<B: GraphicsBackend> Prepass<B> {
         texture: depth_srv.texture(),
       },
     ]);
-  
-    Self {
+
+    PrepassTargets {
       depth_buffer: dsv,
+      depth_srv,
       motion: motion_view,
       motion_srv,
-      depth_srv,
       normals: normals_view,
-      normals_srv,
-      pipeline
+      normals_srv
     }
   }
 
+  pub fn resize(&mut self, device: &Arc<B::Device>, swapchain: &Arc<B::Swapchain>, init_cmd_buffer: &mut B::CommandBuffer) {
+    let targets = Self::create_targets(device, swapchain, init_cmd_buffer);
+    self.depth_buffer = targets.depth_buffer;
+    self.depth_srv = targets.depth_srv;
+    self.motion = targets.motion;
+    self.motion_srv = targets.motion_srv;
+    self.normals = targets.normals;
+    self.normals_srv = targets.normals_srv;
+  }
+
   pub(super) fn execute(
     &mut self,
     cmd_buffer: &mut B::CommandBuffer,
@@ -273,7 +311,6 @@ impl<B: GraphicsBackend> Prepass<B> {
     scene: &RendererScene<B>,
     view: &View,
     swapchain_transform: Matrix4,
-    frame: u64,
     camera_buffer: &Arc<B::Buffer>,
     camera_history_buffer: &Arc<B::Buffer>
   ) {
@@ -351,8 +388,7 @@ impl<B: GraphicsBackend> Prepass<B> {
 
     let info = self.motion.texture().get_info();
     let per_frame = FrameData {
-      swapchain_transform,
-      halton_point: scaled_halton_point(info.width, info.height, (frame % 8) as u32)
+      swapchain_transform
     };
     let transform_constant_buffer = cmd_buffer.upload_dynamic_data(&[per_frame], BufferUsage::FRAGMENT_SHADER_CONSTANT | BufferUsage::VERTEX_SHADER_CONSTANT | BufferUsage::COMPUTE_SHADER_CONSTANT);
 
@@ -388,11 +424,11 @@ impl<B: GraphicsBackend> Prepass<B> {
           old_model: drawable.old_transform
         }], ShaderType::VertexShader);
 
-        let mesh = &model.mesh;
+        let mesh = model.lod_mesh(part.lod);
 
         command_buffer.set_vertex_buffer(&mesh.vertices);
         if mesh.indices.is_some() {
-          command_buffer.set_index_buffer(mesh.indices.as_ref().unwrap());
+          command_buffer.set_index_buffer(mesh.indices.as_ref().unwrap(), IndexFormat::U32);
         }
 
         let range = &mesh.parts[part.part_index];
@@ -0,0 +1,101 @@
+use std::{path::PathBuf, sync::Arc};
+
+use sourcerenderer_core::graphics::{Backend as GraphicsBackend, Barrier, Buffer, BufferInfo, BufferUsage, CommandBuffer, Device, Fence, Format, MemoryUsage, Texture};
+
+struct PendingScreenshot<B: GraphicsBackend> {
+  path: PathBuf,
+  buffer: Arc<B::Buffer>,
+  fence: Arc<B::Fence>,
+  width: u32,
+  height: u32,
+  format: Format
+}
+
+/// Copies the final image into a readback buffer and writes it to a PNG once the GPU is done with the frame.
+pub struct ScreenshotPass<B: GraphicsBackend> {
+  requested_path: Option<PathBuf>,
+  pending: Option<PendingScreenshot<B>>
+}
+
+impl<B: GraphicsBackend> ScreenshotPass<B> {
+  pub fn new() -> Self {
+    Self {
+      requested_path: None,
+      pending: None
+    }
+  }
+
+  pub fn request(&mut self, path: PathBuf) {
+    self.requested_path = Some(path);
+  }
+
+  /// Expects the texture to be in the COPY_SRC state.
+  pub fn execute(&mut self, cmd_buffer: &mut B::CommandBuffer, device: &Arc<B::Device>, texture: &Arc<B::Texture>, frame_fence: &Arc<B::Fence>) {
+    let path = if let Some(path) = self.requested_path.take() {
+      path
+    } else {
+      return;
+    };
+
+    let info = texture.get_info();
+    if info.format != Format::RGBA8 && info.format != Format::BGRA8UNorm {
+      println!("Taking a screenshot of a texture with the format {:?} is unsupported", info.format);
+      return;
+    }
+
+    let buffer = device.create_buffer(&BufferInfo {
+      size: (info.width * info.height * 4) as usize,
+      usage: BufferUsage::COPY_DST | BufferUsage::HOST_READ
+    }, MemoryUsage::GpuToCpu, Some("ScreenshotReadback"));
+    cmd_buffer.copy_texture_to_buffer(texture, 0, 0, &buffer);
+    cmd_buffer.barrier(&[
+      Barrier::BufferBarrier {
+        old_primary_usage: BufferUsage::COPY_DST,
+        new_primary_usage: BufferUsage::HOST_READ,
+        old_usages: BufferUsage::COPY_DST,
+        new_usages: BufferUsage::HOST_READ,
+        buffer: &buffer
+      }
+    ]);
+    cmd_buffer.flush_barriers();
+    self.pending = Some(PendingScreenshot {
+      path,
+      buffer,
+      fence: frame_fence.clone(),
+      width: info.width,
+      height: info.height,
+      format: info.format
+    });
+  }
+
+  /// Writes the PNG once the GPU is done with the frame that copied the image.
+  /// Has to be called before the fence of that frame gets reset.
+  pub fn write_pending(&mut self) {
+    let is_done = self.pending.as_ref().map_or(false, |pending| pending.fence.is_signaled());
+    if !is_done {
+      return;
+    }
+    let pending = self.pending.take().unwrap();
+
+    let row_pitch = pending.width as usize * 4;
+    let mut pixels = vec![0u8; row_pitch * pending.height as usize];
+    unsafe {
+      let ptr = pending.buffer.map_unsafe(true).expect("Failed to map screenshot buffer");
+      for row in 0..pending.height as usize {
+        let src = std::slice::from_raw_parts(ptr.add(row * row_pitch), row_pitch);
+        pixels[row * row_pitch .. (row + 1) * row_pitch].copy_from_slice(src);
+      }
+      pending.buffer.unmap_unsafe(false);
+    }
+    if pending.format == Format::BGRA8UNorm {
+      for pixel in pixels.chunks_exact_mut(4) {
+        pixel.swap(0, 2);
+      }
+    }
+
+    let result = image::save_buffer(&pending.path, &pixels, pending.width, pending.height, image::ColorType::Rgba8);
+    if let Err(e) = result {
+      println!("Failed to write screenshot to {:?}: {:?}", pending.path, e);
+    }
+  }
+}
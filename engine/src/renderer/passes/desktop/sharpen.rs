@@ -1,14 +1,35 @@
-use sourcerenderer_core::{graphics::{AddressMode, Backend as GraphicsBackend, Barrier, BindingFrequency, CommandBuffer, Device, Filter, Format, PipelineBinding, SamplerInfo, ShaderType, Swapchain, Texture, TextureInfo, TextureShaderResourceView, TextureUnorderedAccessView, TextureUnorderedAccessViewInfo, TextureUsage}};
+use sourcerenderer_core::{graphics::{AddressMode, Backend as GraphicsBackend, Barrier, BindingFrequency, BufferUsage, CommandBuffer, Device, Filter, Format, PipelineBinding, SamplerInfo, ShaderType, Swapchain, Texture, TextureDimension, TextureInfo, TextureShaderResourceView, TextureUnorderedAccessView, TextureUnorderedAccessViewInfo, TextureUsage}};
 use sourcerenderer_core::Platform;
 use std::sync::Arc;
 use std::path::Path;
 use std::io::Read;
 use sourcerenderer_core::platform::io::IO;
 
+use crate::renderer::TonemappingOperator;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct TonemappingParams {
+  operator: u32,
+  exposure: f32,
+  debug_output: u32
+}
+
+/// Has to match the debug output constants in sharpen.comp.glsl
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugOutput {
+  /// Writes the input as is
+  Passthrough,
+  /// Maps the normals from -1..1 to 0..1
+  Normals
+}
+
 pub struct SharpenPass<B: GraphicsBackend> {
   pipeline: Arc<B::ComputePipeline>,
   sampler: Arc<B::Sampler>,
-  sharpen_uav: Arc<B::TextureUnorderedAccessView>
+  sharpen_uav: Arc<B::TextureUnorderedAccessView>,
+  tonemapping_operator: TonemappingOperator,
+  exposure: f32
 }
 
 impl<B: GraphicsBackend> SharpenPass<B> {
@@ -35,7 +56,20 @@ impl<B: GraphicsBackend> SharpenPass<B> {
       max_lod: 1.0,
     });
 
+    let uav = Self::create_target(device, swapchain, init_cmd_buffer);
+
+    Self {
+      pipeline,
+      sampler,
+      sharpen_uav: uav,
+      tonemapping_operator: TonemappingOperator::Aces,
+      exposure: 1.0f32
+    }
+  }
+
+  fn create_target(device: &Arc<B::Device>, swapchain: &Arc<B::Swapchain>, init_cmd_buffer: &mut B::CommandBuffer) -> Arc<B::TextureUnorderedAccessView> {
     let texture = device.create_texture(&TextureInfo {
+      dimension: TextureDimension::Dim2D,
       format: Format::RGBA8,
       width: swapchain.width(),
       height: swapchain.height(),
@@ -44,6 +78,7 @@ impl<B: GraphicsBackend> SharpenPass<B> {
       array_length: 1,
       samples: sourcerenderer_core::graphics::SampleCount::Samples1,
       usage: TextureUsage::COMPUTE_SHADER_STORAGE_WRITE | TextureUsage::COPY_SRC,
+      is_cubemap: false,
     }, Some("SharpenOutput"));
     let uav = device.create_unordered_access_view(&texture, &TextureUnorderedAccessViewInfo {
       base_mip_level: 0,
@@ -61,12 +96,19 @@ impl<B: GraphicsBackend> SharpenPass<B> {
         texture: &texture,
       }
     ]);
-    
-    Self {
-      pipeline,
-      sampler,
-      sharpen_uav: uav
-    }
+    uav
+  }
+
+  pub fn resize(&mut self, device: &Arc<B::Device>, swapchain: &Arc<B::Swapchain>, init_cmd_buffer: &mut B::CommandBuffer) {
+    self.sharpen_uav = Self::create_target(device, swapchain, init_cmd_buffer);
+  }
+
+  pub fn set_tonemapping_operator(&mut self, operator: TonemappingOperator) {
+    self.tonemapping_operator = operator;
+  }
+
+  pub fn set_exposure(&mut self, exposure: f32) {
+    self.exposure = exposure;
   }
 
   pub fn execute(&mut self, cmd_buffer: &mut B::CommandBuffer, input_image: &Arc<B::TextureShaderResourceView>) {
@@ -87,15 +129,56 @@ impl<B: GraphicsBackend> SharpenPass<B> {
       },
     ]);
 
+    // Has to match the operator constants in sharpen.comp.glsl
+    let operator = match self.tonemapping_operator {
+      TonemappingOperator::Aces => 0,
+      TonemappingOperator::Reinhard => 1,
+      TonemappingOperator::Uncharted2 => 2
+    };
+    let params = cmd_buffer.upload_dynamic_data(&[TonemappingParams {
+      operator,
+      exposure: self.exposure,
+      debug_output: 0
+    }], BufferUsage::COMPUTE_SHADER_CONSTANT);
     cmd_buffer.set_pipeline(PipelineBinding::Compute(&self.pipeline));
     cmd_buffer.bind_texture_view(BindingFrequency::PerDraw, 0, input_image, &self.sampler);
     cmd_buffer.bind_storage_texture(BindingFrequency::PerDraw, 1, &self.sharpen_uav);
+    cmd_buffer.bind_uniform_buffer(BindingFrequency::PerDraw, 2, &params);
     cmd_buffer.finish_binding();
 
     let info = self.sharpen_uav.texture().get_info();
     cmd_buffer.dispatch(info.width, info.height, 1);
   }
 
+  /// Overwrites the output with a debug view, skipping sharpening and tonemapping.
+  /// Expects the input to be sampled by compute shaders already.
+  pub fn execute_debug_output(&mut self, cmd_buffer: &mut B::CommandBuffer, input_image: &Arc<B::TextureShaderResourceView>, debug_output: DebugOutput) {
+    cmd_buffer.barrier(&[
+      Barrier::TextureBarrier {
+        old_primary_usage: TextureUsage::COMPUTE_SHADER_STORAGE_WRITE,
+        new_primary_usage: TextureUsage::COMPUTE_SHADER_STORAGE_WRITE,
+        old_usages: TextureUsage::COMPUTE_SHADER_STORAGE_WRITE,
+        new_usages: TextureUsage::COMPUTE_SHADER_STORAGE_WRITE,
+        texture: self.sharpen_uav.texture()
+      },
+    ]);
+    let params = cmd_buffer.upload_dynamic_data(&[TonemappingParams {
+      operator: 0,
+      exposure: 1f32,
+      debug_output: match debug_output {
+        DebugOutput::Passthrough => 1,
+        DebugOutput::Normals => 2
+      }
+    }], BufferUsage::COMPUTE_SHADER_CONSTANT);
+    cmd_buffer.set_pipeline(PipelineBinding::Compute(&self.pipeline));
+    cmd_buffer.bind_texture_view(BindingFrequency::PerDraw, 0, input_image, &self.sampler);
+    cmd_buffer.bind_storage_texture(BindingFrequency::PerDraw, 1, &self.sharpen_uav);
+    cmd_buffer.bind_uniform_buffer(BindingFrequency::PerDraw, 2, &params);
+    cmd_buffer.finish_binding();
+    let info = self.sharpen_uav.texture().get_info();
+    cmd_buffer.dispatch(info.width, info.height, 1);
+  }
+
   pub fn sharpened_texture(&self) -> &Arc<B::Texture> {
     self.sharpen_uav.texture()
   }
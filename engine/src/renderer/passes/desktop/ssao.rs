@@ -1,9 +1,49 @@
 use std::{io::Read, path::Path, sync::Arc};
 
-use sourcerenderer_core::{Platform, Vec2UI, Vec4, graphics::{AddressMode, Backend as GraphicsBackend, Barrier, BindingFrequency, BufferInfo, BufferUsage, CommandBuffer, Device, Filter, Format, MemoryUsage, PipelineBinding, SampleCount, SamplerInfo, ShaderType, Texture, TextureInfo, TextureShaderResourceView, TextureShaderResourceViewInfo, TextureUnorderedAccessViewInfo, TextureUsage}, platform::io::IO};
+use sourcerenderer_core::{Platform, Vec2UI, Vec4, graphics::{AddressMode, Backend as GraphicsBackend, Barrier, BindingFrequency, BufferInfo, BufferUsage, CommandBuffer, Device, Filter, Format, MemoryUsage, PipelineBinding, SampleCount, SamplerInfo, ShaderType, Texture, TextureDimension, TextureInfo, TextureShaderResourceView, TextureShaderResourceViewInfo, TextureUnorderedAccessViewInfo, TextureUsage}, platform::io::IO};
 
 use rand::random;
 
+const MAX_KERNEL_SIZE: u32 = 16;
+
+#[derive(Debug, Clone, Copy)]
+pub struct SsaoSettings {
+  pub radius: f32,
+  pub bias: f32,
+  pub intensity: f32,
+  pub kernel_size: u32
+}
+
+impl Default for SsaoSettings {
+  fn default() -> Self {
+    Self {
+      radius: 0.5f32,
+      bias: 0.025f32,
+      intensity: 1.0f32,
+      kernel_size: MAX_KERNEL_SIZE
+    }
+  }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct SsaoParams {
+  radius: f32,
+  bias: f32,
+  intensity: f32,
+  kernel_size: u32
+}
+
+/// Everything that has the size of the swapchain.
+struct SsaoTargets<B: GraphicsBackend> {
+  ssao_texture: Arc<B::Texture>,
+  ssao_uav: Arc<B::TextureUnorderedAccessView>,
+  ssao_srv: Arc<B::TextureShaderResourceView>,
+  blurred_texture: Arc<B::Texture>,
+  blurred_uav: Arc<B::TextureUnorderedAccessView>,
+  blurred_srv: Arc<B::TextureShaderResourceView>
+}
+
 pub struct SsaoPass<B: GraphicsBackend> {
   ssao_texture: Arc<B::Texture>,
   ssao_uav: Arc<B::TextureUnorderedAccessView>,
@@ -17,7 +57,8 @@ pub struct SsaoPass<B: GraphicsBackend> {
   blurred_texture: Arc<B::Texture>,
   blurred_uav: Arc<B::TextureUnorderedAccessView>,
   blurred_srv: Arc<B::TextureShaderResourceView>,
-  blur_sampler: Arc<B::Sampler>
+  blur_sampler: Arc<B::Sampler>,
+  settings: SsaoSettings
 }
 
 fn lerp(a: f32, b: f32, f: f32) -> f32 {
@@ -26,48 +67,6 @@ fn lerp(a: f32, b: f32, f: f32) -> f32 {
 
 impl<B: GraphicsBackend> SsaoPass<B> {
   pub fn new<P: Platform>(device: &Arc<B::Device>, resolution: Vec2UI, init_cmd_buffer: &mut B::CommandBuffer) -> Self {
-    let ssao_texture = device.create_texture(&TextureInfo {
-      format: Format::R16Float,
-      width: resolution.x,
-      height: resolution.y,
-      depth: 1,
-      mip_levels: 1,
-      array_length: 1,
-      samples: SampleCount::Samples1,
-      usage: TextureUsage::COMPUTE_SHADER_STORAGE_WRITE | TextureUsage::COMPUTE_SHADER_SAMPLED,
-    }, Some("SSAO"));
-    let blurred_texture = device.create_texture(&TextureInfo {
-      format: Format::R16Float,
-      width: resolution.x,
-      height: resolution.y,
-      depth: 1,
-      mip_levels: 1,
-      array_length: 1,
-      samples: SampleCount::Samples1,
-      usage: TextureUsage::COMPUTE_SHADER_STORAGE_WRITE | TextureUsage::FRAGMENT_SHADER_SAMPLED,
-    }, Some("SSAOBlurred"));
-
-    let uav_info = TextureUnorderedAccessViewInfo {
-      base_mip_level: 0,
-      mip_level_length: 1,
-      base_array_level: 0,
-      array_level_length: 1,
-    };
-    let ssao_uav = device.create_unordered_access_view(&ssao_texture, &uav_info);
-    let blurred_uav = device.create_unordered_access_view(&blurred_texture, &uav_info);
-    let ssao_srv = device.create_shader_resource_view(&ssao_texture, &TextureShaderResourceViewInfo {
-      base_mip_level: 0,
-      mip_level_length: 1,
-      base_array_level: 0,
-      array_level_length: 1,
-    });
-    let blurred_srv = device.create_shader_resource_view(&blurred_texture, &TextureShaderResourceViewInfo {
-      base_mip_level: 0,
-      mip_level_length: 1,
-      base_array_level: 0,
-      array_level_length: 1,
-    });
-
     let shader = {
       let mut file = <P::IO as IO>::open_asset(Path::new("shaders").join(Path::new("ssao.comp.spv"))).unwrap();
       let mut bytes: Vec<u8> = Vec::new();
@@ -76,24 +75,9 @@ impl<B: GraphicsBackend> SsaoPass<B> {
     };  
     let pipeline = device.create_compute_pipeline(&shader);
 
-    init_cmd_buffer.barrier(&[
-      Barrier::TextureBarrier {
-        old_primary_usage: TextureUsage::UNINITIALIZED,
-        new_primary_usage: TextureUsage::COMPUTE_SHADER_SAMPLED,
-        old_usages: TextureUsage::empty(),
-        new_usages: TextureUsage::empty(),
-        texture: &ssao_texture,
-      },
-      Barrier::TextureBarrier {
-        old_primary_usage: TextureUsage::UNINITIALIZED,
-        new_primary_usage: TextureUsage::FRAGMENT_SHADER_SAMPLED,
-        old_usages: TextureUsage::empty(),
-        new_usages: TextureUsage::empty(),
-        texture: &blurred_texture,
-      }
-    ]);
+    let SsaoTargets { ssao_texture, ssao_uav, ssao_srv, blurred_texture, blurred_uav, blurred_srv } = Self::create_targets(device, resolution, init_cmd_buffer);
 
-    let kernel = Self::create_hemisphere(device, 16);
+    let kernel = Self::create_hemisphere(device, MAX_KERNEL_SIZE);
     let noise = Self::create_noise(device, 4);
 
     let blur_shader = {
@@ -157,10 +141,120 @@ impl<B: GraphicsBackend> SsaoPass<B> {
       blur_pipeline,
       ssao_srv,
       blur_sampler,
+      blurred_srv,
+      settings: SsaoSettings::default()
+    }
+  }
+
+  fn create_targets(device: &Arc<B::Device>, resolution: Vec2UI, init_cmd_buffer: &mut B::CommandBuffer) -> SsaoTargets<B> {
+    let ssao_texture = device.create_texture(&TextureInfo {
+      dimension: TextureDimension::Dim2D,
+      format: Format::R16Float,
+      width: resolution.x,
+      height: resolution.y,
+      depth: 1,
+      mip_levels: 1,
+      array_length: 1,
+      samples: SampleCount::Samples1,
+      usage: TextureUsage::COMPUTE_SHADER_STORAGE_WRITE | TextureUsage::COMPUTE_SHADER_SAMPLED,
+      is_cubemap: false,
+    }, Some("SSAO"));
+    let blurred_texture = device.create_texture(&TextureInfo {
+      dimension: TextureDimension::Dim2D,
+      format: Format::R16Float,
+      width: resolution.x,
+      height: resolution.y,
+      depth: 1,
+      mip_levels: 1,
+      array_length: 1,
+      samples: SampleCount::Samples1,
+      usage: TextureUsage::COMPUTE_SHADER_STORAGE_WRITE | TextureUsage::FRAGMENT_SHADER_SAMPLED,
+      is_cubemap: false,
+    }, Some("SSAOBlurred"));
+
+    let uav_info = TextureUnorderedAccessViewInfo {
+      base_mip_level: 0,
+      mip_level_length: 1,
+      base_array_level: 0,
+      array_level_length: 1,
+    };
+    let ssao_uav = device.create_unordered_access_view(&ssao_texture, &uav_info);
+    let blurred_uav = device.create_unordered_access_view(&blurred_texture, &uav_info);
+    let ssao_srv = device.create_shader_resource_view(&ssao_texture, &TextureShaderResourceViewInfo {
+      base_mip_level: 0,
+      mip_level_length: 1,
+      base_array_level: 0,
+      array_level_length: 1,
+    });
+    let blurred_srv = device.create_shader_resource_view(&blurred_texture, &TextureShaderResourceViewInfo {
+      base_mip_level: 0,
+      mip_level_length: 1,
+      base_array_level: 0,
+      array_level_length: 1,
+    });
+
+    init_cmd_buffer.barrier(&[
+      Barrier::TextureBarrier {
+        old_primary_usage: TextureUsage::UNINITIALIZED,
+        new_primary_usage: TextureUsage::COMPUTE_SHADER_SAMPLED,
+        old_usages: TextureUsage::empty(),
+        new_usages: TextureUsage::empty(),
+        texture: &ssao_texture,
+      },
+      Barrier::TextureBarrier {
+        old_primary_usage: TextureUsage::UNINITIALIZED,
+        new_primary_usage: TextureUsage::FRAGMENT_SHADER_SAMPLED,
+        old_usages: TextureUsage::empty(),
+        new_usages: TextureUsage::empty(),
+        texture: &blurred_texture,
+      }
+    ]);
+
+    SsaoTargets {
+      ssao_texture,
+      ssao_uav,
+      ssao_srv,
+      blurred_texture,
+      blurred_uav,
       blurred_srv
     }
   }
 
+  pub fn resize(&mut self, device: &Arc<B::Device>, resolution: Vec2UI, init_cmd_buffer: &mut B::CommandBuffer) {
+    let targets = Self::create_targets(device, resolution, init_cmd_buffer);
+    self.ssao_texture = targets.ssao_texture;
+    self.ssao_uav = targets.ssao_uav;
+    self.ssao_srv = targets.ssao_srv;
+    self.blurred_texture = targets.blurred_texture;
+    self.blurred_uav = targets.blurred_uav;
+    self.blurred_srv = targets.blurred_srv;
+  }
+
+  pub fn settings(&self) -> &SsaoSettings {
+    &self.settings
+  }
+
+  pub fn set_settings(&mut self, settings: &SsaoSettings) {
+    self.settings = *settings;
+    self.settings.kernel_size = self.settings.kernel_size.min(MAX_KERNEL_SIZE);
+  }
+
+  pub fn set_radius(&mut self, radius: f32) {
+    self.settings.radius = radius;
+  }
+
+  pub fn set_bias(&mut self, bias: f32) {
+    self.settings.bias = bias;
+  }
+
+  pub fn set_intensity(&mut self, intensity: f32) {
+    self.settings.intensity = intensity;
+  }
+
+  pub fn set_kernel_size(&mut self, kernel_size: u32) {
+    self.settings.kernel_size = kernel_size.min(MAX_KERNEL_SIZE);
+  }
+
   fn create_hemisphere(device: &Arc<B::Device>, samples: u32) -> Arc<B::Buffer> {
     let mut ssao_kernel = Vec::<Vec4>::with_capacity(samples as usize);
     for i in 0..samples {
@@ -201,6 +295,7 @@ impl<B: GraphicsBackend> SsaoPass<B> {
     }
     
     let texture = device.create_texture(&TextureInfo {
+      dimension: TextureDimension::Dim2D,
       format: Format::RGBA32Float,
       width: size,
       height: size,
@@ -209,6 +304,7 @@ impl<B: GraphicsBackend> SsaoPass<B> {
       array_length: 1,
       samples: SampleCount::Samples1,
       usage: TextureUsage::COPY_DST | TextureUsage::COMPUTE_SHADER_SAMPLED,
+      is_cubemap: false,
     }, Some("SSAONoise"));
     let buffer = device.upload_data(&ssao_noise[..], MemoryUsage::CpuToGpu, BufferUsage::COPY_SRC);
     device.init_texture(&texture, &buffer, 0, 0);
@@ -247,6 +343,12 @@ impl<B: GraphicsBackend> SsaoPass<B> {
       },
     ]);
     cmd_buffer.flush_barriers();
+    let params = cmd_buffer.upload_dynamic_data(&[SsaoParams {
+      radius: self.settings.radius,
+      bias: self.settings.bias,
+      intensity: self.settings.intensity,
+      kernel_size: self.settings.kernel_size
+    }], BufferUsage::COMPUTE_SHADER_CONSTANT);
     cmd_buffer.set_pipeline(PipelineBinding::Compute(&self.pipeline));
     cmd_buffer.bind_uniform_buffer(BindingFrequency::PerDraw, 0, &self.kernel);
     cmd_buffer.bind_texture_view(BindingFrequency::PerDraw, 1, &self.noise, &self.nearest_sampler);
@@ -254,6 +356,7 @@ impl<B: GraphicsBackend> SsaoPass<B> {
     cmd_buffer.bind_texture_view(BindingFrequency::PerDraw, 3, normals, &self.nearest_sampler);
     cmd_buffer.bind_uniform_buffer(BindingFrequency::PerDraw, 4, camera);
     cmd_buffer.bind_storage_texture(BindingFrequency::PerDraw, 5, &self.ssao_uav);
+    cmd_buffer.bind_uniform_buffer(BindingFrequency::PerDraw, 6, &params);
     let info = depth.texture().get_info();
     cmd_buffer.finish_binding();
     cmd_buffer.dispatch(info.width, info.height, info.depth);
@@ -1,4 +1,4 @@
-use sourcerenderer_core::{Vec2, graphics::{AddressMode, Backend as GraphicsBackend, Barrier, BindingFrequency, CommandBuffer, Device, Filter, Format, PipelineBinding, SampleCount, SamplerInfo, ShaderType, Swapchain, Texture, TextureInfo, TextureShaderResourceView, TextureShaderResourceViewInfo, TextureUnorderedAccessViewInfo, TextureUsage}};
+use sourcerenderer_core::{Vec2, graphics::{AddressMode, Backend as GraphicsBackend, Barrier, BindingFrequency, CommandBuffer, Device, Filter, Format, PipelineBinding, SampleCount, SamplerInfo, ShaderType, Swapchain, Texture, TextureDimension, TextureInfo, TextureShaderResourceView, TextureShaderResourceViewInfo, TextureUnorderedAccessViewInfo, TextureUsage}};
 use sourcerenderer_core::Platform;
 use std::sync::Arc;
 use std::path::Path;
@@ -14,12 +14,17 @@ pub(crate) fn scaled_halton_point(width: u32, height: u32, index: u32) -> Vec2 {
   halton_point
 }
 
+/// Sample positions come from the Halton(2, 3) sequence, callers should start at index 1 because index 0 is always the corner of the pixel.
 pub(crate) fn halton_point(index: u32) -> Vec2 {
   Vec2::new(
     halton_sequence(index, 2) * 2f32 - 1f32, halton_sequence(index, 3) * 2f32 - 1f32
   )
 }
 
+fn jitter_sample_index(frame: u64, sample_count: u32) -> u32 {
+  (frame % sample_count.max(1) as u64) as u32 + 1
+}
+
 pub(crate) fn halton_sequence(mut index: u32, base: u32) -> f32 {
   let mut f = 1.0f32;
   let mut r = 0.0f32;
@@ -33,6 +38,16 @@ pub(crate) fn halton_sequence(mut index: u32, base: u32) -> f32 {
   return r;
 }
 
+/// Everything that has the size of the swapchain.
+struct TAATargets<B: GraphicsBackend> {
+  taa_texture: Arc<B::Texture>,
+  taa_texture_b: Arc<B::Texture>,
+  taa_srv: Arc<B::TextureShaderResourceView>,
+  taa_srv_b: Arc<B::TextureShaderResourceView>,
+  taa_uav: Arc<B::TextureUnorderedAccessView>,
+  taa_uav_b: Arc<B::TextureUnorderedAccessView>
+}
+
 pub struct TAAPass<B: GraphicsBackend> {
   taa_texture: Arc<B::Texture>,
   taa_texture_b: Arc<B::Texture>,
@@ -42,7 +57,8 @@ pub struct TAAPass<B: GraphicsBackend> {
   taa_uav_b: Arc<B::TextureUnorderedAccessView>,
   pipeline: Arc<B::ComputePipeline>,
   nearest_sampler: Arc<B::Sampler>,
-  linear_sampler: Arc<B::Sampler>
+  linear_sampler: Arc<B::Sampler>,
+  pub jitter_sample_count: u32
 }
 
 impl<B: GraphicsBackend> TAAPass<B> {
@@ -83,8 +99,26 @@ impl<B: GraphicsBackend> TAAPass<B> {
       max_lod: 1.0,
     });
 
+    let TAATargets { taa_texture, taa_texture_b, taa_srv, taa_srv_b, taa_uav, taa_uav_b } = Self::create_targets(device, swapchain, init_cmd_buffer);
+
+    Self {
+      pipeline,
+      taa_texture,
+      taa_texture_b,
+      taa_srv,
+      taa_srv_b,
+      taa_uav,
+      taa_uav_b,
+      linear_sampler,
+      nearest_sampler,
+      jitter_sample_count: 8
+    }
+  }
+
+  fn create_targets(device: &Arc<B::Device>, swapchain: &Arc<B::Swapchain>, init_cmd_buffer: &mut B::CommandBuffer) -> TAATargets<B> {
     let texture_info = TextureInfo {
-      format: Format::RGBA8,
+      dimension: TextureDimension::Dim2D,
+      format: Format::RGBA16Float,
       width: swapchain.width(),
       height: swapchain.height(),
       depth: 1,
@@ -92,6 +126,7 @@ impl<B: GraphicsBackend> TAAPass<B> {
       array_length: 1,
       samples: SampleCount::Samples1,
       usage: TextureUsage::COMPUTE_SHADER_SAMPLED | TextureUsage::COMPUTE_SHADER_STORAGE_WRITE,
+      is_cubemap: false,
     };
     let taa_texture = device.create_texture(&texture_info, Some("TAAOutput"));
     let taa_texture_b = device.create_texture(&texture_info, Some("TAAOutput_b"));
@@ -131,19 +166,32 @@ impl<B: GraphicsBackend> TAAPass<B> {
       }
     ]);
 
-    Self {
-      pipeline,
+    TAATargets {
       taa_texture,
       taa_texture_b,
       taa_srv,
       taa_srv_b,
       taa_uav,
-      taa_uav_b,
-      linear_sampler,
-      nearest_sampler
+      taa_uav_b
     }
   }
 
+  /// Drops the history, the first frame after this doesn't have anything to blend with.
+  pub fn resize(&mut self, device: &Arc<B::Device>, swapchain: &Arc<B::Swapchain>, init_cmd_buffer: &mut B::CommandBuffer) {
+    let targets = Self::create_targets(device, swapchain, init_cmd_buffer);
+    self.taa_texture = targets.taa_texture;
+    self.taa_texture_b = targets.taa_texture_b;
+    self.taa_srv = targets.taa_srv;
+    self.taa_srv_b = targets.taa_srv_b;
+    self.taa_uav = targets.taa_uav;
+    self.taa_uav_b = targets.taa_uav_b;
+  }
+
+  pub fn jitter_point(&self, frame: u64) -> Vec2 {
+    let info = self.taa_texture.get_info();
+    scaled_halton_point(info.width, info.height, jitter_sample_index(frame, self.jitter_sample_count))
+  }
+
   pub fn execute(
     &mut self,
     cmd_buf: &mut B::CommandBuffer,
@@ -195,3 +243,35 @@ impl<B: GraphicsBackend> TAAPass<B> {
     std::mem::swap(&mut self.taa_uav, &mut self.taa_uav_b);
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn halton_points_match_the_sequence() {
+    let expected = [
+      (1f32 / 2f32, 1f32 / 3f32), (1f32 / 4f32, 2f32 / 3f32), (3f32 / 4f32, 1f32 / 9f32), (1f32 / 8f32, 4f32 / 9f32),
+      (5f32 / 8f32, 7f32 / 9f32), (3f32 / 8f32, 2f32 / 9f32), (7f32 / 8f32, 5f32 / 9f32), (1f32 / 16f32, 8f32 / 9f32)
+    ];
+    for (index, (x, y)) in (1u32..).zip(expected.iter()) {
+      let point = halton_point(index);
+      assert!((point.x - (x * 2f32 - 1f32)).abs() < 0.0001f32, "x of point {} is {}", index, point.x);
+      assert!((point.y - (y * 2f32 - 1f32)).abs() < 0.0001f32, "y of point {} is {}", index, point.y);
+    }
+  }
+
+  #[test]
+  fn jitter_averages_to_roughly_zero_over_a_cycle() {
+    let (width, height) = (1920u32, 1080u32);
+    let sample_count = 8u32;
+    let mut sum = Vec2::new(0f32, 0f32);
+    for frame in 0..sample_count as u64 {
+      sum += scaled_halton_point(width, height, jitter_sample_index(frame, sample_count));
+    }
+    let average = sum / sample_count as f32;
+    // Within an eighth of the jitter range of a pixel
+    assert!(average.x.abs() < 0.125f32 / width as f32, "{}", average.x);
+    assert!(average.y.abs() < 0.125f32 / height as f32, "{}", average.y);
+  }
+}
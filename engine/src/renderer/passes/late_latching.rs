@@ -1,14 +1,22 @@
 use sourcerenderer_core::graphics::{Backend as GraphicsBackend, Barrier, BindingFrequency, BufferInfo, BufferUsage, CommandBuffer, Device, MemoryUsage, PipelineBinding, ShaderType};
-use sourcerenderer_core::{Matrix4, Platform};
+use sourcerenderer_core::{Matrix4, Platform, Vec2};
 use std::sync::Arc;
 use std::path::Path;
 use std::io::Read;
 use sourcerenderer_core::platform::io::IO;
 
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct CameraJitter {
+  jitter: Vec2,
+  old_jitter: Vec2
+}
+
 pub struct LateLatchingPass<B: GraphicsBackend> {
   pipeline: Arc<B::ComputePipeline>,
   camera_buffer: Arc<B::Buffer>,
-  camera_buffer_b: Arc<B::Buffer>
+  camera_buffer_b: Arc<B::Buffer>,
+  jitter: Vec2
 }
 
 impl<B: GraphicsBackend> LateLatchingPass<B> {
@@ -22,7 +30,7 @@ impl<B: GraphicsBackend> LateLatchingPass<B> {
   
     let copy_camera_pipeline = device.create_compute_pipeline(&copy_camera_compute_shader);
     let buffer_info = BufferInfo {
-      size: std::mem::size_of::<Matrix4>() * 2,
+      size: std::mem::size_of::<Matrix4>() * 4 + std::mem::size_of::<CameraJitter>(),
       usage: BufferUsage::COMPUTE_SHADER_CONSTANT | BufferUsage::VERTEX_SHADER_CONSTANT | BufferUsage::FRAGMENT_SHADER_CONSTANT
         | BufferUsage::COMPUTE_SHADER_STORAGE_READ | BufferUsage::VERTEX_SHADER_STORAGE_READ | BufferUsage::FRAGMENT_SHADER_STORAGE_READ
         | BufferUsage::COMPUTE_SHADER_STORAGE_WRITE,
@@ -32,11 +40,19 @@ impl<B: GraphicsBackend> LateLatchingPass<B> {
     Self {
       pipeline: copy_camera_pipeline,
       camera_buffer,
-      camera_buffer_b
+      camera_buffer_b,
+      jitter: Vec2::new(0f32, 0f32)
     }
   }
 
-  pub fn execute(&mut self, command_buffer: &mut B::CommandBuffer, camera_ring_buffer: &Arc<B::Buffer>) {
+  /// The camera also stores the jitter of the previous frame, so motion vectors can remove both offsets.
+  pub fn execute(&mut self, command_buffer: &mut B::CommandBuffer, camera_ring_buffer: &Arc<B::Buffer>, jitter: Vec2) {
+    let jitter_buffer = command_buffer.upload_dynamic_data(&[CameraJitter {
+      jitter,
+      old_jitter: self.jitter
+    }], BufferUsage::COMPUTE_SHADER_CONSTANT);
+    self.jitter = jitter;
+
     command_buffer.barrier(&[
       Barrier::BufferBarrier {
         old_primary_usage: BufferUsage::VERTEX_SHADER_CONSTANT,
@@ -51,6 +67,7 @@ impl<B: GraphicsBackend> LateLatchingPass<B> {
     command_buffer.set_pipeline(PipelineBinding::Compute(&self.pipeline));
     command_buffer.bind_storage_buffer(BindingFrequency::PerDraw, 0, camera_ring_buffer);
     command_buffer.bind_storage_buffer(BindingFrequency::PerDraw, 1, &self.camera_buffer);
+    command_buffer.bind_uniform_buffer(BindingFrequency::PerDraw, 2, &jitter_buffer);
     command_buffer.finish_binding();
     command_buffer.dispatch(1, 1, 1);
   }
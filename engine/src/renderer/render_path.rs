@@ -1,11 +1,63 @@
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc};
 
-use sourcerenderer_core::{atomic_refcell::AtomicRefCell, graphics::{Backend, SwapchainError}};
+use sourcerenderer_core::{atomic_refcell::AtomicRefCell, graphics::{Backend, SampleCount, SwapchainError}};
 
-use super::{LateLatchCamera, drawable::View, renderer_assets::RendererTexture, renderer_scene::RendererScene};
+use super::{AntiAliasing, LateLatchCamera, drawable::View, occlusion::HierarchicalDepthBuffer, renderer_assets::RendererTexture, renderer_scene::RendererScene};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TonemappingOperator {
+  Aces,
+  Reinhard,
+  Uncharted2
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugView {
+  None,
+  Normals,
+  Albedo,
+  Depth,
+  Overdraw,
+  Clusters,
+  SsaoOnly
+}
+
+/// Settings that the render path gets created with.
+#[derive(Debug, Clone)]
+pub struct RendererSettings {
+  pub anti_aliasing: AntiAliasing,
+  pub msaa: SampleCount
+}
+
+impl RendererSettings {
+  /// The configured MSAA sample count, reduced to what the device supports.
+  pub fn supported_msaa(&self, max_sample_count: SampleCount) -> SampleCount {
+    if self.msaa.count() > max_sample_count.count() {
+      println!("MSAA with {} samples isn't supported, falling back to {}", self.msaa.count(), max_sample_count.count());
+      max_sample_count
+    } else {
+      self.msaa
+    }
+  }
+}
+
+impl Default for RendererSettings {
+  fn default() -> Self {
+    Self {
+      anti_aliasing: AntiAliasing::Taa,
+      msaa: SampleCount::Samples1
+    }
+  }
+}
 
 pub(super) trait RenderPath<B: Backend> {
   fn on_swapchain_changed(&mut self, swapchain: &Arc<B::Swapchain>);
+  fn set_tonemapping_operator(&mut self, operator: TonemappingOperator);
+  fn set_exposure(&mut self, exposure: f32);
+  fn set_debug_view(&mut self, debug_view: DebugView);
+  fn set_indirect_draws(&mut self, indirect_draws: bool);
+  fn occlusion_buffer(&self) -> Option<&HierarchicalDepthBuffer>;
+  fn request_screenshot(&mut self, path: PathBuf);
   fn render(
     &mut self,
     scene: &Arc<AtomicRefCell<RendererScene<B>>>,
@@ -14,3 +66,19 @@ pub(super) trait RenderPath<B: Backend> {
     primary_camera: &Arc<LateLatchCamera<B>>
   ) -> Result<(), SwapchainError>;
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn msaa_is_limited_to_the_device_maximum() {
+    let settings = RendererSettings {
+      msaa: SampleCount::Samples8,
+      ..Default::default()
+    };
+    assert_eq!(settings.supported_msaa(SampleCount::Samples4), SampleCount::Samples4);
+    assert_eq!(settings.supported_msaa(SampleCount::Samples8), SampleCount::Samples8);
+    assert_eq!(RendererSettings::default().supported_msaa(SampleCount::Samples8), SampleCount::Samples1);
+  }
+}
@@ -1,11 +1,11 @@
-use std::{cmp::max, sync::{Arc, Mutex, MutexGuard, atomic::AtomicBool}};
+use std::{cmp::max, path::PathBuf, sync::{Arc, Mutex, MutexGuard, atomic::AtomicBool}};
 use crossbeam_channel::{Sender, unbounded};
 
 use sourcerenderer_core::{atomic_refcell::AtomicRefCell, graphics::SwapchainError, platform::{Platform, Window, WindowState}};
 use sourcerenderer_core::graphics::{Backend, Swapchain};
 use sourcerenderer_core::Matrix4;
 
-use crate::{asset::AssetManager, transform::interpolation::InterpolatedTransform};
+use crate::{Projection, asset::AssetManager, transform::interpolation::InterpolatedTransform};
 
 use std::sync::atomic::{Ordering, AtomicUsize};
 
@@ -16,7 +16,7 @@ use legion::systems::Builder;
 use crate::renderer::RendererInternal;
 use crate::renderer::camera::LateLatchCamera;
 
-use super::{StaticRenderableComponent, drawable::View, ecs::{PointLightComponent, RendererInterface}, renderer_assets::RendererTexture, renderer_scene::RendererScene};
+use super::{DebugView, RendererSettings, StaticRenderableComponent, TonemappingOperator, drawable::View, ecs::{DirectionalLightComponent, PointLightComponent, RendererInterface, SpotLightComponent}, renderer_assets::RendererTexture, renderer_scene::RendererScene};
 
 pub struct Renderer<P: Platform> {
   sender: Sender<RendererCommand>,
@@ -55,7 +55,8 @@ impl<P: Platform> Renderer<P> {
              instance: &Arc<<P::GraphicsBackend as Backend>::Instance>,
              device: &Arc<<P::GraphicsBackend as Backend>::Device>,
              swapchain: &Arc<<P::GraphicsBackend as Backend>::Swapchain>,
-             asset_manager: &Arc<AssetManager<P>>) -> Arc<Renderer<P>> {
+             asset_manager: &Arc<AssetManager<P>>,
+             settings: RendererSettings) -> Arc<Renderer<P>> {
     let (sender, receiver) = unbounded::<RendererCommand>();
     let renderer = Arc::new(Renderer::new(sender.clone(), instance, device, window, swapchain.surface()));
 
@@ -67,7 +68,7 @@ impl<P: Platform> Renderer<P> {
     std::thread::Builder::new()
       .name("RenderThread".to_string())
       .spawn(move || {
-      let mut internal = RendererInternal::new(&c_renderer, &c_device, &c_swapchain, &c_asset_manager, sender, receiver, c_renderer.primary_camera());
+      let mut internal = RendererInternal::new(&c_renderer, &c_device, &c_swapchain, &c_asset_manager, sender, receiver, c_renderer.primary_camera(), &settings);
       loop {
         if !c_renderer.is_running.load(Ordering::SeqCst) {
           break;
@@ -114,6 +115,43 @@ impl<P: Platform> Renderer<P> {
   pub fn stop(&self) {
     self.is_running.store(false, Ordering::SeqCst);
   }
+
+  pub fn set_tonemapping(&self, operator: TonemappingOperator, exposure: f32) {
+    let result = self.sender.send(RendererCommand::SetTonemapping { operator, exposure });
+    if result.is_err() {
+      panic!("Sending message to render thread failed");
+    }
+  }
+
+  pub fn set_debug_view(&self, debug_view: DebugView) {
+    let result = self.sender.send(RendererCommand::SetDebugView(debug_view));
+    if result.is_err() {
+      panic!("Sending message to render thread failed");
+    }
+  }
+
+  pub fn set_indirect_draws(&self, indirect_draws: bool) {
+    let result = self.sender.send(RendererCommand::SetIndirectDraws(indirect_draws));
+    if result.is_err() {
+      panic!("Sending message to render thread failed");
+    }
+  }
+
+  /// Time in seconds the camera takes to catch up with most of a position change, 0 disables smoothing.
+  pub fn set_camera_smoothing(&self, smoothing: f32) {
+    let result = self.sender.send(RendererCommand::SetCameraSmoothing(smoothing));
+    if result.is_err() {
+      panic!("Sending message to render thread failed");
+    }
+  }
+
+  /// Writes the next frame to a PNG file.
+  pub fn request_screenshot(&self, path: PathBuf) {
+    let result = self.sender.send(RendererCommand::RequestScreenshot(path));
+    if result.is_err() {
+      panic!("Sending message to render thread failed");
+    }
+  }
 }
 
 impl<P: Platform> RendererInterface for Arc<Renderer<P>> {
@@ -122,6 +160,7 @@ impl<P: Platform> RendererInterface for Arc<Renderer<P>> {
       entity,
       transform: transform.0,
       model_path: renderable.model_path.to_string(),
+      skin: renderable.skin,
       receive_shadows: renderable.receive_shadows,
       cast_shadows: renderable.cast_shadows,
       can_move: renderable.can_move
@@ -156,8 +195,46 @@ impl<P: Platform> RendererInterface for Arc<Renderer<P>> {
     }
   }
 
-  fn update_camera_transform(&self, camera_transform_mat: Matrix4, fov: f32) {
-    let result = self.sender.send(RendererCommand::UpdateCameraTransform { camera_transform_mat, fov });
+  fn register_directional_light(&self, entity: Entity, transform: &InterpolatedTransform, component: &DirectionalLightComponent) {
+    let result = self.sender.send(RendererCommand::RegisterDirectionalLight {
+      entity,
+      transform: transform.0,
+      color: component.color,
+      intensity: component.intensity
+    });
+    if result.is_err() {
+      panic!("Sending message to render thread failed");
+    }
+  }
+
+  fn unregister_directional_light(&self, entity: Entity) {
+    let result = self.sender.send(RendererCommand::UnregisterDirectionalLight(entity));
+    if result.is_err() {
+      panic!("Sending message to render thread failed");
+    }
+  }
+
+  fn register_spot_light(&self, entity: Entity, transform: &InterpolatedTransform, component: &SpotLightComponent) {
+    let result = self.sender.send(RendererCommand::RegisterSpotLight {
+      entity,
+      transform: transform.0,
+      intensity: component.intensity,
+      angle: component.angle
+    });
+    if result.is_err() {
+      panic!("Sending message to render thread failed");
+    }
+  }
+
+  fn unregister_spot_light(&self, entity: Entity) {
+    let result = self.sender.send(RendererCommand::UnregisterSpotLight(entity));
+    if result.is_err() {
+      panic!("Sending message to render thread failed");
+    }
+  }
+
+  fn update_camera_transform(&self, camera_transform_mat: Matrix4, projection: Projection) {
+    let result = self.sender.send(RendererCommand::UpdateCameraTransform { camera_transform_mat, projection });
     if result.is_err() {
       panic!("Sending message to render thread failed");
     }
@@ -1,10 +1,10 @@
 use std::sync::Arc;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 
 use sourcerenderer_core::graphics::{Backend, BufferInfo, Device, Fence, TextureUsage};
 use crate::{asset::{Asset, AssetManager, Material, Mesh, Model, Texture, AssetLoadPriority, MeshRange}, math::BoundingBox};
 use sourcerenderer_core::Platform;
-use sourcerenderer_core::graphics::{ TextureInfo, MemoryUsage, SampleCount, Format, TextureShaderResourceViewInfo, BufferUsage };
+use sourcerenderer_core::graphics::{ TextureDimension, TextureInfo, MemoryUsage, SampleCount, Format, TextureShaderResourceViewInfo, BufferUsage };
 
 use sourcerenderer_core::atomic_refcell::AtomicRefCell;
 
@@ -38,7 +38,35 @@ impl<B: Backend> Ord for RendererMaterial<B> {
 
 pub(super) struct RendererModel<B: Backend> {
   pub(super) mesh: Arc<RendererMesh<B>>,
-  pub(super) materials: Box<[Arc<RendererMaterial<B>>]>
+  pub(super) materials: Box<[Arc<RendererMaterial<B>>]>,
+  pub(super) lods: Box<[RendererModelLod<B>]>
+}
+
+pub(super) struct RendererModelLod<B: Backend> {
+  pub(super) mesh: Arc<RendererMesh<B>>,
+  pub(super) screen_size: f32
+}
+
+impl<B: Backend> RendererModel<B> {
+  /// LOD 0 is the full detail mesh
+  pub(super) fn lod_mesh(&self, lod: usize) -> &Arc<RendererMesh<B>> {
+    if lod == 0 {
+      &self.mesh
+    } else {
+      &self.lods[lod - 1].mesh
+    }
+  }
+
+  pub(super) fn select_lod(&self, screen_size: f32) -> usize {
+    lod_for_screen_size(self.lods.iter().map(|lod| lod.screen_size), screen_size)
+  }
+}
+
+/// Picks the coarsest LOD whose threshold is above the projected size of the model.
+pub(super) fn lod_for_screen_size<I: DoubleEndedIterator<Item = f32> + ExactSizeIterator>(mut thresholds: I, screen_size: f32) -> usize {
+  thresholds
+    .rposition(|threshold| screen_size < threshold)
+    .map_or(0, |index| index + 1)
 }
 
 pub(super) struct RendererMesh<B: Backend> {
@@ -65,7 +93,62 @@ pub(super) struct RendererAssets<P: Platform> {
   materials: HashMap<String, Arc<RendererMaterial<P::GraphicsBackend>>>,
   textures: HashMap<String, Arc<RendererTexture<P::GraphicsBackend>>>,
   zero_view: Arc<<P::GraphicsBackend as Backend>::TextureShaderResourceView>,
-  delayed_assets: Vec<DelayedAsset<P::GraphicsBackend>>
+  delayed_assets: Vec<DelayedAsset<P::GraphicsBackend>>,
+  placeholder_textures: HashSet<String>,
+  texture_budget: TextureBudget,
+  /// The models and materials as the loaders described them, used to release their dependencies again
+  model_assets: HashMap<String, Model>,
+  material_texture_paths: HashMap<String, Vec<String>>
+}
+
+#[derive(Default)]
+struct TextureBudget {
+  sizes: HashMap<String, u64>,
+  memory: u64,
+  /// Textures that are no longer referenced, least recently used first
+  unreferenced: VecDeque<String>
+}
+
+impl TextureBudget {
+  fn add(&mut self, path: &str, size: u64) {
+    if let Some(old_size) = self.sizes.insert(path.to_string(), size) {
+      self.memory -= old_size;
+    }
+    self.memory += size;
+    self.unreferenced.retain(|texture_path| texture_path != path);
+  }
+
+  fn contains(&self, path: &str) -> bool {
+    self.sizes.contains_key(path)
+  }
+
+  fn mark_unreferenced(&mut self, path: &str) {
+    if !self.contains(path) {
+      return;
+    }
+    self.unreferenced.retain(|texture_path| texture_path != path);
+    self.unreferenced.push_back(path.to_string());
+  }
+
+  /// Returns the textures that need to be dropped to get back under the budget.
+  fn evict<F: Fn(&str) -> bool>(&mut self, memory_budget: u64, can_evict: F) -> Vec<String> {
+    let mut evicted = Vec::<String>::new();
+    while self.memory > memory_budget {
+      let path = if let Some(path) = self.unreferenced.pop_front() {
+        path
+      } else {
+        break;
+      };
+      if !can_evict(&path) {
+        continue;
+      }
+      if let Some(size) = self.sizes.remove(&path) {
+        self.memory -= size;
+        evicted.push(path);
+      }
+    }
+    evicted
+  }
 }
 
 impl<P: Platform> RendererAssets<P> {
@@ -73,6 +156,7 @@ impl<P: Platform> RendererAssets<P> {
     let zero_data = [255u8; 16];
     let zero_buffer = device.upload_data(&zero_data, MemoryUsage::CpuOnly, BufferUsage::COPY_SRC);
     let zero_texture = device.create_texture(&TextureInfo {
+      dimension: TextureDimension::Dim2D,
       format: Format::RGBA8,
       width: 2,
       height: 2,
@@ -80,7 +164,8 @@ impl<P: Platform> RendererAssets<P> {
       mip_levels: 1,
       array_length: 1,
       samples: SampleCount::Samples1,
-      usage: TextureUsage::VERTEX_SHADER_SAMPLED | TextureUsage::FRAGMENT_SHADER_SAMPLED | TextureUsage::COMPUTE_SHADER_SAMPLED | TextureUsage::COPY_DST
+      usage: TextureUsage::VERTEX_SHADER_SAMPLED | TextureUsage::FRAGMENT_SHADER_SAMPLED | TextureUsage::COMPUTE_SHADER_SAMPLED | TextureUsage::COPY_DST,
+      is_cubemap: false
     }, Some("AssetManagerZeroTexture"));
     device.init_texture(&zero_texture, &zero_buffer, 0, 0);
     let zero_view = device.create_shader_resource_view(&zero_texture, &TextureShaderResourceViewInfo {
@@ -98,7 +183,11 @@ impl<P: Platform> RendererAssets<P> {
       materials: HashMap::new(),
       textures: HashMap::new(),
       zero_view,
-      delayed_assets: Vec::new()
+      delayed_assets: Vec::new(),
+      placeholder_textures: HashSet::new(),
+      texture_budget: TextureBudget::default(),
+      model_assets: HashMap::new(),
+      material_texture_paths: HashMap::new()
     }
   }
 
@@ -148,17 +237,28 @@ impl<P: Platform> RendererAssets<P> {
 
   pub fn upload_texture(&mut self, texture_path: &str, texture: Texture, do_async: bool) -> (Arc<<P::GraphicsBackend as Backend>::TextureShaderResourceView>, Option<Arc<<P::GraphicsBackend as Backend>::Fence>>) {
     let gpu_texture = self.device.create_texture(&texture.info, Some(texture_path));
+    let size = texture.data.iter().map(|data| data.len() as u64).sum::<u64>();
+    self.texture_budget.add(texture_path, size);
     let subresources = texture.info.array_length * texture.info.mip_levels;
     let mut fence = Option::<Arc<<P::GraphicsBackend as Backend>::Fence>>::None;
-    for subresource in 0..subresources {
-      let mip_level = subresource % texture.info.mip_levels;
-      let array_index = subresource / texture.info.array_length;
-      let init_buffer = self.device.upload_data(
-        &texture.data[subresource as usize][..], MemoryUsage::CpuToGpu, BufferUsage::COPY_SRC);
-      if do_async {
+    if do_async {
+      // The data holds all mips of a layer before the next layer starts, so the layer index has to be divided by the mip count.
+      // This used to divide by the layer count which uploaded mips into the wrong layers.
+      for subresource in 0..subresources {
+        let mip_level = subresource % texture.info.mip_levels;
+        let array_index = subresource / texture.info.mip_levels;
+        let init_buffer = self.device.upload_data(
+          &texture.data[subresource as usize][..], MemoryUsage::CpuToGpu, BufferUsage::COPY_SRC);
         fence = self.device.init_texture_async(&gpu_texture, &init_buffer, mip_level, array_index);
-      } else {
-        self.device.init_texture(&gpu_texture, &init_buffer, mip_level, array_index);
+      }
+    } else {
+      for array_index in 0..texture.info.array_length {
+        let first_subresource = (array_index * texture.info.mip_levels) as usize;
+        let init_buffers: Vec<Arc<<P::GraphicsBackend as Backend>::Buffer>> = texture.data[first_subresource .. first_subresource + texture.info.mip_levels as usize]
+          .iter()
+          .map(|data| self.device.upload_data(&data[..], MemoryUsage::CpuToGpu, BufferUsage::COPY_SRC))
+          .collect();
+        self.device.init_texture_mips(&gpu_texture, &init_buffers, array_index);
       }
     }
     let view = self.device.create_shader_resource_view(
@@ -166,7 +266,8 @@ impl<P: Platform> RendererAssets<P> {
           base_mip_level: 0,
           mip_level_length: texture.info.mip_levels,
           base_array_level: 0,
-          array_level_length: texture.info.array_length
+          // Materials can't pick a frame yet, so animated textures only show their first one
+          array_level_length: texture.info.array_length / texture.frame_count.max(1)
     });
 
     (view, fence)
@@ -199,21 +300,32 @@ impl<P: Platform> RendererAssets<P> {
     for material in &model.material_paths {
       let renderer_material = self.materials.get(material).cloned()
         .or_else(|| {
-        Some(self.integrate_material(material, &Material {
-          albedo_texture_path: "NULL".to_string()
-        }))
+        Some(self.integrate_material(material, &Material::new("NULL")))
       }).unwrap();
       renderer_materials.push(renderer_material);
     }
 
+    let mut lods = Vec::<RendererModelLod<P::GraphicsBackend>>::with_capacity(model.lods.len());
+    for lod in &model.lods {
+      lods.push(RendererModelLod {
+        mesh: self.meshes.get(&lod.mesh_path).cloned()?,
+        screen_size: lod.screen_size
+      });
+    }
+
     let renderer_model = Arc::new(RendererModel {
       materials: renderer_materials.into_boxed_slice(),
-      mesh
+      mesh,
+      lods: lods.into_boxed_slice()
     });
     self.models.insert(model_path.to_owned(), renderer_model.clone());
     Some(renderer_model)
   }
 
+  pub fn has_model(&self, model_path: &str) -> bool {
+    self.models.contains_key(model_path)
+  }
+
   pub fn get_model(&self, model_path: &str) -> Arc<RendererModel<P::GraphicsBackend>> {
     self.models.get(model_path)
       .cloned()
@@ -235,9 +347,79 @@ impl<P: Platform> RendererAssets<P> {
       view: AtomicRefCell::new(self.zero_view.clone())
     });
     self.textures.insert(texture_path.to_string(), texture.clone());
+    self.placeholder_textures.insert(texture_path.to_string());
     texture
   }
 
+  /// Drops a model and its skins once nothing references it anymore and releases the materials it used.
+  fn remove_model(&mut self, model_path: &str, asset_manager: &AssetManager<P>) {
+    let mut material_paths = Vec::<String>::new();
+    let mut skin = 0u32;
+    let mut path = Model::skin_path(model_path, skin);
+    while let Some(model) = self.model_assets.remove(&path) {
+      self.models.remove(&path);
+      self.meshes.remove(&model.mesh_path);
+      for lod in &model.lods {
+        self.meshes.remove(&lod.mesh_path);
+      }
+      for material_path in model.material_paths {
+        if !material_paths.contains(&material_path) {
+          material_paths.push(material_path);
+        }
+      }
+      asset_manager.notify_unloaded(&path);
+      skin += 1;
+      path = Model::skin_path(model_path, skin);
+    }
+
+    for material_path in &material_paths {
+      asset_manager.release(material_path);
+    }
+  }
+
+  /// Drops a material once nothing references it anymore and releases its textures.
+  fn remove_material(&mut self, material_path: &str, asset_manager: &AssetManager<P>) {
+    let texture_paths = if let Some(texture_paths) = self.material_texture_paths.remove(material_path) {
+      texture_paths
+    } else {
+      return;
+    };
+    // Models that are still alive keep the RendererMaterial around.
+    self.materials.remove(material_path);
+    asset_manager.notify_unloaded(material_path);
+    for texture_path in &texture_paths {
+      asset_manager.release(texture_path);
+    }
+  }
+
+  fn evict_assets(&mut self, asset_manager: &AssetManager<P>) {
+    while let Some(path) = asset_manager.receive_released_asset() {
+      if asset_manager.is_referenced(&path) {
+        // Got requested again in the meantime
+        continue;
+      }
+      if self.model_assets.contains_key(&path) {
+        self.remove_model(&path, asset_manager);
+      } else if self.material_texture_paths.contains_key(&path) {
+        self.remove_material(&path, asset_manager);
+      } else {
+        self.texture_budget.mark_unreferenced(&path);
+      }
+    }
+
+    let placeholder_textures = &self.placeholder_textures;
+    let evicted = self.texture_budget.evict(asset_manager.memory_budget(), |path| {
+      !asset_manager.is_referenced(path) && !placeholder_textures.contains(path)
+    });
+    for path in evicted {
+      // Materials keep pointing at the RendererTexture, so only swap out the view to drop the GPU texture.
+      if let Some(texture) = self.textures.get(&path) {
+        *texture.view.borrow_mut() = self.zero_view.clone();
+      }
+      asset_manager.notify_unloaded(&path);
+    }
+  }
+
   pub(super) fn receive_assets(&mut self, asset_manager: &AssetManager<P>) {
     let mut retained_delayed_assets = Vec::<DelayedAsset<P::GraphicsBackend>>::new();
     let mut ready_delayed_assets = Vec::<DelayedAsset<P::GraphicsBackend>>::new();
@@ -253,6 +435,10 @@ impl<P: Platform> RendererAssets<P> {
     for delayed_asset in ready_delayed_assets.drain(..) {
       match &delayed_asset.asset {
         DelayedAssetType::TextureView(view) => {
+          if !self.texture_budget.contains(&delayed_asset.path) {
+            // Got evicted while it was still uploading
+            continue;
+          }
           self.integrate_texture(&delayed_asset.path, view);
         }
       }
@@ -262,8 +448,15 @@ impl<P: Platform> RendererAssets<P> {
     while asset_opt.is_some() {
       let asset = asset_opt.unwrap();
       match asset.asset {
-        Asset::Material(material) => { self.integrate_material(&asset.path, &material); }
-        Asset::Model(model) => { self.integrate_model(&asset.path, &model); }
+        Asset::Material(material) => {
+          self.integrate_material(&asset.path, &material);
+          self.material_texture_paths.insert(asset.path.clone(), material_texture_paths(&material));
+        }
+        Asset::Model(model) => {
+          if self.integrate_model(&asset.path, &model).is_some() {
+            self.model_assets.insert(asset.path.clone(), model);
+          }
+        }
         Asset::Mesh(mesh) => { self.integrate_mesh(&asset.path, mesh); }
         Asset::Texture(texture) => {
           let do_async = asset.priority == AssetLoadPriority::Low;
@@ -283,7 +476,66 @@ impl<P: Platform> RendererAssets<P> {
       asset_opt = asset_manager.receive_render_asset();
     }
 
+    self.evict_assets(asset_manager);
+
     // Make sure the work initializing the resources actually gets submitted
     self.device.flush_transfers();
   }
 }
+
+/// The textures that the material loader requested for the material.
+fn material_texture_paths(material: &Material) -> Vec<String> {
+  let mut paths = vec![material.albedo_texture_path.clone()];
+  paths.extend(material.normal_texture.iter().chain(material.detail_texture.iter()).map(|texture| texture.path.clone()));
+  paths
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::asset::MaterialTexture;
+
+  #[test]
+  fn exceeding_the_budget_evicts_the_least_recently_released_texture() {
+    let mut budget = TextureBudget::default();
+    budget.add("materials/a.vtf", 100);
+    budget.add("materials/b.vtf", 100);
+    budget.add("materials/c.vtf", 100);
+
+    budget.mark_unreferenced("materials/b.vtf");
+    budget.mark_unreferenced("materials/a.vtf");
+    assert!(budget.evict(300, |_| true).is_empty());
+
+    let evicted = budget.evict(250, |_| true);
+    assert_eq!(evicted, vec!["materials/b.vtf".to_string()]);
+    assert!(!budget.contains("materials/b.vtf"));
+    assert!(budget.contains("materials/a.vtf"));
+    assert_eq!(budget.memory, 200);
+  }
+
+  #[test]
+  fn referenced_textures_are_not_evicted() {
+    let mut budget = TextureBudget::default();
+    budget.add("materials/a.vtf", 100);
+    budget.add("materials/b.vtf", 100);
+    budget.mark_unreferenced("materials/a.vtf");
+    budget.mark_unreferenced("materials/b.vtf");
+
+    let evicted = budget.evict(0, |path| path != "materials/a.vtf");
+    assert_eq!(evicted, vec!["materials/b.vtf".to_string()]);
+    assert_eq!(budget.memory, 100);
+
+    // Uploading it again takes it off the eviction list
+    budget.add("materials/b.vtf", 50);
+    budget.mark_unreferenced("materials/c.vtf");
+    assert!(budget.evict(0, |_| true).is_empty());
+    assert_eq!(budget.memory, 150);
+  }
+
+  #[test]
+  fn material_releases_every_texture_it_requested() {
+    let mut material = Material::new("materials/albedo.vtf");
+    material.normal_texture = Some(MaterialTexture { path: "materials/normal.vtf".to_string(), tex_coord: 0 });
+    assert_eq!(material_texture_paths(&material), vec!["materials/albedo.vtf".to_string(), "materials/normal.vtf".to_string()]);
+  }
+}
@@ -3,8 +3,9 @@ use crate::renderer::{Renderer, RendererStaticDrawable};
 use crossbeam_channel::{Receiver, Sender};
 use crate::renderer::command::RendererCommand;
 use std::time::{SystemTime, Duration};
-use crate::asset::AssetManager;
-use sourcerenderer_core::{Platform, Vec4};
+use crate::asset::{AssetManager, Model};
+use crate::Projection;
+use sourcerenderer_core::{Matrix4, Platform, Vec3, Vec4};
 use sourcerenderer_core::graphics::{SwapchainError, Backend,Swapchain, Device};
 use crate::renderer::View;
 use sourcerenderer_core::platform::WindowState;
@@ -14,11 +15,11 @@ use crate::renderer::drawable::DrawablePart;
 use crate::renderer::renderer_assets::*;
 use sourcerenderer_core::atomic_refcell::AtomicRefCell;
 use rayon::prelude::*;
-use crate::math::Frustum;
+use crate::math::{BoundingBox, Frustum};
 
-use super::PointLight;
+use super::{DirectionalLight, PointLight, SpotLight};
 use super::passes::desktop::desktop_renderer::DesktopRenderer;
-use super::render_path::RenderPath;
+use super::render_path::{RenderPath, RendererSettings};
 use super::renderer_scene::RendererScene;
 
 pub(super) struct RendererInternal<P: Platform> {
@@ -33,6 +34,9 @@ pub(super) struct RendererInternal<P: Platform> {
   sender: Sender<RendererCommand>,
   receiver: Receiver<RendererCommand>,
   last_tick: SystemTime,
+  last_frame: SystemTime,
+  camera_target_position: Vec3,
+  camera_smoothing: f32,
   primary_camera: Arc<LateLatchCamera<P::GraphicsBackend>>,
   assets: RendererAssets<P>
 }
@@ -45,7 +49,8 @@ impl<P: Platform> RendererInternal<P> {
     asset_manager: &Arc<AssetManager<P>>,
     sender: Sender<RendererCommand>,
     receiver: Receiver<RendererCommand>,
-    primary_camera: &Arc<LateLatchCamera<P::GraphicsBackend>>) -> Self {
+    primary_camera: &Arc<LateLatchCamera<P::GraphicsBackend>>,
+    settings: &RendererSettings) -> Self {
 
     let mut assets = RendererAssets::new(device);
     let lightmap = assets.insert_placeholder_texture("lightmap");
@@ -53,7 +58,7 @@ impl<P: Platform> RendererInternal<P> {
     let scene = Arc::new(AtomicRefCell::new(RendererScene::new()));
     let view = Arc::new(AtomicRefCell::new(View::default()));
 
-    let path = Box::new(DesktopRenderer::new::<P>(device, swapchain));
+    let path = Box::new(DesktopRenderer::new::<P>(device, swapchain, settings.anti_aliasing, settings.supported_msaa(device.max_sample_count())));
 
     Self {
       renderer: renderer.clone(),
@@ -66,6 +71,9 @@ impl<P: Platform> RendererInternal<P> {
       sender,
       receiver,
       last_tick: SystemTime::now(),
+      last_frame: SystemTime::now(),
+      camera_target_position: Vec3::new(0f32, 0f32, 0f32),
+      camera_smoothing: 0f32,
       primary_camera: primary_camera.clone(),
       assets,
       lightmap
@@ -90,15 +98,32 @@ impl<P: Platform> RendererInternal<P> {
           break;
         }
 
-        RendererCommand::UpdateCameraTransform { camera_transform_mat, fov } => {
+        RendererCommand::SetTonemapping { operator, exposure } => {
+          self.render_path.set_tonemapping_operator(operator);
+          self.render_path.set_exposure(exposure);
+        }
+
+        RendererCommand::SetDebugView(debug_view) => {
+          self.render_path.set_debug_view(debug_view);
+        }
+
+        RendererCommand::SetIndirectDraws(indirect_draws) => {
+          self.render_path.set_indirect_draws(indirect_draws);
+        }
+
+        RendererCommand::RequestScreenshot(path) => {
+          self.render_path.request_screenshot(path);
+        }
+
+        RendererCommand::UpdateCameraTransform { camera_transform_mat, projection } => {
           view.camera_transform = camera_transform_mat;
-          view.camera_fov = fov;
+          view.camera_projection = projection;
+          self.primary_camera.update_projection_mode(projection);
+          self.camera_target_position = camera_transform_mat.column(3).xyz();
+        }
 
-          view.old_camera_matrix = view.proj_matrix * view.view_matrix;
-          let position = camera_transform_mat.column(3).xyz();
-          self.primary_camera.update_position(position);
-          view.view_matrix = self.primary_camera.view();
-          view.proj_matrix = self.primary_camera.proj();
+        RendererCommand::SetCameraSmoothing(smoothing) => {
+          self.camera_smoothing = smoothing;
         }
 
         RendererCommand::UpdateTransform { entity, transform_mat } => {
@@ -106,11 +131,21 @@ impl<P: Platform> RendererInternal<P> {
         }
 
         RendererCommand::RegisterStatic {
-          model_path, entity, transform, receive_shadows, cast_shadows, can_move
+          model_path, skin, entity, transform, receive_shadows, cast_shadows, can_move
          } => {
+          let asset_path = model_path;
+          let skin_path = Model::skin_path(&asset_path, skin);
+          let model_path = if self.assets.has_model(&skin_path) {
+            skin_path
+          } else {
+            println!("Model {:?} has no skin {}, falling back to the default one", &asset_path, skin);
+            asset_path.clone()
+          };
           let model = self.assets.get_model(&model_path);
           scene.add_static_drawable(entity, RendererStaticDrawable::<P::GraphicsBackend> {
             entity,
+            asset_path,
+            model_path,
             transform,
             old_transform: transform,
             model,
@@ -121,7 +156,10 @@ impl<P: Platform> RendererInternal<P> {
         }
 
         RendererCommand::UnregisterStatic(entity) => {
-          scene.remove_static_drawable(&entity);
+          if let Some(drawable) = scene.remove_static_drawable(&entity) {
+            // Balances the request that the level made for every renderable
+            self.asset_manager.release(&drawable.asset_path);
+          }
         }
         RendererCommand::RegisterPointLight {
           entity,
@@ -136,6 +174,37 @@ impl<P: Platform> RendererInternal<P> {
         RendererCommand::UnregisterPointLight(entity) => {
           scene.remove_point_light(&entity);
         },
+        RendererCommand::RegisterDirectionalLight {
+          entity,
+          transform,
+          color,
+          intensity
+        } => {
+          scene.add_directional_light(entity, DirectionalLight {
+            direction: (transform * Vec4::new(0f32, 0f32, -1f32, 0f32)).xyz().normalize(),
+            color,
+            intensity
+          });
+        },
+        RendererCommand::UnregisterDirectionalLight(entity) => {
+          scene.remove_directional_light(&entity);
+        },
+        RendererCommand::RegisterSpotLight {
+          entity,
+          transform,
+          intensity,
+          angle
+        } => {
+          scene.add_spot_light(entity, SpotLight {
+            position: (transform * Vec4::new(0f32, 0f32, 0f32, 1f32)).xyz(),
+            intensity,
+            direction: (transform * Vec4::new(0f32, 0f32, -1f32, 0f32)).xyz().normalize(),
+            angle
+          });
+        },
+        RendererCommand::UnregisterSpotLight(entity) => {
+          scene.remove_spot_light(&entity);
+        },
       }
 
       let message_res = self.receiver.recv();
@@ -175,6 +244,7 @@ impl<P: Platform> RendererInternal<P> {
 
     self.assets.receive_assets(&self.asset_manager);
     self.receive_messages();
+    self.update_camera();
     self.update_visibility();
     self.reorder();
 
@@ -206,12 +276,31 @@ impl<P: Platform> RendererInternal<P> {
         new_swapchain_result.unwrap()
       };
       self.render_path.on_swapchain_changed(&new_swapchain);
-      self.render_path.render(&self.scene, &self.view, &self.lightmap, &self.primary_camera).expect("Rendering still fails after recreating swapchain.");
       self.swapchain = new_swapchain;
+      // The new swapchain can be out of date right away while the window is still being resized or minimized.
+      // Drop the frame in that case, the next one recreates the swapchain again.
+      if let Err(swapchain_error) = self.render_path.render(&self.scene, &self.view, &self.lightmap, &self.primary_camera) {
+        println!("Rendering failed after recreating the swapchain: {:?}", swapchain_error);
+      }
     }
     self.renderer.dec_queued_frames_counter();
   }
 
+  /// Moves the camera towards the position of the last tick.
+  fn update_camera(&mut self) {
+    let now = SystemTime::now();
+    let delta = now.duration_since(self.last_frame).unwrap_or_default().as_secs_f32();
+    self.last_frame = now;
+
+    let position = smooth_camera_position(&self.primary_camera.position(), &self.camera_target_position, self.camera_smoothing, delta);
+
+    let mut view = self.view.borrow_mut();
+    view.old_camera_matrix = view.proj_matrix * view.view_matrix;
+    self.primary_camera.update_position(position);
+    view.view_matrix = self.primary_camera.view();
+    view.proj_matrix = self.primary_camera.proj();
+  }
+
   fn update_visibility(&mut self) {
     let scene = self.scene.borrow();
     let static_meshes = scene.static_drawables();
@@ -223,8 +312,13 @@ impl<P: Platform> RendererInternal<P> {
     existing_parts.clear();
     let visible_parts = Mutex::new(existing_parts);
 
-    let frustum = Frustum::new(self.primary_camera.z_near(), self.primary_camera.z_far(), self.primary_camera.fov(), self.primary_camera.aspect_ratio());
+    let projection = self.primary_camera.projection();
+    let frustum = match projection {
+      Projection::Perspective { fov } => Frustum::new(self.primary_camera.z_near(), self.primary_camera.z_far(), fov, self.primary_camera.aspect_ratio()),
+      Projection::Orthographic { height } => Frustum::new_orthographic(self.primary_camera.z_near(), self.primary_camera.z_far(), height, self.primary_camera.aspect_ratio())
+    };
     let camera_matrix = self.primary_camera.view();
+    let occlusion_buffer = self.render_path.occlusion_buffer();
     const CHUNK_SIZE: usize = 64;
     static_meshes.par_chunks(CHUNK_SIZE).enumerate().for_each(|(chunk_index, chunk)| {
       let mut chunk_visible_parts = SmallVec::<[DrawablePart; 64]>::new();
@@ -237,8 +331,12 @@ impl<P: Platform> RendererInternal<P> {
           if !is_visible {
             continue;
           }
+          if occlusion_buffer.map_or(false, |occlusion_buffer| occlusion_buffer.is_occluded(bounding_box, &static_mesh.transform)) {
+            continue;
+          }
           let drawable_index = chunk_index * CHUNK_SIZE + index;
-          for part_index in 0..model.mesh.parts.len() {
+          let lod = model.select_lod(projected_screen_size(bounding_box, &model_view_matrix, &projection, self.primary_camera.aspect_ratio()));
+          for part_index in 0..model.lod_mesh(lod).parts.len() {
             if chunk_visible_parts.len() == chunk_visible_parts.capacity() {
               let mut global_parts = visible_parts.lock().unwrap();
               global_parts.extend_from_slice(&chunk_visible_parts[..]);
@@ -247,7 +345,8 @@ impl<P: Platform> RendererInternal<P> {
 
             chunk_visible_parts.push(DrawablePart {
               drawable_index,
-              part_index
+              part_index,
+              lod
             });
           }
         }
@@ -266,24 +365,9 @@ impl<P: Platform> RendererInternal<P> {
     let static_meshes = scene.static_drawables();
 
     let mut view_mut = self.view.borrow_mut();
-    view_mut.drawable_parts.sort_by(|a, b| {
-      // if the drawable index is greater than the amount of static meshes, it is a skinned mesh
-      let b_is_skinned = a.drawable_index > static_meshes.len();
-      let a_is_skinned = a.drawable_index > static_meshes.len();
-      return if b_is_skinned && a_is_skinned {
-        unimplemented!()
-      } else if b_is_skinned {
-        std::cmp::Ordering::Less
-      } else if a_is_skinned {
-        std::cmp::Ordering::Greater
-      } else {
-        let static_mesh_a = &static_meshes[a.drawable_index];
-        let static_mesh_b = &static_meshes[b.drawable_index];
-        let material_a = &static_mesh_a.model.materials[a.part_index];
-        let material_b = &static_mesh_b.model.materials[b.part_index];
-        material_a.cmp(material_b)
-      }
-    });
+    // if the drawable index is outside of the static meshes, it is a skinned mesh
+    let material = |part: &DrawablePart| static_meshes.get(part.drawable_index).map(|static_mesh| &static_mesh.model.materials[part.part_index]);
+    view_mut.drawable_parts.sort_by(|a, b| compare_drawable_parts(a, b, &material));
   }
 }
 
@@ -292,3 +376,101 @@ impl<P: Platform> Drop for RendererInternal<P> {
     self.renderer.stop();
   }
 }
+
+/// The smoothing is the time in seconds it takes to cover ~63% of the distance, 0 snaps to the target.
+fn smooth_camera_position(current_position: &Vec3, target_position: &Vec3, smoothing: f32, delta: f32) -> Vec3 {
+  let factor = if smoothing > 0f32 {
+    1f32 - (-delta / smoothing).exp()
+  } else {
+    1f32
+  };
+  current_position + (target_position - current_position) * factor
+}
+
+/// Groups static parts by material and puts skinned parts, which have no material here, last.
+fn compare_drawable_parts<M: Ord, F: Fn(&DrawablePart) -> Option<M>>(a: &DrawablePart, b: &DrawablePart, material: F) -> std::cmp::Ordering {
+  let order = match (material(a), material(b)) {
+    (Some(material_a), Some(material_b)) => material_a.cmp(&material_b),
+    (Some(_), None) => std::cmp::Ordering::Less,
+    (None, Some(_)) => std::cmp::Ordering::Greater,
+    (None, None) => std::cmp::Ordering::Equal
+  };
+  // Fall back to the indices so the order is total
+  order
+    .then(a.drawable_index.cmp(&b.drawable_index))
+    .then(a.part_index.cmp(&b.part_index))
+}
+
+/// Approximates the height of the bounding box on screen as a fraction of the screen height.
+fn projected_screen_size(bounding_box: &BoundingBox, model_view_matrix: &Matrix4, projection: &Projection, aspect_ratio: f32) -> f32 {
+  let center = (bounding_box.min + bounding_box.max) * 0.5f32;
+  let view_center = model_view_matrix * Vec4::new(center.x, center.y, center.z, 1f32);
+  let scale = model_view_matrix.column(0).xyz().norm()
+    .max(model_view_matrix.column(1).xyz().norm())
+    .max(model_view_matrix.column(2).xyz().norm());
+  let radius = (bounding_box.max - bounding_box.min).norm() * 0.5f32 * scale;
+  let tan_half_fov_y = match projection {
+    Projection::Perspective { fov } => (fov * 0.5f32).tan() / aspect_ratio,
+    // The size doesn't depend on the distance
+    Projection::Orthographic { height } => return (2f32 * radius / height).min(1f32)
+  };
+  let distance = view_center.xyz().norm();
+  if distance <= radius {
+    return 1f32;
+  }
+  radius / (distance * tan_half_fov_y)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn camera_snaps_without_smoothing() {
+    let target = Vec3::new(10f32, 0f32, 0f32);
+    let position = smooth_camera_position(&Vec3::new(0f32, 0f32, 0f32), &target, 0f32, 1f32 / 60f32);
+    assert_eq!(position, target);
+  }
+
+  #[test]
+  fn smoothed_camera_moves_part_of_the_way() {
+    let start = Vec3::new(0f32, 0f32, 0f32);
+    let target = Vec3::new(10f32, 0f32, 0f32);
+    let position = smooth_camera_position(&start, &target, 0.1f32, 1f32 / 60f32);
+    assert!(position.x > 0f32 && position.x < 10f32);
+    assert!((position.x - 10f32 * (1f32 - (-1f32 / 6f32).exp())).abs() < 0.0001f32);
+
+    // After the smoothing time, roughly 63% of the distance is covered
+    let position = smooth_camera_position(&start, &target, 0.1f32, 0.1f32);
+    assert!((position.x - 6.32f32).abs() < 0.01f32);
+  }
+
+  #[test]
+  fn twice_the_distance_picks_a_coarser_lod() {
+    let bounding_box = BoundingBox::new(Vec3::new(-0.5f32, -0.5f32, -0.5f32), Vec3::new(0.5f32, 0.5f32, 0.5f32));
+    let projection = Projection::Perspective { fov: std::f32::consts::FRAC_PI_2 };
+    let thresholds = [0.15f32, 0.05f32];
+
+    let near_size = projected_screen_size(&bounding_box, &Matrix4::new_translation(&Vec3::new(0f32, 0f32, -5f32)), &projection, 1f32);
+    let far_size = projected_screen_size(&bounding_box, &Matrix4::new_translation(&Vec3::new(0f32, 0f32, -10f32)), &projection, 1f32);
+    assert!((near_size - 2f32 * far_size).abs() < 0.0001f32);
+
+    let near_lod = lod_for_screen_size(thresholds.iter().copied(), near_size);
+    let far_lod = lod_for_screen_size(thresholds.iter().copied(), far_size);
+    assert_eq!(near_lod, 0);
+    assert!(far_lod > near_lod);
+  }
+
+  #[test]
+  fn drawable_parts_are_grouped_by_material() {
+    // Drawable 3 is skinned
+    let materials = [vec!["b", "a"], vec!["a"], vec!["b", "b"]];
+    let material = |part: &DrawablePart| materials.get(part.drawable_index).map(|parts| parts[part.part_index]);
+    let part = |drawable_index: usize, part_index: usize| DrawablePart { drawable_index, part_index, lod: 0 };
+    let mut parts = vec![part(3, 0), part(2, 1), part(0, 0), part(1, 0), part(2, 0), part(3, 1), part(0, 1)];
+    parts.sort_by(|a, b| compare_drawable_parts(a, b, &material));
+
+    let order: Vec<(usize, usize)> = parts.iter().map(|part| (part.drawable_index, part.part_index)).collect();
+    assert_eq!(order, vec![(0, 1), (1, 0), (0, 0), (2, 0), (2, 1), (3, 0), (3, 1)]);
+  }
+}
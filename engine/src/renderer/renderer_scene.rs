@@ -3,13 +3,18 @@ use std::{collections::HashMap, usize};
 use legion::Entity;
 use sourcerenderer_core::{Matrix4, Vec4, graphics::Backend};
 
-use super::{PointLight, RendererStaticDrawable};
+use super::{DirectionalLight, PointLight, RendererStaticDrawable, SpotLight};
 
 pub struct RendererScene<B: Backend> {
   static_meshes: Vec<RendererStaticDrawable<B>>,
   point_lights: Vec<PointLight>,
+  directional_lights: Vec<DirectionalLight>,
+  spot_lights: Vec<SpotLight>,
   drawable_entity_map: HashMap<Entity, usize>,
-  light_entity_map: HashMap<Entity, usize>
+  model_instance_counts: HashMap<String, usize>,
+  light_entity_map: HashMap<Entity, usize>,
+  directional_light_entity_map: HashMap<Entity, usize>,
+  spot_light_entity_map: HashMap<Entity, usize>
 }
 
 impl<B: Backend> RendererScene<B> {
@@ -17,8 +22,13 @@ impl<B: Backend> RendererScene<B> {
     Self {
       static_meshes: Vec::new(),
       point_lights: Vec::new(),
+      directional_lights: Vec::new(),
+      spot_lights: Vec::new(),
       drawable_entity_map: HashMap::new(),
-      light_entity_map: HashMap::new()
+      model_instance_counts: HashMap::new(),
+      light_entity_map: HashMap::new(),
+      directional_light_entity_map: HashMap::new(),
+      spot_light_entity_map: HashMap::new()
     }
   }
 
@@ -30,19 +40,42 @@ impl<B: Backend> RendererScene<B> {
     &self.point_lights
   }
 
+  pub(super) fn directional_lights(&self) -> &[DirectionalLight] {
+    &self.directional_lights
+  }
+
+  pub(super) fn spot_lights(&self) -> &[SpotLight] {
+    &self.spot_lights
+  }
+
   pub(super) fn add_static_drawable(&mut self, entity: Entity, static_drawable: RendererStaticDrawable<B>) {
     self.drawable_entity_map.insert(entity, self.static_meshes.len());
+    *self.model_instance_counts.entry(static_drawable.model_path.clone()).or_insert(0) += 1;
     self.static_meshes.push(static_drawable);
   }
 
-  pub(super) fn remove_static_drawable(&mut self, entity: &Entity) {
+  /// Whether the drawable shares its model with other drawables and can be drawn using instancing
+  pub(super) fn is_instanced(&self, drawable_index: usize) -> bool {
+    let drawable = &self.static_meshes[drawable_index];
+    drawable.model.mesh.indices.is_some()
+      && self.model_instance_counts.get(&drawable.model_path).map_or(false, |count| *count > 1)
+  }
+
+  pub(super) fn remove_static_drawable(&mut self, entity: &Entity) -> Option<RendererStaticDrawable<B>> {
     let index = self.drawable_entity_map.get(&entity);
     debug_assert!(index.is_some());
     if index.is_none() {
-      return;
+      return None;
     }
     let index = *index.unwrap();
-    self.static_meshes.remove(index);
+    let model_path = &self.static_meshes[index].model_path;
+    if let Some(count) = self.model_instance_counts.get_mut(model_path) {
+      *count -= 1;
+      if *count == 0 {
+        self.model_instance_counts.remove(model_path);
+      }
+    }
+    Some(self.static_meshes.remove(index))
   }
 
   pub(super) fn update_transform(&mut self, entity: &Entity, transform: Matrix4) {
@@ -60,6 +93,21 @@ impl<B: Backend> RendererScene<B> {
       return;
     }
 
+    let index = self.directional_light_entity_map.get(&entity);
+    if let Some(index) = index {
+      let directional_light = &mut self.directional_lights[*index];
+      directional_light.direction = (transform * Vec4::new(0f32, 0f32, -1f32, 0f32)).xyz().normalize();
+      return;
+    }
+
+    let index = self.spot_light_entity_map.get(&entity);
+    if let Some(index) = index {
+      let spot_light = &mut self.spot_lights[*index];
+      spot_light.position = (transform * Vec4::new(0f32, 0f32, 0f32, 1f32)).xyz();
+      spot_light.direction = (transform * Vec4::new(0f32, 0f32, -1f32, 0f32)).xyz().normalize();
+      return;
+    }
+
     debug_assert!(false); // debug unreachable
   }
 
@@ -77,4 +125,34 @@ impl<B: Backend> RendererScene<B> {
     let index = *index.unwrap();
     self.point_lights.remove(index);
   }
+
+  pub(super) fn add_directional_light(&mut self, entity: Entity, light: DirectionalLight) {
+    self.directional_light_entity_map.insert(entity, self.directional_lights.len());
+    self.directional_lights.push(light);
+  }
+
+  pub(super) fn remove_directional_light(&mut self, entity: &Entity) {
+    let index = self.directional_light_entity_map.get(&entity);
+    debug_assert!(index.is_some());
+    if index.is_none() {
+      return;
+    }
+    let index = *index.unwrap();
+    self.directional_lights.remove(index);
+  }
+
+  pub(super) fn add_spot_light(&mut self, entity: Entity, light: SpotLight) {
+    self.spot_light_entity_map.insert(entity, self.spot_lights.len());
+    self.spot_lights.push(light);
+  }
+
+  pub(super) fn remove_spot_light(&mut self, entity: &Entity) {
+    let index = self.spot_light_entity_map.get(&entity);
+    debug_assert!(index.is_some());
+    if index.is_none() {
+      return;
+    }
+    let index = *index.unwrap();
+    self.spot_lights.remove(index);
+  }
 }
\ No newline at end of file
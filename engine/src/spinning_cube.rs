@@ -1,6 +1,6 @@
 use legion::{World, Resources, component};
 use sourcerenderer_core::platform::{InputState, Key};
-use crate::{Transform, Camera};
+use crate::{Transform, Camera, Projection};
 use nalgebra::{Unit, UnitQuaternion};
 use crate::asset::{AssetManager, MeshRange};
 use sourcerenderer_core::{Platform, Quaternion};
@@ -235,7 +235,8 @@ pub fn install<P: Platform>(world: &mut World, resources: &mut Resources, system
     depth: 1,
     mip_levels: 1,
     array_length: 1,
-    samples: SampleCount::Samples1
+    samples: SampleCount::Samples1,
+    is_cubemap: false
   };*/
 
   let triangle_data = unsafe { std::slice::from_raw_parts(triangle.as_ptr() as *const u8, std::mem::size_of_val(&triangle[..])) }.to_vec().into_boxed_slice();
@@ -253,12 +254,13 @@ pub fn install<P: Platform>(world: &mut World, resources: &mut Resources, system
     receive_shadows: true,
     cast_shadows: true,
     can_move: true,
-    model_path: "cube_model".to_owned()
+    model_path: "cube_model".to_owned(),
+    skin: 0
   }, Transform::new(Vec3::new(0f32, 0f32, -5f32)), SpinningCube {}));
 
   let camera = world.push((Camera {
-    fov: f32::consts::PI / 2f32
-  }, Transform::new(Vec3::new(0.0f32, 0.0f32, -5.0f32)), FPSCameraComponent {}));
+    projection: Projection::Perspective { fov: f32::consts::PI / 2f32 }
+  }, Transform::new(Vec3::new(0.0f32, 0.0f32, -5.0f32)), FPSCameraComponent::default()));
 
   resources.insert(ActiveCamera(camera));
 }
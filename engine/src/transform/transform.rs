@@ -1,4 +1,5 @@
 use sourcerenderer_core::{Vec3, Quaternion, Matrix4};
+use nalgebra::Matrix3;
 use legion::{Entity, IntoQuery, component, maybe_changed, EntityStore};
 
 use legion::systems::{CommandBuffer, Builder};
@@ -48,6 +49,36 @@ impl Transform {
   pub fn transform(&self, vector: &Vec3) -> Vec3 {
     Matrix4::from(self).transform_vector(&vector)
   }
+
+  /// Decomposes an affine matrix into translation, rotation and scale.
+  /// A reflection is represented by negating the X scale.
+  pub fn from_matrix(matrix: &Matrix4) -> Self {
+    let mut columns = [
+      matrix.column(0).xyz(),
+      matrix.column(1).xyz(),
+      matrix.column(2).xyz()
+    ];
+    let mut scale = Vec3::new(columns[0].magnitude(), columns[1].magnitude(), columns[2].magnitude());
+    if columns[0].cross(&columns[1]).dot(&columns[2]) < 0f32 {
+      scale.x = -scale.x;
+    }
+
+    let rotation = if scale.x.abs() > f32::EPSILON && scale.y.abs() > f32::EPSILON && scale.z.abs() > f32::EPSILON {
+      for (column, scale) in columns.iter_mut().zip(scale.iter()) {
+        *column /= *scale;
+      }
+      Quaternion::from_matrix(&Matrix3::<f32>::from_columns(&columns))
+    } else {
+      // A collapsed axis has no defined rotation
+      Quaternion::identity()
+    };
+
+    Self {
+      position: matrix.column(3).xyz(),
+      rotation,
+      scale
+    }
+  }
 }
 
 
@@ -164,10 +195,10 @@ fn maintain_children(command_buffer: &mut CommandBuffer, world: &mut SubWorld, #
 
       command_buffer.add_component(*entity, PreviousParent(parent.0));
     } else {
-      command_buffer.remove(*entity);
+      // The parent is gone, keep the entity around as a root
+      command_buffer.remove_component::<Parent>(*entity);
+      command_buffer.add_component(*entity, TransformDirty(true));
     }
-
-    command_buffer.add_component(*entity, PreviousParent(parent.0));
   }
 
   // handle changed parents
@@ -198,7 +229,9 @@ fn maintain_children(command_buffer: &mut CommandBuffer, world: &mut SubWorld, #
 
       command_buffer.add_component(*entity, PreviousParent(parent.0));
     } else {
-      command_buffer.remove(*entity);
+      command_buffer.remove_component::<Parent>(*entity);
+      command_buffer.remove_component::<PreviousParent>(*entity);
+      command_buffer.add_component(*entity, TransformDirty(true));
     }
   }
 
@@ -229,3 +262,73 @@ fn maintain_children(command_buffer: &mut CommandBuffer, world: &mut SubWorld, #
     command_buffer.add_component(entity, TransformDirty(true));
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use legion::{World, Resources, Schedule};
+
+  fn run_transform_systems(world: &mut World) {
+    let mut builder = Schedule::builder();
+    install(&mut builder);
+    let mut schedule = builder.build();
+    schedule.execute(world, &mut Resources::default());
+  }
+
+  fn global_transform(world: &World, entity: Entity) -> Matrix4 {
+    world.entry_ref(entity).unwrap().get_component::<GlobalTransform>().unwrap().0
+  }
+
+  #[test]
+  fn child_global_transform_is_parent_times_local() {
+    let mut parent_transform = Transform::new(Vec3::new(1f32, 2f32, 3f32));
+    parent_transform.rotation = Quaternion::from_axis_angle(&Vec3::y_axis(), std::f32::consts::FRAC_PI_2);
+    let mut child_transform = Transform::new(Vec3::new(1f32, 0f32, 0f32));
+    child_transform.scale = Vec3::new(2f32, 2f32, 2f32);
+    let expected = Matrix4::from(&parent_transform) * Matrix4::from(&child_transform);
+
+    let mut world = World::default();
+    let parent = world.push((parent_transform,));
+    let child = world.push((child_transform, Parent(parent)));
+    run_transform_systems(&mut world);
+
+    assert!((global_transform(&world, child) - expected).amax() < 0.0001f32);
+  }
+
+  #[test]
+  fn orphaned_child_becomes_a_root() {
+    let mut world = World::default();
+    let parent = world.push((Transform::new(Vec3::new(5f32, 0f32, 0f32)),));
+    let child = world.push((Transform::new(Vec3::new(1f32, 0f32, 0f32)), Parent(parent)));
+    world.remove(parent);
+    run_transform_systems(&mut world);
+    run_transform_systems(&mut world);
+
+    assert!(world.entry_ref(child).unwrap().get_component::<Parent>().is_err());
+    let expected = Matrix4::new_translation(&Vec3::new(1f32, 0f32, 0f32));
+    assert!((global_transform(&world, child) - expected).amax() < 0.0001f32);
+  }
+
+  #[test]
+  fn from_matrix_round_trips() {
+    let transform = Transform {
+      position: Vec3::new(1f32, -2f32, 3f32),
+      rotation: Quaternion::from_euler_angles(0.3f32, -1.2f32, 2f32),
+      scale: Vec3::new(2f32, 0.5f32, 3f32)
+    };
+    let decomposed = Transform::from_matrix(&Matrix4::from(&transform));
+    assert!((decomposed.position - transform.position).norm() < 0.0001f32);
+    assert!(decomposed.rotation.angle_to(&transform.rotation) < 0.0001f32);
+    assert!((decomposed.scale - transform.scale).norm() < 0.0001f32);
+  }
+
+  #[test]
+  fn from_matrix_keeps_reflections() {
+    let matrix = Matrix4::new_translation(&Vec3::new(4f32, 5f32, 6f32))
+      * Matrix4::new_rotation(Vec3::new(0f32, 0.7f32, 0f32))
+      * Matrix4::new_nonuniform_scaling(&Vec3::new(1f32, -2f32, 1f32));
+    let decomposed = Transform::from_matrix(&matrix);
+    assert!(decomposed.scale.x < 0f32);
+    assert!((Matrix4::from(&decomposed) - matrix).amax() < 0.0001f32);
+  }
+}
@@ -174,7 +174,13 @@ impl Adapter<VkBackend> for VkAdapter {
         });
       }
 
-      let enabled_features: vk::PhysicalDeviceFeatures = Default::default();
+      let supported_features = self.instance.instance.get_physical_device_features(self.physical_device);
+      let enabled_features = vk::PhysicalDeviceFeatures {
+        multi_draw_indirect: supported_features.multi_draw_indirect,
+        texture_compression_bc: supported_features.texture_compression_bc,
+        image_cube_array: supported_features.image_cube_array,
+        ..Default::default()
+      };
       let mut extension_names: Vec<&str> = vec!(SWAPCHAIN_EXT_NAME);
 
       if self.extensions.intersects(VkAdapterExtensionSupport::DEDICATED_ALLOCATION) {
@@ -227,6 +233,7 @@ impl Adapter<VkBackend> for VkAdapter {
         compute_queue_info,
         transfer_queue_info,
         self.extensions,
+        enabled_features,
         max_image_count)
     };
   }
@@ -245,7 +245,7 @@ fn align_up_32(value: u32, alignment: u32) -> u32 {
   if value == 0 {
     return 0
   }
-  (value + alignment - 1) & (alignment - 1)
+  (value + alignment - 1) & !(alignment - 1)
 }
 
 fn align_down_32(value: u32, alignment: u32) -> u32 {
@@ -259,7 +259,7 @@ fn align_up_64(value: u64, alignment: u64) -> u64 {
   if alignment == 0 {
     return value
   }
-  (value + alignment - 1) & (alignment - 1)
+  (value + alignment - 1) & !(alignment - 1)
 }
 
 fn align_down_64(value: u64, alignment: u64) -> u64 {
@@ -323,6 +323,9 @@ const BUFFER_SLAB_SIZE: usize = 1024;
 const SMALL_BUFFER_SLAB_SIZE: usize = 512;
 const TINY_BUFFER_SLAB_SIZE: usize = 256;
 
+/// Big buffers that weren't handed out or referenced for this many frames get destroyed
+const BIG_BUFFER_MAX_UNUSED_FRAMES: u32 = 16;
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord, Hash)]
 struct BufferKey {
   memory_usage: MemoryUsage,
@@ -332,6 +335,7 @@ struct BufferKey {
 pub struct BufferAllocator {
   device: Arc<RawVkDevice>,
   buffers: Mutex<HashMap<BufferKey, Vec<Arc<VkBuffer>>>>,
+  big_buffers: Mutex<HashMap<BufferKey, BigBuffers<Arc<VkBuffer>>>>,
   device_limits: vk::PhysicalDeviceLimits,
   reuse_automatically: bool
 }
@@ -350,6 +354,7 @@ impl BufferAllocator {
     BufferAllocator {
       device: device.clone(),
       buffers: Mutex::new(buffers),
+      big_buffers: Mutex::new(HashMap::new()),
       device_limits: limits2.properties.limits,
       reuse_automatically
     }
@@ -357,12 +362,7 @@ impl BufferAllocator {
 
   pub fn get_slice(&self, info: &BufferInfo, memory_usage: MemoryUsage, name: Option<&str>) -> Arc<VkBufferSlice> {
     if info.size > BIG_BUFFER_SLAB_SIZE {
-      let buffer = VkBuffer::new(&self.device, 1, memory_usage, info, &self.device.allocator, name);
-      let mut free_slices = buffer.free_slices.lock().unwrap();
-      let slice = free_slices.pop().unwrap();
-      let mut used_slices = buffer.used_slices.lock().unwrap();
-      used_slices.push(slice.clone());
-      return slice;
+      return self.get_big_slice(info, memory_usage, name);
     }
 
     let mut info = info.clone();
@@ -444,14 +444,238 @@ impl BufferAllocator {
     slice
   }
 
-  pub fn reset(&self) {
-    let buffers_types = self.buffers.lock().unwrap();
-    for (_key, buffers) in buffers_types.iter() {
-      for buffer in buffers {
-        let mut used_slices = buffer.used_slices.lock().unwrap();
-        let mut free_slices = buffer.free_slices.lock().unwrap();
+  fn get_big_slice(&self, info: &BufferInfo, memory_usage: MemoryUsage, name: Option<&str>) -> Arc<VkBufferSlice> {
+    let key = BufferKey { memory_usage, buffer_usage: info.usage };
+    let mut guard = self.big_buffers.lock().unwrap();
+    let matching_buffers = guard.entry(key).or_default();
+    let reused_slice = matching_buffers.reuse(info.size, |buffer| {
+      let mut used_slices = buffer.used_slices.lock().unwrap();
+      let mut free_slices = buffer.free_slices.lock().unwrap();
+      if self.reuse_automatically && used_slices.first().map_or(false, |slice| Arc::strong_count(slice) == 1) {
         free_slices.append(used_slices.as_mut());
       }
+      let slice = free_slices.pop()?;
+      used_slices.push(slice.clone());
+      Some(slice)
+    });
+    if let Some(slice) = reused_slice {
+      return slice;
+    }
+
+    let buffer = VkBuffer::new(&self.device, 1, memory_usage, info, &self.device.allocator, name);
+    let slice = {
+      let mut free_slices = buffer.free_slices.lock().unwrap();
+      let slice = free_slices.pop().unwrap();
+      let mut used_slices = buffer.used_slices.lock().unwrap();
+      used_slices.push(slice.clone());
+      slice
+    };
+    matching_buffers.push(buffer, info.size);
+    slice
+  }
+
+  pub fn reset(&self) {
+    {
+      let buffer_types = self.buffers.lock().unwrap();
+      for buffer in buffer_types.values().flatten() {
+        release_slices(buffer);
+      }
+    }
+
+    {
+      let big_buffer_types = self.big_buffers.lock().unwrap();
+      for buffer in big_buffer_types.values().flat_map(|big_buffers| big_buffers.iter()) {
+        release_slices(buffer);
+      }
+    }
+    self.remove_unused_big_buffers();
+  }
+
+  /// Needs to be called once per frame for allocators that never get reset.
+  pub fn remove_unused_big_buffers(&self) {
+    let mut big_buffer_types = self.big_buffers.lock().unwrap();
+    for big_buffers in big_buffer_types.values_mut() {
+      for buffer in big_buffers.remove_unused(|buffer| has_outside_references(&buffer.used_slices.lock().unwrap())) {
+        // The slices keep their buffer alive, so dropping them is what destroys it
+        buffer.used_slices.lock().unwrap().clear();
+        buffer.free_slices.lock().unwrap().clear();
+      }
+    }
+  }
+}
+
+/// The allocator holds one reference to every slice it handed out
+fn has_outside_references<S>(slices: &[Arc<S>]) -> bool {
+  slices.iter().any(|slice| Arc::strong_count(slice) > 1)
+}
+
+fn release_slices(buffer: &VkBuffer) {
+  let mut used_slices = buffer.used_slices.lock().unwrap();
+  let mut free_slices = buffer.free_slices.lock().unwrap();
+  free_slices.append(used_slices.as_mut());
+}
+
+struct BigBuffer<T> {
+  buffer: T,
+  size: usize,
+  unused_frames: u32
+}
+
+/// Buffers that are too big for a slab, each of those only has a single slice.
+/// Doesn't know about VkBuffer so the reuse policy can be tested without a device.
+struct BigBuffers<T> {
+  buffers: Vec<BigBuffer<T>>
+}
+
+impl<T> Default for BigBuffers<T> {
+  fn default() -> Self {
+    Self {
+      buffers: Vec::new()
+    }
+  }
+}
+
+impl<T> BigBuffers<T> {
+  /// Returns the slice of the first buffer with a fitting size that take_slice succeeds on.
+  fn reuse<S, F: FnMut(&T) -> Option<S>>(&mut self, size: usize, mut take_slice: F) -> Option<S> {
+    for big_buffer in &mut self.buffers {
+      // Don't waste a lot of memory on a much smaller allocation
+      if big_buffer.size < size || big_buffer.size > size * 2 {
+        continue;
+      }
+      if let Some(slice) = take_slice(&big_buffer.buffer) {
+        big_buffer.unused_frames = 0;
+        return Some(slice);
+      }
+    }
+    None
+  }
+
+  fn push(&mut self, buffer: T, size: usize) {
+    self.buffers.push(BigBuffer {
+      buffer,
+      size,
+      unused_frames: 0
+    });
+  }
+
+  fn iter(&self) -> impl Iterator<Item = &T> {
+    self.buffers.iter().map(|big_buffer| &big_buffer.buffer)
+  }
+
+  /// Gets called once per frame, returns the buffers that weren't handed out or referenced for too long.
+  fn remove_unused<F: FnMut(&T) -> bool>(&mut self, mut is_referenced: F) -> Vec<T> {
+    let mut removed = Vec::new();
+    let mut index = 0;
+    while index < self.buffers.len() {
+      let big_buffer = &mut self.buffers[index];
+      if is_referenced(&big_buffer.buffer) {
+        big_buffer.unused_frames = 0;
+        index += 1;
+        continue;
+      }
+      big_buffer.unused_frames += 1;
+      if big_buffer.unused_frames > BIG_BUFFER_MAX_UNUSED_FRAMES {
+        removed.push(self.buffers.swap_remove(index).buffer);
+      } else {
+        index += 1;
+      }
+    }
+    removed
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn align_up_rounds_to_the_next_multiple() {
+    assert_eq!(align_up(10, 16), 16);
+    assert_eq!(align_up(16, 16), 16);
+    assert_eq!(align_up_32(10, 16), 16);
+    assert_eq!(align_up_32(32, 16), 32);
+    assert_eq!(align_up_32(0, 16), 0);
+    assert_eq!(align_up_32(7, 0), 7);
+    assert_eq!(align_up_64(4097, 4096), 8192);
+    assert_eq!(align_up_64(4096, 4096), 4096);
+    assert_eq!(align_up_64(1, 256), 256);
+  }
+
+  #[test]
+  fn align_down_rounds_to_the_previous_multiple() {
+    assert_eq!(align_down(17, 16), 16);
+    assert_eq!(align_down_32(15, 16), 0);
+    assert_eq!(align_down_64(8191, 4096), 4096);
+  }
+
+  /// Stands in for the single slice of a big buffer
+  fn take_free(in_use: &mut [bool]) -> impl FnMut(&usize) -> Option<usize> + '_ {
+    move |buffer| {
+      if in_use[*buffer] {
+        None
+      } else {
+        in_use[*buffer] = true;
+        Some(*buffer)
+      }
+    }
+  }
+
+  #[test]
+  fn big_buffer_is_reused_after_its_slice_is_dropped() {
+    let mut in_use = [false; 2];
+    let mut big_buffers = BigBuffers::<usize>::default();
+    assert_eq!(big_buffers.reuse(5000, take_free(&mut in_use)), None);
+    big_buffers.push(0, 5000);
+    in_use[0] = true;
+
+    // Still in use, so a second request needs a new buffer
+    assert_eq!(big_buffers.reuse(5000, take_free(&mut in_use)), None);
+    big_buffers.push(1, 5000);
+    in_use[1] = true;
+
+    in_use[0] = false;
+    assert_eq!(big_buffers.reuse(4500, take_free(&mut in_use)), Some(0));
+    in_use[0] = false;
+    assert_eq!(big_buffers.reuse(6000, take_free(&mut in_use)), None);
+    assert_eq!(big_buffers.reuse(2000, take_free(&mut in_use)), None);
+  }
+
+  #[test]
+  fn unused_big_buffers_get_removed() {
+    let mut in_use = [false; 2];
+    let mut big_buffers = BigBuffers::<usize>::default();
+    big_buffers.push(0, 5000);
+    big_buffers.push(1, 8000);
+    for _ in 0..BIG_BUFFER_MAX_UNUSED_FRAMES {
+      assert_eq!(big_buffers.reuse(8000, take_free(&mut in_use)), Some(1));
+      in_use[1] = false;
+      assert!(big_buffers.remove_unused(|_| false).is_empty());
+    }
+    assert_eq!(big_buffers.remove_unused(|_| false), vec![0]);
+    assert_eq!(big_buffers.iter().copied().collect::<Vec<usize>>(), vec![1]);
+  }
+
+  #[test]
+  fn referenced_big_buffers_are_kept() {
+    // The used slices of each buffer, like the allocator keeps them
+    let mut big_buffers = BigBuffers::<Vec<Arc<usize>>>::default();
+    big_buffers.push(vec![Arc::new(0)], 5000);
+    big_buffers.push(vec![Arc::new(1)], 5000);
+    let slice = big_buffers.iter().next().unwrap()[0].clone();
+
+    for _ in 0..BIG_BUFFER_MAX_UNUSED_FRAMES {
+      assert!(big_buffers.remove_unused(|slices| has_outside_references(slices)).is_empty());
+    }
+    let removed = big_buffers.remove_unused(|slices| has_outside_references(slices));
+    assert_eq!(removed.len(), 1);
+    assert_eq!(*removed[0][0], 1);
+
+    drop(slice);
+    for _ in 0..BIG_BUFFER_MAX_UNUSED_FRAMES {
+      assert!(big_buffers.remove_unused(|slices| has_outside_references(slices)).is_empty());
     }
+    assert_eq!(big_buffers.remove_unused(|slices| has_outside_references(slices)).len(), 1);
+    assert_eq!(big_buffers.iter().count(), 0);
   }
 }
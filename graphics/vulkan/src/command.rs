@@ -9,13 +9,14 @@ use ash::vk;
 use crossbeam_channel::{Receiver, Sender, unbounded};
 
 use smallvec::SmallVec;
-use sourcerenderer_core::graphics::{AttachmentInfo, Barrier, BindingFrequency, Buffer, BufferInfo, BufferUsage, LoadOp, MemoryUsage, PipelineBinding, RenderPassBeginInfo, RenderPassInfo, ShaderType, StoreOp, Texture, TextureUsage, get_default_state};
+use sourcerenderer_core::graphics::{AttachmentInfo, Barrier, BindingFrequency, Buffer, BufferInfo, BufferUsage, IndexFormat, LoadOp, MemoryUsage, PipelineBinding, RenderPassBeginInfo, RenderPassInfo, SampleCount, ShaderType, StoreOp, Texture, TextureUsage, get_default_state};
 use sourcerenderer_core::graphics::CommandBuffer;
 use sourcerenderer_core::graphics::CommandBufferType;
 use sourcerenderer_core::graphics::RenderpassRecordingMode;
 use sourcerenderer_core::graphics::Viewport;
 use sourcerenderer_core::graphics::Scissor;
 use sourcerenderer_core::graphics::Resettable;
+use sourcerenderer_core::graphics::TimestampStage;
 
 use crate::{raw::RawVkDevice, texture::VkSampler};
 use crate::VkRenderPass;
@@ -28,6 +29,7 @@ use crate::buffer::{VkBufferSlice, BufferAllocator};
 use crate::VkTexture;
 use crate::descriptor::{VkBindingManager, VkBoundResource};
 use crate::texture::VkTextureView;
+use crate::VkQueryPool;
 use crate::lifetime_tracker::VkLifetimeTrackers;
 
 #[allow(clippy::vec_box)]
@@ -128,14 +130,15 @@ pub struct VkCommandBuffer {
   pending_src_stage_flags: vk::PipelineStageFlags,
   pending_dst_stage_flags: vk::PipelineStageFlags,  
   frame: u64,
-  inheritance: Option<VkInnerCommandBufferInfo>
+  inheritance: Option<VkInnerCommandBufferInfo>,
+  recording_mode: RenderpassRecordingMode
 }
 
 impl VkCommandBuffer {
   pub(crate) fn new(device: &Arc<RawVkDevice>, pool: &Arc<RawVkCommandPool>, command_buffer_type: CommandBufferType, queue_family_index: u32, shared: &Arc<VkShared>, buffer_allocator: &Arc<BufferAllocator>) -> Self {
     let buffers_create_info = vk::CommandBufferAllocateInfo {
       command_pool: ***pool,
-      level: if command_buffer_type == CommandBufferType::PRIMARY { vk::CommandBufferLevel::PRIMARY } else { vk::CommandBufferLevel::SECONDARY }, // TODO: support secondary command buffers / bundles
+      level: if command_buffer_type == CommandBufferType::PRIMARY { vk::CommandBufferLevel::PRIMARY } else { vk::CommandBufferLevel::SECONDARY },
       command_buffer_count: 1, // TODO: figure out how many buffers per pool (maybe create a new pool once we've run out?)
       ..Default::default()
     };
@@ -159,7 +162,8 @@ impl VkCommandBuffer {
       pending_src_stage_flags: vk::PipelineStageFlags::empty(),
       pending_dst_stage_flags: vk::PipelineStageFlags::empty(),
       frame: 0,
-      inheritance: None
+      inheritance: None,
+      recording_mode: RenderpassRecordingMode::Commands
     }
   }
 
@@ -173,6 +177,7 @@ impl VkCommandBuffer {
 
   pub(crate) fn reset(&mut self) {
     self.state = VkCommandBufferState::Ready;
+    self.inheritance = None;
     self.trackers.reset();
     self.descriptor_manager.reset();
   }
@@ -265,6 +270,7 @@ impl VkCommandBuffer {
     }
     self.render_pass = Some(render_pass.clone());
     self.sub_pass = 0;
+    self.recording_mode = recording_mode;
     self.trackers.track_frame_buffer(frame_buffer);
     self.trackers.track_render_pass(render_pass);
   }
@@ -276,30 +282,46 @@ impl VkCommandBuffer {
       self.device.cmd_end_render_pass(self.buffer);
     }
     self.render_pass = None;
+    self.inheritance = None;
   }
 
   pub(crate) fn advance_subpass(&mut self) {
     debug_assert_eq!(self.state, VkCommandBufferState::Recording);
     debug_assert!(self.render_pass.is_some());
     unsafe {
-      self.device.cmd_next_subpass(self.buffer, vk::SubpassContents::INLINE);
+      self.device.cmd_next_subpass(self.buffer, if self.recording_mode == RenderpassRecordingMode::Commands { vk::SubpassContents::INLINE } else { vk::SubpassContents::SECONDARY_COMMAND_BUFFERS });
     }
     self.sub_pass += 1;
+    if let Some(inheritance) = self.inheritance.as_mut() {
+      inheritance.sub_pass = self.sub_pass;
+    }
   }
 
   pub(crate) fn set_vertex_buffer(&mut self, vertex_buffer: &Arc<VkBufferSlice>) {
+    self.set_vertex_buffers(0, &[vertex_buffer], &[0]);
+  }
+
+  pub(crate) fn set_vertex_buffers(&mut self, first_binding: u32, vertex_buffers: &[&Arc<VkBufferSlice>], offsets: &[usize]) {
     debug_assert_eq!(self.state, VkCommandBufferState::Recording);
-    self.trackers.track_buffer(vertex_buffer);
+    debug_assert_eq!(vertex_buffers.len(), offsets.len());
+    let mut handles = SmallVec::<[vk::Buffer; 4]>::with_capacity(vertex_buffers.len());
+    let mut vk_offsets = SmallVec::<[u64; 4]>::with_capacity(vertex_buffers.len());
+    for (vertex_buffer, offset) in vertex_buffers.iter().zip(offsets) {
+      debug_assert!(*offset < vertex_buffer.get_length());
+      self.trackers.track_buffer(vertex_buffer);
+      handles.push(*vertex_buffer.get_buffer().get_handle());
+      vk_offsets.push((vertex_buffer.get_offset() + offset) as u64);
+    }
     unsafe {
-      self.device.cmd_bind_vertex_buffers(self.buffer, 0, &[*vertex_buffer.get_buffer().get_handle()], &[vertex_buffer.get_offset() as u64]);
+      self.device.cmd_bind_vertex_buffers(self.buffer, first_binding, &handles, &vk_offsets);
     }
   }
 
-  pub(crate) fn set_index_buffer(&mut self, index_buffer: &Arc<VkBufferSlice>) {
+  pub(crate) fn set_index_buffer(&mut self, index_buffer: &Arc<VkBufferSlice>, format: IndexFormat) {
     debug_assert_eq!(self.state, VkCommandBufferState::Recording);
     self.trackers.track_buffer(index_buffer);
     unsafe {
-      self.device.cmd_bind_index_buffer(self.buffer, *index_buffer.get_buffer().get_handle(), index_buffer.get_offset() as u64, vk::IndexType::UINT32);
+      self.device.cmd_bind_index_buffer(self.buffer, *index_buffer.get_buffer().get_handle(), index_buffer.get_offset() as u64, index_format_to_vk(format));
     }
   }
 
@@ -356,6 +378,24 @@ impl VkCommandBuffer {
     }
   }
 
+  pub(crate) fn draw_indexed_indirect(&mut self, draw_buffer: &Arc<VkBufferSlice>, draw_buffer_offset: u32, draw_count: u32, stride: u32) {
+    debug_assert_eq!(self.state, VkCommandBufferState::Recording);
+    debug_assert!(self.pipeline.is_some());
+    debug_assert!(self.pipeline.as_ref().unwrap().is_graphics());
+    debug_assert!(self.pending_image_barriers.is_empty() && self.pending_buffer_barriers.is_empty() && self.pending_dst_stage_flags.is_empty() && self.pending_src_stage_flags.is_empty());
+    let buffer_offset = draw_buffer.get_offset_and_length().0 as u64 + draw_buffer_offset as u64;
+    unsafe {
+      if self.device.features.multi_draw_indirect == vk::TRUE {
+        self.device.cmd_draw_indexed_indirect(self.buffer, *draw_buffer.get_buffer().get_handle(), buffer_offset, draw_count, stride);
+      } else {
+        for i in 0..draw_count {
+          self.device.cmd_draw_indexed_indirect(self.buffer, *draw_buffer.get_buffer().get_handle(), buffer_offset + (i * stride) as u64, 1, stride);
+        }
+      }
+    }
+    self.trackers.track_buffer(draw_buffer);
+  }
+
   pub(crate) fn init_texture_mip_level(&mut self, src_buffer: &Arc<VkBufferSlice>, texture: &Arc<VkTexture>, mip_level: u32, array_layer: u32) {
     debug_assert_eq!(self.state, VkCommandBufferState::Recording);
     unsafe {
@@ -484,7 +524,7 @@ impl VkCommandBuffer {
 
     if !descriptor_sets.is_empty() {
       unsafe {
-        self.device.cmd_bind_descriptor_sets(self.buffer, vk::PipelineBindPoint::GRAPHICS, *pipeline_layout.get_handle(), base_index, &descriptor_sets, &offsets);
+        self.device.cmd_bind_descriptor_sets(self.buffer, if pipeline.is_graphics() { vk::PipelineBindPoint::GRAPHICS } else { vk::PipelineBindPoint::COMPUTE }, *pipeline_layout.get_handle(), base_index, &descriptor_sets, &offsets);
       }
     }
   }
@@ -513,6 +553,7 @@ impl VkCommandBuffer {
     let pipeline_layout = pipeline.get_layout();
     let range = pipeline_layout.push_constant_range(visible_for_shader_type).expect("No push constants set up for shader");
     let size = std::mem::size_of_val(data);
+    debug_assert!(size <= range.size as usize, "Pushed {} bytes but the shader only declares {} bytes of push constants", size, range.size);
     unsafe {
       self.device.cmd_push_constants(
         self.buffer,
@@ -524,14 +565,14 @@ impl VkCommandBuffer {
     }
   }
 
-  pub(crate) fn begin_label(&self, label: &str) {
+  pub(crate) fn begin_label(&self, label: &str, color: [f32; 4]) {
     debug_assert_eq!(self.state, VkCommandBufferState::Recording);
     let label_cstring = CString::new(label).unwrap();
     if let Some(debug_utils) = self.device.instance.debug_utils.as_ref() {
       unsafe {
         debug_utils.debug_utils_loader.cmd_begin_debug_utils_label(self.buffer, &vk::DebugUtilsLabelEXT {
           p_label_name: label_cstring.as_ptr(),
-          color: [0.0f32; 4],
+          color,
           ..Default::default()
         });
       }
@@ -549,6 +590,8 @@ impl VkCommandBuffer {
 
   pub(crate) fn execute_inner(&mut self, mut submissions: Vec<VkCommandBufferSubmission>) {
     debug_assert_eq!(self.state, VkCommandBufferState::Recording);
+    debug_assert!(self.render_pass.is_some());
+    debug_assert!(self.recording_mode == RenderpassRecordingMode::CommandBuffers);
     if submissions.is_empty() {
       return;
     }
@@ -572,12 +615,57 @@ impl VkCommandBuffer {
     debug_assert_eq!(self.state, VkCommandBufferState::Recording);
     debug_assert!(self.pipeline.is_some());
     debug_assert!(!self.pipeline.as_ref().unwrap().is_graphics());
+    debug_assert!(self.render_pass.is_none());
     debug_assert!(self.pending_image_barriers.is_empty() && self.pending_buffer_barriers.is_empty() && self.pending_dst_stage_flags.is_empty() && self.pending_src_stage_flags.is_empty());
     unsafe {
       self.device.cmd_dispatch(self.buffer, group_count_x, group_count_y, group_count_z);
     }
   }
 
+  pub(crate) fn dispatch_indirect(&mut self, buffer: &Arc<VkBufferSlice>, offset: u32) {
+    debug_assert_eq!(self.state, VkCommandBufferState::Recording);
+    debug_assert!(self.pipeline.is_some());
+    debug_assert!(!self.pipeline.as_ref().unwrap().is_graphics());
+    debug_assert!(self.render_pass.is_none());
+    debug_assert!(self.pending_image_barriers.is_empty() && self.pending_buffer_barriers.is_empty() && self.pending_dst_stage_flags.is_empty() && self.pending_src_stage_flags.is_empty());
+    unsafe {
+      self.device.cmd_dispatch_indirect(self.buffer, *buffer.get_buffer().get_handle(), buffer.get_offset_and_length().0 as u64 + offset as u64);
+    }
+    self.trackers.track_buffer(buffer);
+  }
+
+  pub(crate) fn reset_query_pool(&mut self, query_pool: &Arc<VkQueryPool>, first_query: u32, query_count: u32) {
+    debug_assert_eq!(self.state, VkCommandBufferState::Recording);
+    debug_assert!(self.render_pass.is_none());
+    unsafe {
+      self.device.cmd_reset_query_pool(self.buffer, *query_pool.get_handle(), first_query, query_count);
+    }
+    query_pool.mark_reset(first_query, query_count);
+    self.trackers.track_query_pool(query_pool);
+  }
+
+  pub(crate) fn write_timestamp(&mut self, query_pool: &Arc<VkQueryPool>, query_index: u32, stage: TimestampStage) {
+    debug_assert_eq!(self.state, VkCommandBufferState::Recording);
+    if !query_pool.supports_timestamps() {
+      return;
+    }
+    let vk_stage = match stage {
+      TimestampStage::Top => vk::PipelineStageFlags::TOP_OF_PIPE,
+      TimestampStage::Bottom => vk::PipelineStageFlags::BOTTOM_OF_PIPE
+    };
+    if !query_pool.is_reset(query_index) {
+      // Queries have to be reset before their first use and between writes, that can't happen inside a render pass
+      debug_assert!(self.render_pass.is_none());
+      unsafe {
+        self.device.cmd_reset_query_pool(self.buffer, *query_pool.get_handle(), query_index, 1);
+      }
+    }
+    unsafe {
+      self.device.cmd_write_timestamp(self.buffer, vk_stage, *query_pool.get_handle(), query_index);
+    }
+    query_pool.mark_written(query_index);
+    self.trackers.track_query_pool(query_pool);
+  }
 
   pub(crate) fn blit(&mut self, src_texture: &Arc<VkTexture>, src_array_layer: u32, src_mip_level: u32, dst_texture: &Arc<VkTexture>, dst_array_layer: u32, dst_mip_level: u32) {
     debug_assert_eq!(self.state, VkCommandBufferState::Recording);
@@ -643,6 +731,89 @@ impl VkCommandBuffer {
     }
   }
 
+  pub(crate) fn resolve(&mut self, src_texture: &Arc<VkTexture>, dst_texture: &Arc<VkTexture>) {
+    debug_assert_eq!(self.state, VkCommandBufferState::Recording);
+    debug_assert!(self.render_pass.is_none());
+    debug_assert!(self.pending_image_barriers.is_empty() && self.pending_buffer_barriers.is_empty() && self.pending_dst_stage_flags.is_empty() && self.pending_src_stage_flags.is_empty());
+    let src_info = src_texture.get_info();
+    let dst_info = dst_texture.get_info();
+    debug_assert!(!src_info.format.is_depth() && !src_info.format.is_stencil());
+    debug_assert_eq!(src_info.format, dst_info.format);
+    debug_assert_eq!(dst_info.samples, SampleCount::Samples1);
+
+    unsafe {
+      self.device.cmd_resolve_image(self.buffer, *src_texture.get_handle(), vk::ImageLayout::TRANSFER_SRC_OPTIMAL, *dst_texture.get_handle(), vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+      &[vk::ImageResolve {
+        src_subresource: vk::ImageSubresourceLayers {
+          aspect_mask: vk::ImageAspectFlags::COLOR,
+          mip_level: 0,
+          base_array_layer: 0,
+          layer_count: 1
+        },
+        src_offset: vk::Offset3D {
+          x: 0,
+          y: 0,
+          z: 0
+        },
+        dst_subresource: vk::ImageSubresourceLayers {
+          aspect_mask: vk::ImageAspectFlags::COLOR,
+          mip_level: 0,
+          base_array_layer: 0,
+          layer_count: 1
+        },
+        dst_offset: vk::Offset3D {
+          x: 0,
+          y: 0,
+          z: 0
+        },
+        extent: vk::Extent3D {
+          width: src_info.width.min(dst_info.width),
+          height: src_info.height.min(dst_info.height),
+          depth: src_info.depth.min(dst_info.depth)
+        }
+      }]);
+    }
+  }
+
+  pub(crate) fn copy_texture_to_buffer(&mut self, src_texture: &Arc<VkTexture>, src_array_layer: u32, src_mip_level: u32, dst_buffer: &Arc<VkBufferSlice>) {
+    debug_assert_eq!(self.state, VkCommandBufferState::Recording);
+    debug_assert!(self.render_pass.is_none());
+    debug_assert!(self.pending_image_barriers.is_empty() && self.pending_buffer_barriers.is_empty() && self.pending_dst_stage_flags.is_empty() && self.pending_src_stage_flags.is_empty());
+    let src_info = src_texture.get_info();
+    debug_assert!(!src_info.format.is_depth() && !src_info.format.is_stencil());
+    let width = max(src_info.width >> src_mip_level, 1);
+    let height = max(src_info.height >> src_mip_level, 1);
+    let depth = max(src_info.depth >> src_mip_level, 1);
+
+    unsafe {
+      // The rows are tightly packed in the buffer
+      self.device.cmd_copy_image_to_buffer(self.buffer, *src_texture.get_handle(), vk::ImageLayout::TRANSFER_SRC_OPTIMAL, *dst_buffer.get_buffer().get_handle(),
+      &[vk::BufferImageCopy {
+        buffer_offset: dst_buffer.get_offset() as u64,
+        buffer_row_length: 0,
+        buffer_image_height: 0,
+        image_subresource: vk::ImageSubresourceLayers {
+          aspect_mask: vk::ImageAspectFlags::COLOR,
+          mip_level: src_mip_level,
+          base_array_layer: src_array_layer,
+          layer_count: 1
+        },
+        image_offset: vk::Offset3D {
+          x: 0,
+          y: 0,
+          z: 0
+        },
+        image_extent: vk::Extent3D {
+          width,
+          height,
+          depth
+        }
+      }]);
+    }
+    self.trackers.track_texture(src_texture);
+    self.trackers.track_buffer(dst_buffer);
+  }
+
   pub(crate) fn barrier_1<'a>(
     &mut self,
     barriers: &[Barrier<VkBackend>]
@@ -896,6 +1067,7 @@ impl VkCommandBuffer {
       self.device.cmd_begin_render_pass(self.buffer, &begin_info, if recording_mode == RenderpassRecordingMode::Commands { vk::SubpassContents::INLINE } else { vk::SubpassContents::SECONDARY_COMMAND_BUFFERS });
     }
     self.sub_pass = 0;
+    self.recording_mode = recording_mode;
     self.trackers.track_frame_buffer(&framebuffer);
     self.trackers.track_render_pass(&renderpass);
     self.render_pass = Some(renderpass.clone());
@@ -907,7 +1079,7 @@ impl VkCommandBuffer {
   }
 
   pub fn inheritance(&self) -> &VkInnerCommandBufferInfo {
-    self.inheritance.as_ref().unwrap()
+    self.inheritance.as_ref().expect("Inheritance info is only available inside a render pass")
   }
 
   pub fn wait_events(
@@ -1030,8 +1202,13 @@ impl CommandBuffer<VkBackend> for VkCommandBufferRecorder {
   }
 
   #[inline(always)]
-  fn set_index_buffer(&mut self, index_buffer: &Arc<VkBufferSlice>) {
-    self.item.as_mut().unwrap().set_index_buffer(index_buffer)
+  fn set_vertex_buffers(&mut self, first_binding: u32, vertex_buffers: &[&Arc<VkBufferSlice>], offsets: &[usize]) {
+    self.item.as_mut().unwrap().set_vertex_buffers(first_binding, vertex_buffers, offsets)
+  }
+
+  #[inline(always)]
+  fn set_index_buffer(&mut self, index_buffer: &Arc<VkBufferSlice>, format: IndexFormat) {
+    self.item.as_mut().unwrap().set_index_buffer(index_buffer, format)
   }
 
   #[inline(always)]
@@ -1071,6 +1248,11 @@ impl CommandBuffer<VkBackend> for VkCommandBufferRecorder {
     self.item.as_mut().unwrap().draw_indexed(instances, first_instance, indices, first_index, vertex_offset);
   }
 
+  #[inline(always)]
+  fn draw_indexed_indirect(&mut self, draw_buffer: &Arc<VkBufferSlice>, draw_buffer_offset: u32, draw_count: u32, stride: u32) {
+    self.item.as_mut().unwrap().draw_indexed_indirect(draw_buffer, draw_buffer_offset, draw_count, stride);
+  }
+
   #[inline(always)]
   fn bind_texture_view(&mut self, frequency: BindingFrequency, binding: u32, texture: &Arc<VkTextureView>, sampler: &Arc<VkSampler>) {
     self.item.as_mut().unwrap().bind_texture_view(frequency, binding, texture, sampler);
@@ -1097,8 +1279,8 @@ impl CommandBuffer<VkBackend> for VkCommandBufferRecorder {
   }
 
   #[inline(always)]
-  fn begin_label(&mut self, label: &str) {
-    self.item.as_mut().unwrap().begin_label(label);
+  fn begin_label(&mut self, label: &str, color: [f32; 4]) {
+    self.item.as_mut().unwrap().begin_label(label, color);
   }
 
   #[inline(always)]
@@ -1111,11 +1293,36 @@ impl CommandBuffer<VkBackend> for VkCommandBufferRecorder {
     self.item.as_mut().unwrap().dispatch(group_count_x, group_count_y, group_count_z);
   }
 
+  #[inline(always)]
+  fn dispatch_indirect(&mut self, buffer: &Arc<VkBufferSlice>, offset: u32) {
+    self.item.as_mut().unwrap().dispatch_indirect(buffer, offset);
+  }
+
+  #[inline(always)]
+  fn reset_query_pool(&mut self, query_pool: &Arc<VkQueryPool>, first_query: u32, query_count: u32) {
+    self.item.as_mut().unwrap().reset_query_pool(query_pool, first_query, query_count);
+  }
+
+  #[inline(always)]
+  fn write_timestamp(&mut self, query_pool: &Arc<VkQueryPool>, query_index: u32, stage: TimestampStage) {
+    self.item.as_mut().unwrap().write_timestamp(query_pool, query_index, stage);
+  }
+
   #[inline(always)]
   fn blit(&mut self, src_texture: &Arc<VkTexture>, src_array_layer: u32, src_mip_level: u32, dst_texture: &Arc<VkTexture>, dst_array_layer: u32, dst_mip_level: u32) {
     self.item.as_mut().unwrap().blit(src_texture, src_array_layer, src_mip_level, dst_texture, dst_array_layer, dst_mip_level);
   }
 
+  #[inline(always)]
+  fn resolve(&mut self, src_texture: &Arc<VkTexture>, dst_texture: &Arc<VkTexture>) {
+    self.item.as_mut().unwrap().resolve(src_texture, dst_texture);
+  }
+
+  #[inline(always)]
+  fn copy_texture_to_buffer(&mut self, src_texture: &Arc<VkTexture>, src_array_layer: u32, src_mip_level: u32, dst_buffer: &Arc<VkBufferSlice>) {
+    self.item.as_mut().unwrap().copy_texture_to_buffer(src_texture, src_array_layer, src_mip_level, dst_buffer);
+  }
+
   fn finish(self) -> VkCommandBufferSubmission {
     assert_eq!(self.item.as_ref().unwrap().state, VkCommandBufferState::Recording);
     let mut mut_self = self;
@@ -1239,7 +1446,7 @@ fn texture_usage_to_access(texture_usage: TextureUsage) -> vk::AccessFlags {
   flags
 }
 
-fn texture_usage_to_stage(texture_usage: TextureUsage) -> vk::PipelineStageFlags {
+pub(crate) fn texture_usage_to_stage(texture_usage: TextureUsage) -> vk::PipelineStageFlags {
   let mut flags = vk::PipelineStageFlags::empty();
   if texture_usage.contains(TextureUsage::BLIT_DST)
     || texture_usage.contains(TextureUsage::COPY_DST)
@@ -1337,7 +1544,7 @@ fn texture_usage_to_image_layout(texture_usage: TextureUsage) -> vk::ImageLayout
   panic!("Unsupported texture usage combination");
 }
 
-fn buffer_usage_to_access(buffer_usage: BufferUsage) -> vk::AccessFlags {
+pub(crate) fn buffer_usage_to_access(buffer_usage: BufferUsage) -> vk::AccessFlags {
   let mut flags = vk::AccessFlags::empty();
   if buffer_usage.contains(BufferUsage::COPY_DST) {
     flags |= vk::AccessFlags::TRANSFER_WRITE;
@@ -1367,12 +1574,22 @@ fn buffer_usage_to_access(buffer_usage: BufferUsage) -> vk::AccessFlags {
     || buffer_usage.contains(BufferUsage::COMPUTE_SHADER_CONSTANT) {
     flags |= vk::AccessFlags::SHADER_READ;
   }
+  if buffer_usage.contains(BufferUsage::HOST_READ) {
+    flags |= vk::AccessFlags::HOST_READ;
+  }
   /*if buffer_usage.contains(BufferUsage::CPU_IN_FLIGHT_WRITE) {
     flags |= vk::AccessFlags::HOST_WRITE;
   }*/
   flags
 }
 
+fn index_format_to_vk(format: IndexFormat) -> vk::IndexType {
+  match format {
+    IndexFormat::U16 => vk::IndexType::UINT16,
+    IndexFormat::U32 => vk::IndexType::UINT32
+  }
+}
+
 fn buffer_usage_to_stage(buffer_usage: BufferUsage) -> vk::PipelineStageFlags {
   let mut flags = vk::PipelineStageFlags::empty();
   if buffer_usage.contains(BufferUsage::COPY_DST)
@@ -1403,6 +1620,9 @@ fn buffer_usage_to_stage(buffer_usage: BufferUsage) -> vk::PipelineStageFlags {
     || buffer_usage.contains(BufferUsage::COMPUTE_SHADER_CONSTANT) {
     flags |= vk::PipelineStageFlags::COMPUTE_SHADER;
   }
+  if buffer_usage.contains(BufferUsage::HOST_READ) {
+    flags |= vk::PipelineStageFlags::HOST;
+  }
   flags
 }
 
@@ -424,7 +424,7 @@ impl Default for VkBoundResource {
 pub(crate) struct VkDescriptorSetBinding {
   pub(crate) set: Arc<VkDescriptorSet>,
   pub(crate) dynamic_offset_count: u32,
-  pub(crate) dynamic_offsets: [u64; 8]
+  pub(crate) dynamic_offsets: [u64; 16]
 }
 
 struct VkDescriptorSetCacheEntry {
@@ -1,16 +1,19 @@
 use std::sync::{Arc};
+use std::ffi::c_void;
+use std::path::PathBuf;
 
 use ash::vk;
 
 use sourcerenderer_core::graphics::*;
 use crate::{queue::VkQueue, texture::VkSampler};
 use crate::queue::VkQueueInfo;
-use crate::{VkBackend, VkRenderPass, VkSemaphore};
+use crate::{VkBackend, VkSemaphore};
 use crate::VkAdapterExtensionSupport;
 use crate::pipeline::VkPipeline;
 use crate::pipeline::VkShader;
 use crate::texture::VkTexture;
 use crate::sync::VkFence;
+use crate::VkQueryPool;
 
 use crate::{VkThreadManager, VkShared};
 use crate::raw::{RawVkDevice, RawVkInstance};
@@ -19,6 +22,7 @@ use crate::buffer::VkBufferSlice;
 use std::cmp::min;
 use crate::texture::VkTextureView;
 use crate::transfer::VkTransfer;
+use smallvec::SmallVec;
 
 pub struct VkDevice {
   device: Arc<RawVkDevice>,
@@ -27,7 +31,8 @@ pub struct VkDevice {
   transfer_queue: Option<Arc<VkQueue>>,
   extensions: VkAdapterExtensionSupport,
   context: Arc<VkThreadManager>,
-  transfer: VkTransfer
+  transfer: VkTransfer,
+  max_sample_count: SampleCount
 }
 
 impl VkDevice {
@@ -39,6 +44,7 @@ impl VkDevice {
     compute_queue_info: Option<VkQueueInfo>,
     transfer_queue_info: Option<VkQueueInfo>,
     extensions: VkAdapterExtensionSupport,
+    features: vk::PhysicalDeviceFeatures,
     max_surface_image_count: u32) -> Self {
 
     let allocator_info = vk_mem::AllocatorCreateInfo {
@@ -52,15 +58,20 @@ impl VkDevice {
     };
     let allocator = vk_mem::Allocator::new(&allocator_info).expect("Failed to create memory allocator.");
 
+    let properties = unsafe { instance.instance.get_physical_device_properties(physical_device) };
+    let pipeline_cache = create_pipeline_cache(&device, &properties);
+
     let raw = Arc::new(RawVkDevice {
       device,
       allocator,
       physical_device,
       instance: instance.clone(),
       extensions,
+      features,
       graphics_queue_info,
       transfer_queue_info,
-      compute_queue_info
+      compute_queue_info,
+      pipeline_cache
     });
 
     let shared = Arc::new(VkShared::new(&raw));
@@ -91,7 +102,8 @@ impl VkDevice {
       transfer_queue,
       extensions,
       context,
-      transfer
+      transfer,
+      max_sample_count: max_sample_count(&properties.limits)
     }
   }
 
@@ -213,17 +225,7 @@ impl Device<VkBackend> for VkDevice {
 
   fn create_graphics_pipeline(&self, info: &GraphicsPipelineInfo<VkBackend>, renderpass_info: &RenderPassInfo, subpass: u32) -> Arc<<VkBackend as Backend>::GraphicsPipeline> {
     let shared = self.context.get_shared();
-    let mut rp_opt = {
-      let render_passes = shared.get_render_passes().read().unwrap();
-      render_passes.get(renderpass_info).map(|rp_ref| rp_ref.clone())
-    };
-    if rp_opt.is_none() {
-      let rp = Arc::new(VkRenderPass::new(&self.device, renderpass_info));
-      let mut render_passes = shared.get_render_passes().write().unwrap();
-      render_passes.insert(renderpass_info.clone(), rp.clone());
-      rp_opt = Some(rp);
-    }
-    let rp = rp_opt.unwrap();
+    let rp = shared.get_render_pass(renderpass_info);
     let vk_info = VkGraphicsPipelineInfo {
       info,
       render_pass: &rp,
@@ -236,6 +238,10 @@ impl Device<VkBackend> for VkDevice {
     self.transfer.init_texture(texture, buffer, mip_level, array_layer);
   }
 
+  fn init_texture_mips(&self, texture: &Arc<VkTexture>, buffers: &[Arc<VkBufferSlice>], array_layer: u32) {
+    self.transfer.init_texture_mips(texture, buffers, array_layer);
+  }
+
   fn init_texture_async(&self, texture: &Arc<VkTexture>, buffer: &Arc<VkBufferSlice>, mip_level: u32, array_layer: u32) -> Option<Arc<VkFence>> {
     self.transfer.init_texture_async(texture, buffer, mip_level, array_layer)
   }
@@ -256,18 +262,131 @@ impl Device<VkBackend> for VkDevice {
     self.context.shared().get_fence()
   }
 
+  fn wait_for_fences(&self, fences: &[&Arc<VkFence>], wait_all: bool, timeout_ns: u64) -> bool {
+    if fences.is_empty() {
+      return true;
+    }
+    let fence_handles = fences.iter().map(|f| *f.get_handle()).collect::<SmallVec<[vk::Fence; 8]>>();
+    let result = unsafe {
+      self.device.wait_for_fences(&fence_handles, wait_all, timeout_ns)
+    };
+    match result {
+      Ok(_) => {
+        if wait_all {
+          for fence in fences {
+            fence.mark_signalled();
+          }
+        }
+        true
+      }
+      Err(vk::Result::TIMEOUT) => false,
+      Err(e) => panic!("Waiting for fences failed: {:?}", e)
+    }
+  }
+
   fn create_semaphore(&self) -> Arc<VkSemaphore> {
     self.context.shared().get_semaphore()
   }
 
+  fn create_timestamp_query_pool(&self, query_count: u32) -> Arc<VkQueryPool> {
+    Arc::new(VkQueryPool::new_timestamp(&self.device, query_count))
+  }
+
+  fn get_query_results(&self, query_pool: &Arc<VkQueryPool>, first_query: u32, results: &mut [u64]) -> bool {
+    query_pool.get_timestamps(first_query, results)
+  }
+
   fn graphics_queue(&self) -> &Arc<VkQueue> {
     &self.graphics_queue
   }
+
+  fn max_sample_count(&self) -> SampleCount {
+    self.max_sample_count
+  }
 }
 
 impl Drop for VkDevice {
   fn drop(&mut self) {
     self.wait_for_idle();
+    save_pipeline_cache(&self.device);
+  }
+}
+
+const PIPELINE_CACHE_FILE: &str = "pipeline_cache.bin";
+const PIPELINE_CACHE_TEMP_FILE: &str = "pipeline_cache.bin.tmp";
+
+/// The per user cache directory of the platform, falls back to the working directory if there is none.
+fn pipeline_cache_dir() -> PathBuf {
+  let cache_dir = if cfg!(windows) {
+    std::env::var_os("LOCALAPPDATA").map(PathBuf::from)
+  } else if cfg!(target_os = "macos") {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join("Library").join("Caches"))
+  } else {
+    std::env::var_os("XDG_CACHE_HOME")
+      .filter(|dir| !dir.is_empty())
+      .map(PathBuf::from)
+      .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+  };
+  cache_dir.map_or_else(PathBuf::new, |dir| dir.join("sourcerenderer"))
+}
+
+fn create_pipeline_cache(device: &ash::Device, properties: &vk::PhysicalDeviceProperties) -> vk::PipelineCache {
+  let initial_data = std::fs::read(pipeline_cache_dir().join(PIPELINE_CACHE_FILE))
+    .ok()
+    .filter(|data| is_pipeline_cache_compatible(data, properties));
+  let pipeline_cache_create_info = vk::PipelineCacheCreateInfo {
+    initial_data_size: initial_data.as_ref().map_or(0, |data| data.len()),
+    p_initial_data: initial_data.as_ref().map_or(std::ptr::null(), |data| data.as_ptr() as *const c_void),
+    ..Default::default()
+  };
+  unsafe {
+    device.create_pipeline_cache(&pipeline_cache_create_info, None)
+  }.unwrap()
+}
+
+/// Drivers should reject data from a different device or driver version on their own
+/// but some of them crash instead, so check the header first.
+fn is_pipeline_cache_compatible(data: &[u8], properties: &vk::PhysicalDeviceProperties) -> bool {
+  if data.len() < 32 {
+    return false;
+  }
+  let read_u32 = |offset: usize| u32::from_ne_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]]);
+  read_u32(0) >= 32
+    && read_u32(4) == vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32
+    && read_u32(8) == properties.vendor_id
+    && read_u32(12) == properties.device_id
+    && data[16..32] == properties.pipeline_cache_uuid
+}
+
+fn save_pipeline_cache(device: &RawVkDevice) {
+  let data = unsafe { device.get_pipeline_cache_data(device.pipeline_cache) };
+  match data {
+    Ok(data) => {
+      let cache_dir = pipeline_cache_dir();
+      let temp_path = cache_dir.join(PIPELINE_CACHE_TEMP_FILE);
+      // A truncated file would still pass the header check, so never write the cache in place.
+      let result = std::fs::create_dir_all(&cache_dir)
+        .and_then(|_| std::fs::write(&temp_path, data))
+        .and_then(|_| std::fs::rename(&temp_path, cache_dir.join(PIPELINE_CACHE_FILE)));
+      if let Err(e) = result {
+        println!("Failed to write pipeline cache: {:?}", e);
+        let _ = std::fs::remove_file(&temp_path);
+      }
+    }
+    Err(e) => println!("Failed to retrieve pipeline cache data: {:?}", e)
+  }
+}
+
+fn max_sample_count(limits: &vk::PhysicalDeviceLimits) -> SampleCount {
+  let counts = limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts;
+  if counts.contains(vk::SampleCountFlags::TYPE_8) {
+    SampleCount::Samples8
+  } else if counts.contains(vk::SampleCountFlags::TYPE_4) {
+    SampleCount::Samples4
+  } else if counts.contains(vk::SampleCountFlags::TYPE_2) {
+    SampleCount::Samples2
+  } else {
+    SampleCount::Samples1
   }
 }
 
@@ -279,3 +398,55 @@ pub fn memory_usage_to_vma(memory_usage: MemoryUsage) -> vk_mem::MemoryUsage {
     MemoryUsage::GpuToCpu => vk_mem::MemoryUsage::GpuToCpu,
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn max_sample_count_needs_color_and_depth_support() {
+    let limits = vk::PhysicalDeviceLimits {
+      framebuffer_color_sample_counts: vk::SampleCountFlags::TYPE_1 | vk::SampleCountFlags::TYPE_4 | vk::SampleCountFlags::TYPE_8,
+      framebuffer_depth_sample_counts: vk::SampleCountFlags::TYPE_1 | vk::SampleCountFlags::TYPE_2 | vk::SampleCountFlags::TYPE_4,
+      ..Default::default()
+    };
+    assert_eq!(max_sample_count(&limits), SampleCount::Samples4);
+    assert_eq!(max_sample_count(&vk::PhysicalDeviceLimits::default()), SampleCount::Samples1);
+  }
+
+  fn pipeline_cache_header(properties: &vk::PhysicalDeviceProperties) -> Vec<u8> {
+    let mut data = Vec::<u8>::new();
+    data.extend_from_slice(&32u32.to_ne_bytes());
+    data.extend_from_slice(&(vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32).to_ne_bytes());
+    data.extend_from_slice(&properties.vendor_id.to_ne_bytes());
+    data.extend_from_slice(&properties.device_id.to_ne_bytes());
+    data.extend_from_slice(&properties.pipeline_cache_uuid);
+    data
+  }
+
+  #[test]
+  fn pipeline_cache_header_round_trip() {
+    let properties = vk::PhysicalDeviceProperties {
+      vendor_id: 0x1002,
+      device_id: 0x73bf,
+      pipeline_cache_uuid: [7u8; 16],
+      ..Default::default()
+    };
+    let mut data = pipeline_cache_header(&properties);
+    assert!(is_pipeline_cache_compatible(&data, &properties));
+    data.extend_from_slice(&[0u8; 64]);
+    assert!(is_pipeline_cache_compatible(&data, &properties));
+    assert!(!is_pipeline_cache_compatible(&data[..31], &properties));
+
+    let other_device = vk::PhysicalDeviceProperties {
+      device_id: 0x73df,
+      ..properties
+    };
+    assert!(!is_pipeline_cache_compatible(&data, &other_device));
+    let other_driver = vk::PhysicalDeviceProperties {
+      pipeline_cache_uuid: [8u8; 16],
+      ..properties
+    };
+    assert!(!is_pipeline_cache_compatible(&data, &other_driver));
+  }
+}
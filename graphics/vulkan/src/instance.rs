@@ -12,6 +12,8 @@ use crate::raw::{RawVkInstance, RawVkDebugUtils};
 
 use std::os::raw::{c_void, c_char};
 
+const SWAPCHAIN_COLORSPACE_EXT_NAME: &str = "VK_EXT_swapchain_colorspace";
+
 pub struct VkInstance {
   raw: Arc<RawVkInstance>,
 }
@@ -25,6 +27,7 @@ impl VkInstance {
     let mut supports_khronos_validation = false;
     let mut supports_lunarg_validation = false;
     let mut supports_debug_utils = false;
+    let mut supports_swapchain_colorspace = false;
     for layer in &layers {
       let name = unsafe { CStr::from_ptr(&layer.layer_name as *const c_char) };
       match name.to_str().unwrap() {
@@ -43,6 +46,9 @@ impl VkInstance {
       if name == debug_utils_name {
         supports_debug_utils = true;
       }
+      if name.to_str() == Ok(SWAPCHAIN_COLORSPACE_EXT_NAME) {
+        supports_swapchain_colorspace = true;
+      }
     }
 
     let app_name = CString::new("CS:GO").unwrap();
@@ -75,6 +81,10 @@ impl VkInstance {
     } else {
       println!("Vulkan debug utils are unsupported");
     }
+    // Required for any swapchain color space other than SRGB_NONLINEAR
+    if supports_swapchain_colorspace {
+      extension_names_c.push(CString::new(SWAPCHAIN_COLORSPACE_EXT_NAME).unwrap());
+    }
     let extension_names_ptr: Vec<*const c_char> = extension_names_c
       .iter()
       .map(|ext_c| ext_c.as_ptr())
@@ -123,7 +133,8 @@ impl VkInstance {
         raw: Arc::new(RawVkInstance {
           entry,
           instance,
-          debug_utils
+          debug_utils,
+          supports_swapchain_colorspace
         })
       }
     }
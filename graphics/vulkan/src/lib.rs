@@ -32,6 +32,7 @@ pub use self::pipeline::VkPipeline;
 pub use self::renderpass::VkFrameBuffer;
 pub use self::renderpass::VkRenderPass;
 pub use self::backend::VkBackend;
+pub use self::query::VkQueryPool;
 pub(crate) use self::shared::VkShared;
 pub(crate) use self::lifetime_tracker::VkLifetimeTrackers;
 pub(crate) use self::thread_manager::VkThreadManager;
@@ -57,3 +58,4 @@ mod descriptor;
 mod transfer;
 mod shared;
 mod lifetime_tracker;
+mod query;
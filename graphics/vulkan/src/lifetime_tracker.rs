@@ -4,6 +4,7 @@ use crate::buffer::VkBufferSlice;
 use crate::{VkPipeline, VkRenderPass, VkTexture};
 use crate::texture::VkTextureView;
 use crate::VkFrameBuffer;
+use crate::VkQueryPool;
 
 pub struct VkLifetimeTrackers {
   semaphores: Vec<Arc<VkSemaphore>>,
@@ -14,7 +15,8 @@ pub struct VkLifetimeTrackers {
   render_passes: Vec<Arc<VkRenderPass>>,
   frame_buffers: Vec<Arc<VkFrameBuffer>>,
   samplers: Vec<Arc<VkSampler>>,
-  pipelines: Vec<Arc<VkPipeline>>
+  pipelines: Vec<Arc<VkPipeline>>,
+  query_pools: Vec<Arc<VkQueryPool>>
 }
 
 impl VkLifetimeTrackers {
@@ -28,7 +30,8 @@ impl VkLifetimeTrackers {
       render_passes: Vec::new(),
       frame_buffers: Vec::new(),
       samplers: Vec::new(),
-      pipelines: Vec::new()
+      pipelines: Vec::new(),
+      query_pools: Vec::new()
     }
   }
 
@@ -42,6 +45,7 @@ impl VkLifetimeTrackers {
     self.frame_buffers.clear();
     self.samplers.clear();
     self.pipelines.clear();
+    self.query_pools.clear();
   }
 
   pub(crate) fn track_semaphore(&mut self, semaphore: &Arc<VkSemaphore>) {
@@ -80,6 +84,10 @@ impl VkLifetimeTrackers {
     self.pipelines.push(pipeline.clone());
   }
 
+  pub(crate) fn track_query_pool(&mut self, query_pool: &Arc<VkQueryPool>) {
+    self.query_pools.push(query_pool.clone());
+  }
+
   pub(crate) fn is_empty(&self) -> bool {
     self.texture_views.is_empty()
     && self.semaphores.is_empty()
@@ -90,5 +98,6 @@ impl VkLifetimeTrackers {
     && self.frame_buffers.is_empty()
     && self.samplers.is_empty()
     && self.pipelines.is_empty()
+    && self.query_pools.is_empty()
   }
 }
@@ -95,7 +95,7 @@ impl VkShader {
         size: 0,
       };
       for range in buffer_ranges {
-        push_constant_range.size += range.range as u32;
+        push_constant_range.size = push_constant_range.size.max((range.offset + range.range) as u32);
       }
 
       if push_constant_range.size > 128 {
@@ -604,8 +604,7 @@ impl VkPipeline {
       };
       let set_layout = existing_set_layout.unwrap_or_else(|| {
         let mut cache = cache_lock.write().unwrap();
-        cache.insert(hash, Arc::new(VkDescriptorSetLayout::new(&bindings, device)));
-        cache.get(&hash).unwrap().clone()
+        cache.entry(hash).or_insert_with(|| Arc::new(VkDescriptorSetLayout::new(&bindings, device))).clone()
       });
       descriptor_set_layouts[index] = Some(set_layout);
       if index > 0 && descriptor_set_layouts[index - 1].is_none() {
@@ -641,8 +640,7 @@ impl VkPipeline {
     };
     let layout = existing_handle.unwrap_or_else(|| {
       let mut cache = cache_lock.write().unwrap();
-      cache.insert(hash, Arc::new(VkPipelineLayout::new(&descriptor_set_layouts, remapped_push_constant_ranges, device)));
-      cache.get(&hash).unwrap().clone()
+      cache.entry(hash).or_insert_with(|| Arc::new(VkPipelineLayout::new(&descriptor_set_layouts, remapped_push_constant_ranges, device))).clone()
     });
 
     let viewport_info = vk::PipelineViewportStateCreateInfo {
@@ -690,7 +688,7 @@ impl VkPipeline {
     };
 
     let pipeline = unsafe {
-      vk_device.create_graphics_pipelines(vk::PipelineCache::null(), &[ pipeline_create_info ], None).unwrap()[0]
+      vk_device.create_graphics_pipelines(device.pipeline_cache, &[ pipeline_create_info ], None).unwrap()[0]
     };
     Self {
       pipeline,
@@ -737,8 +735,7 @@ impl VkPipeline {
       };
       let set_layout = existing_set_layout.unwrap_or_else(|| {
         let mut cache = cache_lock.write().unwrap();
-        cache.insert(hash, Arc::new(VkDescriptorSetLayout::new(&bindings, device)));
-        cache.get(&hash).unwrap().clone()
+        cache.entry(hash).or_insert_with(|| Arc::new(VkDescriptorSetLayout::new(&bindings, device))).clone()
       });
       descriptor_set_layouts[index] = Some(set_layout);
       if index > 0 && descriptor_set_layouts[index - 1].is_none() {
@@ -768,8 +765,7 @@ impl VkPipeline {
     };
     let layout = existing_handle.unwrap_or_else(|| {
       let mut cache = cache_lock.write().unwrap();
-      cache.insert(hash, Arc::new(VkPipelineLayout::new(&descriptor_set_layouts, push_constants_ranges, device)));
-      cache.get(&hash).unwrap().clone()
+      cache.entry(hash).or_insert_with(|| Arc::new(VkPipelineLayout::new(&descriptor_set_layouts, push_constants_ranges, device))).clone()
     });
 
     let pipeline_create_info = vk::ComputePipelineCreateInfo {
@@ -781,7 +777,7 @@ impl VkPipeline {
       ..Default::default()
     };
     let pipeline = unsafe {
-      device.create_compute_pipelines(vk::PipelineCache::null(), &[ pipeline_create_info ], None).unwrap()[0]
+      device.create_compute_pipelines(device.pipeline_cache, &[ pipeline_create_info ], None).unwrap()[0]
     };
 
     VkPipeline {
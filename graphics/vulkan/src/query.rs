@@ -0,0 +1,115 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use ash::vk;
+
+use sourcerenderer_core::graphics::QueryPool;
+
+use crate::raw::RawVkDevice;
+
+pub struct VkQueryPool {
+  device: Arc<RawVkDevice>,
+  query_pool: vk::QueryPool,
+  query_count: u32,
+  timestamp_period: f32,
+  /// Zero if the graphics queue doesn't support timestamps
+  timestamp_valid_bits: u32,
+  /// Writing a query that wasn't reset or reading one that wasn't written is undefined
+  query_states: Vec<AtomicU8>
+}
+
+const QUERY_STATE_UNINITIALIZED: u8 = 0;
+const QUERY_STATE_RESET: u8 = 1;
+const QUERY_STATE_WRITTEN: u8 = 2;
+
+impl VkQueryPool {
+  pub fn new_timestamp(device: &Arc<RawVkDevice>, query_count: u32) -> Self {
+    let properties = unsafe { device.instance.get_physical_device_properties(device.physical_device) };
+    let queue_families = unsafe { device.instance.get_physical_device_queue_family_properties(device.physical_device) };
+    // Only the queues that support graphics and compute are guaranteed to support timestamps
+    let timestamp_valid_bits = queue_families[device.graphics_queue_info.queue_family_index].timestamp_valid_bits;
+    if timestamp_valid_bits == 0 {
+      println!("The graphics queue doesn't support timestamps, timestamp_compute_and_graphics: {}", properties.limits.timestamp_compute_and_graphics);
+    }
+    let query_pool = unsafe {
+      device.create_query_pool(&vk::QueryPoolCreateInfo {
+        query_type: vk::QueryType::TIMESTAMP,
+        query_count,
+        ..Default::default()
+      }, None)
+    }.unwrap();
+    Self {
+      device: device.clone(),
+      query_pool,
+      query_count,
+      timestamp_period: properties.limits.timestamp_period,
+      timestamp_valid_bits,
+      query_states: (0..query_count).map(|_| AtomicU8::new(QUERY_STATE_UNINITIALIZED)).collect()
+    }
+  }
+
+  pub fn get_handle(&self) -> &vk::QueryPool {
+    &self.query_pool
+  }
+
+  pub(crate) fn supports_timestamps(&self) -> bool {
+    self.timestamp_valid_bits != 0
+  }
+
+  pub(crate) fn mark_reset(&self, first_query: u32, query_count: u32) {
+    for state in &self.query_states[first_query as usize .. (first_query + query_count) as usize] {
+      state.store(QUERY_STATE_RESET, Ordering::Release);
+    }
+  }
+
+  pub(crate) fn is_reset(&self, query_index: u32) -> bool {
+    self.query_states[query_index as usize].load(Ordering::Acquire) == QUERY_STATE_RESET
+  }
+
+  pub(crate) fn mark_written(&self, query_index: u32) {
+    self.query_states[query_index as usize].store(QUERY_STATE_WRITTEN, Ordering::Release);
+  }
+
+  /// Reads back the timestamps in nanoseconds, returns false if they aren't available yet.
+  pub(crate) fn get_timestamps(&self, first_query: u32, results: &mut [u64]) -> bool {
+    debug_assert!(first_query + results.len() as u32 <= self.query_count);
+    if results.is_empty() {
+      return true;
+    }
+    if !self.supports_timestamps() {
+      return false;
+    }
+    let states = &self.query_states[first_query as usize .. first_query as usize + results.len()];
+    if !states.iter().all(|state| state.load(Ordering::Acquire) == QUERY_STATE_WRITTEN) {
+      return false;
+    }
+    let result = unsafe {
+      self.device.get_query_pool_results(self.query_pool, first_query, results.len() as u32, results, vk::QueryResultFlags::TYPE_64)
+    };
+    match result {
+      Ok(_) => {
+        let mask = if self.timestamp_valid_bits >= 64 { u64::MAX } else { (1u64 << self.timestamp_valid_bits) - 1 };
+        for timestamp in results {
+          *timestamp = ((*timestamp & mask) as f64 * self.timestamp_period as f64) as u64;
+        }
+        true
+      }
+      Err(vk::Result::NOT_READY) => false,
+      Err(e) => panic!("Failed to read query results: {:?}", e)
+    }
+  }
+}
+
+impl QueryPool for VkQueryPool {
+  fn query_count(&self) -> u32 {
+    self.query_count
+  }
+}
+
+impl Drop for VkQueryPool {
+  fn drop(&mut self) {
+    unsafe {
+      self.device.destroy_query_pool(self.query_pool, None);
+    }
+  }
+}
@@ -5,12 +5,12 @@ use std::iter::*;
 use ash::vk;
 
 use sourcerenderer_core::graphics::Queue;
-use sourcerenderer_core::graphics::{CommandBufferType, Swapchain};
+use sourcerenderer_core::graphics::{CommandBufferType, Swapchain, TextureUsage};
 
 
 use crate::VkBackend;
 use crate::VkCommandBufferRecorder;
-use crate::command::VkInnerCommandBufferInfo;
+use crate::command::{VkInnerCommandBufferInfo, texture_usage_to_stage};
 use crate::raw::RawVkDevice;
 use crate::command::VkCommandPool;
 use crate::swapchain::{VkSwapchain, VkSwapchainState};
@@ -96,7 +96,10 @@ impl VkQueue {
     let mut command_buffers = SmallVec::<[vk::CommandBuffer; 32]>::new();
     let mut batch = SmallVec::<[vk::SubmitInfo; 8]>::new();
     let vk_queue = guard.queue;
-    for submission in guard.virtual_queue.drain(..) {
+    // The submit infos point into the semaphore lists of the virtual submissions,
+    // so those need to stay alive until everything is submitted.
+    let submissions = std::mem::take(&mut guard.virtual_queue);
+    for submission in &submissions {
       let mut append = false;
       match submission {
         VkVirtualSubmission::CommandBuffer {
@@ -105,7 +108,7 @@ impl VkQueue {
           if fence.is_none() && wait_semaphores.is_empty() && signal_semaphores.is_empty() {
             if let Some(last_info) = batch.last_mut() {
               if last_info.wait_semaphore_count == 0 && last_info.signal_semaphore_count == 0 && command_buffers.len() < command_buffers.capacity() {
-                command_buffers.push(command_buffer);
+                command_buffers.push(*command_buffer);
                 last_info.command_buffer_count += 1;
                 append = true;
               }
@@ -130,7 +133,7 @@ impl VkQueue {
                 p_wait_semaphores: wait_semaphores.as_ptr(),
                 p_wait_dst_stage_mask: wait_stages.as_ptr(),
                 command_buffer_count: 1,
-                p_command_buffers: &command_buffer as *const vk::CommandBuffer,
+                p_command_buffers: command_buffer as *const vk::CommandBuffer,
                 signal_semaphore_count: signal_semaphores.len() as u32,
                 p_signal_semaphores: signal_semaphores.as_ptr(),
                 ..Default::default()
@@ -156,7 +159,7 @@ impl VkQueue {
                 command_buffers.clear();
               }
 
-              command_buffers.push(command_buffer);
+              command_buffers.push(*command_buffer);
               let submit = vk::SubmitInfo {
                 wait_semaphore_count: wait_semaphores.len() as u32,
                 p_wait_semaphores: wait_semaphores.as_ptr(),
@@ -190,14 +193,14 @@ impl VkQueue {
           let present_info = vk::PresentInfoKHR {
             p_swapchains: &*swapchain_handle,
             swapchain_count: 1,
-            p_image_indices: &image_index as *const u32,
+            p_image_indices: image_index as *const u32,
             p_wait_semaphores: wait_semaphores.as_ptr(),
             wait_semaphore_count: wait_semaphores.len() as u32,
             ..Default::default()
           };
           unsafe {
             let result = swapchain.get_loader().queue_present(vk_queue, &present_info);
-            swapchain.set_presented_image(image_index);
+            swapchain.set_presented_image(*image_index);
             match result {
               Ok(suboptimal) => {
                 if suboptimal {
@@ -225,6 +228,10 @@ impl VkQueue {
         }
       }
     }
+
+    // Hand the allocation back so we don't reallocate the virtual queue every frame
+    guard.virtual_queue = submissions;
+    guard.virtual_queue.clear();
   }
 
   pub fn submit_transfer(&self, command_buffer: &VkTransferCommandBuffer) {
@@ -243,7 +250,7 @@ impl VkQueue {
     guard.virtual_queue.push(submission);
   }
 
-  pub fn submit(&self, command_buffer: VkCommandBufferSubmission, fence: Option<&Arc<VkFence>>, wait_semaphores: &[ &VkSemaphore ], signal_semaphores: &[ &VkSemaphore ]) {
+  pub fn submit(&self, command_buffer: VkCommandBufferSubmission, fence: Option<&Arc<VkFence>>, wait_semaphores: &[ (&VkSemaphore, vk::PipelineStageFlags) ], signal_semaphores: &[ &VkSemaphore ]) {
     assert_eq!(command_buffer.command_buffer_type(), CommandBufferType::PRIMARY);
     debug_assert_eq!(command_buffer.queue_family_index(), self.info.queue_family_index as u32);
     debug_assert!(fence.is_none() || !fence.unwrap().is_signalled());
@@ -260,9 +267,9 @@ impl VkQueue {
 
     let mut cmd_buffer_mut = command_buffer;
     cmd_buffer_mut.mark_submitted();
-    let wait_semaphore_handles = wait_semaphores.iter().map(|s| *s.get_handle()).collect::<SmallVec<[vk::Semaphore; 4]>>();
+    let wait_semaphore_handles = wait_semaphores.iter().map(|(s, _)| *s.get_handle()).collect::<SmallVec<[vk::Semaphore; 4]>>();
     let signal_semaphore_handles = signal_semaphores.iter().map(|s| *s.get_handle()).collect::<SmallVec<[vk::Semaphore; 4]>>();
-    let stage_masks = wait_semaphores.iter().map(|_| vk::PipelineStageFlags::TOP_OF_PIPE).collect::<SmallVec<[vk::PipelineStageFlags; 4]>>();
+    let stage_masks = wait_semaphores.iter().map(|(_, stage)| *stage).collect::<SmallVec<[vk::PipelineStageFlags; 4]>>();
 
     let vk_cmd_buffer = *cmd_buffer_mut.get_handle();
     let submission = VkVirtualSubmission::CommandBuffer {
@@ -314,16 +321,19 @@ impl Queue<VkBackend> for VkQueue {
     self.threads.get_thread_local().get_frame_local().get_command_buffer()
   }
 
-  fn submit(&self, submission: VkCommandBufferSubmission, fence: Option<&Arc<VkFence>>, wait_semaphores: &[&Arc<VkSemaphore>], signal_semaphores: &[&Arc<VkSemaphore>]) {
-    let mut wait_semaphore_refs = SmallVec::<[&VkSemaphore; 8]>::with_capacity(wait_semaphores.len());
+  fn submit(&self, submission: VkCommandBufferSubmission, fence: Option<&Arc<VkFence>>, wait_semaphores: &[(&Arc<VkSemaphore>, TextureUsage)], signal_semaphores: &[&Arc<VkSemaphore>]) {
+    let mut wait_semaphore_refs = SmallVec::<[(&VkSemaphore, vk::PipelineStageFlags); 8]>::with_capacity(wait_semaphores.len());
     let mut signal_semaphore_refs = SmallVec::<[&VkSemaphore; 8]>::with_capacity(signal_semaphores.len());
-    for sem in wait_semaphores {
-      wait_semaphore_refs.push(sem.as_ref());
+    for (sem, usage) in wait_semaphores {
+      let mut stage = texture_usage_to_stage(*usage);
+      if stage.is_empty() {
+        stage = vk::PipelineStageFlags::ALL_COMMANDS;
+      }
+      wait_semaphore_refs.push((sem.as_ref(), stage));
     }
     for sem in signal_semaphores {
       signal_semaphore_refs.push(sem.as_ref());
     }
-    // TODO: clean up
 
     self.submit(submission, fence, &wait_semaphore_refs, &signal_semaphore_refs);
     self.process_submissions(); // TODO bring back threaded submission
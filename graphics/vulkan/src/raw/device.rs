@@ -13,9 +13,11 @@ pub struct RawVkDevice {
   pub physical_device: vk::PhysicalDevice,
   pub instance: Arc<RawVkInstance>,
   pub extensions: VkAdapterExtensionSupport,
+  pub features: vk::PhysicalDeviceFeatures,
   pub graphics_queue_info: VkQueueInfo,
   pub compute_queue_info: Option<VkQueueInfo>,
-  pub transfer_queue_info: Option<VkQueueInfo>
+  pub transfer_queue_info: Option<VkQueueInfo>,
+  pub pipeline_cache: vk::PipelineCache
 }
 
 impl Deref for RawVkDevice {
@@ -30,6 +32,7 @@ impl Drop for RawVkDevice {
   fn drop(&mut self) {
     self.allocator.destroy();
     unsafe {
+      self.device.destroy_pipeline_cache(self.pipeline_cache, None);
       self.device.destroy_device(None);
     }
   }
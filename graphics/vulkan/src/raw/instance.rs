@@ -19,6 +19,7 @@ pub struct RawVkInstance {
   pub debug_utils: Option<RawVkDebugUtils>,
   pub instance: ash::Instance,
   pub entry: ash::Entry,
+  pub supports_swapchain_colorspace: bool
 }
 
 impl Deref for RawVkInstance {
@@ -97,10 +97,11 @@ impl VkShared {
         return renderpass.clone();
       }
     }
-    let renderpass = Arc::new(VkRenderPass::new(&self.device, info));
     let mut cache = self.render_passes.write().unwrap();
-    cache.insert(info.clone(), renderpass.clone());
-    renderpass
+    // Another thread might have created it in the meantime
+    cache.entry(info.clone())
+      .or_insert_with(|| Arc::new(VkRenderPass::new(&self.device, info)))
+      .clone()
   }
 
   pub(crate) fn get_framebuffer(&self, render_pass: &Arc<VkRenderPass>, attachments: &[&Arc<VkTextureView>]) -> Arc<VkFrameBuffer> {
@@ -112,10 +113,10 @@ impl VkShared {
       }
     }
     let (width, height) = attachments.iter().fold((0, 0), |old, a| (a.texture().get_info().width.max(old.0), a.texture().get_info().height.max(old.1)));
-    let frame_buffer = Arc::new(VkFrameBuffer::new(&self.device, width, height, render_pass, attachments));
     let mut cache = self.frame_buffers.write().unwrap();
-    cache.insert(key, frame_buffer.clone());
-    frame_buffer
+    cache.entry(key)
+      .or_insert_with(|| Arc::new(VkFrameBuffer::new(&self.device, width, height, render_pass, attachments)))
+      .clone()
   }
 
   #[inline]
@@ -7,7 +7,7 @@ use crossbeam_utils::atomic::AtomicCell;
 use ash::vk;
 use ash::extensions::khr::Swapchain as SwapchainLoader;
 
-use sourcerenderer_core::graphics::{SampleCount, Swapchain, SwapchainError, TextureInfo, TextureUsage};
+use sourcerenderer_core::graphics::{PresentMode, SampleCount, Swapchain, SwapchainError, TextureDimension, TextureInfo, TextureUsage};
 use sourcerenderer_core::graphics::Texture;
 use sourcerenderer_core::graphics::Format;
 
@@ -37,7 +37,8 @@ pub struct VkSwapchain {
   instance: Arc<RawVkInstance>,
   surface: Arc<VkSurface>,
   device: Arc<RawVkDevice>,
-  vsync: bool,
+  present_mode: PresentMode,
+  preferred_color_space: Option<vk::ColorSpaceKHR>,
   state: AtomicCell<VkSwapchainState>,
   acquired_image: AtomicU32,
   presented_image: AtomicU32,
@@ -45,7 +46,7 @@ pub struct VkSwapchain {
 }
 
 impl VkSwapchain {
-  fn new_internal(vsync: bool, width: u32, height: u32, device: &Arc<RawVkDevice>, surface: &Arc<VkSurface>, old_swapchain: Option<&Self>) -> Result<Arc<Self>, SwapchainError> {
+  fn new_internal(present_mode: PresentMode, preferred_color_space: Option<vk::ColorSpaceKHR>, width: u32, height: u32, device: &Arc<RawVkDevice>, surface: &Arc<VkSurface>, old_swapchain: Option<&Self>) -> Result<Arc<Self>, SwapchainError> {
     if surface.is_lost() {
       return Err(SwapchainError::SurfaceLost);
     }
@@ -67,7 +68,7 @@ impl VkSwapchain {
           }
         }
       };
-      let present_mode = VkSwapchain::pick_present_mode(vsync, present_modes);
+      let vk_present_mode = VkSwapchain::pick_present_mode(present_mode, present_modes);
       let swapchain_loader = SwapchainLoader::new(&instance.instance, vk_device);
 
       let capabilities = match surface.get_capabilities(&physical_device) {
@@ -94,7 +95,9 @@ impl VkSwapchain {
           }
         }
       };
-      let format = VkSwapchain::pick_format(&formats);
+      // HDR color spaces can only be used with VK_EXT_swapchain_colorspace
+      let preferred_color_space = preferred_color_space.filter(|_| device.instance.supports_swapchain_colorspace);
+      let format = VkSwapchain::pick_format(&formats, preferred_color_space);
 
       let (width, height) = VkSwapchain::pick_extent(&capabilities, width, height);
       let extent = vk::Extent2D {
@@ -182,7 +185,7 @@ impl VkSwapchain {
           image_extent: extent,
           image_array_layers: 1,
           image_usage: vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::TRANSFER_DST,
-          present_mode,
+          present_mode: vk_present_mode,
           image_sharing_mode: vk::SharingMode::EXCLUSIVE,
           pre_transform: transform,
           composite_alpha: if capabilities.supported_composite_alpha.contains(vk::CompositeAlphaFlagsKHR::OPAQUE) {
@@ -211,6 +214,7 @@ impl VkSwapchain {
         .iter()
         .map(|image|
           Arc::new(VkTexture::from_image(device, *image, TextureInfo {
+            dimension: TextureDimension::Dim2D,
             format: surface_vk_format_to_core(format.format),
             width,
             height,
@@ -218,7 +222,8 @@ impl VkSwapchain {
             mip_levels: 1u32,
             depth: 1u32,
             samples: SampleCount::Samples1,
-            usage: TextureUsage::RENDER_TARGET | TextureUsage::COPY_DST | TextureUsage::BLIT_DST | TextureUsage::PRESENT
+            usage: TextureUsage::RENDER_TARGET | TextureUsage::COPY_DST | TextureUsage::BLIT_DST | TextureUsage::PRESENT,
+            is_cubemap: false
           })))
         .collect();
 
@@ -237,7 +242,8 @@ impl VkSwapchain {
         instance: device.instance.clone(),
         surface: surface.clone(),
         device: device.clone(),
-        vsync,
+        present_mode,
+        preferred_color_space,
         state: AtomicCell::new(VkSwapchainState::Okay),
         presented_image: AtomicU32::new(0),
         acquired_image: AtomicU32::new(0),
@@ -246,8 +252,14 @@ impl VkSwapchain {
     }
   }
 
-  pub fn new(vsync: bool, width: u32, height: u32, device: &Arc<RawVkDevice>, surface: &Arc<VkSurface>) -> Result<Arc<Self>, SwapchainError> {
-    VkSwapchain::new_internal(vsync, width, height, device, surface, None)
+  pub fn new(present_mode: PresentMode, width: u32, height: u32, device: &Arc<RawVkDevice>, surface: &Arc<VkSurface>) -> Result<Arc<Self>, SwapchainError> {
+    VkSwapchain::new_internal(present_mode, None, width, height, device, surface, None)
+  }
+
+  /// Prefers a surface format in the given color space (like HDR10_ST2084_EXT or EXTENDED_SRGB_LINEAR_EXT)
+  /// and falls back to an SDR format if the surface doesn't support it.
+  pub fn new_with_color_space(present_mode: PresentMode, preferred_color_space: vk::ColorSpaceKHR, width: u32, height: u32, device: &Arc<RawVkDevice>, surface: &Arc<VkSurface>) -> Result<Arc<Self>, SwapchainError> {
+    VkSwapchain::new_internal(present_mode, Some(preferred_color_space), width, height, device, surface, None)
   }
 
   pub fn pick_extent(capabilities: &vk::SurfaceCapabilitiesKHR, preferred_width: u32, preferred_height: u32) -> (u32, u32) {
@@ -261,7 +273,19 @@ impl VkSwapchain {
     }
   }
 
-  pub fn pick_format(formats: &[vk::SurfaceFormatKHR]) -> vk::SurfaceFormatKHR {
+  pub fn pick_format(formats: &[vk::SurfaceFormatKHR], preferred_color_space: Option<vk::ColorSpaceKHR>) -> vk::SurfaceFormatKHR {
+    if let Some(color_space) = preferred_color_space {
+      let preferred_format = formats
+        .iter()
+        .find(|&format|
+          format.color_space == color_space
+          && (format.format == vk::Format::A2B10G10R10_UNORM_PACK32 || format.format == vk::Format::R16G16B16A16_SFLOAT)
+        );
+      if let Some(format) = preferred_format {
+        return *format;
+      }
+    }
+
     if formats.len() == 1 && formats[0].format == vk::Format::UNDEFINED {
       vk::SurfaceFormatKHR {
         format: vk::Format::B8G8R8A8_UNORM,
@@ -286,20 +310,19 @@ impl VkSwapchain {
     image_count
   }
 
-  unsafe fn pick_present_mode(vsync: bool, present_modes: Vec<vk::PresentModeKHR>) -> vk::PresentModeKHR {
-    if !vsync {
-      if let Some(mode) = present_modes
-        .iter()
-        .filter(|&&mode| mode == vk::PresentModeKHR::IMMEDIATE)
-        .next() {
-        return *mode;
-      }
-    }
-
-    *present_modes
-      .iter()
-      .filter(|&&mode| mode == vk::PresentModeKHR::FIFO)
-      .next().expect("No compatible present mode found")
+  fn pick_present_mode(present_mode: PresentMode, present_modes: Vec<vk::PresentModeKHR>) -> vk::PresentModeKHR {
+    // Mailbox doesn't tear and still doesn't block, so it's the closest match for immediate
+    let fallbacks: &[PresentMode] = match present_mode {
+      PresentMode::Immediate => &[PresentMode::Immediate, PresentMode::Mailbox],
+      PresentMode::Mailbox => &[PresentMode::Mailbox],
+      PresentMode::FifoRelaxed => &[PresentMode::FifoRelaxed],
+      PresentMode::Fifo => &[]
+    };
+    // FIFO is the only mode that's guaranteed to be supported
+    fallbacks.iter()
+      .map(|mode| present_mode_to_vk(*mode))
+      .find(|mode| present_modes.contains(mode))
+      .unwrap_or(vk::PresentModeKHR::FIFO)
   }
 
   pub fn get_loader(&self) -> &SwapchainLoader {
@@ -326,15 +349,14 @@ impl VkSwapchain {
     self.textures.first().unwrap().get_info().height
   }
 
-  #[allow(clippy::logic_bug)]
   pub fn acquire_back_buffer(&self, semaphore: &VkSemaphore) -> VkResult<(u32, bool)> {
     while self.presented_image.load(Ordering::SeqCst) != self.acquired_image.load(Ordering::SeqCst) {}
     let result = {
       let swapchain_handle = self.get_handle();
       unsafe { self.swapchain_loader.acquire_next_image(*swapchain_handle, std::u64::MAX, *semaphore.get_handle(), vk::Fence::null()) }
     };
-    if let Ok((image, is_optimal)) = result {
-      if !is_optimal && false {
+    if let Ok((image, is_suboptimal)) = result {
+      if is_suboptimal {
         self.set_state(VkSwapchainState::Suboptimal);
       }
       self.acquired_image.store(image, Ordering::SeqCst);
@@ -394,14 +416,14 @@ impl Drop for VkSwapchain {
 impl Swapchain<VkBackend> for VkSwapchain {
   fn recreate(old: &Self, width: u32, height: u32) -> Result<Arc<Self>, SwapchainError> {
     if old.state() == VkSwapchainState::Retired {
-      VkSwapchain::new_internal(old.vsync, width, height, &old.device, &old.surface, None)
+      VkSwapchain::new_internal(old.present_mode, old.preferred_color_space, width, height, &old.device, &old.surface, None)
     } else {
-      VkSwapchain::new_internal(old.vsync, width, height, &old.device, &old.surface, Some(&old))
+      VkSwapchain::new_internal(old.present_mode, old.preferred_color_space, width, height, &old.device, &old.surface, Some(&old))
     }
   }
 
   fn recreate_on_surface(old: &Self, surface: &Arc<VkSurface>, width: u32, height: u32) -> Result<Arc<Self>, SwapchainError> {
-    VkSwapchain::new_internal(old.vsync, width, height, &old.device, surface, None)
+    VkSwapchain::new_internal(old.present_mode, old.preferred_color_space, width, height, &old.device, surface, None)
   }
 
   fn sample_count(&self) -> SampleCount {
@@ -416,16 +438,20 @@ impl Swapchain<VkBackend> for VkSwapchain {
     &self.surface
   }
 
-  fn prepare_back_buffer(&self, semaphore: &Arc<VkSemaphore>) -> Option<Arc<VkTextureView>> {
-    let res = self.acquire_back_buffer(semaphore);
-    res
-      .ok()
-      .and_then(|(img_index, _optimal)| // TODO: handle optimal
-        //optimal.then(||
-        Some(
-          self.views.get(img_index as usize).unwrap().clone()
-        )
-      )
+  fn prepare_back_buffer(&self, semaphore: &Arc<VkSemaphore>) -> Result<Arc<VkTextureView>, SwapchainError> {
+    // A previous present might have found out that the swapchain needs to be recreated.
+    match self.state() {
+      VkSwapchainState::Okay => {}
+      VkSwapchainState::Suboptimal | VkSwapchainState::OutOfDate => { return Err(SwapchainError::OutOfDate); }
+      VkSwapchainState::Retired => { return Err(SwapchainError::SurfaceLost); }
+    }
+    if self.surface.is_lost() {
+      return Err(SwapchainError::SurfaceLost);
+    }
+
+    let (img_index, _suboptimal) = self.acquire_back_buffer(semaphore)
+      .map_err(|err| if self.surface.is_lost() { SwapchainError::SurfaceLost } else { vk_result_to_swapchain_error(err) })?;
+    Ok(self.views.get(img_index as usize).unwrap().clone())
   }
 
   fn width(&self) -> u32 {
@@ -450,10 +476,82 @@ pub(crate) enum VkSwapchainAcquireResult<'a> {
   DeviceLost
 }
 
+pub(crate) fn vk_result_to_swapchain_error(result: vk::Result) -> SwapchainError {
+  match result {
+    vk::Result::ERROR_OUT_OF_DATE_KHR | vk::Result::SUBOPTIMAL_KHR => SwapchainError::OutOfDate,
+    vk::Result::ERROR_SURFACE_LOST_KHR => SwapchainError::SurfaceLost,
+    _ => SwapchainError::Other
+  }
+}
+
 fn surface_vk_format_to_core(format: vk::Format) -> Format {
   match format {
     vk::Format::B8G8R8A8_UNORM => Format::BGRA8UNorm,
     vk::Format::R8G8B8A8_UNORM => Format::RGBA8,
+    vk::Format::A2B10G10R10_UNORM_PACK32 => Format::RGB10A2,
+    vk::Format::R16G16B16A16_SFLOAT => Format::RGBA16Float,
     _ => panic!("Unsupported format: {:?}", format)
   }
 }
+
+fn present_mode_to_vk(present_mode: PresentMode) -> vk::PresentModeKHR {
+  match present_mode {
+    PresentMode::Fifo => vk::PresentModeKHR::FIFO,
+    PresentMode::FifoRelaxed => vk::PresentModeKHR::FIFO_RELAXED,
+    PresentMode::Mailbox => vk::PresentModeKHR::MAILBOX,
+    PresentMode::Immediate => vk::PresentModeKHR::IMMEDIATE
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn surface_format(format: vk::Format, color_space: vk::ColorSpaceKHR) -> vk::SurfaceFormatKHR {
+    vk::SurfaceFormatKHR { format, color_space }
+  }
+
+  #[test]
+  fn hdr_format_is_picked_when_requested() {
+    let formats = [
+      surface_format(vk::Format::B8G8R8A8_UNORM, vk::ColorSpaceKHR::SRGB_NONLINEAR),
+      surface_format(vk::Format::A2B10G10R10_UNORM_PACK32, vk::ColorSpaceKHR::HDR10_ST2084_EXT)
+    ];
+    let format = VkSwapchain::pick_format(&formats, Some(vk::ColorSpaceKHR::HDR10_ST2084_EXT));
+    assert_eq!(format.format, vk::Format::A2B10G10R10_UNORM_PACK32);
+    assert_eq!(format.color_space, vk::ColorSpaceKHR::HDR10_ST2084_EXT);
+
+    let format = VkSwapchain::pick_format(&formats, None);
+    assert_eq!(format.format, vk::Format::B8G8R8A8_UNORM);
+  }
+
+  #[test]
+  fn sdr_format_is_picked_without_hdr_support() {
+    let formats = [
+      surface_format(vk::Format::R8G8B8A8_UNORM, vk::ColorSpaceKHR::SRGB_NONLINEAR)
+    ];
+    let format = VkSwapchain::pick_format(&formats, Some(vk::ColorSpaceKHR::HDR10_ST2084_EXT));
+    assert_eq!(format.format, vk::Format::R8G8B8A8_UNORM);
+    assert_eq!(format.color_space, vk::ColorSpaceKHR::SRGB_NONLINEAR);
+  }
+
+  #[test]
+  fn vk_results_map_to_swapchain_errors() {
+    assert_eq!(vk_result_to_swapchain_error(vk::Result::ERROR_OUT_OF_DATE_KHR), SwapchainError::OutOfDate);
+    assert_eq!(vk_result_to_swapchain_error(vk::Result::SUBOPTIMAL_KHR), SwapchainError::OutOfDate);
+    assert_eq!(vk_result_to_swapchain_error(vk::Result::ERROR_SURFACE_LOST_KHR), SwapchainError::SurfaceLost);
+    assert_eq!(vk_result_to_swapchain_error(vk::Result::ERROR_DEVICE_LOST), SwapchainError::Other);
+  }
+
+  #[test]
+  fn unsupported_present_modes_fall_back_to_fifo() {
+    let supported = vec![vk::PresentModeKHR::FIFO];
+    for &present_mode in &[PresentMode::Immediate, PresentMode::Mailbox, PresentMode::FifoRelaxed, PresentMode::Fifo] {
+      assert_eq!(VkSwapchain::pick_present_mode(present_mode, supported.clone()), vk::PresentModeKHR::FIFO);
+    }
+
+    let supported = vec![vk::PresentModeKHR::FIFO, vk::PresentModeKHR::MAILBOX];
+    assert_eq!(VkSwapchain::pick_present_mode(PresentMode::Immediate, supported.clone()), vk::PresentModeKHR::MAILBOX);
+    assert_eq!(VkSwapchain::pick_present_mode(PresentMode::FifoRelaxed, supported), vk::PresentModeKHR::FIFO);
+  }
+}
@@ -112,11 +112,31 @@ impl VkFenceInner {
   }
 
   pub fn await_signal(&self) {
+    let signalled = self.wait(std::u64::MAX);
+    debug_assert!(signalled);
+  }
+
+  pub fn wait(&self, timeout_ns: u64) -> bool {
+    if self.state.load() == VkFenceState::Signalled {
+      return true;
+    }
+
     let vk_device = &self.device.device;
     let fence_guard = self.fence.lock().unwrap();
-    unsafe {
-      vk_device.wait_for_fences(&[*fence_guard], true, std::u64::MAX).unwrap();
+    let result = unsafe {
+      vk_device.wait_for_fences(&[*fence_guard], true, timeout_ns)
+    };
+    match result {
+      Ok(_) => {
+        self.state.store(VkFenceState::Signalled);
+        true
+      }
+      Err(vk::Result::TIMEOUT) => false,
+      Err(e) => panic!("Waiting for fence failed: {:?}", e)
     }
+  }
+
+  pub(crate) fn mark_signalled(&self) {
     self.state.store(VkFenceState::Signalled);
   }
 
@@ -190,6 +210,15 @@ impl Fence for VkFence {
   fn await_signal(&self) {
     self.inner.await_signal();
   }
+
+  fn wait(&self, timeout_ns: u64) -> bool {
+    self.inner.wait(timeout_ns)
+  }
+
+  fn reset(&self) {
+    debug_assert_ne!(self.inner.state(), VkFenceState::Submitted);
+    self.inner.reset();
+  }
 }
 
 pub struct VkEvent {
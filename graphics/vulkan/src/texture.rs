@@ -5,6 +5,7 @@ use ash::vk;
 use sourcerenderer_core::graphics::TextureDepthStencilView;
 use sourcerenderer_core::graphics::TextureRenderTargetView;
 use sourcerenderer_core::graphics::TextureUsage;
+use sourcerenderer_core::graphics::TextureDimension;
 use sourcerenderer_core::graphics::{AddressMode, Filter, SamplerInfo, Texture, TextureInfo, TextureShaderResourceView, TextureShaderResourceViewInfo, TextureUnorderedAccessView};
 
 use crate::{VkBackend, raw::RawVkDevice};
@@ -27,12 +28,12 @@ pub struct VkTexture {
 impl VkTexture {
   pub fn new(device: &Arc<RawVkDevice>, info: &TextureInfo, name: Option<&str>) -> Self {
     let create_info = vk::ImageCreateInfo {
-      flags: vk::ImageCreateFlags::empty(),
+      flags: if info.is_cubemap { vk::ImageCreateFlags::CUBE_COMPATIBLE } else { vk::ImageCreateFlags::empty() },
       tiling: vk::ImageTiling::OPTIMAL,
       initial_layout: vk::ImageLayout::UNDEFINED,
       sharing_mode: vk::SharingMode::EXCLUSIVE,
       usage: texture_usage_to_vk(info.usage),
-      image_type: vk::ImageType::TYPE_2D, // FIXME: if info.height <= 1 { vk::ImageType::TYPE_1D } else if info.depth <= 1 { vk::ImageType::TYPE_2D } else { vk::ImageType::TYPE_3D},
+      image_type: image_type(info),
       extent: vk::Extent3D {
         width: max(1, info.width),
         height: max(1, info.height),
@@ -84,6 +85,24 @@ impl VkTexture {
   }
 }
 
+fn image_type(info: &TextureInfo) -> vk::ImageType {
+  match info.dimension {
+    TextureDimension::Dim1D => vk::ImageType::TYPE_1D,
+    TextureDimension::Dim2D => vk::ImageType::TYPE_2D,
+    TextureDimension::Dim3D => vk::ImageType::TYPE_3D
+  }
+}
+
+fn shader_resource_view_type(info: &TextureInfo, array_level_length: u32) -> vk::ImageViewType {
+  match info.dimension {
+    TextureDimension::Dim3D => vk::ImageViewType::TYPE_3D,
+    TextureDimension::Dim1D => if array_level_length > 1 { vk::ImageViewType::TYPE_1D_ARRAY } else { vk::ImageViewType::TYPE_1D },
+    TextureDimension::Dim2D => if info.is_cubemap && array_level_length % 6 == 0 {
+      if array_level_length > 6 { vk::ImageViewType::CUBE_ARRAY } else { vk::ImageViewType::CUBE }
+    } else if array_level_length > 1 { vk::ImageViewType::TYPE_2D_ARRAY } else { vk::ImageViewType::TYPE_2D }
+  }
+}
+
 fn texture_usage_to_vk(usage: TextureUsage) -> vk::ImageUsageFlags {
   let mut flags = vk::ImageUsageFlags::empty();
 
@@ -198,7 +217,7 @@ impl VkTextureView {
   pub(crate) fn new_shader_resource_view(device: &Arc<RawVkDevice>, texture: &Arc<VkTexture>, info: &TextureShaderResourceViewInfo) -> Self {
     let view_create_info = vk::ImageViewCreateInfo {
       image: *texture.get_handle(),
-      view_type: vk::ImageViewType::TYPE_2D, // FIXME: if texture.get_info().height <= 1 { vk::ImageViewType::TYPE_1D } else if texture.get_info().depth <= 1 { vk::ImageViewType::TYPE_2D } else { vk::ImageViewType::TYPE_3D},
+      view_type: shader_resource_view_type(texture.get_info(), info.array_level_length),
       format: format_to_vk(texture.info.format),
       components: vk::ComponentMapping {
         r: vk::ComponentSwizzle::IDENTITY,
@@ -236,7 +255,11 @@ impl VkTextureView {
     let info = texture.get_info();
     let vk_info = vk::ImageViewCreateInfo {
       image: *texture.get_handle(),
-      view_type: if texture.get_info().height <= 1 { vk::ImageViewType::TYPE_1D } else if texture.get_info().depth <= 1 { vk::ImageViewType::TYPE_2D } else { vk::ImageViewType::TYPE_3D},
+      view_type: match info.dimension {
+        TextureDimension::Dim1D => vk::ImageViewType::TYPE_1D,
+        TextureDimension::Dim2D => vk::ImageViewType::TYPE_2D,
+        TextureDimension::Dim3D => vk::ImageViewType::TYPE_3D
+      },
       format: format_to_vk(info.format),
       components: vk::ComponentMapping {
         r: vk::ComponentSwizzle::IDENTITY,
@@ -335,11 +358,11 @@ impl VkSampler {
   pub fn new(device: &Arc<RawVkDevice>, info: &SamplerInfo) -> Self {
     let sampler_create_info = vk::SamplerCreateInfo {
       mag_filter: filter_to_vk(info.mag_filter),
-      min_filter: filter_to_vk(info.mag_filter),
+      min_filter: filter_to_vk(info.min_filter),
       mipmap_mode: filter_to_vk_mip(info.mip_filter),
       address_mode_u: address_mode_to_vk(info.address_mode_u),
       address_mode_v: address_mode_to_vk(info.address_mode_v),
-      address_mode_w: address_mode_to_vk(info.address_mode_u),
+      address_mode_w: address_mode_to_vk(info.address_mode_w),
       mip_lod_bias: info.mip_bias,
       anisotropy_enable: (info.max_anisotropy.abs() >= 1.0f32) as u32,
       max_anisotropy: info.max_anisotropy,
@@ -388,3 +411,47 @@ impl PartialEq for VkSampler {
 }
 
 impl Eq for VkSampler {}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use sourcerenderer_core::graphics::{Format, SampleCount};
+
+  fn info(dimension: TextureDimension, width: u32, height: u32, depth: u32, array_length: u32) -> TextureInfo {
+    TextureInfo {
+      dimension,
+      format: Format::RGBA8,
+      width,
+      height,
+      depth,
+      mip_levels: 1,
+      array_length,
+      samples: SampleCount::Samples1,
+      usage: TextureUsage::FRAGMENT_SHADER_SAMPLED | TextureUsage::COPY_DST,
+      is_cubemap: false
+    }
+  }
+
+  #[test]
+  fn lut_is_a_3d_image_with_a_3d_view() {
+    let lut = info(TextureDimension::Dim3D, 16, 16, 16, 1);
+    assert_eq!(image_type(&lut), vk::ImageType::TYPE_3D);
+    assert_eq!(shader_resource_view_type(&lut, 1), vk::ImageViewType::TYPE_3D);
+  }
+
+  #[test]
+  fn short_2d_textures_stay_2d() {
+    let strip = info(TextureDimension::Dim2D, 256, 1, 1, 1);
+    assert_eq!(image_type(&strip), vk::ImageType::TYPE_2D);
+    assert_eq!(shader_resource_view_type(&strip, 1), vk::ImageViewType::TYPE_2D);
+    assert_eq!(shader_resource_view_type(&info(TextureDimension::Dim2D, 256, 1, 1, 4), 4), vk::ImageViewType::TYPE_2D_ARRAY);
+  }
+
+  #[test]
+  fn one_dimensional_textures_only_on_request() {
+    let gradient = info(TextureDimension::Dim1D, 256, 1, 1, 2);
+    assert_eq!(image_type(&gradient), vk::ImageType::TYPE_1D);
+    assert_eq!(shader_resource_view_type(&gradient, 1), vk::ImageViewType::TYPE_1D);
+    assert_eq!(shader_resource_view_type(&gradient, 2), vk::ImageViewType::TYPE_1D_ARRAY);
+  }
+}
@@ -102,6 +102,8 @@ impl VkThreadManager {
   }
 
   pub fn end_frame(&self, fence: &Arc<VkFence>) {
+    // The shared allocator never gets reset, so it needs to get rid of big buffers nobody uses anymore here
+    self.shared.get_buffer_allocator().remove_unused_big_buffers();
     let counter = self.frame_counter.fetch_add(1, Ordering::SeqCst);
     let mut guard = self.prepared_frames.lock().unwrap();
     guard.push_back(VkFrame {
@@ -5,8 +5,10 @@ use std::sync::{Arc, Mutex};
 use std::collections::VecDeque;
 use crate::buffer::VkBufferSlice;
 use crate::VkFence;
+use crate::command::buffer_usage_to_access;
+use crate::format::format_to_vk;
 
-use sourcerenderer_core::graphics::Texture;
+use sourcerenderer_core::graphics::{Buffer, BufferUsage, Texture};
 use std::cmp::{max, min};
 use std::ffi::CString;
 use crate::{VkShared, VkLifetimeTrackers};
@@ -53,6 +55,8 @@ struct VkTransferCommands {
   pre_barriers: Vec<VkTransferBarrier>,
   copies: Vec<VkTransferCopy>,
   post_barriers: Vec<(Option<Arc<VkFence>>, VkTransferBarrier)>,
+  /// Recorded after the post barriers, so the first mip level is already initialized
+  mip_generations: Vec<Arc<VkTexture>>,
   used_cmd_buffers: VecDeque<Box<VkTransferCommandBuffer>>,
   pool: Arc<RawVkCommandPool>,
   fence: Arc<VkFence>,
@@ -71,6 +75,8 @@ impl VkTransfer {
     let graphics_pool = Arc::new(RawVkCommandPool::new(device, &graphics_pool_info).unwrap());
 
     let transfer_commands = if let Some(transfer_queue) = transfer_queue {
+      // The ownership transfer barriers in init_texture_async rely on the two queues being in different families
+      debug_assert_ne!(transfer_queue.get_queue_family_index(), graphics_queue.get_queue_family_index());
       let transfer_pool_info = vk::CommandPoolCreateInfo {
         flags: vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER | vk::CommandPoolCreateFlags::TRANSIENT,
         queue_family_index: transfer_queue.get_queue_family_index(),
@@ -83,6 +89,7 @@ impl VkTransfer {
         pre_barriers: Vec::new(),
         copies: Vec::new(),
         post_barriers: Vec::new(),
+        mip_generations: Vec::new(),
         used_cmd_buffers: VecDeque::new(),
         fence: transfer_fence,
         queue_name: "Transfer",
@@ -98,6 +105,7 @@ impl VkTransfer {
           pre_barriers: Vec::new(),
           copies: Vec::new(),
           post_barriers: Vec::new(),
+          mip_generations: Vec::new(),
           pool: graphics_pool,
           used_cmd_buffers: VecDeque::new(),
           fence: graphics_fence,
@@ -121,8 +129,8 @@ impl VkTransfer {
         dst_access_mask: vk::AccessFlags::TRANSFER_WRITE,
         old_layout: vk::ImageLayout::UNDEFINED,
         new_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-        src_queue_family_index: self.graphics_queue.get_queue_family_index(),
-        dst_queue_family_index: self.graphics_queue.get_queue_family_index(),
+        src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+        dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
         subresource_range: vk::ImageSubresourceRange {
           base_mip_level: mip_level,
           level_count: 1,
@@ -166,8 +174,8 @@ impl VkTransfer {
         dst_access_mask: vk::AccessFlags::MEMORY_READ,
         old_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
         new_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
-        src_queue_family_index: self.graphics_queue.get_queue_family_index(),
-        dst_queue_family_index: self.graphics_queue.get_queue_family_index(),
+        src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+        dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
         subresource_range: vk::ImageSubresourceRange {
           base_mip_level: mip_level,
           level_count: 1,
@@ -180,7 +188,104 @@ impl VkTransfer {
     })));
   }
 
+  pub fn init_texture_mips(&self, texture: &Arc<VkTexture>, slices_per_mip: &[Arc<VkBufferSlice>], array_layer: u32) {
+    if slices_per_mip.is_empty() {
+      return;
+    }
+    debug_assert!(slices_per_mip.len() as u32 <= texture.get_info().mip_levels);
+
+    let subresource_range = vk::ImageSubresourceRange {
+      base_mip_level: 0,
+      level_count: slices_per_mip.len() as u32,
+      base_array_layer: array_layer,
+      aspect_mask: vk::ImageAspectFlags::COLOR,
+      layer_count: 1
+    };
+
+    let mut guard = self.inner.lock().unwrap();
+    guard.graphics.pre_barriers.push(VkTransferBarrier::Image (
+      vk::ImageMemoryBarrier {
+        src_access_mask: vk::AccessFlags::empty(),
+        dst_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+        old_layout: vk::ImageLayout::UNDEFINED,
+        new_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+        dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+        subresource_range,
+        image: *texture.get_handle(),
+        ..Default::default()
+      }));
+
+    for (mip_level, src_buffer) in slices_per_mip.iter().enumerate() {
+      let mip_level = mip_level as u32;
+      guard.graphics.copies.push(VkTransferCopy::BufferToImage {
+        src: src_buffer.clone(),
+        dst: texture.clone(),
+        region: vk::BufferImageCopy {
+          buffer_offset: src_buffer.get_offset_and_length().0 as u64,
+          image_offset: vk::Offset3D {
+            x: 0,
+            y: 0,
+            z: 0
+          },
+          buffer_row_length: 0,
+          buffer_image_height: 0,
+          image_extent: vk::Extent3D {
+            width: max(texture.get_info().width >> mip_level, 1),
+            height: max(texture.get_info().height >> mip_level, 1),
+            depth: max(texture.get_info().depth >> mip_level, 1),
+          },
+          image_subresource: vk::ImageSubresourceLayers {
+            mip_level,
+            base_array_layer: array_layer,
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            layer_count: 1
+          }
+        }
+      });
+    }
+
+    guard.graphics.post_barriers.push((None, VkTransferBarrier::Image (
+      vk::ImageMemoryBarrier {
+        src_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+        dst_access_mask: vk::AccessFlags::MEMORY_READ,
+        old_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        new_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+        dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+        subresource_range,
+        image: *texture.get_handle(),
+        ..Default::default()
+    })));
+  }
+
+  /// Fills all mip levels but the first one by repeatedly downsampling the previous level.
+  /// The first level has to be initialized already, blits require a graphics queue and a format that supports linear filtering.
+  pub fn generate_mipmaps(&self, texture: &Arc<VkTexture>) -> Result<(), vk::Result> {
+    let required_features = vk::FormatFeatureFlags::BLIT_SRC | vk::FormatFeatureFlags::BLIT_DST | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR;
+    let format_properties = unsafe {
+      self.device.instance.get_physical_device_format_properties(self.device.physical_device, format_to_vk(texture.get_info().format))
+    };
+    if !format_properties.optimal_tiling_features.contains(required_features) {
+      return Err(vk::Result::ERROR_FORMAT_NOT_SUPPORTED);
+    }
+    if texture.get_info().mip_levels <= 1 {
+      return Ok(());
+    }
+
+    let mut guard = self.inner.lock().unwrap();
+    guard.graphics.mip_generations.push(texture.clone());
+    Ok(())
+  }
+
   pub fn init_buffer(&self, src_buffer: &Arc<VkBufferSlice>, dst_buffer: &Arc<VkBufferSlice>) {
+    debug_assert!(src_buffer.get_length() <= dst_buffer.get_length());
+    // Make the copied data visible to whatever the destination buffer is going to be used for
+    let mut dst_access_mask = buffer_usage_to_access(dst_buffer.get_info().usage & !(BufferUsage::COPY_SRC | BufferUsage::COPY_DST));
+    if dst_access_mask.is_empty() {
+      dst_access_mask = vk::AccessFlags::MEMORY_READ;
+    }
+
     let mut guard = self.inner.lock().unwrap();
     guard.graphics.copies.push(VkTransferCopy::BufferToBuffer {
       src: src_buffer.clone(),
@@ -195,9 +300,9 @@ impl VkTransfer {
     guard.graphics.post_barriers.push((None, VkTransferBarrier::Buffer (
       vk::BufferMemoryBarrier {
         src_access_mask: vk::AccessFlags::TRANSFER_WRITE,
-        dst_access_mask: vk::AccessFlags::MEMORY_READ,
-        src_queue_family_index: self.graphics_queue.get_queue_family_index(),
-        dst_queue_family_index: self.graphics_queue.get_queue_family_index(),
+        dst_access_mask,
+        src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+        dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
         buffer: *dst_buffer.get_buffer().get_handle(),
         offset: dst_buffer.get_offset() as u64,
         size: dst_buffer.get_length() as u64,
@@ -223,8 +328,9 @@ impl VkTransfer {
           dst_access_mask: vk::AccessFlags::TRANSFER_WRITE,
           old_layout: vk::ImageLayout::UNDEFINED,
           new_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-          src_queue_family_index: self.transfer_queue.as_ref().unwrap().get_queue_family_index(),
-          dst_queue_family_index: self.transfer_queue.as_ref().unwrap().get_queue_family_index(),
+          // The contents are discarded, so the transfer queue can take the image without an ownership transfer
+          src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+          dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
           subresource_range: vk::ImageSubresourceRange {
             base_mip_level: mip_level,
             level_count: 1,
@@ -327,7 +433,7 @@ impl VkTransfer {
   }
 
   fn flush_commands(&self, commands: &mut VkTransferCommands) -> Option<Box<VkTransferCommandBuffer>> {
-    if commands.copies.is_empty() && (commands.post_barriers.is_empty()
+    if commands.copies.is_empty() && commands.mip_generations.is_empty() && (commands.post_barriers.is_empty()
         || commands.post_barriers.iter().all(|(fence, _)| fence.as_ref().map_or(false, |f| !f.is_signalled()))) {
       return None;
     }
@@ -414,6 +520,11 @@ impl VkTransfer {
       );
     }
 
+    for texture in commands.mip_generations.drain(..) {
+      cmd_buffer.trackers.track_texture(&texture);
+      self.record_mip_generation(cmd_buffer.get_handle(), &texture);
+    }
+
     unsafe {
       self.device.end_command_buffer(*cmd_buffer.get_handle()).unwrap();
     }
@@ -424,6 +535,68 @@ impl VkTransfer {
     Some(cmd_buffer)
   }
 
+  fn record_mip_generation(&self, cmd_buffer: &vk::CommandBuffer, texture: &Arc<VkTexture>) {
+    let info = texture.get_info();
+    let mip_barrier = |mip_level: u32, level_count: u32, src_access_mask: vk::AccessFlags, dst_access_mask: vk::AccessFlags, old_layout: vk::ImageLayout, new_layout: vk::ImageLayout| {
+      vk::ImageMemoryBarrier {
+        src_access_mask,
+        dst_access_mask,
+        old_layout,
+        new_layout,
+        src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+        dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+        subresource_range: vk::ImageSubresourceRange {
+          base_mip_level: mip_level,
+          level_count,
+          base_array_layer: 0,
+          aspect_mask: vk::ImageAspectFlags::COLOR,
+          layer_count: info.array_length
+        },
+        image: *texture.get_handle(),
+        ..Default::default()
+      }
+    };
+    let mip_extent = |mip_level: u32| {
+      vk::Offset3D {
+        x: max(info.width >> mip_level, 1) as i32,
+        y: max(info.height >> mip_level, 1) as i32,
+        z: max(info.depth >> mip_level, 1) as i32
+      }
+    };
+
+    unsafe {
+      self.device.cmd_pipeline_barrier(*cmd_buffer, vk::PipelineStageFlags::ALL_COMMANDS, vk::PipelineStageFlags::TRANSFER, vk::DependencyFlags::empty(), &[], &[], &[
+        mip_barrier(0, 1, vk::AccessFlags::MEMORY_WRITE, vk::AccessFlags::TRANSFER_READ, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL, vk::ImageLayout::TRANSFER_SRC_OPTIMAL),
+        mip_barrier(1, info.mip_levels - 1, vk::AccessFlags::empty(), vk::AccessFlags::TRANSFER_WRITE, vk::ImageLayout::UNDEFINED, vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+      ]);
+
+      for mip_level in 1..info.mip_levels {
+        let subresource = |mip_level: u32| vk::ImageSubresourceLayers {
+          mip_level,
+          base_array_layer: 0,
+          aspect_mask: vk::ImageAspectFlags::COLOR,
+          layer_count: info.array_length
+        };
+        self.device.cmd_blit_image(*cmd_buffer, *texture.get_handle(), vk::ImageLayout::TRANSFER_SRC_OPTIMAL, *texture.get_handle(), vk::ImageLayout::TRANSFER_DST_OPTIMAL, &[
+          vk::ImageBlit {
+            src_subresource: subresource(mip_level - 1),
+            src_offsets: [vk::Offset3D::default(), mip_extent(mip_level - 1)],
+            dst_subresource: subresource(mip_level),
+            dst_offsets: [vk::Offset3D::default(), mip_extent(mip_level)]
+          }
+        ], vk::Filter::LINEAR);
+
+        self.device.cmd_pipeline_barrier(*cmd_buffer, vk::PipelineStageFlags::TRANSFER, vk::PipelineStageFlags::TRANSFER, vk::DependencyFlags::empty(), &[], &[], &[
+          mip_barrier(mip_level, 1, vk::AccessFlags::TRANSFER_WRITE, vk::AccessFlags::TRANSFER_READ, vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+        ]);
+      }
+
+      self.device.cmd_pipeline_barrier(*cmd_buffer, vk::PipelineStageFlags::TRANSFER, vk::PipelineStageFlags::ALL_COMMANDS, vk::DependencyFlags::empty(), &[], &[], &[
+        mip_barrier(0, info.mip_levels, vk::AccessFlags::TRANSFER_READ, vk::AccessFlags::MEMORY_READ, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+      ]);
+    }
+  }
+
   pub fn flush(&self) {
     self.try_free_used_buffers();
 
@@ -1,6 +1,7 @@
 use sourcerenderer_core::Platform;
 use std::sync::Arc;
 use sourcerenderer_core::platform::{Window, WindowState, InputState};
+use sourcerenderer_core::graphics::PresentMode;
 use std::error::Error;
 use sourcerenderer_vulkan::{VkBackend, VkInstance, VkSurface, VkDevice, VkSwapchain};
 use ndk::native_window::NativeWindow;
@@ -101,9 +102,9 @@ impl Window<AndroidPlatform> for AndroidWindow {
     Arc::new(VkSurface::new(instance_raw, surface, surface_loader))
   }
 
-  fn create_swapchain(&self, vsync: bool, device: &VkDevice, surface: &Arc<VkSurface>) -> Arc<VkSwapchain> {
+  fn create_swapchain(&self, present_mode: PresentMode, device: &VkDevice, surface: &Arc<VkSurface>) -> Arc<VkSwapchain> {
     let device_inner = device.get_inner();
-    return VkSwapchain::new(vsync, self.native_window.width() as u32, self.native_window.height() as u32, device_inner, surface).unwrap();
+    return VkSwapchain::new(present_mode, self.native_window.width() as u32, self.native_window.height() as u32, device_inner, surface).unwrap();
   }
 
   fn state(&self) -> WindowState {
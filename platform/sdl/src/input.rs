@@ -4,11 +4,12 @@ use std::collections::HashMap;
 
 
 use sdl2::EventPump;
+use sdl2::controller::{Axis, GameController};
 use sdl2::mouse::MouseUtil;
 use sdl2::keyboard::{Scancode};
 
 use sourcerenderer_core::platform::{Key, Window, WindowState, InputState, InputCommands};
-use sourcerenderer_core::{Vec2I};
+use sourcerenderer_core::{Vec2, Vec2I};
 
 use crate::sdl_platform::SDLWindow;
 
@@ -28,7 +29,7 @@ static ref KEY_TO_SCANCODE: HashMap<Key, Scancode> = {
     };
 }
 
-pub fn process(previous_commands: &mut InputCommands, commands: InputCommands, event_pump: &EventPump, mouse_util: &MouseUtil, window: &SDLWindow) -> InputState {
+pub fn process(previous_commands: &mut InputCommands, commands: InputCommands, event_pump: &EventPump, mouse_util: &MouseUtil, game_controller: Option<&GameController>, window: &SDLWindow) -> InputState {
   let window_state = window.state();
   let (has_focus, width, height) = match &window_state {
     WindowState::Visible { focussed, width, height } => (*focussed, *width, *height),
@@ -53,11 +54,16 @@ pub fn process(previous_commands: &mut InputCommands, commands: InputCommands, e
   }
 
   let keyboard_state = event_pump.keyboard_state();
-  for key_index in 0..7 {
-    let key = unsafe { std::mem::transmute(key_index as u32) };
-    input_state.set_key_down(key, keyboard_state.is_scancode_pressed(
-      *KEY_TO_SCANCODE.get(&key).unwrap()
-    ));
+  for (key, scancode) in KEY_TO_SCANCODE.iter() {
+    input_state.set_key_down(*key, keyboard_state.is_scancode_pressed(*scancode));
+  }
+
+  if let Some(game_controller) = game_controller {
+    let axis = |axis: Axis| (game_controller.axis(axis) as f32 / i16::MAX as f32).max(-1f32);
+    input_state.set_left_stick(Vec2::new(axis(Axis::LeftX), axis(Axis::LeftY)));
+    input_state.set_right_stick(Vec2::new(axis(Axis::RightX), axis(Axis::RightY)));
+    input_state.set_left_trigger(axis(Axis::TriggerLeft));
+    input_state.set_right_trigger(axis(Axis::TriggerRight));
   }
 
   *previous_commands = commands;
@@ -1,6 +1,7 @@
 use sourcerenderer_core::platform::io::IO;
 use std::path::Path;
 use std::io::Result as IOResult;
+use std::time::SystemTime;
 
 pub struct StdIO {}
 
@@ -22,4 +23,8 @@ impl IO for StdIO {
   fn external_asset_exists<P: AsRef<Path>>(path: P) -> bool {
     path.as_ref().exists()
   }
+
+  fn external_asset_modified_time<P: AsRef<Path>>(path: P) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+  }
 }
\ No newline at end of file
@@ -7,6 +7,7 @@ use sourcerenderer_core::platform::Window;
 use sourcerenderer_core::platform::PlatformEvent;
 use sourcerenderer_core::platform::GraphicsApi;
 use sourcerenderer_core::platform::WindowState;
+use sourcerenderer_core::graphics::PresentMode;
 
 use sourcerenderer_vulkan::VkInstance;
 use sourcerenderer_vulkan::VkSurface;
@@ -18,6 +19,8 @@ use sdl2::keyboard::Keycode;
 use sdl2::Sdl;
 use sdl2::VideoSubsystem;
 use sdl2::EventPump;
+use sdl2::GameControllerSubsystem;
+use sdl2::controller::GameController;
 
 use sdl2_sys::SDL_WindowFlags;
 
@@ -28,6 +31,8 @@ pub struct SDLPlatform {
   sdl_context: Sdl,
   video_subsystem: VideoSubsystem,
   event_pump: EventPump,
+  game_controller_subsystem: GameControllerSubsystem,
+  game_controller: Option<GameController>,
   window: SDLWindow,
   input_commands: InputCommands,
   input_state: InputState
@@ -44,6 +49,7 @@ impl SDLPlatform {
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
     let event_pump = sdl_context.event_pump().unwrap();
+    let game_controller_subsystem = sdl_context.game_controller().unwrap();
 
     let window = SDLWindow::new(&sdl_context, &video_subsystem, graphics_api);
 
@@ -51,6 +57,8 @@ impl SDLPlatform {
       sdl_context,
       video_subsystem,
       event_pump,
+      game_controller_subsystem,
+      game_controller: None,
       window,
       input_commands: InputCommands::default(),
       input_state: InputState::default()
@@ -66,6 +74,16 @@ impl SDLPlatform {
           self.window.is_active = false;
           return PlatformEvent::Quit;
         },
+        Event::ControllerDeviceAdded { which, .. } => {
+          if self.game_controller.is_none() {
+            self.game_controller = self.game_controller_subsystem.open(which).ok();
+          }
+        },
+        Event::ControllerDeviceRemoved { which, .. } => {
+          if self.game_controller.as_ref().map_or(false, |controller| controller.instance_id() == which) {
+            self.game_controller = None;
+          }
+        },
         _ => {}
       }
 
@@ -75,7 +93,7 @@ impl SDLPlatform {
   }
 
   pub(crate) fn process_input(&mut self, input_commands: InputCommands) {
-    self.input_state = crate::input::process(&mut self.input_commands, input_commands, &self.event_pump, &self.sdl_context.mouse(), &self.window);
+    self.input_state = crate::input::process(&mut self.input_commands, input_commands, &self.event_pump, &self.sdl_context.mouse(), self.game_controller.as_ref(), &self.window);
   }
 }
 
@@ -134,10 +152,10 @@ impl Window<SDLPlatform> for SDLWindow {
     Arc::new(VkSurface::new(instance_raw, SurfaceKHR::from_raw(surface), surface_loader))
   }
 
-  fn create_swapchain(&self, vsync: bool, device: &VkDevice, surface: &Arc<VkSurface>) -> Arc<VkSwapchain> {
+  fn create_swapchain(&self, present_mode: PresentMode, device: &VkDevice, surface: &Arc<VkSurface>) -> Arc<VkSwapchain> {
     let device_inner = device.get_inner();
     let (width, height) = self.window.drawable_size();
-    VkSwapchain::new(vsync, width, height, device_inner, surface).unwrap()
+    VkSwapchain::new(present_mode, width, height, device_inner, surface).unwrap()
   }
 
   fn state(&self) -> WindowState {
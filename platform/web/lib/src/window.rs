@@ -3,6 +3,7 @@ use std::sync::Arc;
 use web_sys::HtmlCanvasElement;
 
 use sourcerenderer_core::platform::Window;
+use sourcerenderer_core::graphics::PresentMode;
 use sourcerenderer_webgl::{WebGLDevice, WebGLInstance, WebGLSurface, WebGLSwapchain};
 
 use crate::platform::WebPlatform;
@@ -24,7 +25,7 @@ impl Window<WebPlatform> for WebWindow {
     Arc::new(WebGLSurface::new(&self.canvas))
   }
 
-  fn create_swapchain(&self, _vsync: bool, _device: &WebGLDevice, surface: &Arc<WebGLSurface>) -> Arc<WebGLSwapchain> {
+  fn create_swapchain(&self, _present_mode: PresentMode, _device: &WebGLDevice, surface: &Arc<WebGLSurface>) -> Arc<WebGLSwapchain> {
     Arc::new(WebGLSwapchain::new(surface))
   }
 
@@ -15,5 +15,6 @@ mod lump_data;
 mod map_header;
 mod map;
 mod read_util;
+mod lzma;
 
 pub(crate) use self::read_util::*;
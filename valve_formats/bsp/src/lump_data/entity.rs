@@ -1,29 +1,69 @@
 use std::collections::HashMap;
 use std::io::{Read, Result as IOResult};
-use crate::StringRead;
+use crate::RawDataRead;
 
 pub struct Entities {
   pub entities: Vec<Entity>
 }
 
 impl Entities {
-  pub fn read(read: &mut dyn Read) -> IOResult<Entities> {
+  pub fn read(read: &mut dyn Read, length: u32) -> IOResult<Entities> {
+    let data = read.read_data(length as usize)?;
+    let text = String::from_utf8_lossy(&data);
+
     let mut entities = Vec::<Entity>::new();
-    let text = read.read_null_terminated_string().unwrap();
+    let mut key_values = HashMap::<String, String>::new();
+    let mut key: Option<String> = None;
+    let mut in_block = false;
+    let mut chars = text.chars().peekable();
+    while let Some(char) = chars.next() {
+      match char {
+        '{' => {
+          in_block = true;
+          key = None;
+        }
+        '}' => {
+          if in_block {
+            entities.push(Entity {
+              key_values: std::mem::take(&mut key_values)
+            });
+          }
+          in_block = false;
+        }
+        // Whitespace, line endings and the trailing null terminator(s) separate tokens
+        _ if char.is_whitespace() || char == '\0' => {}
+        _ => {
+          let mut token = String::new();
+          if char == '"' {
+            // Quoted values can contain spaces and braces
+            for char in chars.by_ref() {
+              if char == '"' {
+                break;
+              }
+              token.push(char);
+            }
+          } else {
+            token.push(char);
+            while let Some(char) = chars.peek() {
+              if char.is_whitespace() || *char == '\0' || *char == '{' || *char == '}' || *char == '"' {
+                break;
+              }
+              token.push(*char);
+              chars.next();
+            }
+          }
 
-    let mut remaining_text = text.as_str();
-    loop {
-      let block_begin = remaining_text.find('{');
-      if block_begin.is_none() {
-        break;
+          if !in_block {
+            continue;
+          }
+          // Duplicate keys overwrite the previous value
+          if let Some(key) = key.take() {
+            key_values.insert(key, token);
+          } else {
+            key = Some(token.to_lowercase());
+          }
+        }
       }
-      let block_begin = block_begin.unwrap();
-      remaining_text = &remaining_text[block_begin + 1..];
-      let block_end = remaining_text.find('}').expect("Unfinished block");
-      let block = &remaining_text[..block_end];
-      entities.push(Entity {
-        key_values: parse_key_value(block, true)
-      });
     }
 
     Ok(Self {
@@ -42,8 +82,12 @@ impl Entity {
     self.key_values.get(&lower_key).map(|s| s.as_str())
   }
 
+  pub fn iter(&self) -> impl Iterator<Item = (&String, &String)> {
+    self.key_values.iter()
+  }
+
   pub fn class_name(&self) -> EntityClass {
-    let class_name = self.key_values.get("classname").unwrap().as_str();
+    let class_name = self.key_values.get("classname").map_or("", |class_name| class_name.as_str());
     match class_name {
       "prop_detail" => EntityClass::PropDetail,
       "prop_static" => EntityClass::PropStatic,
@@ -53,29 +97,14 @@ impl Entity {
       "prop_physics_multiplayer" => EntityClass::PropPhysicsMultiplayer,
       "prop_physics_override" => EntityClass::PropPhysicsOverride,
       "prop_dynamic_override" => EntityClass::PropDynamicOverride,
+      "light_environment" => EntityClass::LightEnvironment,
+      "light" => EntityClass::Light,
+      "light_spot" => EntityClass::LightSpot,
       _ => EntityClass::Unknown(class_name.to_string())
     }
   }
 }
 
-pub fn parse_key_value(text: &str, turn_keys_lower_case: bool) -> HashMap<String, String> {
-  let mut data = HashMap::<String, String>::new();
-  let text = text.replace("\r\n", "\n");
-  let lines = text.trim().split('\n');
-  for line in lines {
-    let space_pos = line.find(' ').unwrap();
-    let key = (&line[..space_pos]).trim().trim_matches('\"').trim();
-    let value = (&line[space_pos + 1..]).trim().trim_matches('\"').trim();
-    let owned_key = if turn_keys_lower_case {
-      key.to_lowercase()
-    } else {
-      key.to_string()
-    };
-    data.insert(owned_key, value.to_string());
-  }
-  data
-}
-
 #[derive(Eq, PartialEq, Hash, Debug)]
 pub enum EntityClass {
   PropDetail,
@@ -86,5 +115,40 @@ pub enum EntityClass {
   PropPhysicsMultiplayer,
   PropPhysicsOverride,
   PropDynamicOverride,
+  LightEnvironment,
+  Light,
+  LightSpot,
   Unknown(String)
 }
+
+#[cfg(test)]
+mod tests {
+  use std::io::Cursor;
+
+  use super::{Entities, EntityClass};
+
+  #[test]
+  fn parses_light_entities() {
+    let text = "{\r\n\"classname\" \"light\"\r\n\"origin\" \"1 2 3\"\r\n\"_light\" \"255 128 0 200\"\r\n}\r\n\
+      {\n\"classname\"\t\"light_spot\"\n\"angles\" \"-90 0 0\"\n\"_cone\" \"45\"\n\"targetname\" \"a {weird} name\"\n}\n\0";
+    let entities = Entities::read(&mut Cursor::new(text.as_bytes()), text.len() as u32).unwrap().entities;
+    assert_eq!(entities.len(), 2);
+
+    assert_eq!(entities[0].class_name(), EntityClass::Light);
+    assert_eq!(entities[0].get("origin"), Some("1 2 3"));
+    assert_eq!(entities[0].get("_LIGHT"), Some("255 128 0 200"));
+
+    assert_eq!(entities[1].class_name(), EntityClass::LightSpot);
+    assert_eq!(entities[1].get("angles"), Some("-90 0 0"));
+    assert_eq!(entities[1].get("_cone"), Some("45"));
+    assert_eq!(entities[1].get("targetname"), Some("a {weird} name"));
+  }
+
+  #[test]
+  fn later_duplicate_keys_win() {
+    let text = "{ \"classname\" \"light\" \"_light\" \"1 1 1 1\" \"_light\" \"2 2 2 2\" }";
+    let entities = Entities::read(&mut Cursor::new(text.as_bytes()), text.len() as u32).unwrap().entities;
+    assert_eq!(entities.len(), 1);
+    assert_eq!(entities[0].get("_light"), Some("2 2 2 2"));
+  }
+}
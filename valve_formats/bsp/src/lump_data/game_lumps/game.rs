@@ -1,6 +1,7 @@
 use std::io::{Read, Result as IOResult, Seek, SeekFrom, Cursor, Error as IOError, ErrorKind};
 use crate::read_util::PrimitiveRead;
 use crate::lump_data::game_lumps::StaticPropDict;
+use crate::lzma::{decompress_lzma, is_lzma_compressed};
 
 pub struct GameLumps {
   game_lumps: Box<[GameLump]>
@@ -28,13 +29,17 @@ impl GameLumps {
           data.set_len(lump.file_length as usize);
         }
         read.read_exact(&mut data)?;
+        if is_lzma_compressed(&data) {
+          data = decompress_lzma(&data).map_err(|e| IOError::new(ErrorKind::InvalidData, format!("Failed to decompress static props: {}", e)))?;
+        }
         let mut cursor = Cursor::new(data);
         let static_props = StaticPropDict::read(&mut cursor, lump.version)?;
         return Ok(static_props);
       }
     }
 
-    Err(IOError::new(ErrorKind::Other, "Game lump not found"))
+    // Maps without any static props don't have the game lump at all
+    Ok(StaticPropDict::default())
   }
 }
 
@@ -53,7 +58,6 @@ impl GameLump {
     let version = read.read_u16()?;
     let file_offset = read.read_i32()?;
     let file_length = read.read_i32()?;
-    println!("entity id: {:?}", id);
     Ok(Self {
       id,
       flags,
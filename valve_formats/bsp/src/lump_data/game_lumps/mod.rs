@@ -1,4 +1,4 @@
-pub use self::static_prop_dict::StaticPropDict;
+pub use self::static_prop_dict::{StaticPropDict, StaticProp, StaticPropFlags};
 pub use self::game::*;
 
 mod static_prop_dict;
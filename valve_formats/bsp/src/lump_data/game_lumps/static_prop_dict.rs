@@ -3,6 +3,7 @@ use crate::{PrimitiveRead, StringRead};
 use nalgebra::Vector3;
 use crate::lump_data::leaf::ColorRGBExp32;
 
+#[derive(Default)]
 pub struct StaticPropDict {
   pub names: Box<[String]>,
   pub leaves: Box<[u16]>,
@@ -15,6 +16,10 @@ impl StaticPropDict {
   }
 
   pub fn read(read: &mut dyn Read, version: u16) -> IOResult<Self> {
+    if !(4..=11).contains(&version) {
+      return Err(IOError::new(ErrorKind::InvalidData, format!("Unsupported static prop lump version: {}", version)));
+    }
+
     let dict_entries = read.read_i32()?;
     let mut names = Vec::<String>::with_capacity(dict_entries as usize);
     for _ in 0..dict_entries {
@@ -121,9 +126,11 @@ impl StaticProp {
         diffuse_modulation = ColorRGBExp32::read(read)?;
       }
 
-      disable_x360 = read.read_u8()? != 0;
-      for _ in 0..3 {
-        read.read_u8()?;
+      if version >= 9 {
+        disable_x360 = read.read_u8()? != 0;
+        for _ in 0..3 {
+          read.read_u8()?;
+        }
       }
 
       if version >= 10 {
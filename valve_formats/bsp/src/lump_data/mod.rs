@@ -22,7 +22,7 @@ pub use crate::lump_data::vertex_normal::VertexNormal;
 pub use crate::lump_data::vertex_normal_index::VertexNormalIndex;
 pub use crate::lump_data::visibility::Visibility;
 pub use crate::game_lumps::GameLumps;
-pub use crate::lump_data::entity::Entities;
+pub use crate::lump_data::entity::{Entities, Entity, EntityClass};
 
 pub use self::brush::Brush;
 pub use self::leaf::Leaf;
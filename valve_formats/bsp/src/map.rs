@@ -1,5 +1,5 @@
 use crate::map_header::{MapHeader};
-use std::io::{Seek, SeekFrom, Read, Result as IOResult};
+use std::io::{Cursor, Error as IOError, ErrorKind, Seek, SeekFrom, Read, Result as IOResult};
 use crate::lump_data::{Brush, Node, Leaf, Face,
                        Plane, Edge, BrushSide, LumpData,
                        TextureInfo, LeafFace, LeafBrush,
@@ -8,6 +8,7 @@ use crate::lump_data::{Brush, Node, Leaf, Face,
                        TextureDataStringTable, TextureStringData};
 use crate::{LumpType, BrushModel, RawDataRead, PakFile, DispTri, DispInfo, DispVert, Lighting, Visibility, GameLumps, Entities};
 use crate::lump_data::game_lumps::StaticPropDict;
+use crate::lzma::{decompress_lzma, is_lzma_compressed};
 
 pub struct Map<R: Read + Seek> {
   pub name: String,
@@ -97,10 +98,8 @@ impl<R: Read + Seek> Map<R> {
   }
 
   pub fn read_texture_string_data(&mut self) -> IOResult<TextureStringData> {
-    let index = LumpType::TextureStringData as usize;
-    let lump = self.header.lumps[index];
-    self.reader.seek(SeekFrom::Start(lump.file_offset as u64))?;
-    TextureStringData::read(&mut self.reader, lump.file_length as u32)
+    let data = self.read_lump(LumpType::TextureStringData)?;
+    TextureStringData::read(&mut Cursor::new(&data), data.len() as u32)
   }
 
   pub fn read_texture_data_string_table(&mut self) -> IOResult<Vec<TextureDataStringTable>> {
@@ -140,10 +139,8 @@ impl<R: Read + Seek> Map<R> {
   }
 
   pub fn read_visibility(&mut self) -> IOResult<Visibility> {
-    let index = LumpType::Visibility as usize;
-    let lump = self.header.lumps[index];
-    self.reader.seek(SeekFrom::Start(lump.file_offset as u64))?;
-    Visibility::read(&mut self.reader)
+    let data = self.read_lump(LumpType::Visibility)?;
+    Visibility::read(&mut Cursor::new(&data))
   }
 
   pub fn read_static_props(&mut self) -> IOResult<StaticPropDict> {
@@ -151,10 +148,21 @@ impl<R: Read + Seek> Map<R> {
   }
 
   pub fn read_entities(&mut self) -> IOResult<Entities> {
-    let index = LumpType::Entities as usize;
-    let lump = self.header.lumps[index];
+    let data = self.read_lump(LumpType::Entities)?;
+    Entities::read(&mut Cursor::new(&data), data.len() as u32)
+  }
+
+  /// Reads the whole lump and transparently decompresses it if it's LZMA compressed.
+  fn read_lump(&mut self, lump_type: LumpType) -> IOResult<Box<[u8]>> {
+    let lump = self.header.lumps[lump_type as usize];
     self.reader.seek(SeekFrom::Start(lump.file_offset as u64))?;
-    Entities::read(&mut self.reader)
+    let data = self.reader.read_data(lump.file_length as usize)?;
+    if !is_lzma_compressed(&data) {
+      return Ok(data);
+    }
+    decompress_lzma(&data)
+      .map(|data| data.into_boxed_slice())
+      .map_err(|e| IOError::new(ErrorKind::InvalidData, format!("Failed to decompress lump {:?}: {}", lump_type, e)))
   }
 
   fn read_lump_data<T: LumpData>(&mut self) -> IOResult<Vec<T>> {
@@ -168,16 +176,95 @@ impl<R: Read + Seek> Map<R> {
       }
     }
 
-    let index = lump_type as usize;
-    let lump = &self.header.lumps[index];
-    self.reader.seek(SeekFrom::Start(lump.file_offset as u64))?;
-
-    let element_count = lump.file_length / T::element_size(self.header.version) as i32;
-    let mut elements: Vec<T> = Vec::new();
+    let data = self.read_lump(lump_type)?;
+    let mut reader = Cursor::new(&data);
+    let element_count = data.len() / T::element_size(self.header.version);
+    let mut elements: Vec<T> = Vec::with_capacity(element_count);
     for _ in 0..element_count {
-      let element = T::read(&mut self.reader, self.header.version)?;
+      let element = T::read(&mut reader, self.header.version)?;
       elements.push(element);
     }
     Ok(elements)
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use nalgebra::Vector3;
+  use crate::lzma::LZMA_MAGIC;
+
+  /// Compresses the data and wraps it in Valve's LZMA header.
+  fn valve_lzma(data: &[u8]) -> Vec<u8> {
+    let mut lzma = Vec::<u8>::new();
+    let options = lzma_rs::compress::Options {
+      unpacked_size: lzma_rs::compress::UnpackedSize::SkipWritingToHeader
+    };
+    lzma_rs::lzma_compress_with_options(&mut Cursor::new(data), &mut lzma, &options).unwrap();
+    let (properties, stream) = lzma.split_at(5);
+
+    let mut compressed = Vec::<u8>::new();
+    compressed.extend_from_slice(&LZMA_MAGIC.to_le_bytes());
+    compressed.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    compressed.extend_from_slice(&(stream.len() as u32).to_le_bytes());
+    compressed.extend_from_slice(properties);
+    compressed.extend_from_slice(stream);
+    compressed
+  }
+
+  /// A map that only contains the given lumps and an empty game lump.
+  fn map_data(lumps: &[(LumpType, &[u8])]) -> Vec<u8> {
+    let header_size = 8 + 64 * 16;
+    let mut lump_headers = vec![[0i32; 4]; 64];
+    let mut content = Vec::<u8>::new();
+    for (lump_type, data) in lumps {
+      lump_headers[*lump_type as usize] = [(header_size + content.len()) as i32, data.len() as i32, 0, 0];
+      content.extend_from_slice(data);
+    }
+    lump_headers[LumpType::GameLump as usize] = [(header_size + content.len()) as i32, 4, 0, 0];
+    content.extend_from_slice(&0i32.to_le_bytes());
+
+    let mut data = Vec::<u8>::new();
+    data.extend_from_slice(b"VBSP");
+    data.extend_from_slice(&21i32.to_le_bytes());
+    for lump_header in &lump_headers {
+      for value in lump_header {
+        data.extend_from_slice(&value.to_le_bytes());
+      }
+    }
+    data.extend_from_slice(&content);
+    data
+  }
+
+  fn vec3_data(values: &[[f32; 3]]) -> Vec<u8> {
+    values.iter().flatten().flat_map(|value| value.to_le_bytes()).collect()
+  }
+
+  #[test]
+  fn compressed_lumps_get_decompressed() {
+    let positions = vec![[1f32, 2f32, 3f32]; 64];
+    let compressed_vertices = valve_lzma(&vec3_data(&positions));
+    // The element count has to come from the decompressed size
+    assert!(compressed_vertices.len() < positions.len() * 12);
+    let normals = vec3_data(&[[0f32, 0f32, 1f32], [0f32, 1f32, 0f32]]);
+    let data = map_data(&[(LumpType::Vertices, &compressed_vertices), (LumpType::VertexNormals, &normals)]);
+    let mut map = Map::read("test", Cursor::new(data)).unwrap();
+
+    let vertices = map.read_vertices().unwrap();
+    assert_eq!(vertices.len(), 64);
+    assert!(vertices.iter().all(|vertex| vertex.position == Vector3::new(1f32, 2f32, 3f32)));
+    let vertex_normals = map.read_vertex_normals().unwrap();
+    assert_eq!(vertex_normals.len(), 2);
+    assert_eq!(vertex_normals[1].normal, Vector3::new(0f32, 1f32, 0f32));
+  }
+
+  #[test]
+  fn corrupted_compressed_lump_is_invalid_data() {
+    let mut compressed_vertices = valve_lzma(&vec3_data(&[[1f32, 2f32, 3f32]; 16]));
+    // Claim more compressed data than there is
+    compressed_vertices[8] = 0xFF;
+    let data = map_data(&[(LumpType::Vertices, &compressed_vertices)]);
+    let mut map = Map::read("test", Cursor::new(data)).unwrap();
+    assert_eq!(map.read_vertices().unwrap_err().kind(), ErrorKind::InvalidData);
+  }
+}
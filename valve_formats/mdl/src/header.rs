@@ -145,7 +145,7 @@ impl Header {
     let id = read.read_i32()?;
     let version = read.read_i32()?;
     let checksum = read.read_i32()?;
-    let name = read.read_fixed_length_null_terminated_string(64).unwrap();
+    let name = read.read_fixed_length_null_terminated_string(64)?;
 
     let data_length = read.read_i32()?;
 
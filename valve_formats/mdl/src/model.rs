@@ -38,7 +38,7 @@ impl ModelVertexData {
 
 impl Model {
   pub fn read(read: &mut dyn Read) -> IOResult<Self> {
-    let name = read.read_fixed_length_null_terminated_string(64).unwrap();
+    let name = read.read_fixed_length_null_terminated_string(64)?;
     let model_type = read.read_i32()?;
     let bounding_radius = read.read_f32()?;
     let meshes_count = read.read_i32()?;
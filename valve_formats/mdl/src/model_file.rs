@@ -3,6 +3,9 @@ use crate::header::Header;
 use crate::header2::Header2;
 use crate::{Bone, BoneController, HitboxSet, AnimDesc, SequenceDesc, Texture, StringRead, PrimitiveRead, BodyPart, Model, Mesh};
 
+/// Names in MDL files are paths, anything longer than this means the file is corrupt.
+pub const MAX_NAME_LENGTH: usize = 260;
+
 pub struct ModelFile<R: Read + Seek> {
   header: Header,
   secondary_header: Header2,
@@ -88,7 +91,7 @@ impl<R: Read + Seek> ModelFile<R> {
       let texture = Texture::read(&mut self.reader)?;
       let offset = self.reader.seek(SeekFrom::Current(0)).unwrap();
       self.reader.seek(SeekFrom::Start(offset + texture.name_offset as u64))?;
-      let name = self.reader.read_null_terminated_string().unwrap();
+      let name = self.reader.read_null_terminated_string_max(MAX_NAME_LENGTH)?;
       textures.push((name, texture));
     }
     Ok(textures)
@@ -103,7 +106,7 @@ impl<R: Read + Seek> ModelFile<R> {
     let mut texture_dirs = Vec::<String>::with_capacity(self.header.texture_dir_count as usize);
     for offset in dir_offsets {
       self.reader.seek(SeekFrom::Start(self.start_offset + offset as u64))?;
-      texture_dirs.push(self.reader.read_fixed_length_null_terminated_string(255).unwrap());
+      texture_dirs.push(self.reader.read_fixed_length_null_terminated_string(255)?);
     }
     Ok(texture_dirs)
   }
@@ -116,7 +119,7 @@ impl<R: Read + Seek> ModelFile<R> {
       let start = self.reader.seek(SeekFrom::Current(0)).unwrap();
       body_part.model_index += start;
       self.reader.seek(SeekFrom::Start(start + body_part.name_index as u64))?;
-      let name = self.reader.read_null_terminated_string().unwrap();
+      let name = self.reader.read_null_terminated_string_max(MAX_NAME_LENGTH)?;
       body_parts.push((name, body_part));
       self.reader.seek(SeekFrom::Start(start))?;
     }
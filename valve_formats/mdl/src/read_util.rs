@@ -1,25 +1,45 @@
-use std::io::{Read, Result as IOResult, Error as IOError};
+use std::io::{Read, Result as IOResult, Error as IOError, ErrorKind};
 use std::string::FromUtf8Error;
 
 #[derive(Debug)]
 pub enum StringReadError {
   IOError(IOError),
-  StringConstructionError(FromUtf8Error)
+  StringConstructionError(FromUtf8Error),
+  /// No terminator was found within the given maximum length
+  MaxLengthExceeded(usize)
+}
+
+impl From<StringReadError> for IOError {
+  fn from(error: StringReadError) -> Self {
+    match error {
+      StringReadError::IOError(e) => e,
+      StringReadError::StringConstructionError(e) => IOError::new(ErrorKind::InvalidData, e),
+      StringReadError::MaxLengthExceeded(max) => IOError::new(ErrorKind::InvalidData, format!("No string terminator within {} bytes", max))
+    }
+  }
 }
 
 pub trait StringRead {
   fn read_null_terminated_string(&mut self) -> Result<String, StringReadError>;
+  fn read_null_terminated_string_max(&mut self, max: usize) -> Result<String, StringReadError>;
   fn read_fixed_length_null_terminated_string(&mut self, length: u32) -> Result<String, StringReadError>;
 }
 
 impl<T: Read + ?Sized> StringRead for T {
   fn read_null_terminated_string(&mut self) -> Result<String, StringReadError> {
+    self.read_null_terminated_string_max(usize::MAX)
+  }
+
+  fn read_null_terminated_string_max(&mut self, max: usize) -> Result<String, StringReadError> {
     let mut buffer = Vec::<u8>::new();
     loop {
       let char = self.read_u8().map_err(StringReadError::IOError)?;
       if char == 0 {
         break;
       }
+      if buffer.len() == max {
+        return Err(StringReadError::MaxLengthExceeded(max));
+      }
       buffer.push(char);
     }
     String::from_utf8(buffer).map_err(StringReadError::StringConstructionError)
@@ -64,6 +84,14 @@ pub trait PrimitiveRead {
   fn read_i64(&mut self) -> IOResult<i64>;
   fn read_f32(&mut self) -> IOResult<f32>;
   fn read_f64(&mut self) -> IOResult<f64>;
+  fn read_u16_be(&mut self) -> IOResult<u16>;
+  fn read_u32_be(&mut self) -> IOResult<u32>;
+  fn read_u64_be(&mut self) -> IOResult<u64>;
+  fn read_i16_be(&mut self) -> IOResult<i16>;
+  fn read_i32_be(&mut self) -> IOResult<i32>;
+  fn read_i64_be(&mut self) -> IOResult<i64>;
+  fn read_f32_be(&mut self) -> IOResult<f32>;
+  fn read_f64_be(&mut self) -> IOResult<f64>;
 }
 
 impl<T: Read + ?Sized> PrimitiveRead for T {
@@ -126,4 +154,107 @@ impl<T: Read + ?Sized> PrimitiveRead for T {
     self.read_exact(&mut buffer)?;
     Ok(f64::from_le_bytes(buffer))
   }
+
+  fn read_u16_be(&mut self) -> IOResult<u16> {
+    let mut buffer = [0u8; 2];
+    self.read_exact(&mut buffer)?;
+    Ok(u16::from_be_bytes(buffer))
+  }
+
+  fn read_u32_be(&mut self) -> IOResult<u32> {
+    let mut buffer = [0u8; 4];
+    self.read_exact(&mut buffer)?;
+    Ok(u32::from_be_bytes(buffer))
+  }
+
+  fn read_u64_be(&mut self) -> IOResult<u64> {
+    let mut buffer = [0u8; 8];
+    self.read_exact(&mut buffer)?;
+    Ok(u64::from_be_bytes(buffer))
+  }
+
+  fn read_i16_be(&mut self) -> IOResult<i16> {
+    let mut buffer = [0u8; 2];
+    self.read_exact(&mut buffer)?;
+    Ok(i16::from_be_bytes(buffer))
+  }
+
+  fn read_i32_be(&mut self) -> IOResult<i32> {
+    let mut buffer = [0u8; 4];
+    self.read_exact(&mut buffer)?;
+    Ok(i32::from_be_bytes(buffer))
+  }
+
+  fn read_i64_be(&mut self) -> IOResult<i64> {
+    let mut buffer = [0u8; 8];
+    self.read_exact(&mut buffer)?;
+    Ok(i64::from_be_bytes(buffer))
+  }
+
+  fn read_f32_be(&mut self) -> IOResult<f32> {
+    let mut buffer = [0u8; 4];
+    self.read_exact(&mut buffer)?;
+    Ok(f32::from_be_bytes(buffer))
+  }
+
+  fn read_f64_be(&mut self) -> IOResult<f64> {
+    let mut buffer = [0u8; 8];
+    self.read_exact(&mut buffer)?;
+    Ok(f64::from_be_bytes(buffer))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::io::Cursor;
+
+  #[test]
+  fn unterminated_string_stops_at_max_length() {
+    let data = vec![b'a'; 1024 * 1024];
+    let result = Cursor::new(data).read_null_terminated_string_max(260);
+    assert!(matches!(result, Err(StringReadError::MaxLengthExceeded(260))));
+
+    let error: IOError = Cursor::new(vec![b'a'; 1024 * 1024]).read_null_terminated_string_max(260).unwrap_err().into();
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+  }
+
+  #[test]
+  fn terminated_string_within_max_length() {
+    let mut cursor = Cursor::new(b"models/player.mdl\0rest".to_vec());
+    assert_eq!(cursor.read_null_terminated_string_max(260).unwrap(), "models/player.mdl");
+    assert_eq!(cursor.position(), 18);
+  }
+
+  #[test]
+  fn integers_read_as_little_and_big_endian() {
+    let data = [0x01u8, 0x02u8, 0x03u8, 0x04u8, 0x05u8, 0x06u8, 0x07u8, 0x08u8];
+    assert_eq!(Cursor::new(data).read_u16().unwrap(), 0x0201);
+    assert_eq!(Cursor::new(data).read_u16_be().unwrap(), 0x0102);
+    assert_eq!(Cursor::new(data).read_u32().unwrap(), 0x04030201);
+    assert_eq!(Cursor::new(data).read_u32_be().unwrap(), 0x01020304);
+    assert_eq!(Cursor::new(data).read_u64().unwrap(), 0x0807060504030201);
+    assert_eq!(Cursor::new(data).read_u64_be().unwrap(), 0x0102030405060708);
+
+    let data = [0xFFu8, 0xFFu8, 0xFFu8, 0xFFu8, 0xFFu8, 0xFFu8, 0xFFu8, 0xFEu8];
+    assert_eq!(Cursor::new(data).read_i16().unwrap(), -1);
+    assert_eq!(Cursor::new(&data[6..]).read_i16().unwrap(), -257);
+    assert_eq!(Cursor::new(&data[6..]).read_i16_be().unwrap(), -2);
+    assert_eq!(Cursor::new(&data[4..]).read_i32().unwrap(), -16777217);
+    assert_eq!(Cursor::new(&data[4..]).read_i32_be().unwrap(), -2);
+    assert_eq!(Cursor::new(data).read_i64().unwrap(), -72057594037927937);
+    assert_eq!(Cursor::new(data).read_i64_be().unwrap(), -2);
+  }
+
+  #[test]
+  fn floats_read_as_little_and_big_endian() {
+    let mut cursor = Cursor::new([0x3Fu8, 0x80u8, 0x00u8, 0x00u8, 0x3Fu8, 0xF0u8, 0x00u8, 0x00u8, 0x00u8, 0x00u8, 0x00u8, 0x00u8]);
+    assert_eq!(cursor.read_f32_be().unwrap(), 1f32);
+    assert_eq!(cursor.read_f64_be().unwrap(), 1f64);
+    assert_eq!(cursor.position(), 12);
+
+    cursor.set_position(0);
+    assert_eq!(cursor.read_f32().unwrap().to_bits(), 0x0000803F);
+    assert_eq!(cursor.read_f64().unwrap().to_bits(), 0x000000000000F03F);
+  }
 }
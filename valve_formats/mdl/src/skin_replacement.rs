@@ -1,49 +1,63 @@
-use std::io::{Read, Result as IOResult, Cursor, ErrorKind};
-use std::collections::HashMap;
+use std::io::{Read, Result as IOResult};
 
 use crate::PrimitiveRead;
 
-pub struct SkinReplacementTableEntry {
-  pub main_body: u16,
-  pub trimming: u16
-}
-
+/// Maps the texture index of every mesh to the texture that's used by a skin family.
+/// Family 0 is the default skin.
 pub struct SkinReplacementTable {
-  table: HashMap<u16, SkinReplacementTableEntry>
+  skin_references_count: usize,
+  table: Vec<u16>
 }
 
 impl SkinReplacementTable {
-  pub fn read(read: &mut dyn Read, skin_families_count: i32, skins_count: i32) -> IOResult<Self> {
-    let len = (skin_families_count * skins_count * 2) as usize;
-    let mut data = Vec::with_capacity(len);
-    unsafe {
-      data.set_len(len);
-    }
-    read.read_exact(&mut data)?;
-
-    let mut table = HashMap::<u16, SkinReplacementTableEntry>::new();
-    let mut i = 0;
-    let mut cursor = Cursor::new(&data);
-    loop {
-      let main_body_res = cursor.read_u16();
-      if let Err(e) = main_body_res {
-        if e.kind() == ErrorKind::UnexpectedEof {
-          break;
-        } else {
-          return Err(e);
-        }
-      }
-      let main_body = main_body_res.unwrap();
-      let trimming = cursor.read_u16()?;
-      table.insert(i, SkinReplacementTableEntry {
-        main_body,
-        trimming
-      });
-      i += 1;
+  pub fn read(read: &mut dyn Read, skin_families_count: i32, skin_references_count: i32) -> IOResult<Self> {
+    let len = (skin_families_count.max(0) * skin_references_count.max(0)) as usize;
+    let mut table = Vec::with_capacity(len);
+    for _ in 0..len {
+      table.push(read.read_u16()?);
     }
 
     Ok(Self {
+      skin_references_count: skin_references_count.max(0) as usize,
       table
     })
   }
-}
\ No newline at end of file
+
+  pub fn families_count(&self) -> usize {
+    self.table.len().checked_div(self.skin_references_count).unwrap_or(0)
+  }
+
+  /// Returns None if either the family or the texture index is out of range.
+  pub fn get(&self, family: usize, texture: usize) -> Option<u16> {
+    if family >= self.families_count() || texture >= self.skin_references_count {
+      return None;
+    }
+    self.table.get(family * self.skin_references_count + texture).copied()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::Cursor;
+
+  use super::SkinReplacementTable;
+
+  #[test]
+  fn looks_up_textures_per_family() {
+    let data: Vec<u8> = [0u16, 1, 2, 1].iter().flat_map(|texture| texture.to_le_bytes()).collect();
+    let table = SkinReplacementTable::read(&mut Cursor::new(data), 2, 2).unwrap();
+    assert_eq!(table.families_count(), 2);
+    assert_eq!(table.get(0, 0), Some(0));
+    assert_eq!(table.get(1, 0), Some(2));
+    assert_eq!(table.get(1, 1), Some(1));
+    assert_eq!(table.get(2, 0), None);
+    assert_eq!(table.get(0, 2), None);
+  }
+
+  #[test]
+  fn empty_table_has_no_families() {
+    let table = SkinReplacementTable::read(&mut Cursor::new(Vec::new()), 3, 0).unwrap();
+    assert_eq!(table.families_count(), 0);
+    assert_eq!(table.get(0, 0), None);
+  }
+}
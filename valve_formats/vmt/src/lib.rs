@@ -11,10 +11,17 @@ pub const SHADER_ENVMAP_TINT: &str = "envmaptint";
 pub const SHADER_WORLD_VERTEX_TRANSITION: &str = "worldvertextransition";
 pub const SHADER_WATER: &str = "water";
 pub const BASE_TEXTURE_NAME: &str = "basetexture";
+pub const BUMP_MAP: &str = "bumpmap";
+pub const DETAIL: &str = "detail";
+pub const TRANSLUCENT: &str = "translucent";
+pub const ALPHA_TEST: &str = "alphatest";
+pub const ALPHA_TEST_REFERENCE: &str = "alphatestreference";
+pub const SURFACE_PROP: &str = "surfaceprop";
+pub const COLOR: &str = "color";
 pub const PATCH: &str = "patch";
 pub const PATCH_INCLUDE: &str = "include";
-#[allow(dead_code)]
-const PATCH_INSERT: &str = "insert";
+pub const PATCH_INSERT: &str = "insert";
+pub const PATCH_REPLACE: &str = "replace";
 
 #[derive(Debug)]
 pub enum VMTError {
@@ -22,29 +29,39 @@ pub enum VMTError {
   FileError(String)
 }
 
+/// A `{ }` block of a KeyValues file. Keys are lower case and don't have the `$` or `%` prefix.
+#[derive(Default, Clone)]
+pub struct VMTBlock {
+  values: HashMap<String, String>,
+  blocks: HashMap<String, VMTBlock>
+}
+
 pub struct VMTMaterial {
   shader_name: String,
-  values: HashMap<String, String>
+  root: VMTBlock
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+  String(String),
+  /// Unquoted text in square brackets, either a vector value or a platform condition
+  Bracketed(String),
+  BlockStart,
+  BlockEnd
 }
 
 impl VMTMaterial {
   pub fn new(reader: &mut dyn Read, length: u32) -> Result<Self, VMTError> {
-    let mut values = HashMap::<String, String>::new();
-
     let data = reader.read_data(length as usize).map_err(VMTError::IOError)?;
-    let mut text = String::from_utf8(data.to_vec()).map_err(|_e| VMTError::FileError("Could not read text".to_string()))?;
-    text = text.replace("\r\n", "\n");
-    text = text.replace('\t', " ");
-    text = text.trim_end_matches('\0').to_string();
-    let block_start = text.find('{').ok_or_else(|| VMTError::FileError("Could not find start of material block".to_string()))?;
-
-    let mut shader_name: String = String::new();
-    let shader_name_lines = (&text[..block_start]).split('\n');
-    for line in shader_name_lines {
-      let line = remove_line_comments(line);
-      if !line.trim().is_empty() {
-        shader_name = line.replace("\"", "").trim().to_lowercase();
-      }
+    let text = String::from_utf8_lossy(&data);
+    let mut tokens = tokenize(text.trim_start_matches('\u{feff}'))?.into_iter();
+
+    let shader_name = match tokens.next() {
+      Some(Token::String(shader_name)) => shader_name.to_lowercase(),
+      _ => return Err(VMTError::FileError("Could not find shader name".to_string()))
+    };
+    if !matches!(tokens.next(), Some(Token::BlockStart)) {
+      return Err(VMTError::FileError("Could not find start of material block".to_string()));
     }
 
     if shader_name != SHADER_LIGHT_MAPPED_GENERIC
@@ -57,33 +74,19 @@ impl VMTMaterial {
       println!("Found unsupported shader: \"{}\"", shader_name);
     }
 
-    let block_end = text.find('}').ok_or_else(|| VMTError::FileError("Could not find end of material block".to_string()))?;
-    let block = &text[block_start .. block_end];
-    let lines = block.split('\n');
-    for line in lines {
-      let trimmed_line = line.trim().replace(&['$', '%', '"', '\''][..], "");
-      if trimmed_line.is_empty() || trimmed_line == "{" || trimmed_line == "}" || trimmed_line == PATCH_INCLUDE {
-        continue;
-      }
-
-      let key_end_opt = trimmed_line.find(' ');
-      if key_end_opt.is_none() {
-        continue;
-      }
-      let key_end = key_end_opt.unwrap();
-      let key = (&trimmed_line[.. key_end]).trim().to_lowercase();
-      let value = remove_line_comments(&trimmed_line[key_end + 1 ..]).trim().to_string();
-      values.insert(key, value);
-    }
-
+    let root = VMTBlock::parse(&mut tokens);
     Ok(Self {
       shader_name,
-      values
+      root
     })
   }
 
   pub fn get_value(&self, key: &str) -> Option<&str> {
-    self.values.get(key).map(|v| v.as_str())
+    self.root.get_value(key)
+  }
+
+  pub fn get_block(&self, name: &str) -> Option<&VMTBlock> {
+    self.root.get_block(name)
   }
 
   pub fn get_shader(&self) -> &str {
@@ -102,19 +105,203 @@ impl VMTMaterial {
     self.shader_name == PATCH
   }
 
+  /// Keys in the insert block get added or overwritten, keys in the replace block only overwrite existing ones.
   pub fn apply_patch(&mut self, patch: &VMTMaterial) {
     if !patch.is_patch() {
       panic!("Material must be a patch");
     }
 
-    self.values.extend(patch.values.iter().map(|(key, value)| (key.clone(), value.clone())));
+    if let Some(insert) = patch.get_block(PATCH_INSERT) {
+      self.root.values.extend(insert.values.iter().map(|(key, value)| (key.clone(), value.clone())));
+      self.root.blocks.extend(insert.blocks.iter().map(|(key, block)| (key.clone(), block.clone())));
+    }
+    if let Some(replace) = patch.get_block(PATCH_REPLACE) {
+      for (key, value) in &replace.values {
+        if let Some(existing_value) = self.root.values.get_mut(key) {
+          *existing_value = value.clone();
+        }
+      }
+      for (key, block) in &replace.blocks {
+        if let Some(existing_block) = self.root.blocks.get_mut(key) {
+          *existing_block = block.clone();
+        }
+      }
+    }
   }
 }
 
-fn remove_line_comments(text: &str) -> &str {
-  let comment_start = text.find("//");
-  if comment_start.is_none() {
-    return text;
+impl VMTBlock {
+  fn parse(tokens: &mut impl Iterator<Item = Token>) -> Self {
+    let mut block = VMTBlock::default();
+    while let Some(token) = tokens.next() {
+      let key = match token {
+        Token::String(key) => normalize_key(&key),
+        Token::BlockEnd => break,
+        Token::BlockStart => {
+          // A block without a name, nothing can reference it
+          VMTBlock::parse(tokens);
+          continue;
+        }
+        Token::Bracketed(_) => continue
+      };
+
+      match tokens.next() {
+        Some(Token::String(value)) | Some(Token::Bracketed(value)) => {
+          block.values.insert(key, value);
+        }
+        Some(Token::BlockStart) => {
+          let child = VMTBlock::parse(tokens);
+          block.blocks.insert(key, child);
+        }
+        Some(Token::BlockEnd) | None => break
+      }
+    }
+    block
+  }
+
+  pub fn get_value(&self, key: &str) -> Option<&str> {
+    self.values.get(&normalize_key(key)).map(|v| v.as_str())
+  }
+
+  pub fn get_block(&self, name: &str) -> Option<&VMTBlock> {
+    self.blocks.get(&normalize_key(name))
+  }
+
+  pub fn values(&self) -> impl Iterator<Item = (&str, &str)> {
+    self.values.iter().map(|(key, value)| (key.as_str(), value.as_str()))
+  }
+}
+
+fn normalize_key(key: &str) -> String {
+  key.trim_start_matches(&['$', '%'][..]).to_lowercase()
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, VMTError> {
+  let mut tokens = Vec::<Token>::new();
+  let mut chars = text.char_indices().peekable();
+  while let Some((index, char)) = chars.next() {
+    match char {
+      '{' => tokens.push(Token::BlockStart),
+      '}' => tokens.push(Token::BlockEnd),
+      '"' => {
+        let start = index + 1;
+        let end = loop {
+          match chars.next() {
+            Some((end, '"')) => break end,
+            Some((_, '\n')) | None => return Err(VMTError::FileError("Unterminated string".to_string())),
+            _ => {}
+          }
+        };
+        tokens.push(Token::String(text[start..end].to_string()));
+      }
+      '/' if text[index..].starts_with("//") => {
+        while let Some((_, char)) = chars.peek() {
+          if *char == '\n' {
+            break;
+          }
+          chars.next();
+        }
+      }
+      '[' => {
+        let end = text[index..].find([']', '\n'])
+          .filter(|end| text[index + end..].starts_with(']'))
+          .ok_or_else(|| VMTError::FileError("Unterminated bracket".to_string()))?;
+        while let Some((next_index, _)) = chars.peek() {
+          if *next_index > index + end {
+            break;
+          }
+          chars.next();
+        }
+        tokens.push(Token::Bracketed(text[index..=index + end].to_string()));
+      }
+      _ if char.is_whitespace() || char == '\0' => {}
+      _ => {
+        let mut end = text.len();
+        while let Some((next_index, next_char)) = chars.peek() {
+          if next_char.is_whitespace() || *next_char == '"' || *next_char == '{' || *next_char == '}' || text[*next_index..].starts_with("//") {
+            end = *next_index;
+            break;
+          }
+          chars.next();
+        }
+        tokens.push(Token::String(text[index..end].to_string()));
+      }
+    }
+  }
+  Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::Cursor;
+
+  use super::*;
+
+  fn string(text: &str) -> Token {
+    Token::String(text.to_string())
+  }
+
+  #[test]
+  fn tokenizer_handles_quotes_comments_and_brackets() {
+    let tokens = tokenize("\"LightmappedGeneric\" // comment { }\n{\n$basetexture concrete/wall01\n\"$color\" [1 0.5 0]\n}").unwrap();
+    assert_eq!(tokens, vec![
+      string("LightmappedGeneric"),
+      Token::BlockStart,
+      string("$basetexture"),
+      string("concrete/wall01"),
+      string("$color"),
+      Token::Bracketed("[1 0.5 0]".to_string()),
+      Token::BlockEnd
+    ]);
+  }
+
+  #[test]
+  fn tokenizer_splits_unquoted_values_at_braces_and_comments() {
+    let tokens = tokenize("key value{}key2 value2// comment").unwrap();
+    assert_eq!(tokens, vec![
+      string("key"),
+      string("value"),
+      Token::BlockStart,
+      Token::BlockEnd,
+      string("key2"),
+      string("value2")
+    ]);
+  }
+
+  #[test]
+  fn tokenizer_rejects_unterminated_strings_and_brackets() {
+    assert!(tokenize("\"$basetexture\" \"concrete/wall01").is_err());
+    assert!(tokenize("\"$basetexture\n\"").is_err());
+    assert!(tokenize("$color [1 1 1\n}").is_err());
+  }
+
+  #[test]
+  fn parses_a_multi_key_material() {
+    let text = "\"VertexLitGeneric\"\n\
+      {\n\
+        \"$basetexture\" \"models/props_c17/furniturecouch001a\"\n\
+        \"$BumpMap\" \"models/props_c17/furniturecouch001a_normal\"\n\
+        \"$surfaceprop\" \"Cloth\"\n\
+        \"$alphatest\" \"1\"\n\
+        // The proxies shouldn't break parsing\n\
+        \"Proxies\"\n\
+        {\n\
+          \"Sine\"\n\
+          {\n\
+            \"sineperiod\" \"2\"\n\
+            \"resultVar\" \"$alpha\"\n\
+          }\n\
+        }\n\
+        \"$color\" \"[1 0.5 0.25]\"\n\
+      }\n";
+    let material = VMTMaterial::new(&mut Cursor::new(text.as_bytes()), text.len() as u32).unwrap();
+    assert_eq!(material.get_shader(), SHADER_VERTEX_LIT_GENERIC);
+    assert_eq!(material.get_base_texture_name(), Some("models/props_c17/furniturecouch001a"));
+    assert_eq!(material.get_value("$bumpmap"), Some("models/props_c17/furniturecouch001a_normal"));
+    assert_eq!(material.get_value(SURFACE_PROP), Some("Cloth"));
+    assert_eq!(material.get_value(ALPHA_TEST), Some("1"));
+    assert_eq!(material.get_value(COLOR), Some("[1 0.5 0.25]"));
+    let sine = material.get_block("proxies").and_then(|proxies| proxies.get_block("sine")).unwrap();
+    assert_eq!(sine.get_value("resultvar"), Some("$alpha"));
   }
-  &text[..comment_start.unwrap()]
 }
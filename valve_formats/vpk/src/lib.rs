@@ -31,6 +31,7 @@ extern crate md5;
 extern crate rsa;
 extern crate crc;
 extern crate rand;
+extern crate lzma_rs;
 
 mod utilities;
 mod package;
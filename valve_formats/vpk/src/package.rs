@@ -1,18 +1,22 @@
-use std::io::{Read, BufReader, Seek, Error as IOError, Result as IOResult, SeekFrom};
+use std::io::{Read, Write, BufReader, Seek, Error as IOError, Result as IOResult, SeekFrom};
 use package_entry::PackageEntry;
 use std::collections::HashMap;
 use archive_md5_section_entry::ArchiveMD5SectionEntry;
 use read_util::{PrimitiveRead, StringRead, StringReadError, RawDataRead};
-use crc::crc32;
-use utilities::AsnKeyParser;
+use crc::{crc32, Hasher32};
+use utilities::{AsnKeyParser, is_lzma_compressed, decompress_lzma, glob_matches, LZMA_HEADER_SIZE};
 use rsa::{BigUint, PaddingScheme, Hash, PublicKey};
 use rand::rngs::OsRng;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
+
+type OpenFileCallback<R> = Box<dyn Fn(&str) -> IOResult<R> + Send + Sync>;
+type ArchiveFile<R> = Arc<Mutex<BufReader<R>>>;
 
 #[derive(Debug)]
 pub enum PackageError {
   IOError(IOError),
-  FileError(String)
+  FileError(String),
+  DecompressionError(String)
 }
 
 pub struct Package<R>
@@ -62,7 +66,9 @@ pub struct Package<R>
   /// The archive MD5 checksum section entries. Also known as cache line hashes.
   archive_md5_entries: Vec<ArchiveMD5SectionEntry>,
 
-  archive_files: Mutex<HashMap<u16, BufReader<R>>>
+  /// External archives get opened on first use and stay open after that
+  archive_files: Mutex<HashMap<u16, ArchiveFile<R>>>,
+  open_file_callback: OpenFileCallback<R>
 }
 
 pub const MAGIC: u32 = 0x55AA1234;
@@ -70,6 +76,9 @@ pub const MAGIC: u32 = 0x55AA1234;
 /// Always '/' as per Valve's vpk implementation.
 pub const DIRECTORY_SEPARATOR: &str = "/";
 
+/// Upper bound for type, directory and file names so a corrupt directory tree can't make us read forever.
+const MAX_NAME_LENGTH: usize = 1024;
+
 impl<R> Package<R>
   where R : Read + Seek {
   /// Gets the File Name
@@ -77,6 +86,11 @@ impl<R> Package<R>
     self.file_name.as_str()
   }
 
+  /// Whether all content is stored in this file rather than in _dir and _NNN archives.
+  pub fn is_single_file(&self) -> bool {
+    !self.is_dir_vpk
+  }
+
   /// Gets the VPK version.
   pub fn version(&self) -> u32 {
     self.version
@@ -137,6 +151,11 @@ impl<R> Package<R>
     &self.entries
   }
 
+  /// Iterates over all entries together with their full path.
+  pub fn iter_entries(&self) -> impl Iterator<Item = (String, &PackageEntry)> {
+    self.entries.values().flatten().map(|entry| (entry.full_path(), entry))
+  }
+
   /// Gets the archive MD5 checksum section entries. Also known as cache line hashes.
   pub fn archive_md5_entries(&self) -> &Vec<ArchiveMD5SectionEntry> {
     &self.archive_md5_entries
@@ -187,23 +206,6 @@ impl<R> Package<R>
 
     let entries = Self::read_entries(&mut input)?;
 
-    let mut archive_files = HashMap::<u16, BufReader<R>>::new();
-    for (_name, entries) in &entries {
-      for entry in entries {
-        if archive_files.contains_key(&entry.archive_index) {
-          continue;
-        }
-
-        let file_name = format!("{}_{:03}.vpk", file_name, entry.archive_index);
-        let file = (open_file_callback)(&file_name);
-        if file.is_err() {
-          // apparently broken entries are a thing and supposed to be ignored I guess
-          continue;
-        }
-        archive_files.insert(entry.archive_index, BufReader::new(file.map_err(PackageError::IOError)?));
-      }
-    }
-
     let (archive_md5_entries, tree_checksum, archive_md5_entries_checksum, whole_file_checksum, public_key, signature) =
       if version == 2 {
         input.seek(SeekFrom::Current(file_data_section_size as i64)).map_err(PackageError::IOError)?;
@@ -233,7 +235,8 @@ impl<R> Package<R>
       signature,
       entries,
       archive_md5_entries,
-      archive_files: Mutex::new(archive_files)
+      archive_files: Mutex::new(HashMap::new()),
+      open_file_callback: Box::new(open_file_callback)
     })
   }
 
@@ -279,67 +282,122 @@ impl<R> Package<R>
     self.entries[file_extension].iter().find(|x| x.directory_name.as_str() == trimmed_directory && x.file_name.as_str() == file_name)
   }
 
+  /// Finds all entries whose full path matches the given glob pattern, for example "materials/**/*.vmt".
+  pub fn find_entries_matching(&self, pattern: &str) -> Vec<&PackageEntry> {
+    let pattern = pattern.replace('\\', DIRECTORY_SEPARATOR).to_lowercase();
+    let file_pattern = pattern.rsplit(DIRECTORY_SEPARATOR).next().unwrap();
+    let extension = file_pattern.rfind('.')
+      .map(|dot| &file_pattern[dot + 1 ..])
+      .filter(|extension| !extension.contains('*') && !extension.contains('?'));
+
+    // Only look at the entries with the right file extension if we can
+    let entries: Vec<&Vec<PackageEntry>> = if let Some(extension) = extension {
+      self.entries.get(extension).into_iter().collect()
+    } else {
+      self.entries.values().collect()
+    };
+    entries.into_iter()
+      .flatten()
+      .filter(|entry| glob_matches(pattern.as_bytes(), entry.full_path().as_bytes()))
+      .collect()
+  }
+
   pub fn read_entry(&self, entry: &PackageEntry, validate_crc: bool) -> Result<Box<[u8]>, PackageError> {
-    let output_size = entry.small_data.len() + entry.len as usize;
-    let mut output = Vec::with_capacity(output_size);
-    unsafe { output.set_len(output_size); }
+    let mut output = Vec::<u8>::with_capacity(entry.total_len() as usize);
+    self.read_entry_into(entry, &mut output, validate_crc)?;
+    Ok(output.into_boxed_slice())
+  }
+
+  /// Copies the entry in chunks, so the whole file never has to be in memory.
+  /// LZMA compressed entries are the exception, those get buffered and decompressed before they're written.
+  pub fn read_entry_into<W: Write>(&self, entry: &PackageEntry, out: &mut W, validate_crc: bool) -> Result<(), PackageError> {
+    let mut writer = EntryWriter::new(out);
     if entry.small_data.len() > 0 {
-      output[.. entry.small_data.len()].copy_from_slice(&entry.small_data);
+      writer.write(&entry.small_data)?;
     }
 
     if entry.len > 0 {
-      if entry.archive_index != 0x7FFF {
-        if !self.is_dir_vpk {
-          return Err(PackageError::FileError("Given VPK is not a _dir, but entry is referencing an external archive.".to_string()));
-        }
-
-        let offset = entry.offset;
-        let mut files = self.archive_files.lock().unwrap();
-        let file = files.get_mut(&entry.archive_index).unwrap();
-        file.seek(SeekFrom::Start(offset as u64)).map_err(PackageError::IOError)?;
-        file.read_exact(&mut output[entry.small_data.len() .. entry.small_data.len() + entry.len as usize]).map_err(PackageError::IOError)?;
-      } else {
-        let offset = self.header_size + self.tree_size + entry.offset;
-        let mut reader = self.reader.lock().unwrap();
-        reader.seek(SeekFrom::Start(offset as u64)).map_err(PackageError::IOError)?;
-        reader.read_exact(&mut output[entry.small_data.len() .. entry.small_data.len() + entry.len as usize]).map_err(PackageError::IOError)?;
-      }
+      self.with_entry_data(entry, |reader| copy_chunks(reader, entry.len as usize, &mut writer))?;
     }
 
-    if validate_crc && entry.crc32 != crc32::checksum_ieee(&output) {
+    let crc = writer.finish()?;
+    if validate_crc && entry.crc32 != crc {
       return Err(PackageError::FileError("CRC32 mismatch for read data.".to_string()));
     }
+    Ok(())
+  }
 
-    Ok(output.into_boxed_slice())
+  /// Checks whether the entry starts with Valve's LZMA header.
+  /// The header can be in the preloaded bytes or in the data stored in the archive, so this might have to read from it.
+  pub fn is_compressed(&self, entry: &PackageEntry) -> Result<bool, PackageError> {
+    let mut header: Vec<u8> = entry.small_data.iter().take(LZMA_HEADER_SIZE).copied().collect();
+    let missing_len = (LZMA_HEADER_SIZE - header.len()).min(entry.len as usize);
+    if missing_len > 0 {
+      let start = header.len();
+      header.resize(start + missing_len, 0u8);
+      self.with_entry_data(entry, |reader| reader.read_exact(&mut header[start ..]).map_err(PackageError::IOError))?;
+    }
+    Ok(is_lzma_compressed(&header))
+  }
+
+  /// Calls the callback with a reader that's positioned at the start of the entry data that isn't preloaded.
+  fn with_entry_data<T, F: FnOnce(&mut dyn Read) -> Result<T, PackageError>>(&self, entry: &PackageEntry, callback: F) -> Result<T, PackageError> {
+    // Single file packages (usually version 1) keep everything in their own file data section
+    if entry.archive_index != 0x7FFF && !self.is_single_file() {
+      let file = self.archive_file(entry.archive_index)?;
+      let mut file = file.lock().unwrap();
+      file.seek(SeekFrom::Start(entry.offset as u64)).map_err(PackageError::IOError)?;
+      callback(&mut *file)
+    } else {
+      let offset = self.header_size + self.tree_size + entry.offset;
+      let mut reader = self.reader.lock().unwrap();
+      reader.seek(SeekFrom::Start(offset as u64)).map_err(PackageError::IOError)?;
+      callback(&mut *reader)
+    }
+  }
+
+  fn archive_file(&self, archive_index: u16) -> Result<ArchiveFile<R>, PackageError> {
+    if let Some(file) = self.archive_files.lock().unwrap().get(&archive_index) {
+      return Ok(file.clone());
+    }
+
+    // Don't hold the lock while the callback runs, it's free to read from this package again.
+    // If another thread opened the same archive in the meantime, we just keep the first one.
+    let file_name = format!("{}_{:03}.vpk", self.file_name, archive_index);
+    let file = (self.open_file_callback)(&file_name)
+      .map_err(|e| PackageError::FileError(format!("Failed to open archive {}: {:?}", file_name, e)))?;
+    let mut files = self.archive_files.lock().unwrap();
+    Ok(files.entry(archive_index)
+      .or_insert_with(|| Arc::new(Mutex::new(BufReader::new(file))))
+      .clone())
+  }
+
+  fn read_name(input: &mut R, kind: &str) -> Result<String, PackageError> {
+    input.read_null_terminated_string_max(MAX_NAME_LENGTH).map_err(|e| match e {
+      StringReadError::IOError(e) => PackageError::IOError(e),
+      StringReadError::StringConstructionError(_) => PackageError::FileError(format!("Failed to read {}", kind)),
+      StringReadError::MaxLengthExceeded(max) => PackageError::FileError(format!("Failed to read {}, no terminator within {} bytes", kind, max))
+    })
   }
 
   fn read_entries(input: &mut R) -> Result<HashMap<String, Vec<PackageEntry>>, PackageError> {
     let mut type_entries = HashMap::<String, Vec<PackageEntry>>::new();
 
     'types: loop {
-      let type_name = input.read_null_terminated_string().map_err(|e| match e {
-        StringReadError::IOError(e) => PackageError::IOError(e),
-        StringReadError::StringConstructionError(_) => PackageError::FileError("Failed to read type name".to_string())
-      })?;
+      let type_name = Self::read_name(input, "type name")?;
       if type_name.is_empty() {
         break 'types;
       }
 
       let mut entries = Vec::<PackageEntry>::new();
       'entries: loop {
-        let directory_name = input.read_null_terminated_string().map_err(|e| match e {
-          StringReadError::IOError(e) => PackageError::IOError(e),
-          StringReadError::StringConstructionError(_) => PackageError::FileError("Failed to read type name".to_string())
-        })?;
+        let directory_name = Self::read_name(input, "directory name")?;
         if directory_name.is_empty() {
           break 'entries;
         }
 
         'files: loop {
-          let file_name = input.read_null_terminated_string().map_err(|e| match e {
-            StringReadError::IOError(e) => PackageError::IOError(e),
-            StringReadError::StringConstructionError(_) => PackageError::FileError("Failed to read type name".to_string())
-          })?;
+          let file_name = Self::read_name(input, "file name")?;
           if file_name.is_empty() {
             break 'files;
           }
@@ -373,7 +431,8 @@ impl<R> Package<R>
         }
       }
 
-      type_entries.insert(type_name, entries);
+      // Lookups lowercase the path, so the extension key has to be lowercase too
+      type_entries.entry(type_name.to_lowercase()).or_default().extend(entries);
     }
 
     Ok(type_entries)
@@ -388,20 +447,32 @@ impl<R> Package<R>
     {
       let mut reader = self.reader.lock().unwrap();
       reader.seek(SeekFrom::Start(0)).map_err(PackageError::IOError)?;
-      let mut buffer = reader.read_data((self.header_size + self.tree_size + self.file_data_section_size + self.archive_md5_section_size + 32) as usize).map_err(PackageError::IOError)?;
-      let mut hash = md5::compute(&buffer);
+      let buffer = reader.read_data((self.header_size + self.tree_size + self.file_data_section_size + self.archive_md5_section_size + 32) as usize).map_err(PackageError::IOError)?;
+      let hash = md5::compute(&buffer);
       if hash.0 != self.whole_file_checksum {
         return Err(PackageError::FileError(format!("Package checksum mismatch ({:?} != expected {:?}).", &hash, &self.whole_file_checksum)));
       }
 
       reader.seek(SeekFrom::Start((self.header_size + self.tree_size + self.file_data_section_size) as u64)).map_err(PackageError::IOError)?;
-      reader.read_exact(&mut buffer[..self.archive_md5_section_size as usize]).map_err(PackageError::IOError)?;
-      hash = md5::compute(&buffer[..self.archive_md5_section_size as usize]);
-      if hash.0 != self.whole_file_checksum {
+      let archive_md5_buffer = reader.read_data(self.archive_md5_section_size as usize).map_err(PackageError::IOError)?;
+      let hash = md5::compute(&archive_md5_buffer);
+      if hash.0 != self.archive_md5_entries_checksum {
         return Err(PackageError::FileError(format!("Archive MD5 entries checksum mismatch ({:?} != expected {:?}).", &hash, &self.archive_md5_entries_checksum)));
       }
 
-      // TODO: verify archive checksums
+    }
+
+    {
+      for entry in &self.archive_md5_entries {
+        let file = self.archive_file(entry.archive_index as u16)?;
+        let mut file = file.lock().unwrap();
+        file.seek(SeekFrom::Start(entry.offset as u64)).map_err(PackageError::IOError)?;
+        let data = file.read_data(entry.length as usize).map_err(PackageError::IOError)?;
+        let hash = md5::compute(&data);
+        if hash.0 != entry.checksum {
+          return Err(PackageError::FileError(format!("Archive checksum mismatch in archive {} at offset {} ({:?} != expected {:?}).", entry.archive_index, entry.offset, &hash, &entry.checksum)));
+        }
+      }
     }
 
     if self.public_key.is_empty() || self.signature.is_empty() {
@@ -505,3 +576,372 @@ impl<R> Package<R>
     Ok((public_key, signature))
   }
 }
+
+const COPY_CHUNK_SIZE: usize = 64 * 1024;
+
+fn copy_chunks<S: Read + ?Sized, W: Write>(source: &mut S, len: usize, writer: &mut EntryWriter<W>) -> Result<(), PackageError> {
+  let mut buffer = vec![0u8; COPY_CHUNK_SIZE.min(len)];
+  let mut remaining = len;
+  while remaining > 0 {
+    let chunk_len = remaining.min(buffer.len());
+    source.read_exact(&mut buffer[.. chunk_len]).map_err(PackageError::IOError)?;
+    writer.write(&buffer[.. chunk_len])?;
+    remaining -= chunk_len;
+  }
+  Ok(())
+}
+
+/// Sits between the raw entry data and the output, keeps track of the CRC and decompresses LZMA entries.
+struct EntryWriter<'a, W: Write> {
+  out: &'a mut W,
+  digest: crc32::Digest,
+  /// Holds back the start of the data until we know whether it's compressed and everything after that if it is
+  pending: Vec<u8>,
+  is_compressed: Option<bool>
+}
+
+impl<'a, W: Write> EntryWriter<'a, W> {
+  fn new(out: &'a mut W) -> Self {
+    Self {
+      out,
+      digest: crc32::Digest::new(crc32::IEEE),
+      pending: Vec::new(),
+      is_compressed: None
+    }
+  }
+
+  fn write(&mut self, data: &[u8]) -> Result<(), PackageError> {
+    match self.is_compressed {
+      Some(false) => self.write_output(data),
+      Some(true) => {
+        self.pending.extend_from_slice(data);
+        Ok(())
+      }
+      None => {
+        self.pending.extend_from_slice(data);
+        if self.pending.len() >= LZMA_HEADER_SIZE {
+          self.flush_pending()?;
+        }
+        Ok(())
+      }
+    }
+  }
+
+  fn flush_pending(&mut self) -> Result<(), PackageError> {
+    let is_compressed = is_lzma_compressed(&self.pending);
+    self.is_compressed = Some(is_compressed);
+    if !is_compressed {
+      let pending = std::mem::take(&mut self.pending);
+      self.write_output(&pending)?;
+    }
+    Ok(())
+  }
+
+  fn write_output(&mut self, data: &[u8]) -> Result<(), PackageError> {
+    self.digest.write(data);
+    self.out.write_all(data).map_err(PackageError::IOError)
+  }
+
+  /// Returns the CRC32 of the data that was written to the output.
+  fn finish(mut self) -> Result<u32, PackageError> {
+    if self.is_compressed.is_none() {
+      self.flush_pending()?;
+    }
+    if self.is_compressed == Some(true) {
+      let decompressed = decompress_lzma(&self.pending).map_err(PackageError::DecompressionError)?;
+      self.write_output(&decompressed)?;
+    }
+    Ok(self.digest.sum32())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::Cursor;
+  use std::sync::Arc;
+  use std::sync::atomic::{AtomicUsize, Ordering};
+
+  use crc::crc32;
+  use md5;
+
+  use super::{Package, PackageError, EntryWriter, COPY_CHUNK_SIZE, MAGIC};
+  use utilities::LZMA_MAGIC;
+
+  /// A version 1 directory VPK with one entry that lives in archive 0.
+  fn dir_package_data(content_len: u32) -> Vec<u8> {
+    dir_package_data_with_crc(0, &[], content_len)
+  }
+
+  fn dir_package_data_with_crc(crc32: u32, small_data: &[u8], content_len: u32) -> Vec<u8> {
+    let tree = dir_package_tree(crc32, small_data, content_len);
+    let mut data = Vec::<u8>::new();
+    data.extend_from_slice(&MAGIC.to_le_bytes());
+    data.extend_from_slice(&1u32.to_le_bytes());
+    data.extend_from_slice(&(tree.len() as u32).to_le_bytes());
+    data.extend_from_slice(&tree);
+    data
+  }
+
+  fn dir_package_tree(crc32: u32, small_data: &[u8], content_len: u32) -> Vec<u8> {
+    let mut tree = Vec::<u8>::new();
+    tree.extend_from_slice(b"txt\0dir\0file\0");
+    tree.extend_from_slice(&crc32.to_le_bytes());
+    tree.extend_from_slice(&(small_data.len() as u16).to_le_bytes());
+    tree.extend_from_slice(&0u16.to_le_bytes()); // archive index
+    tree.extend_from_slice(&0u32.to_le_bytes()); // offset
+    tree.extend_from_slice(&content_len.to_le_bytes());
+    tree.extend_from_slice(&0xFFFFu16.to_le_bytes());
+    tree.extend_from_slice(small_data);
+    tree.extend_from_slice(b"\0\0\0");
+    tree
+  }
+
+  /// A version 2 directory VPK with valid checksums and one archive MD5 entry that covers the content of archive 0.
+  fn v2_package_data(content: &[u8]) -> Vec<u8> {
+    let tree = dir_package_tree(0, &[], content.len() as u32);
+    let mut archive_md5_section = Vec::<u8>::new();
+    archive_md5_section.extend_from_slice(&0u32.to_le_bytes()); // archive index
+    archive_md5_section.extend_from_slice(&0u32.to_le_bytes()); // offset
+    archive_md5_section.extend_from_slice(&(content.len() as u32).to_le_bytes());
+    archive_md5_section.extend_from_slice(&md5::compute(content).0);
+
+    let mut data = Vec::<u8>::new();
+    data.extend_from_slice(&MAGIC.to_le_bytes());
+    data.extend_from_slice(&2u32.to_le_bytes());
+    data.extend_from_slice(&(tree.len() as u32).to_le_bytes());
+    data.extend_from_slice(&0u32.to_le_bytes()); // file data section size
+    data.extend_from_slice(&(archive_md5_section.len() as u32).to_le_bytes());
+    data.extend_from_slice(&48u32.to_le_bytes()); // other MD5 section size
+    data.extend_from_slice(&0u32.to_le_bytes()); // signature section size
+    data.extend_from_slice(&tree);
+    data.extend_from_slice(&archive_md5_section);
+    data.extend_from_slice(&md5::compute(&tree).0);
+    data.extend_from_slice(&md5::compute(&archive_md5_section).0);
+    let whole_file_checksum = md5::compute(&data);
+    data.extend_from_slice(&whole_file_checksum.0);
+    data
+  }
+
+  #[test]
+  fn verify_hashes_checks_the_archive_md5_section() {
+    let content = b"hello";
+    let read_package = move |data: Vec<u8>| Package::read("test_dir.vpk", Cursor::new(data), move |_| Ok(Cursor::new(content.to_vec()))).unwrap();
+    assert!(read_package(v2_package_data(content)).verify_hashes().is_ok());
+
+    // Corrupt the checksum inside the archive MD5 section and fix up the whole file checksum,
+    // so only the checksum of the archive MD5 section itself can catch it
+    let mut data = v2_package_data(content);
+    let whole_file_checksum_start = data.len() - 16;
+    data[whole_file_checksum_start - 32 - 1] ^= 0xFF;
+    let whole_file_checksum = md5::compute(&data[.. whole_file_checksum_start]);
+    data[whole_file_checksum_start ..].copy_from_slice(&whole_file_checksum.0);
+    let result = read_package(data).verify_hashes();
+    assert!(matches!(result, Err(PackageError::FileError(ref message)) if message.starts_with("Archive MD5 entries checksum mismatch")), "{:?}", result.err());
+  }
+
+  #[test]
+  fn archives_are_opened_lazily_and_once() {
+    let content = b"hello";
+    let open_count = Arc::new(AtomicUsize::new(0));
+    let callback_open_count = open_count.clone();
+    let package = Package::read("test_dir.vpk", Cursor::new(dir_package_data(content.len() as u32)), move |file_name| {
+      assert_eq!(file_name, "test_000.vpk");
+      callback_open_count.fetch_add(1, Ordering::SeqCst);
+      Ok(Cursor::new(content.to_vec()))
+    }).unwrap();
+    assert_eq!(open_count.load(Ordering::SeqCst), 0);
+
+    let entry = package.find_entry("dir/file.txt").unwrap();
+    assert!(!package.is_compressed(entry).unwrap());
+    for _ in 0..2 {
+      let data = package.read_entry(entry, false).unwrap();
+      assert_eq!(&data[..], content);
+    }
+    assert_eq!(open_count.load(Ordering::SeqCst), 1);
+  }
+
+  /// Compresses the data and wraps it in Valve's LZMA header.
+  fn valve_lzma(data: &[u8]) -> Vec<u8> {
+    let mut lzma = Vec::<u8>::new();
+    let options = lzma_rs::compress::Options {
+      unpacked_size: lzma_rs::compress::UnpackedSize::SkipWritingToHeader
+    };
+    lzma_rs::lzma_compress_with_options(&mut Cursor::new(data), &mut lzma, &options).unwrap();
+    let (properties, stream) = lzma.split_at(5);
+
+    let mut compressed = Vec::<u8>::new();
+    compressed.extend_from_slice(&LZMA_MAGIC.to_le_bytes());
+    compressed.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    compressed.extend_from_slice(&(stream.len() as u32).to_le_bytes());
+    compressed.extend_from_slice(properties);
+    compressed.extend_from_slice(stream);
+    compressed
+  }
+
+  #[test]
+  fn compressed_archive_entries_are_detected_and_decompressed() {
+    let content = b"hello hello hello hello hello hello".to_vec();
+    let compressed = valve_lzma(&content);
+    let data = dir_package_data_with_crc(crc32::checksum_ieee(&content), &[], compressed.len() as u32);
+    let package = Package::read("test_dir.vpk", Cursor::new(data), move |_| Ok(Cursor::new(compressed.clone()))).unwrap();
+
+    let entry = package.find_entry("dir/file.txt").unwrap();
+    assert!(package.is_compressed(entry).unwrap());
+    assert_eq!(&package.read_entry(entry, true).unwrap()[..], &content[..]);
+  }
+
+  #[test]
+  fn streams_preloaded_bytes_first_and_validates_crc() {
+    let small_data = b"preloaded ";
+    let archive_data: Vec<u8> = (0..COPY_CHUNK_SIZE * 2 + 100).map(|i| (i % 251) as u8).collect();
+    let mut content = small_data.to_vec();
+    content.extend_from_slice(&archive_data);
+
+    let data = dir_package_data_with_crc(crc32::checksum_ieee(&content), small_data, archive_data.len() as u32);
+    let package = Package::read("test_dir.vpk", Cursor::new(data), move |_| Ok(Cursor::new(archive_data.clone()))).unwrap();
+    let entry = package.find_entry("dir/file.txt").unwrap();
+    let mut output = Vec::<u8>::new();
+    package.read_entry_into(entry, &mut output, true).unwrap();
+    assert_eq!(output, content);
+
+    let data = dir_package_data_with_crc(0x1234, small_data, 0);
+    let package = Package::read("test_dir.vpk", Cursor::new(data), |_| Ok(Cursor::new(Vec::new()))).unwrap();
+    let entry = package.find_entry("dir/file.txt").unwrap();
+    assert!(matches!(package.read_entry(entry, true), Err(PackageError::FileError(_))));
+    assert_eq!(&package.read_entry(entry, false).unwrap()[..], &small_data[..]);
+  }
+
+  #[test]
+  fn entry_writer_handles_headers_split_across_writes() {
+    let content = b"hello hello hello hello hello hello";
+    let compressed = valve_lzma(content);
+    let mut output = Vec::<u8>::new();
+    let mut writer = EntryWriter::new(&mut output);
+    for byte in &compressed {
+      writer.write(&[*byte]).unwrap();
+    }
+    assert_eq!(writer.finish().unwrap(), crc32::checksum_ieee(content));
+    assert_eq!(&output[..], &content[..]);
+
+    // Data that's shorter than a LZMA header gets written as is
+    let mut output = Vec::<u8>::new();
+    let mut writer = EntryWriter::new(&mut output);
+    writer.write(b"LZMA").unwrap();
+    assert_eq!(writer.finish().unwrap(), crc32::checksum_ieee(b"LZMA"));
+    assert_eq!(&output[..], b"LZMA");
+  }
+
+  /// A directory name with the names of the files in it.
+  type TestDirectory<'a> = (&'a str, &'a [&'a str]);
+
+  /// A version 1 directory VPK whose entries all have empty inline data.
+  fn tree_package_data(types: &[(&str, &[TestDirectory])]) -> Vec<u8> {
+    let mut tree = Vec::<u8>::new();
+    for (type_name, directories) in types {
+      tree.extend_from_slice(type_name.as_bytes());
+      tree.push(0);
+      for (directory_name, file_names) in directories.iter() {
+        tree.extend_from_slice(directory_name.as_bytes());
+        tree.push(0);
+        for file_name in file_names.iter() {
+          tree.extend_from_slice(file_name.as_bytes());
+          tree.push(0);
+          tree.extend_from_slice(&0u32.to_le_bytes()); // crc32
+          tree.extend_from_slice(&0u16.to_le_bytes()); // small data length
+          tree.extend_from_slice(&0x7FFFu16.to_le_bytes()); // archive index
+          tree.extend_from_slice(&0u32.to_le_bytes()); // offset
+          tree.extend_from_slice(&0u32.to_le_bytes()); // length
+          tree.extend_from_slice(&0xFFFFu16.to_le_bytes());
+        }
+        tree.push(0);
+      }
+      tree.push(0);
+    }
+    tree.push(0);
+
+    let mut data = Vec::<u8>::new();
+    data.extend_from_slice(&MAGIC.to_le_bytes());
+    data.extend_from_slice(&1u32.to_le_bytes());
+    data.extend_from_slice(&(tree.len() as u32).to_le_bytes());
+    data.extend_from_slice(&tree);
+    data
+  }
+
+  fn matching_paths(package: &Package<Cursor<Vec<u8>>>, pattern: &str) -> Vec<String> {
+    let mut paths: Vec<String> = package.find_entries_matching(pattern).iter().map(|entry| entry.full_path()).collect();
+    paths.sort();
+    paths
+  }
+
+  #[test]
+  fn glob_search_recurses_and_ignores_case() {
+    let data = tree_package_data(&[
+      ("VTF", &[("materials", &["wall"]), ("Materials/Brick", &["Old"]), ("models", &["prop"])]),
+      ("vmt", &[("materials/brick", &["old"])])
+    ]);
+    let package = Package::read("test_dir.vpk", Cursor::new(data), |_| Ok(Cursor::new(Vec::new()))).unwrap();
+
+    assert_eq!(matching_paths(&package, "materials/**/*.vtf"), vec!["materials/brick/old.vtf", "materials/wall.vtf"]);
+    assert_eq!(matching_paths(&package, "MATERIALS/*.VTF"), vec!["materials/wall.vtf"]);
+    assert_eq!(matching_paths(&package, "materials/brick/old.*"), vec!["materials/brick/old.vmt", "materials/brick/old.vtf"]);
+  }
+
+  #[test]
+  fn glob_search_finds_root_entries() {
+    let data = tree_package_data(&[
+      ("txt", &[(" ", &["readme"]), ("docs", &["manual"])])
+    ]);
+    let package = Package::read("test_dir.vpk", Cursor::new(data), |_| Ok(Cursor::new(Vec::new()))).unwrap();
+
+    assert_eq!(matching_paths(&package, "*.txt"), vec!["readme.txt"]);
+    assert_eq!(matching_paths(&package, "**/*.txt"), vec!["docs/manual.txt", "readme.txt"]);
+    assert!(package.find_entry("readme.txt").is_some());
+  }
+
+  /// Appends a file entry of the tree, the data that isn't preloaded is `len` bytes at `offset` in the given archive.
+  fn push_tree_entry(tree: &mut Vec<u8>, file_name: &str, content: &[u8], preloaded_len: usize, archive_index: u16, offset: u32) {
+    tree.extend_from_slice(file_name.as_bytes());
+    tree.push(0);
+    tree.extend_from_slice(&crc32::checksum_ieee(content).to_le_bytes());
+    tree.extend_from_slice(&(preloaded_len as u16).to_le_bytes());
+    tree.extend_from_slice(&archive_index.to_le_bytes());
+    tree.extend_from_slice(&offset.to_le_bytes());
+    tree.extend_from_slice(&((content.len() - preloaded_len) as u32).to_le_bytes());
+    tree.extend_from_slice(&0xFFFFu16.to_le_bytes());
+    tree.extend_from_slice(&content[.. preloaded_len]);
+  }
+
+  #[test]
+  fn single_file_version_1_package_reads_its_own_data() {
+    let readme = b"read me first";
+    let license = b"preloaded part, then the rest";
+    let preloaded_len = 15;
+
+    // Older tools write archive index 0 for data that lives in the single file
+    let mut tree = Vec::<u8>::new();
+    tree.extend_from_slice(b"txt\0docs\0");
+    push_tree_entry(&mut tree, "readme", readme, 0, 0, 0);
+    push_tree_entry(&mut tree, "license", license, preloaded_len, 0x7FFF, readme.len() as u32);
+    tree.extend_from_slice(b"\0\0\0");
+
+    let mut data = Vec::<u8>::new();
+    data.extend_from_slice(&MAGIC.to_le_bytes());
+    data.extend_from_slice(&1u32.to_le_bytes());
+    data.extend_from_slice(&(tree.len() as u32).to_le_bytes());
+    data.extend_from_slice(&tree);
+    data.extend_from_slice(readme);
+    data.extend_from_slice(&license[preloaded_len ..]);
+
+    let package = Package::read("mod.vpk", Cursor::new(data), |file_name| panic!("Opened external archive {}", file_name)).unwrap();
+    assert!(package.is_single_file());
+    assert_eq!(package.version(), 1);
+    let entry = package.find_entry("docs/readme.txt").unwrap();
+    assert!(!package.is_compressed(entry).unwrap());
+    assert_eq!(&package.read_entry(entry, true).unwrap()[..], &readme[..]);
+    let entry = package.find_entry("docs/license.txt").unwrap();
+    assert_eq!(&package.read_entry(entry, true).unwrap()[..], &license[..]);
+
+    let package = Package::read("test_dir.vpk", Cursor::new(dir_package_data(0)), |_| Ok(Cursor::new(Vec::new()))).unwrap();
+    assert!(!package.is_single_file());
+  }
+}
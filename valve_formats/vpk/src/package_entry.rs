@@ -1,3 +1,5 @@
+use package::DIRECTORY_SEPARATOR;
+
 pub struct PackageEntry {
   /// File name of this entry
   pub file_name: String,
@@ -40,11 +42,12 @@ impl PackageEntry {
     }
   }
 
+  /// The path of the entry inside the package, files in the root directory don't get a directory prefix.
   pub fn full_path(&self) -> String {
     if self.directory_name == " " {
       return self.full_file_name();
     }
-    self.directory_name.clone() + &self.full_file_name()
+    self.directory_name.clone() + DIRECTORY_SEPARATOR + &self.full_file_name()
   }
 }
 
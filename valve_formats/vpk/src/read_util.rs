@@ -4,22 +4,32 @@ use std::string::FromUtf8Error;
 #[derive(Debug)]
 pub enum StringReadError {
   IOError(IOError),
-  StringConstructionError(FromUtf8Error)
+  StringConstructionError(FromUtf8Error),
+  /// No terminator was found within the given maximum length
+  MaxLengthExceeded(usize)
 }
 
 pub trait StringRead {
   fn read_null_terminated_string(&mut self) -> Result<String, StringReadError>;
+  fn read_null_terminated_string_max(&mut self, max: usize) -> Result<String, StringReadError>;
   fn read_fixed_length_null_terminated_string(&mut self, length: u32) -> Result<String, StringReadError>;
 }
 
 impl<T: Read + ?Sized> StringRead for T {
   fn read_null_terminated_string(&mut self) -> Result<String, StringReadError> {
+    self.read_null_terminated_string_max(usize::MAX)
+  }
+
+  fn read_null_terminated_string_max(&mut self, max: usize) -> Result<String, StringReadError> {
     let mut buffer = Vec::<u8>::new();
     loop {
       let char = self.read_u8().map_err(StringReadError::IOError)?;
       if char == 0 {
         break;
       }
+      if buffer.len() == max {
+        return Err(StringReadError::MaxLengthExceeded(max));
+      }
       buffer.push(char);
     }
     String::from_utf8(buffer).map_err(StringReadError::StringConstructionError)
@@ -64,6 +74,14 @@ pub trait PrimitiveRead {
   fn read_i64(&mut self) -> IOResult<i64>;
   fn read_f32(&mut self) -> IOResult<f32>;
   fn read_f64(&mut self) -> IOResult<f64>;
+  fn read_u16_be(&mut self) -> IOResult<u16>;
+  fn read_u32_be(&mut self) -> IOResult<u32>;
+  fn read_u64_be(&mut self) -> IOResult<u64>;
+  fn read_i16_be(&mut self) -> IOResult<i16>;
+  fn read_i32_be(&mut self) -> IOResult<i32>;
+  fn read_i64_be(&mut self) -> IOResult<i64>;
+  fn read_f32_be(&mut self) -> IOResult<f32>;
+  fn read_f64_be(&mut self) -> IOResult<f64>;
 }
 
 impl<T: Read + ?Sized> PrimitiveRead for T {
@@ -126,4 +144,52 @@ impl<T: Read + ?Sized> PrimitiveRead for T {
     self.read_exact(&mut buffer)?;
     Ok(f64::from_le_bytes(buffer))
   }
+
+  fn read_u16_be(&mut self) -> IOResult<u16> {
+    let mut buffer = [0u8; 2];
+    self.read_exact(&mut buffer)?;
+    Ok(u16::from_be_bytes(buffer))
+  }
+
+  fn read_u32_be(&mut self) -> IOResult<u32> {
+    let mut buffer = [0u8; 4];
+    self.read_exact(&mut buffer)?;
+    Ok(u32::from_be_bytes(buffer))
+  }
+
+  fn read_u64_be(&mut self) -> IOResult<u64> {
+    let mut buffer = [0u8; 8];
+    self.read_exact(&mut buffer)?;
+    Ok(u64::from_be_bytes(buffer))
+  }
+
+  fn read_i16_be(&mut self) -> IOResult<i16> {
+    let mut buffer = [0u8; 2];
+    self.read_exact(&mut buffer)?;
+    Ok(i16::from_be_bytes(buffer))
+  }
+
+  fn read_i32_be(&mut self) -> IOResult<i32> {
+    let mut buffer = [0u8; 4];
+    self.read_exact(&mut buffer)?;
+    Ok(i32::from_be_bytes(buffer))
+  }
+
+  fn read_i64_be(&mut self) -> IOResult<i64> {
+    let mut buffer = [0u8; 8];
+    self.read_exact(&mut buffer)?;
+    Ok(i64::from_be_bytes(buffer))
+  }
+
+  fn read_f32_be(&mut self) -> IOResult<f32> {
+    let mut buffer = [0u8; 4];
+    self.read_exact(&mut buffer)?;
+    Ok(f32::from_be_bytes(buffer))
+  }
+
+  fn read_f64_be(&mut self) -> IOResult<f64> {
+    let mut buffer = [0u8; 8];
+    self.read_exact(&mut buffer)?;
+    Ok(f64::from_be_bytes(buffer))
+  }
 }
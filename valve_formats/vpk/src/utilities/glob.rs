@@ -0,0 +1,66 @@
+/// Supports `*` and `?` within a single path segment and `**` for any number of directories.
+/// Matching ignores ASCII case, like Valve's file system does.
+pub fn glob_matches(pattern: &[u8], path: &[u8]) -> bool {
+  match pattern.first() {
+    None => path.is_empty(),
+    Some(b'*') if pattern.get(1) == Some(&b'*') => {
+      let rest = &pattern[2..];
+      // "**/" also matches no directory at all
+      if rest.first() == Some(&b'/') && glob_matches(&rest[1..], path) {
+        return true;
+      }
+      (0..=path.len()).any(|i| glob_matches(rest, &path[i..]))
+    }
+    Some(b'*') => {
+      let rest = &pattern[1..];
+      for i in 0..=path.len() {
+        if glob_matches(rest, &path[i..]) {
+          return true;
+        }
+        if i < path.len() && path[i] == b'/' {
+          break;
+        }
+      }
+      false
+    }
+    Some(b'?') => !path.is_empty() && path[0] != b'/' && glob_matches(&pattern[1..], &path[1..]),
+    Some(c) => matches!(path.first(), Some(p) if p.eq_ignore_ascii_case(c)) && glob_matches(&pattern[1..], &path[1..])
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::glob_matches;
+
+  fn matches(pattern: &str, path: &str) -> bool {
+    glob_matches(pattern.as_bytes(), path.as_bytes())
+  }
+
+  #[test]
+  fn star_stays_within_a_directory() {
+    assert!(matches("materials/*.vtf", "materials/wall.vtf"));
+    assert!(!matches("materials/*.vtf", "materials/brick/wall.vtf"));
+    assert!(matches("materials/*", "materials/wall.vtf"));
+  }
+
+  #[test]
+  fn double_star_recurses() {
+    assert!(matches("materials/**/*.vtf", "materials/wall.vtf"));
+    assert!(matches("materials/**/*.vtf", "materials/brick/old/wall.vtf"));
+    assert!(!matches("materials/**/*.vtf", "models/brick/wall.vtf"));
+    assert!(!matches("materials/**/*.vtf", "materials/brick/wall.vmt"));
+  }
+
+  #[test]
+  fn question_mark_matches_one_character() {
+    assert!(matches("wall?.vtf", "wall1.vtf"));
+    assert!(!matches("wall?.vtf", "wall.vtf"));
+    assert!(!matches("a?b", "a/b"));
+  }
+
+  #[test]
+  fn matching_ignores_case() {
+    assert!(matches("materials/**/*.vtf", "Materials/Brick/Wall.VTF"));
+    assert!(matches("MATERIALS/*.VMT", "materials/wall.vmt"));
+  }
+}
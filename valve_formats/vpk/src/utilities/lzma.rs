@@ -0,0 +1,38 @@
+use std::io::Cursor;
+
+use lzma_rs::decompress::{Options, UnpackedSize};
+
+/// "LZMA" as a little endian u32
+pub const LZMA_MAGIC: u32 = 0x414D5A4C;
+
+/// Valve's header: magic, uncompressed size, compressed size and the 5 LZMA property bytes
+pub const LZMA_HEADER_SIZE: usize = 17;
+
+pub fn is_lzma_compressed(data: &[u8]) -> bool {
+  data.len() >= LZMA_HEADER_SIZE && u32::from_le_bytes([data[0], data[1], data[2], data[3]]) == LZMA_MAGIC
+}
+
+pub fn decompress_lzma(data: &[u8]) -> Result<Vec<u8>, String> {
+  if !is_lzma_compressed(data) {
+    return Err("Data does not start with a LZMA header.".to_string());
+  }
+
+  let actual_size = u32::from_le_bytes([data[4], data[5], data[6], data[7]]) as usize;
+  let lzma_size = u32::from_le_bytes([data[8], data[9], data[10], data[11]]) as usize;
+  if data.len() < LZMA_HEADER_SIZE + lzma_size {
+    return Err(format!("LZMA data is truncated, expected {} bytes but only got {}.", lzma_size, data.len() - LZMA_HEADER_SIZE));
+  }
+
+  // Valve stores the properties but leaves out the uncompressed size of the regular .lzma header
+  let mut input = Cursor::new(&data[12 .. LZMA_HEADER_SIZE + lzma_size]);
+  let mut output = Vec::<u8>::with_capacity(actual_size);
+  let options = Options {
+    unpacked_size: UnpackedSize::UseProvided(Some(actual_size as u64)),
+    ..Default::default()
+  };
+  lzma_rs::lzma_decompress_with_options(&mut input, &mut output, &options).map_err(|e| format!("{:?}", e))?;
+  if output.len() != actual_size {
+    return Err(format!("Decompressed LZMA data has the wrong size: {} != expected {}.", output.len(), actual_size));
+  }
+  Ok(output)
+}
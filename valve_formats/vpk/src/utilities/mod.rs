@@ -1,3 +1,7 @@
 pub mod crc32;
+mod lzma;
+pub use self::lzma::*;
+mod glob;
+pub use self::glob::*;
 mod asn_key_parser;
 pub use self::asn_key_parser::*;
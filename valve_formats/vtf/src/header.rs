@@ -76,9 +76,9 @@ impl Header {
     let reflectivity = [reader.read_f32()?, reader.read_f32()?, reader.read_f32()?];
     reader.seek(SeekFrom::Current(4))?;
     let bumpmap_scale = reader.read_f32()?;
-    let high_res_image_format: ImageFormat = unsafe { std::mem::transmute(reader.read_u32()?) };
+    let high_res_image_format = ImageFormat::from_raw(reader.read_u32()?);
     let mipmap_count = reader.read_u8()?;
-    let low_res_image_format: ImageFormat =  unsafe { std::mem::transmute(reader.read_u32()?) };
+    let low_res_image_format = ImageFormat::from_raw(reader.read_u32()?);
     let low_res_image_width = reader.read_u8()?;
     let low_res_image_height = reader.read_u8()?;
 
@@ -118,4 +118,15 @@ impl Header {
       num_resources
     })
   }
+
+  /// Environment maps have 6 cube faces, older versions also store a sphere map as the 7th face.
+  pub fn faces_count(&self) -> u32 {
+    if !self.flags.contains(TextureFlags::ENV_MAP) {
+      1
+    } else if self.version[0] == 7 && self.version[1] < 5 && self.first_frame != 0xffff {
+      7
+    } else {
+      6
+    }
+  }
 }
\ No newline at end of file
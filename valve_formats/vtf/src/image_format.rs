@@ -29,7 +29,39 @@ pub enum ImageFormat {
   UVWQ8888,
   RGBA16161616F,
   RGBA16161616,
-  UV1X8888
+  UV1X8888,
+  R32F,
+  RGB323232F,
+  RGBA32323232F,
+  NVDST16,
+  NVDST24,
+  NVINTZ,
+  NVRAWZ,
+  ATIDST16,
+  ATIDST24,
+  NVNULL,
+  /// BC5
+  ATI2N,
+  /// BC4
+  ATI1N,
+  /// Stored as -1 when there is no low resolution image, also used for values this crate doesn't know
+  Unknown = 0xffffffff
+}
+
+impl ImageFormat {
+  pub fn from_raw(value: u32) -> Self {
+    const FORMATS: [ImageFormat; 39] = [
+      ImageFormat::RGBA8888, ImageFormat::ABGR8888, ImageFormat::RGB8888, ImageFormat::BGR888, ImageFormat::RGB565,
+      ImageFormat::I8, ImageFormat::IA88, ImageFormat::P8, ImageFormat::A8, ImageFormat::RGB888Bluescreen,
+      ImageFormat::BGR888Bluescreen, ImageFormat::ARGB8888, ImageFormat::BGRA8888, ImageFormat::DXT1, ImageFormat::DXT3,
+      ImageFormat::DXT5, ImageFormat::BGRX8888, ImageFormat::BGR565, ImageFormat::BGRX5551, ImageFormat::BGRA4444,
+      ImageFormat::DXT1OneBitAlpha, ImageFormat::BGRA5551, ImageFormat::UV88, ImageFormat::UVWQ8888, ImageFormat::RGBA16161616F,
+      ImageFormat::RGBA16161616, ImageFormat::UV1X8888, ImageFormat::R32F, ImageFormat::RGB323232F, ImageFormat::RGBA32323232F,
+      ImageFormat::NVDST16, ImageFormat::NVDST24, ImageFormat::NVINTZ, ImageFormat::NVRAWZ, ImageFormat::ATIDST16,
+      ImageFormat::ATIDST24, ImageFormat::NVNULL, ImageFormat::ATI2N, ImageFormat::ATI1N
+    ];
+    FORMATS.get(value as usize).copied().unwrap_or(ImageFormat::Unknown)
+  }
 }
 
 pub enum FormatSizeInfo {
@@ -44,7 +76,7 @@ pub enum FormatSizeInfo {
     block_width: u8,
     block_height: u8,
     block_depth: u8,
-    total_bits_per_block: u8
+    total_bytes_per_block: u8
   }
 }
 
@@ -66,6 +98,34 @@ lazy_static! {
         total_bits_per_pixel: 8
       }
     });
+    m.insert(ImageFormat::I8, ImageFormatInfo {
+      is_supported: true,
+      size_info: FormatSizeInfo::Pixel {
+        red_bits_per_pixel: 8,
+        green_bits_per_pixel: 0,
+        blue_bits_per_pixel: 0,
+        alpha_bits_per_pixel: 0,
+        total_bits_per_pixel: 8
+      }
+    });
+    m.insert(ImageFormat::ATI1N, ImageFormatInfo {
+      is_supported: true,
+      size_info: FormatSizeInfo::Block {
+        block_width: 4,
+        block_height: 4,
+        block_depth: 1,
+        total_bytes_per_block: 8
+      }
+    });
+    m.insert(ImageFormat::ATI2N, ImageFormatInfo {
+      is_supported: true,
+      size_info: FormatSizeInfo::Block {
+        block_width: 4,
+        block_height: 4,
+        block_depth: 1,
+        total_bytes_per_block: 16
+      }
+    });
     m.insert(ImageFormat::ARGB8888, ImageFormatInfo {
       is_supported: true,
       size_info: FormatSizeInfo::Pixel {
@@ -162,7 +222,7 @@ lazy_static! {
         block_width: 4,
         block_height: 4,
         block_depth: 1,
-        total_bits_per_block: 8
+        total_bytes_per_block: 8
       }
     });
     m.insert(ImageFormat::DXT1OneBitAlpha, ImageFormatInfo {
@@ -171,16 +231,16 @@ lazy_static! {
         block_width: 4,
         block_height: 4,
         block_depth: 1,
-        total_bits_per_block: 8
+        total_bytes_per_block: 8
       }
     });
-    m.insert(ImageFormat::DXT1, ImageFormatInfo {
+    m.insert(ImageFormat::DXT3, ImageFormatInfo {
       is_supported: true,
       size_info: FormatSizeInfo::Block {
         block_width: 4,
         block_height: 4,
         block_depth: 1,
-        total_bits_per_block: 8
+        total_bytes_per_block: 16
       }
     });
     m.insert(ImageFormat::DXT5, ImageFormatInfo {
@@ -189,7 +249,7 @@ lazy_static! {
         block_width: 4,
         block_height: 4,
         block_depth: 1,
-        total_bits_per_block: 16
+        total_bytes_per_block: 16
       }
     });
     m.insert(ImageFormat::RGB565, ImageFormatInfo {
@@ -254,13 +314,13 @@ pub(crate) fn calculate_image_size(width: u32, height: u32, depth: u32, format:
   let info = IMAGE_FORMAT_INFO_MAP.get(&format).expect("Unsupported format");
   match info.size_info {
     FormatSizeInfo::Pixel { total_bits_per_pixel, .. } => {
-      total_bits_per_pixel as u32 * width * height * depth
+      total_bits_per_pixel as u32 * width * height * depth / 8
     }
-    FormatSizeInfo::Block { block_width, block_height, block_depth, total_bits_per_block } => {
+    FormatSizeInfo::Block { block_width, block_height, block_depth, total_bytes_per_block } => {
       ((width + block_width as u32 - 1) / block_width as u32)
       * ((height + block_height as u32 - 1) / block_height as u32)
       * ((depth + block_depth as u32 - 1) / block_depth as u32)
-      * total_bits_per_block as u32
+      * total_bytes_per_block as u32
     }
   }
 }
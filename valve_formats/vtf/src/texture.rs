@@ -51,7 +51,7 @@ impl<R: Read + Seek> VtfTexture<R> {
   }
 
   fn calculate_mip_offset(&self, level: u32) -> Option<u64> {
-    if level > self.header.mipmap_count as u32 {
+    if level >= self.header.mipmap_count as u32 {
       return None;
     }
     let mut offset = *self.resource_offsets.get(&Resource::Image).unwrap() as u64;
@@ -64,7 +64,7 @@ impl<R: Read + Seek> VtfTexture<R> {
       let level_height = max(1, self.header.height >> reversed_level) as u32;
       let level_image_size = calculate_image_size(level_width, level_height, 1, self.header.high_res_image_format) as u64;
       let frames_count = self.header.frames as u64;
-      let faces_count = 1u64; // TODO
+      let faces_count = self.header.faces_count() as u64;
       let slices_count = max(1, self.header.depth as u64); // does this perhaps scale with the mip level in some cases?
       offset += level_image_size * frames_count * faces_count * slices_count;
     }
@@ -85,7 +85,7 @@ impl<R: Read + Seek> VtfTexture<R> {
     let level_image_size = calculate_image_size(level_width, level_height, 1, self.header.high_res_image_format);
 
     let frames_count = self.header.frames;
-    let faces_count = 1; // TODO
+    let faces_count = self.header.faces_count();
     let slices_count = max(1, self.header.depth); // does this perhaps scale with the mip level in some cases?
 
     let mut frames = Vec::<Frame>::with_capacity(frames_count as usize);
@@ -144,7 +144,12 @@ impl<R: Read + Seek> VtfTexture<R> {
       if has_thumbnail {
         resource_offsets.insert(Resource::Thumbnail, header.header_size);
       }
-      resource_offsets.insert(Resource::Image, header.header_size + calculate_image_size(header.low_res_image_width as u32, header.low_res_image_height as u32, 1, header.low_res_image_format));
+      let thumbnail_size = if has_thumbnail {
+        calculate_image_size(header.low_res_image_width as u32, header.low_res_image_height as u32, 1, header.low_res_image_format)
+      } else {
+        0
+      };
+      resource_offsets.insert(Resource::Image, header.header_size + thumbnail_size);
     }
 
     Ok(resource_offsets)
@@ -8,6 +8,8 @@ bitflags! {
     const ANISOTROPIC = 0x00000010;
     const HINT_DXT5 = 0x00000020;
     const PWL_CORRECTED = 0x00000040;
+    /// Replaced PWL_CORRECTED in newer games like CS:GO
+    const SRGB = 0x00000040;
     const NORMAL = 0x00000080;
     const NO_MIP = 0x00000100;
     const NO_LOD = 0x00000200;
@@ -18,5 +18,5 @@ pub use self::model_header::ModelHeader;
 pub use self::model_lod_header::ModelLODHeader;
 pub use self::mesh_header::{MeshHeader, MeshFlags};
 pub use self::strip_group_header::StripGroupHeader;
-pub use self::strip_header::StripHeader;
+pub use self::strip_header::{StripHeader, StripFlags};
 pub use self::vertex::Vertex;
@@ -2,6 +2,13 @@ use std::io::{Read, Result as IOResult};
 
 use crate::PrimitiveRead;
 
+bitflags! {
+  pub struct StripFlags : u8 {
+    const STRIP_IS_TRILIST = 0x01;
+    const STRIP_IS_TRISTRIP = 0x02;
+  }
+}
+
 pub struct StripHeader {
   pub indices_count: i32,
   pub index_offset: i32,
@@ -11,7 +18,7 @@ pub struct StripHeader {
 
   pub bones_count: i16,
 
-  pub flags: u8,
+  pub flags: StripFlags,
 
   pub bone_state_changes_count: i32,
   pub bone_state_change_offset: i32
@@ -24,7 +31,8 @@ impl StripHeader {
     let verts_count = read.read_i32()?;
     let vert_offset = read.read_i32()?;
     let bones_count = read.read_i16()?;
-    let flags = read.read_u8()?;
+    let flags_raw = read.read_u8()?;
+    let flags = StripFlags::from_bits(flags_raw).unwrap_or(StripFlags::empty());
     let bone_state_changes_count = read.read_i32()?;
     let bone_state_change_offset = read.read_i32()?;
     Ok(Self {